@@ -4,8 +4,8 @@ use linear_map::{set::LinearSet, LinearMap};
 use rand::SeedableRng;
 
 use crate::dependencies::AppDependencies;
-use crate::manifest::{Manifest, ManifestLatest};
-use crate::util::Apply;
+use crate::instructions::OutputFormat;
+use crate::manifest::{Manifest, ManifestLatest, Provenance};
 use crate::util::{from_yaml_async_reader, PersistencePath, YamlUpdateHandle};
 use crate::Error;
 use crate::ResultExt as _;
@@ -38,12 +38,29 @@ pub struct AppInfo {
     #[serde(default)]
     #[serde(skip_serializing_if = "not")]
     pub needs_restart: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "not")]
+    pub pinned: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "not")]
+    pub system: bool,
+    /// The network the app runs against, if its package declares modes.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// Copied from the manifest on install.
+    #[serde(default)]
+    #[serde(flatten)]
+    pub provenance: Provenance,
 }
 
 #[derive(Clone, Debug, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct AppStatus {
     pub status: DockerStatus,
+    /// Of a running app with health checks, once the monitor has probed it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<crate::health::Health>,
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -124,6 +141,28 @@ pub async fn set_recoverable(id: &str, recoverable: bool) -> Result<(), Error> {
     Ok(())
 }
 
+pub async fn set_pinned(id: &str, pinned: bool) -> Result<(), Error> {
+    let mut apps = list_info_mut().await?;
+    let mut app = apps
+        .get_mut(id)
+        .ok_or_else(|| failure::format_err!("App Not Installed: {}", id))
+        .with_code(crate::error::NOT_FOUND)?;
+    app.pinned = pinned;
+    apps.commit().await?;
+    Ok(())
+}
+
+pub async fn set_mode(id: &str, mode: Option<String>) -> Result<(), Error> {
+    let mut apps = list_info_mut().await?;
+    let mut app = apps
+        .get_mut(id)
+        .ok_or_else(|| failure::format_err!("App Not Installed: {}", id))
+        .with_code(crate::error::NOT_FOUND)?;
+    app.mode = mode;
+    apps.commit().await?;
+    Ok(())
+}
+
 pub async fn remove(id: &str) -> Result<(), failure::Error> {
     let mut apps = list_info_mut().await?;
     apps.remove(id);
@@ -132,47 +171,35 @@ pub async fn remove(id: &str) -> Result<(), failure::Error> {
 }
 
 pub async fn status(id: &str, remap_crashed: bool) -> Result<AppStatus, Error> {
-    let output = std::process::Command::new("docker")
-        .args(&["inspect", id, "--format", "{{.State.Status}}"])
-        .stdout(std::process::Stdio::piped())
-        .stderr(match log::max_level() {
-            log::LevelFilter::Error => std::process::Stdio::null(),
-            _ => std::process::Stdio::inherit(),
-        })
-        .spawn()?
-        .wait_with_output()?;
-    crate::ensure_code!(
-        output.status.success(),
-        crate::error::DOCKER_ERROR,
-        "{}: Docker Error: {}",
-        id,
-        std::str::from_utf8(&output.stderr).no_code()?
-    );
-    let status = std::str::from_utf8(&output.stdout).no_code()?;
-    Ok(AppStatus {
-        status: match status.trim() {
-            "running" => DockerStatus::Running,
-            "restarting" => DockerStatus::Restarting,
-            "removing" => DockerStatus::Removing,
-            "dead" => DockerStatus::Dead,
-            "exited"
-                if remap_crashed && {
-                    let path = PersistencePath::from_ref("running.yaml");
-                    if let Some(mut f) = path.maybe_read(false).await.transpose()? {
-                        let running: Vec<String> = from_yaml_async_reader(&mut *f).await?;
-                        running.iter().filter(|a| a.as_str() == id).next().is_some()
-                    } else {
-                        false
-                    }
-                } =>
-            {
-                DockerStatus::Restarting
-            }
-            "created" | "exited" => DockerStatus::Stopped,
-            "paused" => DockerStatus::Paused,
-            _ => Err(format_err!("unknown status: {}", status))?,
-        },
-    })
+    let state = crate::backend::Backend::of_app(id).state(id)?;
+    let status = match state.trim() {
+        "running" => DockerStatus::Running,
+        "restarting" => DockerStatus::Restarting,
+        "removing" => DockerStatus::Removing,
+        "dead" => DockerStatus::Dead,
+        "exited"
+            if remap_crashed && {
+                let path = PersistencePath::from_ref("running.yaml");
+                if let Some(mut f) = path.maybe_read(false).await.transpose()? {
+                    let running: Vec<String> = from_yaml_async_reader(&mut *f).await?;
+                    running.iter().filter(|a| a.as_str() == id).next().is_some()
+                } else {
+                    false
+                }
+            } =>
+        {
+            DockerStatus::Restarting
+        }
+        "created" | "exited" => DockerStatus::Stopped,
+        "paused" => DockerStatus::Paused,
+        _ => Err(format_err!("unknown status: {}", state))?,
+    };
+    let health = if status == DockerStatus::Running {
+        crate::health::overall(crate::health::get(id).await?.values())
+    } else {
+        None
+    };
+    Ok(AppStatus { status, health })
 }
 
 pub async fn manifest(id: &str) -> Result<ManifestLatest, Error> {
@@ -205,48 +232,26 @@ pub async fn config(id: &str) -> Result<AppConfig, Error> {
     let config = PersistencePath::from_ref("apps")
         .join(id)
         .join("config.yaml");
-    let config: Option<crate::config::Config> = match config
-        .maybe_read(false)
-        .await
-        .transpose()?
-        .map(|mut f| async move { from_yaml_async_reader(&mut *f).await })
-        .apply(OptionFuture::from)
-        .await
-    {
+    let config: Option<crate::config::Config> = match config.maybe_read_sealed().await.transpose() {
         Some(Ok(cfg)) => Some(cfg),
         #[cfg(not(feature = "production"))]
         Some(Err(e)) => return Err(e),
         _ => {
-            let volume_config = std::path::Path::new(crate::VOLUMES)
-                .join(id)
-                .join("start9")
-                .join("config.yaml");
-            if volume_config.exists() {
-                let cfg_path = config.path();
-                tokio::fs::copy(&volume_config, &cfg_path)
-                    .await
-                    .with_context(|e| {
-                        format!(
-                            "{}: {} -> {}",
-                            e,
-                            volume_config.display(),
-                            cfg_path.display()
-                        )
-                    })
-                    .with_code(crate::error::FILESYSTEM_ERROR)?;
-                let mut f = tokio::fs::File::open(&volume_config)
-                    .await
-                    .with_context(|e| format!("{}: {}", e, volume_config.display()))
-                    .with_code(crate::error::FILESYSTEM_ERROR)?;
-                match from_yaml_async_reader(&mut f).await {
-                    Ok(a) => Some(a),
-                    #[cfg(not(feature = "production"))]
-                    Err(e) => return Err(e),
-                    #[cfg(feature = "production")]
-                    _ => None,
+            match manifest(id)
+                .await?
+                .config_format
+                .read_volume_config(id)
+                .await
+            {
+                Ok(Some(cfg)) => {
+                    config.write_sealed(&cfg).await?;
+                    Some(cfg)
                 }
-            } else {
-                None
+                Ok(None) => None,
+                #[cfg(not(feature = "production"))]
+                Err(e) => return Err(e),
+                #[cfg(feature = "production")]
+                _ => None,
             }
         }
     };
@@ -429,28 +434,22 @@ pub async fn list(
     .collect()
 }
 
-pub async fn print_instructions(id: &str) -> Result<(), Error> {
+pub async fn print_instructions(id: &str, format: OutputFormat) -> Result<(), Error> {
     if let Some(file) = PersistencePath::from_ref("apps")
         .join(id)
         .join("instructions.md")
         .maybe_read(false)
         .await
     {
-        use tokio::io::AsyncWriteExt;
+        use tokio::io::AsyncReadExt;
 
-        let mut stdout = tokio::io::stdout();
-        tokio::io::copy(&mut *file?, &mut stdout)
-            .await
-            .with_code(crate::error::FILESYSTEM_ERROR)?;
-        stdout
-            .flush()
-            .await
-            .with_code(crate::error::FILESYSTEM_ERROR)?;
-        stdout
-            .shutdown()
+        let mut file = file?;
+        let mut text = Vec::new();
+        file.read_to_end(&mut text)
             .await
             .with_code(crate::error::FILESYSTEM_ERROR)?;
-        Ok(())
+        let source = manifest(id).await?.instructions_format;
+        crate::instructions::print(&text, source, format).await
     } else {
         Err(failure::format_err!("No Instructions: {}", id)).with_code(crate::error::NOT_FOUND)
     }
@@ -0,0 +1,577 @@
+use std::path::{Path, PathBuf};
+
+use failure::ResultExt as _;
+use tokio::io::{AsyncRead, AsyncWriteExt};
+
+use crate::manifest::{ImageConfig, ManifestV0, StaticUnit};
+use crate::util::Invoke;
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const UNIT_DIR: &'static str = "/etc/systemd/system";
+
+/// What an app is run by: docker for images, systemd for static binaries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Docker,
+    Systemd,
+}
+
+pub fn unit_name(id: &str) -> String {
+    format!("start9-{}.service", id)
+}
+
+pub fn unit_path(id: &str) -> PathBuf {
+    Path::new(UNIT_DIR).join(unit_name(id))
+}
+
+fn bin_dir(id: &str) -> PathBuf {
+    Path::new(crate::PERSISTENCE_DIR)
+        .join("apps")
+        .join(id)
+        .join("bin")
+}
+
+/// Where the binary of a static app is installed. It is kept with the metadata of the app, so it
+/// is retained and removed along with it.
+pub fn binary_path(id: &str, binary: &str) -> PathBuf {
+    bin_dir(id).join(binary)
+}
+
+/// Whether `binary` is a plain file name, and none of the names the package gives its other
+/// entries. Anything else could be installed outside the directory of the app.
+pub fn is_valid_binary(binary: &str) -> bool {
+    !binary.is_empty()
+        && !binary.contains('/')
+        && !binary.contains('\0')
+        && binary != "."
+        && binary != ".."
+        // the name it is written to before it is moved in place
+        && !binary.ends_with(".tmp")
+        && crate::checksums::component(binary).starts_with("Asset")
+}
+
+fn validate_binary(binary: &str) -> Result<(), Error> {
+    crate::ensure_code!(
+        is_valid_binary(binary),
+        crate::error::GENERAL_ERROR,
+        "Invalid Binary Name: {}",
+        binary
+    );
+    Ok(())
+}
+
+// the tor address and key, kept out of the unit since anyone can read that back from systemd
+fn tor_env_path(id: &str) -> PathBuf {
+    Path::new(crate::PERSISTENCE_DIR)
+        .join("apps")
+        .join(id)
+        .join("tor.env")
+}
+
+/// The user a static app runs as unless its manifest names one. It is created when the app is
+/// installed, and is given the volume of the app.
+pub fn app_user(id: &str) -> String {
+    format!("start9-{}", id)
+}
+
+fn user(manifest: &ManifestV0, unit: &StaticUnit) -> String {
+    unit.user.clone().unwrap_or_else(|| app_user(&manifest.id))
+}
+
+/// Whether `user` is a name `useradd` would accept, so it is safe to write to the unit as is.
+pub fn is_valid_user(user: &str) -> bool {
+    let mut chars = user.chars();
+    user.len() <= 32
+        && chars
+            .next()
+            .map_or(false, |c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+fn validate_user(user: &str) -> Result<(), Error> {
+    crate::ensure_code!(
+        is_valid_user(user),
+        crate::error::GENERAL_ERROR,
+        "Invalid User: {}",
+        user
+    );
+    Ok(())
+}
+
+/// Checks the names a static app is installed and run under, before anything is written.
+pub fn validate(manifest: &ManifestV0) -> Result<(), Error> {
+    if let ImageConfig::Static { binary, unit } = &manifest.image {
+        validate_binary(binary)?;
+        validate_user(&user(manifest, unit))?;
+    }
+    Ok(())
+}
+
+/// Quotes `s` as a single word of a unit file setting. `%` starts a specifier anywhere in a unit
+/// file, and `$` a variable in a command line, so both are doubled.
+fn quote(s: &str, command: bool) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' | '"' => {
+                res.push('\\');
+                res.push(c);
+            }
+            '%' => res.push_str("%%"),
+            '$' if command => res.push_str("$$"),
+            '\n' => res.push_str("\\n"),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// The unit of a static app. It has no `[Install]` section: like containers, apps are started by
+/// appmgr, which restarts the ones that were running at boot. The ip the app was assigned is added
+/// to the loopback interface while it runs, so tor and nginx reach it the same way they reach a
+/// container. The environment rendered from the config is read from its env file at every start.
+/// The app runs unprivileged, and sees nothing of the home of root but its volume and binary.
+pub fn unit(manifest: &ManifestV0, ip: std::net::Ipv4Addr) -> Result<String, Error> {
+    let (binary, unit) = match &manifest.image {
+        ImageConfig::Static { binary, unit } => (binary, unit),
+        _ => {
+            return Err(failure::format_err!(
+                "{} Is Not A Static Binary",
+                manifest.id
+            ))
+            .with_code(crate::error::GENERAL_ERROR)
+        }
+    };
+    let user = user(manifest, unit);
+    validate_user(&user)?;
+    let volume = Path::new(crate::VOLUMES).join(&manifest.id);
+    let mut exec = quote(
+        &format!("{}", binary_path(&manifest.id, binary).display()),
+        true,
+    );
+    for arg in &unit.args {
+        exec.push(' ');
+        exec.push_str(&quote(arg, true));
+    }
+    let mut res = String::new();
+    res.push_str("[Unit]\n");
+    res.push_str(&format!(
+        "Description={}\n",
+        manifest.title.replace('%', "%%")
+    ));
+    res.push_str("After=network-online.target\n");
+    res.push_str("\n[Service]\n");
+    res.push_str("Type=simple\n");
+    res.push_str(&format!(
+        "ExecStartPre=-+/sbin/ip address add {}/32 dev lo\n",
+        ip
+    ));
+    res.push_str(&format!("ExecStart={}\n", exec));
+    res.push_str(&format!(
+        "ExecStopPost=-+/sbin/ip address delete {}/32 dev lo\n",
+        ip
+    ));
+    res.push_str(&format!(
+        "WorkingDirectory={}\n",
+        quote(&format!("{}", volume.display()), false)
+    ));
+    for (name, value) in &unit.environment {
+        res.push_str(&format!(
+            "Environment={}\n",
+            quote(&format!("{}={}", name, value), false)
+        ));
+    }
+    res.push_str(&format!(
+        "EnvironmentFile=-{}\n",
+        tor_env_path(&manifest.id).display()
+    ));
+    res.push_str(&format!(
+        "EnvironmentFile=-{}\n",
+        crate::config::env::path(&manifest.id).display()
+    ));
+    res.push_str(&format!("User={}\n", user));
+    res.push_str("NoNewPrivileges=yes\n");
+    res.push_str("ProtectSystem=strict\n");
+    res.push_str("ProtectHome=tmpfs\n");
+    res.push_str(&format!("BindPaths={}\n", volume.display()));
+    res.push_str(&format!(
+        "BindReadOnlyPaths={}\n",
+        bin_dir(&manifest.id).display()
+    ));
+    res.push_str("PrivateTmp=yes\n");
+    res.push_str("PrivateDevices=yes\n");
+    res.push_str("ProtectKernelTunables=yes\n");
+    res.push_str("ProtectKernelModules=yes\n");
+    res.push_str("ProtectControlGroups=yes\n");
+    res.push_str("Restart=no\n");
+    res.push_str("TimeoutStopSec=25\n");
+    Ok(res)
+}
+
+/// Installs the binary of a static app from the package.
+pub async fn install_binary<R: AsyncRead + Unpin>(
+    manifest: &ManifestV0,
+    mut binary: R,
+) -> Result<(), Error> {
+    let binary_name = match &manifest.image {
+        ImageConfig::Static { binary, .. } => binary,
+        _ => {
+            return Err(failure::format_err!(
+                "{} Is Not A Static Binary",
+                manifest.id
+            ))
+            .with_code(crate::error::GENERAL_ERROR)
+        }
+    };
+    validate_binary(binary_name)?;
+    let dir = bin_dir(&manifest.id);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .with_context(|e| format!("{}: {}", dir.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    // in case the directory of the app, or anything above it, has been replaced with a link
+    let apps = tokio::fs::canonicalize(Path::new(crate::PERSISTENCE_DIR).join("apps")).await?;
+    let dir = tokio::fs::canonicalize(&dir).await?;
+    crate::ensure_code!(
+        dir == apps.join(&manifest.id).join("bin"),
+        crate::error::FILESYSTEM_ERROR,
+        "{}: Outside Of {}",
+        dir.display(),
+        apps.display()
+    );
+    let path = dir.join(binary_name);
+    let tmp = path.with_extension("tmp");
+    let mut f = tokio::fs::File::create(&tmp)
+        .await
+        .with_context(|e| format!("{}: {}", tmp.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    tokio::io::copy(&mut binary, &mut f).await?;
+    f.flush().await?;
+    drop(f);
+    tokio::fs::set_permissions(&tmp, std::os::unix::fs::PermissionsExt::from_mode(0o755)).await?;
+    // replaced rather than written over, in case the old version is still running
+    tokio::fs::rename(&tmp, &path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(())
+}
+
+/// Writes the unit of a static app, with `env` (the tor address and key) in a file only root can
+/// read.
+pub async fn write_unit(
+    manifest: &ManifestV0,
+    ip: std::net::Ipv4Addr,
+    env: &[String],
+) -> Result<(), Error> {
+    let env_path = tor_env_path(&manifest.id);
+    let mut f = tokio::fs::File::create(&env_path)
+        .await
+        .with_context(|e| format!("{}: {}", env_path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    tokio::fs::set_permissions(
+        &env_path,
+        std::os::unix::fs::PermissionsExt::from_mode(0o600),
+    )
+    .await?;
+    for var in env {
+        f.write_all(var.as_bytes()).await?;
+        f.write_all(b"\n").await?;
+    }
+    f.flush().await?;
+    if let ImageConfig::Static { unit, .. } = &manifest.image {
+        if unit.user.is_none() {
+            add_user(&manifest.id).await?;
+        }
+    }
+    let path = unit_path(&manifest.id);
+    tokio::fs::write(&path, unit(manifest, ip)?)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    daemon_reload().await
+}
+
+async fn user_exists(user: &str) -> Result<bool, Error> {
+    Ok(tokio::process::Command::new("id")
+        .arg("-u")
+        .arg(user)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .await?
+        .success())
+}
+
+// creates the user of the app if it does not exist yet, and gives it the volume
+async fn add_user(id: &str) -> Result<(), Error> {
+    let user = app_user(id);
+    let volume = Path::new(crate::VOLUMES).join(id);
+    if !user_exists(&user).await? {
+        tokio::process::Command::new("useradd")
+            .arg("--system")
+            .arg("--user-group")
+            .arg("--no-create-home")
+            .arg("--home-dir")
+            .arg(&volume)
+            .arg("--shell")
+            .arg("/usr/sbin/nologin")
+            .arg(&user)
+            .invoke("Useradd")
+            .await
+            .with_code(crate::error::GENERAL_ERROR)?;
+    }
+    tokio::process::Command::new("chown")
+        .arg("-R")
+        .arg(format!("{}:{}", user, user))
+        .arg(&volume)
+        .invoke("Chown")
+        .await
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(())
+}
+
+async fn daemon_reload() -> Result<(), Error> {
+    tokio::process::Command::new("systemctl")
+        .arg("daemon-reload")
+        .invoke("Systemd")
+        .await
+        .with_code(crate::error::GENERAL_ERROR)?;
+    Ok(())
+}
+
+impl Backend {
+    pub fn of(image: &ImageConfig) -> Self {
+        match image {
+            ImageConfig::Static { .. } => Backend::Systemd,
+            _ => Backend::Docker,
+        }
+    }
+
+    /// The backend of an installed app, told by whether it has a unit, which also holds while it
+    /// is being removed.
+    pub fn of_app(id: &str) -> Self {
+        if unit_path(id).exists() {
+            Backend::Systemd
+        } else {
+            Backend::Docker
+        }
+    }
+
+    async fn run(self, args: &[&str], what: &str) -> Result<(), Error> {
+        let (cmd, code) = match self {
+            Backend::Docker => ("docker", crate::error::DOCKER_ERROR),
+            Backend::Systemd => ("systemctl", crate::error::GENERAL_ERROR),
+        };
+        let output = tokio::process::Command::new(cmd)
+            .args(args)
+            .stdout(std::process::Stdio::null())
+            .output()
+            .await?;
+        crate::ensure_code!(
+            output.status.success(),
+            code,
+            "Failed to {}: {}",
+            what,
+            std::str::from_utf8(&output.stderr).unwrap_or("Unknown Error")
+        );
+        Ok(())
+    }
+
+    pub async fn start(self, id: &str) -> Result<(), Error> {
+        match self {
+            Backend::Docker => self.run(&["start", id], "Start Application").await,
+            Backend::Systemd => {
+                self.run(&["start", &unit_name(id)], "Start Application")
+                    .await
+            }
+        }
+    }
+
+    pub async fn stop(self, id: &str) -> Result<(), Error> {
+        match self {
+            Backend::Docker => {
+                self.run(&["stop", "-t", "25", id], "Stop Application")
+                    .await
+            }
+            // the unit gives it the same 25 seconds
+            Backend::Systemd => {
+                self.run(&["stop", &unit_name(id)], "Stop Application")
+                    .await
+            }
+        }
+    }
+
+    /// Freezing a unit needs systemd 246 or later, and the unified cgroup hierarchy.
+    pub async fn pause(self, id: &str) -> Result<(), Error> {
+        match self {
+            Backend::Docker => self.run(&["pause", id], "Pause Application").await,
+            Backend::Systemd => {
+                self.run(&["freeze", &unit_name(id)], "Pause Application")
+                    .await
+            }
+        }
+    }
+
+    pub async fn resume(self, id: &str) -> Result<(), Error> {
+        match self {
+            Backend::Docker => self.run(&["unpause", id], "Resume Application").await,
+            Backend::Systemd => {
+                self.run(&["thaw", &unit_name(id)], "Resume Application")
+                    .await
+            }
+        }
+    }
+
+    /// The state of the app, in the terms docker uses for the state of a container.
+    pub fn state(self, id: &str) -> Result<String, Error> {
+        let output = match self {
+            Backend::Docker => std::process::Command::new("docker")
+                .args(&["inspect", id, "--format", "{{.State.Status}}"])
+                .stdout(std::process::Stdio::piped())
+                .stderr(match log::max_level() {
+                    log::LevelFilter::Error => std::process::Stdio::null(),
+                    _ => std::process::Stdio::inherit(),
+                })
+                .spawn()?
+                .wait_with_output()?,
+            Backend::Systemd => std::process::Command::new("systemctl")
+                .args(&["show", unit_name(id).as_str()])
+                .args(&["-p", "LoadState", "-p", "ActiveState", "-p", "FreezerState"])
+                .output()?,
+        };
+        crate::ensure_code!(
+            output.status.success(),
+            crate::error::DOCKER_ERROR,
+            "{}: {:?} Error: {}",
+            id,
+            self,
+            std::str::from_utf8(&output.stderr).no_code()?
+        );
+        let output = std::str::from_utf8(&output.stdout).no_code()?;
+        match self {
+            Backend::Docker => Ok(output.trim().to_owned()),
+            Backend::Systemd => unit_state(id, output),
+        }
+    }
+
+    /// Removes the container or unit of an app. Failures are logged rather than returned, so the
+    /// rest of the app is removed regardless.
+    pub async fn remove(self, id: &str) -> Result<(), Error> {
+        match self {
+            Backend::Docker => {
+                log::info!("Removing docker container.");
+                if let Err(e) = self.run(&["rm", id], "Remove Docker Container").await {
+                    log::error!("{}", e);
+                }
+                let image_name = format!("start9/{}", id);
+                if let Err(e) = self.run(&["rmi", &image_name], "Remove Docker Image").await {
+                    log::error!("{}", e);
+                }
+            }
+            Backend::Systemd => {
+                log::info!("Removing systemd unit.");
+                for path in &[unit_path(id), tor_env_path(id)] {
+                    if let Err(e) = tokio::fs::remove_file(path).await {
+                        log::error!("{}: {}", path.display(), e);
+                    }
+                }
+                daemon_reload().await?;
+                let user = app_user(id);
+                if user_exists(&user).await? {
+                    if let Err(e) = tokio::process::Command::new("userdel")
+                        .arg(&user)
+                        .invoke("Userdel")
+                        .await
+                    {
+                        log::error!("{}", e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maps the output of `systemctl show` to the state docker would report for a container.
+fn unit_state(id: &str, show: &str) -> Result<String, Error> {
+    let prop = |name: &str| {
+        show.lines()
+            .find_map(|l| l.strip_prefix(name)?.strip_prefix('='))
+            .unwrap_or_default()
+    };
+    crate::ensure_code!(
+        prop("LoadState") != "not-found",
+        crate::error::NOT_FOUND,
+        "{}: No Such Unit",
+        id
+    );
+    if prop("FreezerState") == "frozen" {
+        return Ok("paused".to_owned());
+    }
+    Ok(match prop("ActiveState") {
+        "active" => "running",
+        "activating" | "deactivating" | "reloading" => "restarting",
+        "failed" | "inactive" => "exited",
+        a => a,
+    }
+    .to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_unit() {
+        let mut manifest: ManifestV0 = serde_yaml::from_str("id: foo\nversion: 0.1.0\ntitle: Foo 100%\ndescription:\n  short: Foo\n  long: Foo\nrelease-notes: First\nports: []\nimage:\n  type: static\n  binary: food\n  unit:\n    args: [--name, \"$USER \\\"foo\\\"\"]\n    environment:\n      RUST_LOG: info\nmount: /root\n").unwrap();
+        let unit = unit(&manifest, "172.18.0.3".parse().unwrap()).unwrap();
+        assert!(unit.contains("Description=Foo 100%%\n"));
+        assert!(unit.contains(&format!(
+            "ExecStart=\"{}/apps/foo/bin/food\" \"--name\" \"$$USER \\\"foo\\\"\"\n",
+            crate::PERSISTENCE_DIR
+        )));
+        assert!(unit.contains("ExecStartPre=-+/sbin/ip address add 172.18.0.3/32 dev lo\n"));
+        assert!(unit.contains("Environment=\"RUST_LOG=info\"\n"));
+        assert!(unit.contains("User=start9-foo\n"));
+        assert!(unit.contains("NoNewPrivileges=yes\n"));
+        if let ImageConfig::Static { unit, .. } = &mut manifest.image {
+            unit.user = Some("nobody\nUser=root".to_owned());
+        }
+        assert!(super::unit(&manifest, "172.18.0.3".parse().unwrap()).is_err());
+        manifest.image = ImageConfig::Tar;
+        assert!(super::unit(&manifest, "172.18.0.3".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_valid_binary() {
+        assert!(is_valid_binary("food"));
+        assert!(is_valid_binary("food.v2"));
+        for binary in &[
+            "",
+            ".",
+            "..",
+            "../food",
+            "/bin/sh",
+            "food.tmp",
+            "manifest.cbor",
+        ] {
+            assert!(!is_valid_binary(binary), "{}", binary);
+        }
+    }
+
+    #[test]
+    fn test_unit_state() {
+        let show = "LoadState=loaded\nActiveState=active\nFreezerState=running\n";
+        assert_eq!(unit_state("foo", show).unwrap(), "running");
+        let show = "LoadState=loaded\nActiveState=active\nFreezerState=frozen\n";
+        assert_eq!(unit_state("foo", show).unwrap(), "paused");
+        let show = "LoadState=loaded\nActiveState=failed\nFreezerState=running\n";
+        assert_eq!(unit_state("foo", show).unwrap(), "exited");
+        let show = "LoadState=not-found\nActiveState=inactive\nFreezerState=running\n";
+        assert!(unit_state("foo", show).is_err());
+    }
+}
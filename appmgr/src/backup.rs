@@ -3,12 +3,14 @@ use std::path::Path;
 
 use argon2::Config;
 use emver::Version;
-use futures::try_join;
+use futures::stream::StreamExt;
 use futures::TryStreamExt;
+use futures::{join, try_join};
+use linear_map::LinearMap;
 use rand::Rng;
 use serde::Serialize;
 
-use crate::util::from_yaml_async_reader;
+use crate::progress::{ProgressGuard, Unit};
 use crate::util::to_yaml_async_writer;
 use crate::util::Invoke;
 use crate::util::PersistencePath;
@@ -28,6 +30,8 @@ pub async fn create_backup<P: AsRef<Path>>(
     app_id: &str,
     password: &str,
 ) -> Result<(), Error> {
+    let _lock = crate::util::lock_app(app_id).await?;
+    let _io = crate::io_priority::enter(crate::io_priority::JobClass::Backup).await?;
     let path = tokio::fs::canonicalize(path).await?;
     crate::ensure_code!(
         path.is_dir(),
@@ -38,6 +42,7 @@ pub async fn create_backup<P: AsRef<Path>>(
     let pw_path = path.join("password");
     let data_path = path.join("data");
     let tor_path = path.join("tor");
+    let config_path = path.join("config");
     let volume_path = Path::new(crate::VOLUMES).join(app_id);
     let hidden_service_path =
         Path::new(crate::tor::HIDDEN_SERVICE_DIR_ROOT).join(format!("app-{}", app_id));
@@ -75,6 +80,28 @@ pub async fn create_backup<P: AsRef<Path>>(
     )
     .await?;
 
+    // the config appmgr keeps may be sealed under the device key, and the volume may have no
+    // plaintext copy of it, so it is backed up opened
+    if let Some(config) = crate::apps::config(app_id).await?.config {
+        let stage = config_stage(app_id).await?;
+        let res = async {
+            tokio::fs::write(
+                stage.join("config.yaml"),
+                serde_yaml::to_vec(&config).with_code(crate::error::SERDE_ERROR)?,
+            )
+            .await?;
+            duplicity(password, false)
+                .arg(&stage)
+                .arg(format!("file://{}", config_path.display()))
+                .invoke("Duplicity")
+                .await?;
+            Ok::<_, Error>(())
+        }
+        .await;
+        tokio::fs::remove_dir_all(&stage).await?;
+        res?;
+    }
+
     let status = crate::apps::status(app_id, false).await?;
     let exclude = if volume_path.is_dir() {
         let ignore_path = volume_path.join(".backupignore");
@@ -107,18 +134,16 @@ pub async fn create_backup<P: AsRef<Path>>(
             data_cmd.arg(format!("--exclude={}", volume_path.join(exclude).display()));
         }
     }
-    let data_res = data_cmd
+    data_cmd
         .env("PASSPHRASE", password)
         .arg(volume_path)
-        .arg(format!("file://{}", data_path.display()))
-        .invoke("Duplicity")
-        .await;
-    let tor_res = tokio::process::Command::new("duplicity")
+        .arg(format!("file://{}", data_path.display()));
+    let mut tor_cmd = tokio::process::Command::new("duplicity");
+    tor_cmd
         .env("PASSPHRASE", password)
         .arg(hidden_service_path)
-        .arg(format!("file://{}", tor_path.display()))
-        .invoke("Duplicity")
-        .await;
+        .arg(format!("file://{}", tor_path.display()));
+    let (data_res, tor_res) = join!(data_cmd.invoke("Duplicity"), tor_cmd.invoke("Duplicity"));
     if running {
         if crate::apps::info(&app_id).await?.needs_restart {
             crate::control::restart_app(&app_id).await?;
@@ -128,6 +153,7 @@ pub async fn create_backup<P: AsRef<Path>>(
     }
     data_res?;
     tor_res?;
+    crate::events::emit(crate::events::EventKind::BackupComplete, app_id).await;
 
     Ok(())
 }
@@ -147,9 +173,7 @@ pub async fn restore_backup<P: AsRef<Path>>(
     let pw_path = path.join("password");
     let data_path = path.join("data");
     let tor_path = path.join("tor");
-    let volume_path = Path::new(crate::VOLUMES).join(app_id);
-    let hidden_service_path =
-        Path::new(crate::tor::HIDDEN_SERVICE_DIR_ROOT).join(format!("app-{}", app_id));
+    let config_path = path.join("config");
 
     if pw_path.exists() {
         use tokio::io::AsyncReadExt;
@@ -165,24 +189,78 @@ pub async fn restore_backup<P: AsRef<Path>>(
         );
     }
 
+    // backups from before configs were backed up on their own have none
+    let config_url = if config_path.exists() {
+        Some(format!("file://{}", config_path.display()))
+    } else {
+        None
+    };
+    restore_from_urls(
+        app_id,
+        &format!("file://{}", data_path.display()),
+        &format!("file://{}", tor_path.display()),
+        config_url.as_deref(),
+        Some(&metadata_path),
+        password,
+        false,
+    )
+    .await
+}
+
+// a directory only root can read, to pass a config to and from duplicity in
+async fn config_stage(app_id: &str) -> Result<std::path::PathBuf, Error> {
+    let stage = Path::new(crate::TMP_DIR).join("backup-config").join(app_id);
+    if stage.exists() {
+        tokio::fs::remove_dir_all(&stage).await?;
+    }
+    tokio::fs::create_dir_all(&stage).await?;
+    tokio::fs::set_permissions(&stage, std::os::unix::fs::PermissionsExt::from_mode(0o700)).await?;
+    Ok(stage)
+}
+
+/// Runs duplicity with `password` as the passphrase, through torsocks if `via_tor` is set.
+pub(crate) fn duplicity(password: &str, via_tor: bool) -> tokio::process::Command {
+    let mut cmd = if via_tor {
+        let mut cmd = tokio::process::Command::new("torsocks");
+        cmd.arg("duplicity");
+        cmd
+    } else {
+        tokio::process::Command::new("duplicity")
+    };
+    cmd.env("PASSPHRASE", password);
+    cmd
+}
+
+/// Restores the volume and tor keys of an app from duplicity archives at `data_url` and
+/// `tor_url`, and reconfigures it and the apps pointing to it, with the config archived at
+/// `config_url` if there is one, or else the one in its volume.
+pub(crate) async fn restore_from_urls(
+    app_id: &str,
+    data_url: &str,
+    tor_url: &str,
+    config_url: Option<&str>,
+    metadata_path: Option<&Path>,
+    password: &str,
+    via_tor: bool,
+) -> Result<(), Error> {
+    let _lock = crate::util::lock_app(app_id).await?;
+    let volume_path = Path::new(crate::VOLUMES).join(app_id);
+    let hidden_service_path =
+        Path::new(crate::tor::HIDDEN_SERVICE_DIR_ROOT).join(format!("app-{}", app_id));
+
     let status = crate::apps::status(app_id, false).await?;
     let running = status.status == crate::apps::DockerStatus::Running;
     if running {
         crate::control::stop_app(app_id, true, false).await?;
     }
 
-    let mut data_cmd = tokio::process::Command::new("duplicity");
-    data_cmd
-        .env("PASSPHRASE", password)
-        .arg("--force")
-        .arg(format!("file://{}", data_path.display()))
-        .arg(&volume_path);
+    let mut data_cmd = duplicity(password, via_tor);
+    data_cmd.arg("--force").arg(data_url).arg(&volume_path);
 
-    let mut tor_cmd = tokio::process::Command::new("duplicity");
+    let mut tor_cmd = duplicity(password, via_tor);
     tor_cmd
-        .env("PASSPHRASE", password)
         .arg("--force")
-        .arg(format!("file://{}", tor_path.display()))
+        .arg(tor_url)
         .arg(&hidden_service_path);
 
     let (data_output, tor_output) = try_join!(data_cmd.status(), tor_cmd.status())?;
@@ -204,28 +282,55 @@ pub async fn restore_backup<P: AsRef<Path>>(
     }
     yhdl.commit().await?;
 
-    tokio::fs::copy(
-        metadata_path,
-        Path::new(crate::VOLUMES)
-            .join(app_id)
-            .join("start9")
-            .join("restore.yaml"),
-    )
-    .await?;
+    if let Some(metadata_path) = metadata_path {
+        tokio::fs::copy(
+            metadata_path,
+            Path::new(crate::VOLUMES)
+                .join(app_id)
+                .join("start9")
+                .join("restore.yaml"),
+        )
+        .await?;
+    }
 
     // Attempt to configure the service with the config coming from restoration
-    let cfg_path = Path::new(crate::VOLUMES)
-        .join(app_id)
-        .join("start9")
-        .join("config.yaml");
-    if cfg_path.exists() {
-        let cfg = from_yaml_async_reader(tokio::fs::File::open(cfg_path).await?).await?;
+    let cfg = match config_url {
+        Some(config_url) => {
+            let stage = config_stage(app_id).await?;
+            let res = async {
+                duplicity(password, via_tor)
+                    .arg("--force")
+                    .arg(config_url)
+                    .arg(&stage)
+                    .invoke("Duplicity")
+                    .await?;
+                let bytes = tokio::fs::read(stage.join("config.yaml")).await?;
+                serde_yaml::from_slice::<crate::config::Config>(&bytes)
+                    .with_code(crate::error::SERDE_ERROR)
+            }
+            .await;
+            tokio::fs::remove_dir_all(&stage).await?;
+            Some(res?)
+        }
+        None => {
+            crate::apps::manifest(app_id)
+                .await?
+                .config_format
+                .read_volume_config(app_id)
+                .await?
+        }
+    };
+    if cfg.is_some() {
         if let Err(e) = crate::config::configure(app_id, cfg, None, false).await {
             log::warn!("Could not restore backup configuration: {}", e);
         }
     }
 
     crate::tor::restart().await?;
+    // Apps pointing at this one may have captured its old tor address or key
+    if let Err(e) = crate::config::watch::notify(app_id).await {
+        log::warn!("Could not reconfigure apps pointing to {}: {}", app_id, e);
+    }
     // Delete the fullchain certificate, so it can be regenerated with the restored tor pubkey address
     PersistencePath::from_ref("apps")
         .join(&app_id)
@@ -269,6 +374,116 @@ pub async fn backup_to_partition(
     res
 }
 
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BackupAllRes {
+    pub succeeded: Vec<String>,
+    pub failed: LinearMap<String, String>,
+}
+
+// groups apps whose volumes are mounted into one another, so that they are never backed up at
+// the same time
+async fn volume_groups(app_ids: Vec<String>) -> Result<Vec<Vec<String>>, Error> {
+    let mut group_of: LinearMap<String, usize> = app_ids
+        .iter()
+        .cloned()
+        .enumerate()
+        .map(|(idx, id)| (id, idx))
+        .collect();
+    for id in &app_ids {
+        for (dep, info) in crate::apps::manifest(id).await?.dependencies.0 {
+            if !info.mount_public && !info.mount_shared {
+                continue;
+            }
+            if let (Some(&a), Some(&b)) = (group_of.get(id), group_of.get(&dep)) {
+                for (_, group) in group_of.iter_mut() {
+                    if *group == b {
+                        *group = a;
+                    }
+                }
+            }
+        }
+    }
+    let mut groups: LinearMap<usize, Vec<String>> = LinearMap::new();
+    for (id, group) in group_of {
+        groups.entry(group).or_insert_with(Vec::new).push(id);
+    }
+    Ok(groups.into_iter().map(|(_, group)| group).collect())
+}
+
+async fn backup_to_dir(dir: &Path, app_id: &str, password: &str) -> Result<(), Error> {
+    let backup_dir_path = dir.join(app_id);
+    tokio::fs::create_dir_all(&backup_dir_path).await?;
+    create_backup(backup_dir_path, app_id, password).await
+}
+
+/// Backs up every installed app, up to `jobs` at a time. Apps that share volumes are backed up
+/// one after another. A failed app does not stop the others.
+pub async fn backup_all_to_partition(
+    logicalname: &str,
+    password: &str,
+    jobs: usize,
+) -> Result<BackupAllRes, Error> {
+    let app_ids: Vec<String> = crate::apps::list_info()
+        .await?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    backup_many_to_partition(logicalname, password, app_ids, jobs).await
+}
+
+/// Like `backup_all_to_partition`, but only backs up `app_ids`.
+pub async fn backup_many_to_partition(
+    logicalname: &str,
+    password: &str,
+    app_ids: Vec<String>,
+    jobs: usize,
+) -> Result<BackupAllRes, Error> {
+    let backup_mount_path = Path::new(crate::BACKUP_MOUNT_POINT);
+    let guard = crate::disks::MountGuard::new(logicalname, &backup_mount_path).await?;
+    let backup_dir = backup_mount_path.join(crate::BACKUP_DIR);
+
+    let res = async {
+        let progress = ProgressGuard::start("Backing up", Unit::Steps, Some(app_ids.len() as u64));
+        let results: Vec<Vec<(String, Result<(), Error>)>> =
+            futures::stream::iter(volume_groups(app_ids).await?.into_iter().map(|group| {
+                let backup_dir = &backup_dir;
+                // each group locks its apps on its own, alongside the others
+                crate::util::app_lock_scope(async move {
+                    let mut res = Vec::with_capacity(group.len());
+                    for app_id in group {
+                        log::info!("Backing up {}.", app_id);
+                        crate::progress::phase(app_id.as_str());
+                        let app_res = backup_to_dir(backup_dir, &app_id, password).await;
+                        crate::progress::step();
+                        res.push((app_id, app_res));
+                    }
+                    res
+                })
+            }))
+            .buffer_unordered(jobs.max(1))
+            .collect()
+            .await;
+        progress.finish().await;
+        let mut res = BackupAllRes::default();
+        for (app_id, app_res) in results.into_iter().flatten() {
+            match app_res {
+                Ok(()) => res.succeeded.push(app_id),
+                Err(e) => {
+                    log::error!("Backup of {} failed: {}", app_id, e.failure);
+                    res.failed.insert(app_id, format!("{}", e.failure));
+                }
+            }
+        }
+        Ok::<_, Error>(res)
+    }
+    .await;
+
+    guard.unmount().await?;
+
+    res
+}
+
 pub async fn restore_from_partition(
     logicalname: &str,
     app_id: &str,
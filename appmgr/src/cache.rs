@@ -0,0 +1,260 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use failure::ResultExt as _;
+use linear_map::LinearMap;
+
+use crate::util::{PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const CACHE_YAML: &'static str = "cache.yaml";
+pub const DEFAULT_MAX_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Cached {
+    pub id: String,
+    pub version: emver::Version,
+    pub size: u64,
+    pub last_used: u64,
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CacheState {
+    /// In bytes. `DEFAULT_MAX_SIZE` if unset.
+    #[serde(default)]
+    pub max_size: Option<u64>,
+    /// By file name in the cache dir.
+    #[serde(default)]
+    pub packages: LinearMap<String, Cached>,
+}
+impl CacheState {
+    pub fn max_size(&self) -> u64 {
+        self.max_size.unwrap_or(DEFAULT_MAX_SIZE)
+    }
+    pub fn size(&self) -> u64 {
+        self.packages.values().map(|c| c.size).sum()
+    }
+    /// The packages to evict, least recently used first, for the cache to fit in `max_size`.
+    /// `keep` is never evicted, so a package larger than the cache can still be installed.
+    pub fn lru(&self, max_size: u64, keep: Option<&str>) -> Vec<String> {
+        let mut by_use: Vec<(&String, &Cached)> = self
+            .packages
+            .iter()
+            .filter(|(key, _)| Some(key.as_str()) != keep)
+            .collect();
+        by_use.sort_by_key(|(_, cached)| cached.last_used);
+        let mut size = self.size();
+        let mut res = Vec::new();
+        for (key, cached) in by_use {
+            if size <= max_size {
+                break;
+            }
+            size = size.saturating_sub(cached.size);
+            res.push(key.clone());
+        }
+        res
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn dir() -> PathBuf {
+    Path::new(crate::TMP_DIR).join("cache")
+}
+
+fn key(id: &str, version: &emver::Version) -> String {
+    format!("{}@{}.s9pk", id, version)
+}
+
+pub fn path(id: &str, version: &emver::Version) -> PathBuf {
+    dir().join(key(id, version))
+}
+
+async fn state_mut() -> Result<YamlUpdateHandle<CacheState>, Error> {
+    YamlUpdateHandle::new_or_default(PersistencePath::from_ref(CACHE_YAML)).await
+}
+
+pub async fn state() -> Result<CacheState, Error> {
+    let path = PersistencePath::from_ref(CACHE_YAML);
+    Ok(match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await?,
+        None => CacheState::default(),
+    })
+}
+
+async fn remove_file(path: &Path) -> Result<(), Error> {
+    if path.exists() {
+        tokio::fs::remove_file(path)
+            .await
+            .with_context(|e| format!("rm {}: {}", path.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+    }
+    Ok(())
+}
+
+async fn evict(
+    state: &mut CacheState,
+    max_size: u64,
+    keep: Option<&str>,
+) -> Result<Vec<Cached>, Error> {
+    let mut res = Vec::new();
+    for key in state.lru(max_size, keep) {
+        if let Some(cached) = state.packages.remove(&key) {
+            log::info!("Evicting cached {} v{}.", cached.id, cached.version);
+            remove_file(&dir().join(&key)).await?;
+            res.push(cached);
+        }
+    }
+    Ok(res)
+}
+
+/// The cached package of `id` at `version`, if it was downloaded before. Marks it as used.
+pub async fn get(id: &str, version: &emver::Version) -> Result<Option<PathBuf>, Error> {
+    let mut state = state_mut().await?;
+    let key = key(id, version);
+    let path = dir().join(&key);
+    if !path.exists() {
+        state.packages.remove(&key);
+    }
+    let res = state.packages.get_mut(&key).map(|cached| {
+        cached.last_used = now();
+        path
+    });
+    state.commit().await?;
+    Ok(res)
+}
+
+/// The newest cached package of `id` in `range`, for when the registry cannot be reached.
+pub async fn newest(id: &str, range: &emver::VersionRange) -> Result<Option<PathBuf>, Error> {
+    let newest = state()
+        .await?
+        .packages
+        .into_iter()
+        .map(|(_, cached)| cached)
+        .filter(|cached| cached.id == id && cached.version.satisfies(range))
+        .max_by(|a, b| a.version.cmp(&b.version));
+    match newest {
+        Some(cached) => get(id, &cached.version).await,
+        None => Ok(None),
+    }
+}
+
+/// Moves a downloaded package into the cache, and evicts the least recently used packages if
+/// the cache no longer fits in its size limit.
+pub async fn insert(id: &str, version: &emver::Version, download: &Path) -> Result<PathBuf, Error> {
+    tokio::fs::create_dir_all(dir()).await?;
+    let key = key(id, version);
+    let path = dir().join(&key);
+    tokio::fs::rename(download, &path)
+        .await
+        .with_context(|e| format!("{} -> {}: {}", download.display(), path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let size = tokio::fs::metadata(&path).await?.len();
+    let mut state = state_mut().await?;
+    state.packages.insert(
+        key.clone(),
+        Cached {
+            id: id.to_owned(),
+            version: version.clone(),
+            size,
+            last_used: now(),
+        },
+    );
+    let max_size = state.max_size();
+    evict(&mut state, max_size, Some(&key)).await?;
+    state.commit().await?;
+    Ok(path)
+}
+
+/// Evicts packages until the cache fits in `max_size`, or in its size limit if `None`. Also
+/// forgets packages whose files are gone.
+pub async fn gc(max_size: Option<u64>) -> Result<Vec<Cached>, Error> {
+    let _io = crate::io_priority::enter(crate::io_priority::JobClass::Gc).await?;
+    let mut state = state_mut().await?;
+    let missing: Vec<String> = state
+        .packages
+        .keys()
+        .filter(|key| !dir().join(key).exists())
+        .cloned()
+        .collect();
+    for key in missing {
+        state.packages.remove(&key);
+    }
+    let max_size = max_size.unwrap_or_else(|| state.max_size());
+    let res = evict(&mut state, max_size, None).await?;
+    state.commit().await?;
+    Ok(res)
+}
+
+/// Removes the cached packages of `id`, or every cached package if `None`.
+pub async fn clear(id: Option<&str>) -> Result<Vec<Cached>, Error> {
+    let mut state = state_mut().await?;
+    let keys: Vec<String> = state
+        .packages
+        .iter()
+        .filter(|(_, cached)| id.map_or(true, |id| cached.id == id))
+        .map(|(key, _)| key.clone())
+        .collect();
+    let mut res = Vec::new();
+    for key in keys {
+        if let Some(cached) = state.packages.remove(&key) {
+            remove_file(&dir().join(&key)).await?;
+            res.push(cached);
+        }
+    }
+    state.commit().await?;
+    Ok(res)
+}
+
+pub async fn set_max_size(max_size: Option<u64>) -> Result<Vec<Cached>, Error> {
+    let mut state = state_mut().await?;
+    state.max_size = max_size;
+    let max_size = state.max_size();
+    let res = evict(&mut state, max_size, None).await?;
+    state.commit().await?;
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lru() {
+        let version = emver::Version::new(0, 1, 0, 0);
+        let mut state = CacheState::default();
+        for (id, size, last_used) in &[
+            ("bitcoind", 40, 3),
+            ("lnd", 30, 1),
+            ("btc-rpc-proxy", 20, 2),
+        ] {
+            state.packages.insert(
+                key(id, &version),
+                Cached {
+                    id: (*id).to_owned(),
+                    version: version.clone(),
+                    size: *size,
+                    last_used: *last_used,
+                },
+            );
+        }
+        assert!(state.lru(90, None).is_empty());
+        assert_eq!(state.lru(60, None), vec![key("lnd", &version)]);
+        assert_eq!(
+            state.lru(40, None),
+            vec![key("lnd", &version), key("btc-rpc-proxy", &version)]
+        );
+        assert_eq!(
+            state.lru(0, Some(&key("bitcoind", &version))),
+            vec![key("lnd", &version), key("btc-rpc-proxy", &version)]
+        );
+    }
+}
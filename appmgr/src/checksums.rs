@@ -0,0 +1,187 @@
+use std::path::Path;
+
+use failure::ResultExt as _;
+use futures::stream::StreamExt;
+use linear_map::LinearMap;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_tar as tar;
+
+use crate::Error;
+use crate::ResultExt as _;
+
+/// The last entry of a package, after everything the installer reads, so that older versions
+/// of appmgr can still install it.
+pub const CHECKSUMS_CBOR: &'static str = "checksums.cbor";
+// what `tar::Builder::finish` writes
+const END_OF_ARCHIVE_LEN: u64 = 1024;
+
+/// The sha256 of every entry of a package, by path.
+pub type Checksums = LinearMap<String, [u8; 32]>;
+
+/// The part of the package an entry belongs to, for error messages.
+pub fn component(path: &str) -> String {
+    match path {
+        "manifest.cbor" => "Manifest".to_owned(),
+        "config_spec.cbor" => "Config Spec".to_owned(),
+        "config_rules.cbor" => "Config Rules".to_owned(),
+        "instructions.md" => "Instructions".to_owned(),
+        a if a.starts_with("image.") && a.ends_with(".tar") => format!("Image ({})", a),
+        a => format!("Asset ({})", a.trim_start_matches("APPMGR_DIR_END:")),
+    }
+}
+
+pub async fn hash<R: AsyncRead + Unpin>(mut r: R) -> Result<[u8; 32], Error> {
+    let mut hasher = openssl::sha::Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = r.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.finish())
+}
+
+/// Hashes every entry of the package at `path`, and reads its checksums if it has any.
+async fn scan(path: &Path) -> Result<(Checksums, Option<Checksums>), Error> {
+    let f = tokio::fs::File::open(path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let mut pkg = tar::Archive::new(f);
+    let mut entries = pkg.entries()?;
+    let mut actual = LinearMap::new();
+    while let Some(entry) = entries.next().await {
+        let entry = entry?;
+        let entry_path = format!("{}", entry.path()?.display());
+        if entry_path == CHECKSUMS_CBOR {
+            let expected = crate::util::from_cbor_async_reader(entry).await?;
+            return Ok((actual, Some(expected)));
+        }
+        actual.insert(entry_path, hash(entry).await?);
+    }
+    Ok((actual, None))
+}
+
+/// Hashes every entry of the package at `path`.
+pub async fn compute<P: AsRef<Path>>(path: P) -> Result<Checksums, Error> {
+    Ok(scan(path.as_ref()).await?.0)
+}
+
+/// Compares each entry of a package against its checksums, and reports the first part of it
+/// that is missing, corrupted or unexpected.
+pub fn compare(actual: &Checksums, expected: &Checksums) -> Result<(), Error> {
+    for (path, expected) in expected {
+        match actual.get(path) {
+            None => {
+                return Err(Error::new(
+                    failure::format_err!(
+                        "Package File Invalid or Corrupted: {} Is Missing",
+                        component(path)
+                    ),
+                    Some(crate::error::GENERAL_ERROR),
+                ))
+            }
+            Some(actual) if actual != expected => {
+                return Err(Error::new(
+                    failure::format_err!(
+                        "Package File Invalid or Corrupted: {} Does Not Match Its Checksum",
+                        component(path)
+                    ),
+                    Some(crate::error::GENERAL_ERROR),
+                ))
+            }
+            _ => (),
+        }
+    }
+    if let Some(path) = actual.keys().find(|path| !expected.contains_key(*path)) {
+        return Err(Error::new(
+            failure::format_err!(
+                "Package File Invalid or Corrupted: Unexpected {}",
+                component(path)
+            ),
+            Some(crate::error::GENERAL_ERROR),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks every entry of the package at `path` against its checksums. Packages packed before
+/// checksums were added are let through, as there is nothing to check them against.
+pub async fn check<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    let path = path.as_ref();
+    log::info!("Checking {} against its checksums.", path.display());
+    match scan(path).await? {
+        (actual, Some(expected)) => compare(&actual, &expected),
+        (_, None) => {
+            log::warn!("{} has no checksums.", path.display());
+            Ok(())
+        }
+    }
+}
+
+/// Adds the checksums of every entry to the end of a package that was just packed.
+pub async fn append<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+    let path = path.as_ref();
+    let checksums = compute(path).await?;
+    let bin_checksums = serde_cbor::to_vec(&checksums).with_code(crate::error::SERDE_ERROR)?;
+    let mut f = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    // overwrite the end of the archive, so the checksums are read as part of it
+    let len = f.metadata().await?.len();
+    crate::ensure_code!(
+        len >= END_OF_ARCHIVE_LEN,
+        crate::error::GENERAL_ERROR,
+        "{} Is Not A Package",
+        path.display()
+    );
+    f.set_len(len - END_OF_ARCHIVE_LEN).await?;
+    f.seek(std::io::SeekFrom::End(0)).await?;
+    let mut out = tar::Builder::new(f);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bin_checksums.len() as u64);
+    out.append_data(
+        &mut header,
+        CHECKSUMS_CBOR,
+        std::io::Cursor::new(bin_checksums),
+    )
+    .await?;
+    out.into_inner().await?.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let mut expected = Checksums::new();
+        expected.insert("manifest.cbor".to_owned(), [1; 32]);
+        expected.insert("image.tar".to_owned(), [2; 32]);
+        let mut actual = expected.clone();
+        assert!(compare(&actual, &expected).is_ok());
+        actual.insert("image.tar".to_owned(), [3; 32]);
+        assert_eq!(
+            format!("{}", compare(&actual, &expected).unwrap_err().failure),
+            "Package File Invalid or Corrupted: Image (image.tar) Does Not Match Its Checksum"
+        );
+        actual.remove("image.tar");
+        assert_eq!(
+            format!("{}", compare(&actual, &expected).unwrap_err().failure),
+            "Package File Invalid or Corrupted: Image (image.tar) Is Missing"
+        );
+        actual.insert("image.tar".to_owned(), [2; 32]);
+        actual.insert("icon.png".to_owned(), [4; 32]);
+        assert_eq!(
+            format!("{}", compare(&actual, &expected).unwrap_err().failure),
+            "Package File Invalid or Corrupted: Unexpected Asset (icon.png)"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use rand::{CryptoRng, Rng};
+
+use super::spec::{ConfigSpec, ValueSpec};
+use super::Config;
+use crate::util::PersistencePath;
+use crate::Error;
+use crate::ResultExt as _;
+
+/// The answers to the install prompts of an app, kept with its metadata. Sealed like its config,
+/// since they may hold seeds and the like.
+pub const ANSWERS_YAML: &'static str = "answers.yaml";
+
+fn path(id: &str) -> PersistencePath {
+    PersistencePath::from_ref("apps")
+        .join(id)
+        .join(ANSWERS_YAML)
+}
+
+/// The answers `id` was installed with, if it has install prompts.
+pub async fn get(id: &str) -> Result<Option<Config>, Error> {
+    path(id).maybe_read_sealed().await
+}
+
+pub async fn save(id: &str, answers: &Config) -> Result<(), Error> {
+    path(id).write_sealed(answers).await
+}
+
+/// Fills in the prompts that were not answered from their defaults, and checks the result against
+/// the prompts.
+pub fn resolve<R: Rng + CryptoRng + Sync + Send>(
+    prompts: &ConfigSpec,
+    given: Config,
+    rng: &mut R,
+    timeout: &Option<Duration>,
+) -> Result<Config, Error> {
+    if let Some(key) = given.0.keys().find(|k| !prompts.0.contains_key(*k)) {
+        return Err(failure::format_err!("No Install Prompt Named {:?}", key))
+            .with_code(crate::error::CFG_SPEC_VIOLATION);
+    }
+    let mut answers = prompts
+        .gen(rng, timeout)
+        .with_code(crate::error::CFG_SPEC_VIOLATION)?;
+    answers.0.extend(given.0);
+    prompts
+        .matches(&answers)
+        .with_code(crate::error::CFG_SPEC_VIOLATION)?;
+    Ok(answers)
+}
+
+/// Seeds a freshly generated config with the answers to the prompts that share a key (and a valid
+/// value) with one of its top level fields. Returns the keys that were taken from the answers.
+pub fn apply(config: &mut Config, spec: &ConfigSpec, answers: &Config) -> Vec<String> {
+    let mut res = Vec::new();
+    for (key, value) in answers.0.iter() {
+        match spec.0.get(key) {
+            Some(field) if field.matches(value).is_ok() => {
+                config.0.insert(key.clone(), value.clone());
+                res.push(key.clone());
+            }
+            _ => (),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::value::Value;
+    use crate::config::{EntropyProvider, SeededEntropy};
+
+    #[test]
+    fn test_resolve() {
+        let prompts: ConfigSpec = serde_json::from_str(
+            r#"{
+                "restore-seed": {
+                    "name": "Restore From Existing Seed",
+                    "type": "boolean",
+                    "default": false
+                },
+                "seed": {
+                    "name": "Seed",
+                    "type": "string",
+                    "nullable": true,
+                    "masked": true,
+                    "default": null
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut rng = SeededEntropy(1).rng("lnd");
+        let mut given = Config::default();
+        given.0.insert("restore-seed".to_owned(), Value::Bool(true));
+        let answers = resolve(&prompts, given, &mut rng, &None).unwrap();
+        assert_eq!(answers.0.get("restore-seed"), Some(&Value::Bool(true)));
+        assert_eq!(answers.0.get("seed"), Some(&Value::Null));
+
+        let mut unknown = Config::default();
+        unknown.0.insert("reseed".to_owned(), Value::Bool(true));
+        assert!(resolve(&prompts, unknown, &mut rng, &None).is_err());
+
+        let spec: ConfigSpec = serde_json::from_str(
+            r#"{
+                "restore-seed": {
+                    "name": "Restore From Seed",
+                    "type": "boolean",
+                    "default": false
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut config = spec.gen(&mut rng, &None).unwrap();
+        assert_eq!(apply(&mut config, &spec, &answers), vec!["restore-seed"]);
+        assert_eq!(config.0.get("restore-seed"), Some(&Value::Bool(true)));
+    }
+}
@@ -0,0 +1,72 @@
+use std::hash::{Hash, Hasher};
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Supplies the randomness `configure` uses for generated defaults, i.e. passwords and other
+/// entropy strings. Each app being configured gets its own rng.
+pub trait EntropyProvider: Send + Sync {
+    fn rng(&self, app_id: &str) -> StdRng;
+}
+
+/// Seeds from the OS. This is what `configure` uses unless told otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OsEntropy;
+impl EntropyProvider for OsEntropy {
+    fn rng(&self, _app_id: &str) -> StdRng {
+        StdRng::from_entropy()
+    }
+}
+
+/// Derives every app's rng from a single seed, so a configure run can be reproduced exactly.
+/// Never use this for configs that are actually committed.
+#[derive(Clone, Copy, Debug)]
+pub struct SeededEntropy(pub u64);
+impl EntropyProvider for SeededEntropy {
+    fn rng(&self, app_id: &str) -> StdRng {
+        // DefaultHasher::new is not randomized, unlike the hasher of a HashMap
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.0.hash(&mut hasher);
+        app_id.hash(&mut hasher);
+        StdRng::seed_from_u64(hasher.finish())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ConfigSpec;
+
+    #[test]
+    fn test_seeded() {
+        let spec: ConfigSpec = serde_json::from_str(
+            r#"{
+                "rpcpass": {
+                    "name": "RPC User Password",
+                    "type": "string",
+                    "nullable": false,
+                    "default": {
+                        "charset": "a-z,A-Z,2-9",
+                        "len": 20
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let gen = |entropy: &dyn EntropyProvider, id: &str| {
+            spec.gen(&mut entropy.rng(id), &None).unwrap()
+        };
+        assert_eq!(
+            gen(&SeededEntropy(1), "bitcoind"),
+            gen(&SeededEntropy(1), "bitcoind")
+        );
+        assert_ne!(
+            gen(&SeededEntropy(1), "bitcoind"),
+            gen(&SeededEntropy(2), "bitcoind")
+        );
+        assert_ne!(
+            gen(&SeededEntropy(1), "bitcoind"),
+            gen(&SeededEntropy(1), "lnd")
+        );
+    }
+}
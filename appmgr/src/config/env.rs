@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use failure::ResultExt as _;
+use linear_map::LinearMap;
+
+use super::util::get_path;
+use super::value::Value;
+use super::Config;
+use crate::Error;
+use crate::ResultExt as _;
+
+/// The environment of an app, rendered from its config by the `env-map` of its manifest, in the
+/// format of docker's `--env-file`.
+pub fn path(name: &str) -> PathBuf {
+    Path::new(crate::VOLUMES)
+        .join(name)
+        .join("start9")
+        .join("env")
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// One `NAME=value` line per variable of `env_map` (variable name to dotted config path). Fields
+/// that are missing or null are left out. Strings are taken as they are unless they span lines,
+/// everything else is written as json.
+pub fn render(env_map: &LinearMap<String, String>, config: &Config) -> Result<Vec<String>, Error> {
+    let mut res = Vec::with_capacity(env_map.len());
+    for (name, path) in env_map {
+        let value = match get_path(config, path) {
+            None | Some(Value::Null) => continue,
+            Some(Value::String(s)) if !s.contains('\n') => s.clone(),
+            Some(v) => serde_json::to_string(v).with_code(crate::error::SERDE_ERROR)?,
+        };
+        res.push(format!("{}={}", name, value));
+    }
+    Ok(res)
+}
+
+pub async fn write(
+    name: &str,
+    env_map: &LinearMap<String, String>,
+    config: &Config,
+) -> Result<(), Error> {
+    let path = path(name);
+    let mut contents = render(env_map, config)?.join("\n");
+    contents.push('\n');
+    tokio::fs::write(&path, contents)
+        .await
+        .with_context(|e| format!("{}: {}", e, path.display()))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(())
+}
+
+/// The lines of the env file of `name`, if it has one.
+pub async fn read(name: &str) -> Result<Vec<String>, Error> {
+    let path = path(name);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = tokio::fs::read_to_string(&path)
+        .await
+        .with_context(|e| format!("{}: {}", e, path.display()))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(contents
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_owned())
+        .collect())
+}
+
+pub async fn remove(name: &str) -> Result<(), Error> {
+    let path = path(name);
+    if path.exists() {
+        tokio::fs::remove_file(&path)
+            .await
+            .with_context(|e| format!("{}: {}", e, path.display()))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+rpc:
+  user: bitcoin
+  port: 8332
+pruning: null
+peers: ["abc.onion"]
+"#,
+        )
+        .unwrap();
+        let mut env_map = LinearMap::new();
+        env_map.insert("RPC_USER".to_owned(), "rpc.user".to_owned());
+        env_map.insert("RPC_PORT".to_owned(), "rpc.port".to_owned());
+        env_map.insert("PRUNING".to_owned(), "pruning".to_owned());
+        env_map.insert("PEERS".to_owned(), "peers".to_owned());
+        assert_eq!(
+            render(&env_map, &config).unwrap(),
+            vec![
+                "RPC_USER=bitcoin",
+                "RPC_PORT=8332",
+                r#"PEERS=["abc.onion"]"#
+            ]
+        );
+        assert!(is_valid_name("RPC_USER"));
+        assert!(!is_valid_name("1RPC"));
+        assert!(!is_valid_name("RPC-USER"));
+    }
+}
@@ -0,0 +1,193 @@
+use std::path::{Path, PathBuf};
+
+use failure::ResultExt as _;
+use linear_map::LinearMap;
+
+use super::{Config, Value};
+use crate::Error;
+use crate::ResultExt as _;
+
+/// The format an app expects its config to be written to its volume in. appmgr always keeps its
+/// own copy as yaml.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+impl Default for ConfigFormat {
+    fn default() -> Self {
+        ConfigFormat::Yaml
+    }
+}
+impl ConfigFormat {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            ConfigFormat::Yaml => "config.yaml",
+            ConfigFormat::Json => "config.json",
+            ConfigFormat::Toml => "config.toml",
+        }
+    }
+
+    pub fn volume_path(&self, name: &str) -> PathBuf {
+        Path::new(crate::VOLUMES)
+            .join(name)
+            .join("start9")
+            .join(self.file_name())
+    }
+
+    pub fn serialize(&self, config: &Config) -> Result<Vec<u8>, Error> {
+        match self {
+            ConfigFormat::Yaml => serde_yaml::to_vec(config).with_code(crate::error::SERDE_ERROR),
+            ConfigFormat::Json => {
+                serde_json::to_vec_pretty(config).with_code(crate::error::SERDE_ERROR)
+            }
+            ConfigFormat::Toml => {
+                // going through toml::Value puts plain values ahead of tables as toml requires
+                let value = toml::Value::try_from(without_nulls(config)?)
+                    .with_code(crate::error::SERDE_ERROR)?;
+                toml::to_vec(&value).with_code(crate::error::SERDE_ERROR)
+            }
+        }
+    }
+
+    pub fn deserialize(&self, bytes: &[u8]) -> Result<Config, Error> {
+        match self {
+            ConfigFormat::Yaml => {
+                serde_yaml::from_slice(bytes).with_code(crate::error::SERDE_ERROR)
+            }
+            ConfigFormat::Json => {
+                serde_json::from_slice(bytes).with_code(crate::error::SERDE_ERROR)
+            }
+            ConfigFormat::Toml => toml::from_slice(bytes).with_code(crate::error::SERDE_ERROR),
+        }
+    }
+
+    pub async fn write_volume_config(&self, name: &str, config: &Config) -> Result<(), Error> {
+        let path = self.volume_path(name);
+        tokio::fs::write(&path, self.serialize(config)?)
+            .await
+            .with_context(|e| format!("{}: {}", e, path.display()))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+        Ok(())
+    }
+
+    pub async fn read_volume_config(&self, name: &str) -> Result<Option<Config>, Error> {
+        let path = self.volume_path(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = tokio::fs::read(&path)
+            .await
+            .with_context(|e| format!("{}: {}", e, path.display()))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+        self.deserialize(&bytes).map(Some)
+    }
+
+    pub async fn remove_volume_config(&self, name: &str) -> Result<(), Error> {
+        let path = self.volume_path(name);
+        if path.exists() {
+            tokio::fs::remove_file(&path)
+                .await
+                .with_context(|e| format!("{}: {}", e, path.display()))
+                .with_code(crate::error::FILESYSTEM_ERROR)?;
+        }
+        Ok(())
+    }
+}
+
+/// Toml has no null, so null fields are left out, which reads back the same as far as the spec is
+/// concerned. A list has nowhere to leave one out, so a null in a list cannot be written at all.
+fn without_nulls(config: &Config) -> Result<Config, Error> {
+    fn value(v: &Value) -> Result<Value, Error> {
+        Ok(match v {
+            Value::Object(obj) => Value::Object(without_nulls(obj)?),
+            Value::List(list) => Value::List(
+                list.iter()
+                    .map(|v| match v {
+                        Value::Null => Err(failure::format_err!("TOML Lists Cannot Contain Null"))
+                            .with_code(crate::error::SERDE_ERROR),
+                        v => value(v),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            v => v.clone(),
+        })
+    }
+    config
+        .0
+        .iter()
+        .filter(|(_, v)| **v != Value::Null)
+        .map(|(k, v)| Ok((k.clone(), value(v)?)))
+        .collect::<Result<LinearMap<_, _>, Error>>()
+        .map(Config)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::ConfigSpec;
+
+    #[test]
+    fn test_round_trip() {
+        let spec: ConfigSpec = serde_yaml::from_str(
+            r#"
+pruning:
+  name: Pruning
+  type: string
+  nullable: false
+  default: disabled
+rpc:
+  name: RPC
+  type: object
+  nullable: false
+  spec:
+    enable:
+      name: Enable
+      type: boolean
+      default: true
+    port:
+      name: Port
+      type: number
+      integral: true
+      nullable: false
+      range: "[0,65535]"
+      default: 8332
+    password:
+      name: Password
+      type: string
+      nullable: true
+"#,
+        )
+        .unwrap();
+        let config: Config = serde_yaml::from_str(
+            r#"
+pruning: disabled
+rpc:
+  enable: true
+  port: 8332
+  password: ~
+peers: ["abc.onion"]
+"#,
+        )
+        .unwrap();
+        spec.matches(&config).unwrap();
+        for format in &[ConfigFormat::Yaml, ConfigFormat::Json] {
+            let bytes = format.serialize(&config).unwrap();
+            let parsed = format.deserialize(&bytes).unwrap();
+            assert!(config.diff(&parsed).is_empty(), "{:?}", format);
+        }
+        let bytes = ConfigFormat::Toml.serialize(&config).unwrap();
+        assert!(!std::str::from_utf8(&bytes).unwrap().contains("password"));
+        let parsed = ConfigFormat::Toml.deserialize(&bytes).unwrap();
+        spec.matches(&parsed).unwrap();
+        assert_eq!(parsed, without_nulls(&config).unwrap());
+
+        let mut config = config;
+        config
+            .0
+            .insert("peers".to_owned(), Value::List(vec![Value::Null]));
+        assert!(ConfigFormat::Toml.serialize(&config).is_err());
+    }
+}
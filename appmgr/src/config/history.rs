@@ -0,0 +1,109 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{Config, ConfigurationRes};
+use crate::util::PersistencePath;
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const HISTORY_LEN: usize = 10;
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConfigRevision {
+    pub revision: usize,
+    pub saved_at: u64,
+    pub config: Config,
+}
+
+fn history_path(name: &str) -> PersistencePath {
+    PersistencePath::from_ref("apps")
+        .join(name)
+        .join("config_history")
+}
+
+async fn read(path: &PersistencePath) -> Result<Config, Error> {
+    path.maybe_read_sealed()
+        .await?
+        .ok_or_else(|| failure::format_err!("{}: not found", path.path().display()))
+        .with_code(crate::error::NOT_FOUND)
+}
+
+// oldest first
+async fn timestamps(name: &str) -> Result<Vec<u64>, Error> {
+    let path = history_path(name).path();
+    let mut res = Vec::new();
+    if !path.exists() {
+        return Ok(res);
+    }
+    let mut entry_stream = tokio::fs::read_dir(&path).await?;
+    while let Some(entry) = entry_stream.next_entry().await? {
+        let file_name = entry.file_name();
+        if let Some(ts) = file_name
+            .to_str()
+            .and_then(|f| f.strip_suffix(".yaml"))
+            .and_then(|f| f.parse().ok())
+        {
+            res.push(ts);
+        }
+    }
+    res.sort();
+    Ok(res)
+}
+
+/// Saves a config that is about to be replaced, discarding the oldest saved configs beyond
+/// `HISTORY_LEN`.
+pub async fn archive(name: &str, config: &Config) -> Result<(), Error> {
+    let saved_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    history_path(name)
+        .join(format!("{}.yaml", saved_at))
+        .write_sealed(config)
+        .await?;
+    let timestamps = timestamps(name).await?;
+    if timestamps.len() > HISTORY_LEN {
+        for ts in &timestamps[..timestamps.len() - HISTORY_LEN] {
+            history_path(name)
+                .join(format!("{}.yaml", ts))
+                .delete()
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists previous configs of an app, most recent first. Revision 1 is the config that was
+/// replaced by the current one.
+pub async fn list(name: &str) -> Result<Vec<ConfigRevision>, Error> {
+    let mut res = Vec::new();
+    for (idx, ts) in timestamps(name).await?.into_iter().rev().enumerate() {
+        let path = history_path(name).join(format!("{}.yaml", ts));
+        res.push(ConfigRevision {
+            revision: idx + 1,
+            saved_at: ts / 1000,
+            config: read(&path).await?,
+        });
+    }
+    Ok(res)
+}
+
+/// Restores a previous config, running the full configure cascade for its dependents.
+pub async fn revert(
+    name: &str,
+    revision: usize,
+    timeout: Option<Duration>,
+    dry_run: bool,
+) -> Result<ConfigurationRes, Error> {
+    let timestamps = timestamps(name).await?;
+    crate::ensure_code!(
+        revision > 0 && revision <= timestamps.len(),
+        crate::error::NOT_FOUND,
+        "{} has no config revision {}",
+        name,
+        revision
+    );
+    let path = history_path(name).join(format!("{}.yaml", timestamps[timestamps.len() - revision]));
+    let config = read(&path).await?;
+    super::configure(name, Some(config), timeout, dry_run).await
+}
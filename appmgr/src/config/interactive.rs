@@ -0,0 +1,606 @@
+use std::borrow::Cow;
+use std::io::{BufRead, Write};
+
+use linear_map::LinearMap;
+use rand::rngs::StdRng;
+use tokio::io::AsyncBufReadExt;
+
+use super::spec::{ValueSpec, ValueSpecAny};
+use super::value::{Value, MASK};
+use super::{
+    Config, ConfigRuleEntryWithSuggestions, ConfigSpec, Defaultable, EntropyProvider, OsEntropy,
+};
+use crate::util::{from_yaml_async_reader, PersistencePath};
+use crate::Error;
+use crate::ResultExt as _;
+
+// the first rule `config` breaks, if any
+fn failing<'a>(
+    rules: &'a [ConfigRuleEntryWithSuggestions],
+    id: &str,
+    config: &Config,
+) -> Option<&'a ConfigRuleEntryWithSuggestions> {
+    let mut cfgs = LinearMap::new();
+    cfgs.insert(id, Cow::Borrowed(config));
+    rules
+        .iter()
+        .find(|rule| rule.entry.check(config, &cfgs).is_err())
+}
+
+async fn read_spec(id: &str) -> Result<ConfigSpec, Error> {
+    let path = PersistencePath::from_ref("apps")
+        .join(id)
+        .join("config_spec.yaml");
+    from_yaml_async_reader(&mut *path.read(false).await?).await
+}
+
+// `config` if given, otherwise the current config of `id`, or its default
+async fn starting_config(
+    id: &str,
+    spec: &ConfigSpec,
+    config: Option<Config>,
+) -> Result<Config, Error> {
+    Ok(match config {
+        Some(a) => a,
+        None => match crate::apps::config(id).await?.config {
+            Some(a) => a,
+            None => spec
+                .gen(&mut OsEntropy.rng(id), &None)
+                .with_code(crate::error::CFG_SPEC_VIOLATION)?,
+        },
+    })
+}
+
+/// Checks `config` (or the current config of `id`, or its default) against the rules of `id`.
+/// While a rule fails, offers the suggestions of the rule, applies the one chosen on stdin, and
+/// checks again. Returns the config once every rule passes.
+pub async fn fix(id: &str, config: Option<Config>) -> Result<Config, Error> {
+    let spec = read_spec(id).await?;
+    let rules: Vec<ConfigRuleEntryWithSuggestions> = from_yaml_async_reader(
+        &mut *PersistencePath::from_ref("apps")
+            .join(id)
+            .join("config_rules.yaml")
+            .read(false)
+            .await?,
+    )
+    .await?;
+    let mut config = starting_config(id, &spec, config).await?;
+    // rules may read pointers, so resolve them as configure would
+    spec.update(&mut config)
+        .await
+        .with_code(crate::error::CFG_SPEC_VIOLATION)?;
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    while let Some(rule) = failing(&rules, id, &config) {
+        println!("Config does not satisfy: {}", rule.entry.description);
+        let suggestions: Vec<_> = {
+            let mut cfgs = LinearMap::new();
+            cfgs.insert(id, Cow::Borrowed(&config));
+            rule.suggestions
+                .iter()
+                .filter(|s| s.applies(&config, &cfgs))
+                .collect()
+        };
+        crate::ensure_code!(
+            !suggestions.is_empty(),
+            crate::error::CFG_RULES_VIOLATION,
+            "{}",
+            rule.entry.description
+        );
+        for (idx, suggestion) in suggestions.iter().enumerate() {
+            println!("  {}) {}", idx + 1, suggestion);
+        }
+        println!("  0) abort");
+        let choice = loop {
+            print!("Choose a fix: ");
+            std::io::stdout().flush()?;
+            let line = match lines.next_line().await? {
+                Some(a) => a,
+                None => 0.to_string(),
+            };
+            match line.trim().parse::<usize>() {
+                Ok(n) if n <= suggestions.len() => break n,
+                _ => println!("Expected a number from 0 to {}.", suggestions.len()),
+            }
+        };
+        if choice == 0 {
+            return Err(failure::format_err!("Aborted: {}", rule.entry.description))
+                .with_code(crate::error::CFG_RULES_VIOLATION);
+        }
+        let mut cfgs = LinearMap::new();
+        cfgs.insert(id, Cow::Owned(config.clone()));
+        suggestions[choice - 1].apply(id, &mut config, &mut cfgs);
+    }
+    Ok(config)
+}
+
+/// Walks the config spec of `id` field by field on the terminal, starting from `config` (or the
+/// current config of `id`, or its default). Each answer is checked against the spec of its field
+/// before moving on; the result still goes through `configure` like any other config.
+pub async fn edit(id: &str, config: Option<Config>) -> Result<Config, Error> {
+    let spec = read_spec(id).await?;
+    let mut config = starting_config(id, &spec, config).await?;
+    let stdin = std::io::stdin();
+    let mut form = Form {
+        input: &mut stdin.lock(),
+        output: &mut std::io::stdout(),
+        rng: OsEntropy.rng(id),
+    };
+    writeln!(
+        form.output,
+        "Press enter to keep a value, or enter - to clear a field that can be empty."
+    )?;
+    form.edit_spec(&spec, &mut config, 0)?;
+    Ok(config)
+}
+
+// what a field shows as its current value
+fn display(value: &Value, masked: bool) -> String {
+    match value {
+        Value::Null => "none".to_owned(),
+        _ if masked => MASK.to_owned(),
+        Value::String(s) => s.clone(),
+        Value::Bool(true) => "yes".to_owned(),
+        Value::Bool(false) => "no".to_owned(),
+        a => serde_json::to_string(a).unwrap_or_default(),
+    }
+}
+
+struct Form<'a> {
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+    rng: StdRng,
+}
+impl<'a> Form<'a> {
+    // the trimmed answer to `prompt`, or `None` to keep the current value
+    fn ask(&mut self, indent: usize, prompt: &str) -> Result<Option<String>, Error> {
+        write!(self.output, "{:indent$}{}: ", "", prompt, indent = indent)?;
+        self.output.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Err(failure::format_err!("Aborted: End Of Input"))
+                .with_code(crate::error::GENERAL_ERROR);
+        }
+        let line = line.trim();
+        Ok(if line.is_empty() {
+            None
+        } else {
+            Some(line.to_owned())
+        })
+    }
+
+    // asks until the answer parses and matches `spec`, then stores it in `value`
+    fn ask_value<F: Fn(&str) -> Option<Value>>(
+        &mut self,
+        indent: usize,
+        prompt: &str,
+        spec: &ValueSpecAny,
+        value: &mut Value,
+        parse: F,
+    ) -> Result<(), Error> {
+        loop {
+            let answer = match self.ask(indent, prompt)? {
+                Some(a) => a,
+                None => return Ok(()),
+            };
+            let new = if answer == "-" {
+                Value::Null
+            } else {
+                match parse(&answer) {
+                    Some(a) => a,
+                    None => {
+                        writeln!(self.output, "{:indent$}Invalid value.", "", indent = indent)?;
+                        continue;
+                    }
+                }
+            };
+            match spec.matches(&new) {
+                Ok(()) => {
+                    *value = new;
+                    return Ok(());
+                }
+                Err(e) => writeln!(self.output, "{:indent$}{}", "", e.error, indent = indent)?,
+            }
+        }
+    }
+
+    // asks for one of `choices` (value, label) by number, and returns its index
+    fn choose(
+        &mut self,
+        indent: usize,
+        name: &str,
+        choices: &[(&str, &str)],
+        current: Option<&str>,
+        other: Option<&str>,
+    ) -> Result<Option<Result<usize, String>>, Error> {
+        writeln!(self.output, "{:indent$}{}:", "", name, indent = indent)?;
+        for (idx, (value, label)) in choices.iter().enumerate() {
+            let mark = if Some(*value) == current { "*" } else { " " };
+            writeln!(
+                self.output,
+                "{:indent$}{} {}) {}",
+                "",
+                mark,
+                idx + 1,
+                label,
+                indent = indent
+            )?;
+        }
+        if let Some(other) = other {
+            let mark = match current {
+                Some(c) if !choices.iter().any(|(v, _)| *v == c) => format!("* ({})", c),
+                _ => " ".to_owned(),
+            };
+            writeln!(
+                self.output,
+                "{:indent$}{} or type a value: {}",
+                "",
+                mark,
+                other,
+                indent = indent
+            )?;
+        }
+        loop {
+            let answer = match self.ask(indent, "Choice")? {
+                Some(a) => a,
+                None => return Ok(None),
+            };
+            match answer.parse::<usize>() {
+                Ok(n) if (1..=choices.len()).contains(&n) => return Ok(Some(Ok(n - 1))),
+                _ if other.is_some() => return Ok(Some(Err(answer))),
+                _ => writeln!(
+                    self.output,
+                    "{:indent$}Expected a number from 1 to {}.",
+                    "",
+                    choices.len(),
+                    indent = indent
+                )?,
+            }
+        }
+    }
+
+    fn edit_spec(
+        &mut self,
+        spec: &ConfigSpec,
+        config: &mut Config,
+        indent: usize,
+    ) -> Result<(), Error> {
+        for (key, val_spec) in spec.0.iter() {
+            if !config.0.contains_key(key) {
+                let value = val_spec
+                    .gen(&mut self.rng, &None)
+                    .with_code(crate::error::CFG_SPEC_VIOLATION)?;
+                config.0.insert(key.clone(), value);
+            }
+            // hidden fields keep their values, as in the web UI
+            if let Some(rule) = val_spec.visible_if() {
+                if let Ok(compiled) = super::rules::compile(rule) {
+                    if !compiled(&*config, &LinearMap::new()) {
+                        continue;
+                    }
+                }
+            }
+            let mut value = config.0.remove(key).unwrap_or(Value::Null);
+            let res = self.edit_value(val_spec, &mut value, indent);
+            config.0.insert(key.clone(), value);
+            res?;
+        }
+        Ok(())
+    }
+
+    fn edit_value(
+        &mut self,
+        spec: &ValueSpecAny,
+        value: &mut Value,
+        indent: usize,
+    ) -> Result<(), Error> {
+        let name = spec.name().to_owned();
+        if let Some(description) = spec.description() {
+            writeln!(
+                self.output,
+                "{:indent$}# {}",
+                "",
+                description,
+                indent = indent
+            )?;
+        }
+        match spec {
+            ValueSpecAny::Boolean(_) => {
+                let prompt = format!("{} [{}] (y/n)", name, display(value, false));
+                self.ask_value(indent, &prompt, spec, value, |a| {
+                    match a.to_lowercase().as_str() {
+                        "y" | "yes" | "true" => Some(Value::Bool(true)),
+                        "n" | "no" | "false" => Some(Value::Bool(false)),
+                        _ => None,
+                    }
+                })
+            }
+            ValueSpecAny::Enum(e) => {
+                let e = &e.inner.inner;
+                let choices: Vec<(&str, &str)> = e
+                    .values
+                    .iter()
+                    .map(|v| {
+                        (
+                            v.as_str(),
+                            e.value_names.get(v).map(|n| n.as_str()).unwrap_or(v),
+                        )
+                    })
+                    .collect();
+                let current = match value {
+                    Value::String(s) => Some(s.clone()),
+                    _ => None,
+                };
+                let other = e
+                    .other
+                    .as_ref()
+                    .map(|o| format!("{} ({})", o.name, o.pattern.pattern_description));
+                loop {
+                    let new = match self.choose(
+                        indent,
+                        &name,
+                        &choices,
+                        current.as_deref(),
+                        other.as_deref(),
+                    )? {
+                        None => return Ok(()),
+                        Some(Ok(idx)) => Value::String(choices[idx].0.to_owned()),
+                        Some(Err(s)) => Value::String(s),
+                    };
+                    match spec.matches(&new) {
+                        Ok(()) => {
+                            *value = new;
+                            return Ok(());
+                        }
+                        Err(e) => {
+                            writeln!(self.output, "{:indent$}{}", "", e.error, indent = indent)?
+                        }
+                    }
+                }
+            }
+            ValueSpecAny::Number(n) => {
+                let num = &n.inner.inner.inner;
+                let mut hints = Vec::new();
+                if let Some(range) = &num.range {
+                    hints.push(format!("{}", range));
+                }
+                if num.integral {
+                    hints.push("integer".to_owned());
+                }
+                if let Some(units) = &num.units {
+                    hints.push(units.clone());
+                }
+                let prompt = if hints.is_empty() {
+                    format!("{} [{}]", name, display(value, false))
+                } else {
+                    format!("{} {} [{}]", name, hints.join(", "), display(value, false))
+                };
+                self.ask_value(indent, &prompt, spec, value, |a| {
+                    a.parse().ok().map(Value::Number)
+                })
+            }
+            ValueSpecAny::Duration(_) | ValueSpecAny::Bytes(_) => {
+                let example = match spec {
+                    ValueSpecAny::Duration(_) => "i.e. 1h30m",
+                    _ => "i.e. 512MB",
+                };
+                let prompt = format!("{} ({}) [{}]", name, example, display(value, false));
+                // strings are converted to base units by `update`
+                self.ask_value(indent, &prompt, spec, value, |a| {
+                    Some(
+                        a.parse()
+                            .map(Value::Number)
+                            .unwrap_or_else(|_| Value::String(a.to_owned())),
+                    )
+                })
+            }
+            ValueSpecAny::String(s) => {
+                let masked = s.inner.inner.inner.masked;
+                let prompt = match &s.inner.inner.inner.pattern {
+                    Some(p) => format!(
+                        "{} ({}) [{}]",
+                        name,
+                        p.pattern_description,
+                        display(value, masked)
+                    ),
+                    None => format!("{} [{}]", name, display(value, masked)),
+                };
+                self.ask_value(indent, &prompt, spec, value, |a| {
+                    Some(Value::String(a.to_owned()))
+                })
+            }
+            ValueSpecAny::List(_) => {
+                let prompt = format!("{} (as json) [{}]", name, display(value, false));
+                self.ask_value(indent, &prompt, spec, value, |a| {
+                    serde_json::from_str(a).ok()
+                })
+            }
+            ValueSpecAny::Object(o) => {
+                if o.inner.nullable {
+                    let prompt = format!(
+                        "{} [{}] (y/n)",
+                        name,
+                        display(&Value::Bool(*value != Value::Null), false)
+                    );
+                    match self.ask(indent, &prompt)?.map(|a| a.to_lowercase()) {
+                        Some(a) if a == "n" || a == "no" => {
+                            *value = Value::Null;
+                            return Ok(());
+                        }
+                        _ if *value == Value::Null => {
+                            *value = o
+                                .inner
+                                .inner
+                                .spec
+                                .gen(&mut self.rng, &None)
+                                .map(Value::Object)
+                                .with_code(crate::error::CFG_SPEC_VIOLATION)?;
+                        }
+                        _ => (),
+                    }
+                } else {
+                    writeln!(self.output, "{:indent$}{}:", "", name, indent = indent)?;
+                }
+                match value {
+                    Value::Object(obj) => self.edit_spec(&o.inner.inner.spec, obj, indent + 2),
+                    _ => Ok(()),
+                }
+            }
+            ValueSpecAny::Union(u) => {
+                let u = &u.inner.inner;
+                let choices: Vec<(&str, &str)> = u
+                    .variants
+                    .keys()
+                    .map(|v| {
+                        (
+                            v.as_str(),
+                            u.tag.variant_names.get(v).map(|n| n.as_str()).unwrap_or(v),
+                        )
+                    })
+                    .collect();
+                let mut obj = match std::mem::replace(value, Value::Null) {
+                    Value::Object(o) => o,
+                    _ => Config::default(),
+                };
+                let current = match obj.0.remove(&u.tag.id) {
+                    Some(Value::String(s)) => Some(s),
+                    _ => None,
+                };
+                let variant = match self.choose(
+                    indent,
+                    &format!("{}: {}", name, u.tag.name),
+                    &choices,
+                    current.as_deref(),
+                    None,
+                )? {
+                    Some(Ok(idx)) => Some(choices[idx].0.to_owned()),
+                    _ => current.clone(),
+                };
+                let res = match variant.as_ref().and_then(|v| u.variants.get(v)) {
+                    Some(variant_spec) => {
+                        if variant != current {
+                            obj = variant_spec
+                                .gen(&mut self.rng, &None)
+                                .with_code(crate::error::CFG_SPEC_VIOLATION)?;
+                        }
+                        self.edit_spec(variant_spec, &mut obj, indent + 2)
+                    }
+                    None => Ok(()),
+                };
+                let mut tagged = Config::default();
+                if let Some(variant) = variant {
+                    tagged.0.insert(u.tag.id.clone(), Value::String(variant));
+                }
+                tagged.0.extend(obj.0);
+                *value = Value::Object(tagged);
+                res
+            }
+            // resolved by `update`
+            ValueSpecAny::Pointer(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::value::Value;
+
+    #[test]
+    fn test_failing() {
+        let rules: Vec<ConfigRuleEntryWithSuggestions> = serde_yaml::from_str(
+            r#"
+- rule: "'rpc.password != \"\""
+  description: RPC password must not be empty
+  suggestions:
+    - SET:
+        var: rpc.password
+        to-entropy:
+          charset: "a-z"
+          len: 12
+- rule: "pruning?"
+  description: Pruning must be enabled
+"#,
+        )
+        .unwrap();
+        let config: Config =
+            serde_yaml::from_str("rpc:\n  password: \"\"\npruning: true\n").unwrap();
+        let rule = failing(&rules, "bitcoind", &config).unwrap();
+        assert_eq!(
+            format!("{}", rule.suggestions[0]),
+            "set rpc.password to a random value"
+        );
+        let mut fixed = config.clone();
+        rule.suggestions[0].apply("bitcoind", &mut fixed, &mut LinearMap::new());
+        assert!(failing(&rules, "bitcoind", &fixed).is_none());
+        let mut unpruned = fixed.clone();
+        unpruned.0.insert("pruning".to_owned(), Value::Bool(false));
+        assert!(failing(&rules, "bitcoind", &unpruned)
+            .unwrap()
+            .suggestions
+            .is_empty());
+    }
+
+    #[test]
+    fn test_form() {
+        use rand::SeedableRng;
+
+        // yaml, so the fields and variants are asked for in the order they are written
+        let spec: ConfigSpec = serde_yaml::from_str(
+            r#"
+pruning:
+  name: Pruning
+  type: boolean
+  default: false
+network:
+  name: Network
+  type: enum
+  values: [mainnet, testnet]
+  default: mainnet
+dbcache:
+  name: DB Cache
+  type: number
+  nullable: false
+  range: "[100,4096]"
+  integral: true
+  default: 450
+backend:
+  name: Backend
+  type: union
+  tag: type
+  variants:
+    internal: {}
+    external:
+      url:
+        name: URL
+        type: string
+        nullable: false
+        default: http://localhost
+  default: internal
+"#,
+        )
+        .unwrap();
+        let mut config = spec.gen(&mut StdRng::seed_from_u64(0), &None).unwrap();
+        // keep, testnet, out of range then in range, external with a url
+        let mut input = std::io::Cursor::new("\n2\n50\n1000\n2\nhttp://node.onion\n");
+        let mut output = Vec::new();
+        let mut form = Form {
+            input: &mut input,
+            output: &mut output,
+            rng: StdRng::seed_from_u64(0),
+        };
+        form.edit_spec(&spec, &mut config, 0).unwrap();
+        let expected: Config = serde_yaml::from_str(
+            r#"
+pruning: false
+network: testnet
+dbcache: 1000
+backend:
+  type: external
+  url: http://node.onion
+"#,
+        )
+        .unwrap();
+        assert_eq!(config, expected);
+        assert!(String::from_utf8(output).unwrap().contains("[100,4096]"));
+    }
+}
@@ -0,0 +1,202 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use linear_map::{set::LinearSet, LinearMap};
+use rand::{CryptoRng, Rng, SeedableRng};
+
+use super::spec::ValueSpecAny;
+use super::value::Value;
+use super::{Config, ConfigRuleEntry, ConfigSpec, ConfigurationError, Defaultable};
+use crate::manifest::ManifestLatest;
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LintReport {
+    pub samples: usize,
+    pub spec_errors: Vec<String>,
+    pub default_violations: Vec<String>,
+    pub unsatisfiable_rules: Vec<String>,
+    pub unreachable_variants: Vec<String>,
+}
+impl LintReport {
+    pub fn is_ok(&self) -> bool {
+        self.spec_errors.is_empty()
+            && self.default_violations.is_empty()
+            && self.unsatisfiable_rules.is_empty()
+            && self.unreachable_variants.is_empty()
+    }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+// every union variant in the spec, as "path.to.union#variant"
+fn union_variants(spec: &ConfigSpec, prefix: &str, res: &mut Vec<String>) {
+    for (key, val) in spec.0.iter() {
+        let path = join_path(prefix, key);
+        match val {
+            ValueSpecAny::Object(o) => union_variants(&o.inner.inner.spec, &path, res),
+            ValueSpecAny::Union(u) => {
+                for (variant, variant_spec) in u.inner.inner.variants.iter() {
+                    res.push(format!("{}#{}", path, variant));
+                    union_variants(variant_spec, &path, res);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+// like `ConfigSpec::gen`, but picks booleans, enum values, and union variants at random instead of
+// using their defaults
+fn gen_random<R: Rng + CryptoRng + Sync + Send>(
+    spec: &ConfigSpec,
+    prefix: &str,
+    rng: &mut R,
+    timeout: &Option<Duration>,
+    variants: &mut LinearSet<String>,
+) -> Result<Config, ConfigurationError> {
+    let mut res = LinearMap::new();
+    for (key, val) in spec.0.iter() {
+        let path = join_path(prefix, key);
+        let value = match val {
+            ValueSpecAny::Boolean(_) => Value::Bool(rng.gen()),
+            ValueSpecAny::Enum(e) => {
+                let values = &e.inner.inner.values;
+                match values.iter().nth(rng.gen_range(0, values.len().max(1))) {
+                    Some(v) => Value::String(v.clone()),
+                    None => val.gen(rng, timeout)?,
+                }
+            }
+            ValueSpecAny::Object(o) => Value::Object(gen_random(
+                &o.inner.inner.spec,
+                &path,
+                rng,
+                timeout,
+                variants,
+            )?),
+            ValueSpecAny::Union(u) => {
+                let union = &u.inner.inner;
+                match union
+                    .variants
+                    .iter()
+                    .nth(rng.gen_range(0, union.variants.len().max(1)))
+                {
+                    Some((variant, variant_spec)) => {
+                        variants.insert(format!("{}#{}", path, variant));
+                        let mut cfg = LinearMap::new();
+                        cfg.insert(union.tag.id.clone(), Value::String(variant.clone()));
+                        cfg.extend(gen_random(variant_spec, &path, rng, timeout, variants)?.0);
+                        Value::Object(Config(cfg))
+                    }
+                    None => val.gen(rng, timeout)?,
+                }
+            }
+            _ => val.gen(rng, timeout)?,
+        };
+        res.insert(key.clone(), value);
+    }
+    let mut res = Config(res);
+    spec.derive(&mut res)?;
+    Ok(res)
+}
+
+fn failed_rules<'a>(
+    id: &str,
+    config: &Config,
+    rules: &'a [ConfigRuleEntry],
+) -> Vec<&'a ConfigRuleEntry> {
+    let mut cfgs = LinearMap::new();
+    cfgs.insert(id, Cow::Borrowed(config));
+    rules
+        .iter()
+        .filter(|rule| rule.check(config, &cfgs).is_err())
+        .collect()
+}
+
+/// Checks a config spec and its rules for mistakes that would only show up at configure time, by
+/// generating `samples` random configs. Pointers to other apps resolve to null.
+pub fn lint(
+    manifest: &ManifestLatest,
+    spec: &ConfigSpec,
+    rules: &[ConfigRuleEntry],
+    samples: usize,
+) -> LintReport {
+    let mut rng = rand::rngs::StdRng::from_entropy();
+    let timeout = Some(Duration::from_secs(3));
+    let mut report = LintReport {
+        samples,
+        ..Default::default()
+    };
+
+    if let Err(e) = spec.validate(manifest) {
+        report.spec_errors.push(format!("{}", e));
+    }
+
+    match spec.gen(&mut rng, &timeout) {
+        Ok(default) => {
+            if let Err(e) = spec.matches(&default) {
+                report.default_violations.push(format!("{}", e));
+            }
+            for rule in failed_rules(&manifest.id, &default, rules) {
+                report.default_violations.push(format!(
+                    "default config fails rule {:?}: {}",
+                    rule.rule.src, rule.description
+                ));
+            }
+        }
+        Err(e) => report
+            .default_violations
+            .push(format!("could not generate default config: {}", e)),
+    }
+
+    let mut satisfied = vec![false; rules.len()];
+    let mut reached = LinearSet::new();
+    for _ in 0..samples {
+        let mut variants = LinearSet::new();
+        let config = match gen_random(spec, "", &mut rng, &timeout, &mut variants) {
+            Ok(a) => a,
+            Err(e) => {
+                log::warn!("Could not generate config: {}", e);
+                continue;
+            }
+        };
+        if spec.matches(&config).is_err() {
+            continue;
+        }
+        let mut cfgs = LinearMap::new();
+        cfgs.insert(manifest.id.as_str(), Cow::Borrowed(&config));
+        let mut all_passed = true;
+        for (idx, rule) in rules.iter().enumerate() {
+            if rule.check(&config, &cfgs).is_ok() {
+                satisfied[idx] = true;
+            } else {
+                all_passed = false;
+            }
+        }
+        if all_passed {
+            reached.extend(variants);
+        }
+    }
+
+    for (rule, satisfied) in rules.iter().zip(satisfied) {
+        if !satisfied {
+            report
+                .unsatisfiable_rules
+                .push(format!("{:?}: {}", rule.rule.src, rule.description));
+        }
+    }
+    let mut all_variants = Vec::new();
+    union_variants(spec, "", &mut all_variants);
+    report.unreachable_variants = all_variants
+        .into_iter()
+        .filter(|v| !reached.contains(v))
+        .collect();
+
+    report
+}
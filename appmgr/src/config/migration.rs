@@ -0,0 +1,189 @@
+use std::process::Stdio;
+
+use linear_map::LinearMap;
+use tokio::io::AsyncWriteExt;
+
+use super::value::Value;
+use super::Config;
+use crate::manifest::ManifestLatest;
+use crate::util::PersistencePath;
+use crate::Error;
+use crate::ResultExt as _;
+
+/// Carries the config of a previous version forward. Renames are applied first, then removals,
+/// then the script.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConfigMigration {
+    /// The previous versions this migration applies to.
+    pub from: emver::VersionRange,
+    /// Moves the value at one dotted path to another.
+    #[serde(default)]
+    pub rename: LinearMap<String, String>,
+    /// Dotted paths to drop.
+    #[serde(default)]
+    pub remove: Vec<String>,
+    /// A command run in the new image. It is given the config as yaml on stdin, and must print
+    /// the migrated config as yaml. The answers to the install prompts, if any, are in the
+    /// `INSTALL_ANSWERS` environment variable, as json.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub script: Option<Vec<String>>,
+}
+impl ConfigMigration {
+    pub fn apply_declarative(&self, config: &mut Config) -> Result<(), Error> {
+        for (from, to) in self.rename.iter() {
+            if let Some(value) = take(config, from) {
+                super::patch::set(config, to, value)?;
+            }
+        }
+        for path in self.remove.iter() {
+            take(config, path);
+        }
+        Ok(())
+    }
+
+    async fn run_script(
+        &self,
+        id: &str,
+        script: &[String],
+        config: &Config,
+        answers: Option<&Config>,
+    ) -> Result<Config, Error> {
+        let entrypoint = script
+            .get(0)
+            .ok_or_else(|| failure::format_err!("Migration Script Cannot Be Empty"))
+            .no_code()?;
+        let mut cmd = tokio::process::Command::new("docker");
+        cmd.arg("run")
+            .arg("--rm")
+            .arg("-i")
+            .arg("--name")
+            .arg(format!("{}_config-migration", id));
+        if let Some(answers) = answers {
+            cmd.arg("--env").arg(format!(
+                "INSTALL_ANSWERS={}",
+                serde_json::to_string(answers).with_code(crate::error::SERDE_ERROR)?
+            ));
+        }
+        let mut child = cmd
+            .arg("--entrypoint")
+            .arg(entrypoint)
+            .arg(format!("start9/{}", id))
+            .args(&script[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let input = serde_yaml::to_vec(config).with_code(crate::error::SERDE_ERROR)?;
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(&input).await?;
+        drop(stdin);
+        let output = child.wait_with_output().await?;
+        crate::ensure_code!(
+            output.status.success(),
+            crate::error::DOCKER_ERROR,
+            "Config Migration Failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        serde_yaml::from_slice(&output.stdout).with_code(crate::error::SERDE_ERROR)
+    }
+}
+
+// removes the value at a dotted path, if there is one
+fn take(config: &mut Config, path: &str) -> Option<Value> {
+    let mut keys: Vec<&str> = path.split('.').collect();
+    let last = keys.pop()?;
+    let mut obj = config;
+    for key in keys {
+        obj = match obj.0.get_mut(key) {
+            Some(Value::Object(o)) => o,
+            _ => return None,
+        };
+    }
+    obj.0.remove(last)
+}
+
+/// The version and committed config of the installed copy of `id`, if it has been configured.
+/// Must be read before a new version is installed over it.
+pub async fn previous(id: &str) -> Result<Option<(emver::Version, Config)>, Error> {
+    let path = PersistencePath::from_ref("apps")
+        .join(id)
+        .join("config.yaml");
+    let config: Config = match path.maybe_read_sealed().await? {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+    Ok(Some((crate::apps::manifest(id).await?.version, config)))
+}
+
+/// Runs every migration of `manifest` that applies to `from`, in order. The image of the new
+/// version must already be loaded.
+pub async fn migrate(
+    manifest: &ManifestLatest,
+    from: &emver::Version,
+    mut config: Config,
+    answers: Option<&Config>,
+) -> Result<Config, Error> {
+    for migration in manifest
+        .config_migrations
+        .iter()
+        .filter(|m| from.satisfies(&m.from))
+    {
+        log::info!(
+            "Migrating config of {} from {} ({}).",
+            manifest.id,
+            from,
+            migration.from
+        );
+        migration.apply_declarative(&mut config)?;
+        if let Some(script) = &migration.script {
+            config = migration
+                .run_script(&manifest.id, script, &config, answers)
+                .await?;
+        }
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply_declarative() {
+        let migration: ConfigMigration = serde_yaml::from_str(
+            r#"
+from: "<0.20.0"
+rename:
+  rpcuser: rpc.username
+  rpc.pass: rpc.password
+  missing: anywhere
+remove:
+  - txindex
+"#,
+        )
+        .unwrap();
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+rpcuser: bitcoin
+rpc:
+  pass: hunter2
+txindex: true
+pruning: disabled
+"#,
+        )
+        .unwrap();
+        let expected: Config = serde_yaml::from_str(
+            r#"
+rpc:
+  username: bitcoin
+  password: hunter2
+pruning: disabled
+"#,
+        )
+        .unwrap();
+        migration.apply_declarative(&mut config).unwrap();
+        assert!(config.diff(&expected).is_empty());
+    }
+}
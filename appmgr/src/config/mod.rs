@@ -1,28 +1,42 @@
 use std::borrow::Cow;
-use std::path::Path;
-use std::time::Duration;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
 use failure::ResultExt as _;
 use futures::future::{BoxFuture, FutureExt};
 use itertools::Itertools;
 use linear_map::{set::LinearSet, LinearMap};
-use rand::SeedableRng;
 use regex::Regex;
 
 use crate::dependencies::{DependencyError, TaggedDependencyError};
-use crate::util::PersistencePath;
-use crate::util::{from_yaml_async_reader, to_yaml_async_writer};
+use crate::util::from_yaml_async_reader;
+use crate::util::{AppLock, PersistencePath};
 use crate::ResultExt as _;
 
+pub mod answers;
+pub mod entropy;
+pub mod env;
+pub mod format;
+pub mod history;
+pub mod interactive;
+pub mod lint;
+pub mod migration;
+pub mod patch;
+pub mod provenance;
+pub mod rotate;
 pub mod rules;
 pub mod spec;
 pub mod util;
+pub mod validate;
 pub mod value;
+pub mod watch;
 
+pub use entropy::{EntropyProvider, OsEntropy, SeededEntropy};
+pub use format::ConfigFormat;
+pub use provenance::{ConfigProvenance, ValueSource};
 pub use rules::{ConfigRuleEntry, ConfigRuleEntryWithSuggestions};
 pub use spec::{ConfigSpec, Defaultable};
 use util::NumRange;
-pub use value::Config;
+pub use value::{Config, ConfigChange};
 
 #[derive(Debug, Fail)]
 pub enum ConfigurationError {
@@ -73,6 +87,35 @@ impl std::fmt::Display for NoMatchWithPath {
     }
 }
 
+impl serde::Serialize for NoMatchWithPath {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut s = serializer.serialize_struct("NoMatchWithPath", 2)?;
+        s.serialize_field("path", &self.path.iter().rev().collect::<Vec<_>>())?;
+        s.serialize_field("error", &format!("{}", self.error))?;
+        s.end()
+    }
+}
+
+/// Every field of a config that does not match its spec, so they can all be fixed at once.
+#[derive(Clone, Debug, Fail, serde::Serialize)]
+pub struct SpecViolations(pub Vec<NoMatchWithPath>);
+impl std::fmt::Display for SpecViolations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.iter().join("\n"))
+    }
+}
+impl SpecViolations {
+    pub fn check(spec: &ConfigSpec, config: &Config) -> Result<(), Self> {
+        let errors = spec.matches_all(config);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(SpecViolations(errors))
+        }
+    }
+}
+
 #[derive(Clone, Debug, Fail)]
 pub enum MatchError {
     #[fail(display = "String {:?} Does Not Match Pattern {}", _0, _1)]
@@ -89,6 +132,8 @@ pub enum MatchError {
     OutOfRange(NumRange<f64>, f64),
     #[fail(display = "Number Is Not Integral: {}", _0)]
     NonIntegral(f64),
+    #[fail(display = "String {:?} Is Not A Valid {}", _0, _1)]
+    InvalidQuantity(String, &'static str),
     #[fail(display = "Variant {:?} Is Not In Union {:?}", _0, _1)]
     Union(String, LinearSet<String>),
     #[fail(display = "Variant Is Missing Tag {:?}", _0)]
@@ -106,14 +151,164 @@ pub enum MatchError {
     InvalidKey(String),
     #[fail(display = "Value In List Is Not Unique")]
     ListUniquenessViolation,
+    #[fail(display = "Value In List Is Not Unique: {} Same As Item {}", _0, _1)]
+    ListUniquenessViolationBy(String, usize),
+    #[fail(display = "Invalid Visibility Rule: {}", _0)]
+    InvalidVisibleIf(String),
+    #[fail(display = "Invalid Derived Default: {}", _0)]
+    InvalidDerivation(String),
 }
 
 #[derive(Clone, Debug, Default, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ConfigurationRes {
     pub changed: LinearMap<String, Config>,
+    pub diffs: LinearMap<String, Vec<ConfigChange>>,
     pub needs_restart: LinearSet<String>,
     pub stopped: LinearMap<String, TaggedDependencyError>,
+    /// Apps the cascade reached but left as they were, and why.
+    pub skipped: LinearMap<String, SkipReason>,
+    /// Where the values of every config resolved along the way came from. Callers that do not
+    /// want to show it should clear it.
+    #[serde(skip_serializing_if = "LinearMap::is_empty")]
+    pub provenance: LinearMap<String, ConfigProvenance>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+    /// The resolved config is the one already committed.
+    Unchanged,
+    /// A watcher of a changed app that has since been removed.
+    NotInstalled,
+}
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Unchanged => write!(f, "Configuration Unchanged"),
+            SkipReason::NotInstalled => write!(f, "Not Installed"),
+        }
+    }
+}
+
+impl ConfigurationRes {
+    /// Masks the values of masked config fields in the changed configs and their diffs.
+    pub async fn redact(&mut self) -> Result<(), crate::Error> {
+        let names: LinearSet<String> = self
+            .changed
+            .keys()
+            .chain(self.diffs.keys())
+            .cloned()
+            .collect();
+        for name in names {
+            let masked = crate::apps::config(&name).await?.spec.masked_paths();
+            if let Some(config) = self.changed.get_mut(&name) {
+                config.redact(&masked);
+            }
+            for change in self.diffs.get_mut(&name).into_iter().flatten() {
+                change.redact(&masked);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct StagedConfig {
+    spec: ConfigSpec,
+    old: Option<Config>,
+    new: Config,
+    needs_restart: bool,
+}
+
+/// Config writes collected over a configure cascade, so that nothing is persisted unless every
+/// app in the cascade was processed successfully.
+#[derive(Default)]
+struct ConfigTransaction {
+    staged: LinearMap<String, StagedConfig>,
+    unconfigured: LinearSet<String>,
+    // requested configs that have not been applied yet, so a cascade reaching one of these apps
+    // applies the requested config instead of the committed one
+    pending: LinearMap<String, Option<Config>>,
+    // no further app is processed once this has passed
+    deadline: Option<Instant>,
+    // the app being processed, so a timeout can say where the cascade got to
+    current: Option<String>,
+    // every app in the cascade stays locked until the transaction is committed or dropped
+    locks: Vec<AppLock>,
+}
+impl ConfigTransaction {
+    fn enter(&mut self, name: &str) -> Result<(), crate::Error> {
+        if self.deadline.map_or(false, |d| Instant::now() >= d) {
+            return Err(cascade_timeout(name));
+        }
+        self.current = Some(name.to_owned());
+        Ok(())
+    }
+
+    async fn write_config(name: &str, config: Option<&Config>) -> Result<(), crate::Error> {
+        let config_path = PersistencePath::from_ref("apps")
+            .join(name)
+            .join("config.yaml");
+        let manifest = crate::apps::manifest(name).await?;
+        let format = manifest.config_format;
+        if let Some(config) = config {
+            config_path.write_sealed(config).await?;
+            if manifest.plaintext_config || !crate::encryption::configs_encrypted().await? {
+                format.write_volume_config(name, config).await?;
+            } else {
+                // a plaintext copy from before encryption was turned on
+                format.remove_volume_config(name).await?;
+            }
+            if !manifest.env_map.is_empty() {
+                env::write(name, &manifest.env_map, config).await?;
+            }
+        } else {
+            config_path.delete().await?;
+            format.remove_volume_config(name).await?;
+            env::remove(name).await?;
+        }
+        Ok(())
+    }
+
+    async fn commit(self) -> Result<(), crate::Error> {
+        let mut committed: Vec<(&str, &StagedConfig)> = Vec::new();
+        for (name, staged) in &self.staged {
+            if let Some(old) = &staged.old {
+                if let Err(e) = history::archive(name, old).await {
+                    log::warn!("Failed to save previous config for {}: {}", name, e);
+                }
+            }
+            if let Err(e) = Self::write_config(name, Some(&staged.new)).await {
+                log::error!("Failed to write config for {}, rolling back: {}", name, e);
+                for (name, staged) in committed.into_iter().rev() {
+                    if let Err(e) = Self::write_config(name, staged.old.as_ref()).await {
+                        log::error!("Failed to roll back config for {}: {}", name, e);
+                    }
+                }
+                return Err(e);
+            }
+            committed.push((name.as_str(), staged));
+        }
+        for name in &self.unconfigured {
+            crate::apps::set_configured(name, false).await?;
+        }
+        for (name, staged) in &self.staged {
+            watch::record(name, &staged.spec, &staged.new).await?;
+            crate::apps::set_configured(name, true).await?;
+            crate::apps::set_recoverable(name, false).await?;
+            if staged.needs_restart {
+                crate::apps::set_needs_restart(name, true).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn cascade_timeout(name: &str) -> crate::Error {
+    crate::Error::new(
+        failure::format_err!("Configure Timed Out While Processing {}", name),
+        Some(crate::error::TIMEOUT_ERROR),
+    )
 }
 
 // returns apps with changed configurations
@@ -123,16 +318,92 @@ pub async fn configure(
     timeout: Option<Duration>,
     dry_run: bool,
 ) -> Result<ConfigurationRes, crate::Error> {
+    configure_with_entropy(name, config, timeout, dry_run, &OsEntropy).await
+}
+
+/// Like `configure`, but draws generated defaults from `entropy`.
+pub async fn configure_with_entropy(
+    name: &str,
+    config: Option<Config>,
+    timeout: Option<Duration>,
+    dry_run: bool,
+    entropy: &dyn EntropyProvider,
+) -> Result<ConfigurationRes, crate::Error> {
+    configure_many_with_entropy(vec![(name.to_owned(), config)], timeout, dry_run, entropy).await
+}
+
+/// Configures several apps as a single transaction, so related changes cause one restart of each
+/// affected app rather than one per cascade. Requested apps are configured after any requested
+/// apps they depend on, so a cascade never overwrites a config that was passed in.
+///
+/// `timeout` bounds the whole cascade, not just entropy generation. If it runs out, nothing is
+/// written and no app is stopped, and the error names the app that was being processed.
+pub async fn configure_many(
+    configs: Vec<(String, Option<Config>)>,
+    timeout: Option<Duration>,
+    dry_run: bool,
+) -> Result<ConfigurationRes, crate::Error> {
+    configure_many_with_entropy(configs, timeout, dry_run, &OsEntropy).await
+}
+
+// orders requested apps so that each comes after the requested apps it depends on. Apps in a
+// dependency cycle keep the order they were given in.
+async fn dependency_order(
+    mut configs: Vec<(String, Option<Config>)>,
+) -> Result<Vec<(String, Option<Config>)>, crate::Error> {
+    if configs.len() < 2 {
+        return Ok(configs);
+    }
+    let requested: LinearSet<String> = configs.iter().map(|(name, _)| name.clone()).collect();
+    let mut deps = LinearMap::new();
+    for name in requested.iter() {
+        let manifest = crate::apps::manifest(name).await?;
+        deps.insert(
+            name.clone(),
+            manifest
+                .dependencies
+                .0
+                .into_iter()
+                .map(|(dep, _)| dep)
+                .filter(|dep| dep != name && requested.contains(dep))
+                .collect::<LinearSet<String>>(),
+        );
+    }
+    let mut res = Vec::with_capacity(configs.len());
+    let mut done = LinearSet::new();
+    while !configs.is_empty() {
+        let ready = configs
+            .iter()
+            .position(|(name, _)| {
+                deps.get(name)
+                    .map_or(true, |deps| deps.iter().all(|dep| done.contains(dep)))
+            })
+            .unwrap_or(0);
+        let (name, config) = configs.remove(ready);
+        done.insert(name.clone());
+        res.push((name, config));
+    }
+    Ok(res)
+}
+
+/// Like `configure_many`, but draws generated defaults from `entropy`.
+pub async fn configure_many_with_entropy(
+    configs: Vec<(String, Option<Config>)>,
+    timeout: Option<Duration>,
+    dry_run: bool,
+    entropy: &dyn EntropyProvider,
+) -> Result<ConfigurationRes, crate::Error> {
+    // only works out what to stop: apps are stopped once the cascade has been committed, so one
+    // that times out leaves everything running
     async fn handle_broken_dependent(
         name: &str,
         dependent: String,
-        dry_run: bool,
         res: &mut ConfigurationRes,
         error: DependencyError,
     ) -> Result<(), crate::Error> {
         crate::control::stop_dependents(
             &dependent,
-            dry_run,
+            true,
             DependencyError::NotRunning,
             &mut res.stopped,
         )
@@ -140,7 +411,6 @@ pub async fn configure(
         if crate::apps::status(&dependent, false).await?.status
             != crate::apps::DockerStatus::Stopped
         {
-            crate::control::stop_app(&dependent, false, dry_run).await?;
             res.stopped.insert(
                 // TODO: maybe don't do this if its not running
                 dependent,
@@ -157,15 +427,21 @@ pub async fn configure(
         config: Option<Config>,
         timeout: Option<Duration>,
         dry_run: bool,
+        entropy: &'a dyn EntropyProvider,
         res: &'a mut ConfigurationRes,
+        tx: &'a mut ConfigTransaction,
     ) -> BoxFuture<'a, Result<Config, crate::Error>> {
         async move {
+            tx.enter(name)?;
+            crate::progress::phase(name);
+            crate::progress::step();
+            tx.locks.push(crate::util::lock_app(name).await?);
             let info = crate::apps::list_info()
                 .await?
                 .remove(name)
                 .ok_or_else(|| failure::format_err!("{} is not installed", name))
                 .with_code(crate::error::NOT_FOUND)?;
-            let mut rng = rand::rngs::StdRng::from_entropy();
+            let mut rng = entropy.rng(name);
             let spec_path = PersistencePath::from_ref("apps")
                 .join(name)
                 .join("config_spec.yaml");
@@ -179,24 +455,26 @@ pub async fn configure(
                 from_yaml_async_reader(&mut *spec_path.read(false).await?).await?;
             let rules: Vec<ConfigRuleEntry> =
                 from_yaml_async_reader(&mut *rules_path.read(false).await?).await?;
-            let old_config: Option<Config> =
-                if let Some(mut f) = config_path.maybe_read(false).await.transpose()? {
-                    Some(from_yaml_async_reader(&mut *f).await?)
-                } else {
-                    None
-                };
-            let mut config = if let Some(cfg) = config {
-                cfg
+            let old_config: Option<Config> = config_path.maybe_read_sealed().await?;
+            let (mut config, source) = if let Some(mut cfg) = config {
+                if let Some(old) = &old_config {
+                    cfg.unmask(old, &spec.masked_paths());
+                }
+                (cfg, ValueSource::Provided)
             } else {
                 if let Some(old) = &old_config {
-                    old.clone()
+                    (old.clone(), ValueSource::Previous)
                 } else {
-                    spec.gen(&mut rng, &timeout)
-                        .with_code(crate::error::CFG_SPEC_VIOLATION)?
+                    let mut config = spec
+                        .gen(&mut rng, &timeout)
+                        .with_code(crate::error::CFG_SPEC_VIOLATION)?;
+                    if let Some(answers) = answers::get(name).await? {
+                        answers::apply(&mut config, &spec, &answers);
+                    }
+                    (config, ValueSource::Default)
                 }
             };
-            spec.matches(&config)
-                .with_code(crate::error::CFG_SPEC_VIOLATION)?;
+            SpecViolations::check(&spec, &config).with_code(crate::error::CFG_SPEC_VIOLATION)?;
             spec.update(&mut config)
                 .await
                 .with_code(crate::error::CFG_SPEC_VIOLATION)?;
@@ -206,15 +484,45 @@ pub async fn configure(
                 rule.check(&config, &cfgs)
                     .with_code(crate::error::CFG_RULES_VIOLATION)?;
             }
-            match old_config {
-                Some(old) if &old == &config && info.configured && !info.recoverable => {
-                    return Ok(config)
+            res.provenance.insert(
+                name.to_owned(),
+                provenance::explain(&config, source, &spec.pointer_paths()),
+            );
+            match &old_config {
+                Some(old) if old == &config && info.configured && !info.recoverable => {
+                    res.skipped.insert(name.to_owned(), SkipReason::Unchanged);
+                    return Ok(config);
                 }
                 _ => (),
             };
+            res.diffs.insert(
+                name.to_owned(),
+                old_config.clone().unwrap_or_default().diff(&config),
+            );
             res.changed.insert(name.to_owned(), config.clone());
-            for dependent in crate::apps::dependents(name, false).await? {
-                match configure_rec(&dependent, None, timeout, dry_run, res).await {
+            let mut dependents = crate::apps::dependents(name, false).await?;
+            dependents.extend(watch::watchers(name).await?);
+            let installed = crate::apps::list_info().await?;
+            for dependent in dependents {
+                // dependents that have not been configured yet are configured along with it
+                if !installed.contains_key(&dependent) {
+                    res.skipped.insert(dependent, SkipReason::NotInstalled);
+                    continue;
+                }
+                let dependent_config = tx.pending.remove(&dependent).flatten();
+                let dependent_res = configure_rec(
+                    &dependent,
+                    dependent_config,
+                    timeout,
+                    dry_run,
+                    entropy,
+                    res,
+                    tx,
+                )
+                .await;
+                tx.current = Some(name.to_owned());
+                match dependent_res {
+                    Err(e) if e.code == Some(crate::error::TIMEOUT_ERROR) => return Err(e),
                     Ok(dependent_config) => {
                         let man = crate::apps::manifest(&dependent).await?;
                         if let Some(dep_info) = man.dependencies.0.get(name) {
@@ -229,8 +537,7 @@ pub async fn configure(
                             {
                                 Ok(_) => (),
                                 Err(e) => {
-                                    handle_broken_dependent(name, dependent, dry_run, res, e)
-                                        .await?;
+                                    handle_broken_dependent(name, dependent, res, e).await?;
                                 }
                             }
                         }
@@ -239,13 +546,10 @@ pub async fn configure(
                         if e.code == Some(crate::error::CFG_RULES_VIOLATION)
                             || e.code == Some(crate::error::CFG_SPEC_VIOLATION)
                         {
-                            if !dry_run {
-                                crate::apps::set_configured(&dependent, false).await?;
-                            }
+                            tx.unconfigured.insert(dependent.clone());
                             handle_broken_dependent(
                                 name,
                                 dependent,
-                                dry_run,
                                 res,
                                 DependencyError::PointerUpdateError(format!("{}", e)),
                             )
@@ -254,7 +558,6 @@ pub async fn configure(
                             handle_broken_dependent(
                                 name,
                                 dependent,
-                                dry_run,
                                 res,
                                 DependencyError::Other(format!("{}", e)),
                             )
@@ -263,45 +566,131 @@ pub async fn configure(
                     }
                 }
             }
-            if !dry_run {
-                let mut file = config_path.write(None).await?;
-                to_yaml_async_writer(file.as_mut(), &config).await?;
-                file.commit().await?;
-                let volume_config = Path::new(crate::VOLUMES)
-                    .join(name)
-                    .join("start9")
-                    .join("config.yaml");
-                tokio::fs::copy(config_path.path(), &volume_config)
-                    .await
-                    .with_context(|e| {
-                        format!(
-                            "{}: {} -> {}",
-                            e,
-                            config_path.path().display(),
-                            volume_config.display()
-                        )
-                    })
-                    .with_code(crate::error::FILESYSTEM_ERROR)?;
-                crate::apps::set_configured(name, true).await?;
-                crate::apps::set_recoverable(name, false).await?;
-            }
-            if crate::apps::status(name, false).await?.status != crate::apps::DockerStatus::Stopped
-            {
-                if !dry_run {
-                    crate::apps::set_needs_restart(name, true).await?;
-                }
+            let needs_restart = crate::apps::status(name, false).await?.status
+                != crate::apps::DockerStatus::Stopped;
+            if needs_restart {
                 res.needs_restart.insert(name.to_string());
             }
+            tx.staged.insert(
+                name.to_owned(),
+                StagedConfig {
+                    spec,
+                    old: old_config,
+                    new: config.clone(),
+                    needs_restart,
+                },
+            );
             Ok(config)
         }
         .boxed()
     }
     let mut res = ConfigurationRes::default();
-    configure_rec(name, config, timeout, dry_run, &mut res).await?;
+    let mut tx = ConfigTransaction::default();
+    tx.deadline = timeout.map(|t| Instant::now() + t);
+    let configs = dependency_order(configs).await?;
+    let order: Vec<String> = configs.iter().map(|(name, _)| name.clone()).collect();
+    tx.pending = configs.into_iter().collect();
+    let cascade = async {
+        for name in order {
+            if let Some(config) = tx.pending.remove(&name) {
+                configure_rec(&name, config, timeout, dry_run, entropy, &mut res, &mut tx).await?;
+            }
+        }
+        Ok::<_, crate::Error>(())
+    };
+    // the checks in configure_rec only run between apps, this also cuts off an app that hangs
+    let cascade_res = match timeout {
+        Some(t) => tokio::time::timeout(t, cascade).await.unwrap_or_else(|_| {
+            Err(cascade_timeout(
+                tx.current.as_deref().unwrap_or("Configuration"),
+            ))
+        }),
+        None => cascade.await,
+    };
+    if let Err(e) = cascade_res {
+        if e.code == Some(crate::error::TIMEOUT_ERROR) {
+            log::error!(
+                "{}, discarding {} staged configs.",
+                e.failure,
+                tx.staged.len()
+            );
+        }
+        return Err(e);
+    }
+    if !dry_run {
+        tx.commit().await?;
+        for name in res.changed.keys() {
+            crate::events::emit(crate::events::EventKind::ConfigChanged, name).await;
+        }
+        for name in res.stopped.keys() {
+            crate::control::stop_app(name, false, false).await?;
+        }
+    }
     Ok(res)
 }
 
+/// What keeps an app from starting, as far as its config is concerned.
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ConfigStatus {
+    /// The app will not start until this is set.
+    pub configured: bool,
+    /// Data was found in the volume at install, so the app has not been configured since it was
+    /// reinstalled or restored.
+    pub recoverable: bool,
+    pub needs_restart: bool,
+    /// When the config was last written, in seconds since the epoch. `None` if it never was.
+    pub last_configured_at: Option<u64>,
+}
+
+/// Writes the stored config of an app out again, i.e. to seal or open it after encryption of
+/// configs was turned on or off.
+pub async fn rewrite(name: &str) -> Result<(), crate::Error> {
+    let _lock = crate::util::lock_app(name).await?;
+    let config: Option<Config> = PersistencePath::from_ref("apps")
+        .join(name)
+        .join("config.yaml")
+        .maybe_read_sealed()
+        .await?;
+    if let Some(config) = config {
+        ConfigTransaction::write_config(name, Some(&config)).await?;
+    }
+    Ok(())
+}
+
+pub async fn status(name: &str) -> Result<ConfigStatus, crate::Error> {
+    let info = crate::apps::list_info()
+        .await?
+        .remove(name)
+        .ok_or_else(|| failure::format_err!("{} is not installed", name))
+        .with_code(crate::error::NOT_FOUND)?;
+    let config_path = PersistencePath::from_ref("apps")
+        .join(name)
+        .join("config.yaml")
+        .path();
+    let last_configured_at = match tokio::fs::metadata(&config_path).await {
+        Ok(m) => m
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs()),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => {
+            return Err(e)
+                .with_context(|e| format!("{}: {}", config_path.display(), e))
+                .with_code(crate::error::FILESYSTEM_ERROR)
+        }
+    };
+    Ok(ConfigStatus {
+        configured: info.configured,
+        recoverable: info.recoverable,
+        needs_restart: info.needs_restart,
+        last_configured_at,
+    })
+}
+
 pub async fn remove(name: &str) -> Result<(), crate::Error> {
+    let _lock = crate::util::lock_app(name).await?;
     let config_path = PersistencePath::from_ref("apps")
         .join(name)
         .join("config.yaml")
@@ -312,16 +701,11 @@ pub async fn remove(name: &str) -> Result<(), crate::Error> {
             .with_context(|e| format!("{}: {}", e, config_path.display()))
             .with_code(crate::error::FILESYSTEM_ERROR)?;
     }
-    let volume_config = Path::new(crate::VOLUMES)
-        .join(name)
-        .join("start9")
-        .join("config.yaml");
-    if volume_config.exists() {
-        tokio::fs::remove_file(&volume_config)
-            .await
-            .with_context(|e| format!("{}: {}", e, volume_config.display()))
-            .with_code(crate::error::FILESYSTEM_ERROR)?;
-    }
+    crate::apps::manifest(name)
+        .await?
+        .config_format
+        .remove_volume_config(name)
+        .await?;
     crate::apps::set_configured(name, false).await?;
     Ok(())
 }
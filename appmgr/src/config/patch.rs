@@ -0,0 +1,259 @@
+use std::time::Duration;
+
+use linear_map::LinearMap;
+use rand::SeedableRng;
+
+use super::spec::{ConfigSpec, Defaultable};
+use super::value::Value;
+use super::{Config, ConfigurationRes};
+use crate::Error;
+use crate::ResultExt as _;
+
+/// A partial update to an app's config.
+#[derive(Clone, Debug)]
+pub enum ConfigPatch {
+    /// An RFC 6902 JSON Patch.
+    Json(json_patch::Patch),
+    /// A list of dotted paths and the values to set them to.
+    Set(Vec<(String, Value)>),
+}
+impl ConfigPatch {
+    pub fn from_json_slice(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(ConfigPatch::Json(
+            serde_json::from_slice(bytes).with_code(crate::error::SERDE_ERROR)?,
+        ))
+    }
+
+    /// Parses `path.to.key=value` assignments. Values are parsed as yaml, so `port=8332` sets a
+    /// number and `enable=true` sets a boolean; quote them to force a string.
+    pub fn from_assignments<'a, I: IntoIterator<Item = &'a str>>(
+        assignments: I,
+    ) -> Result<Self, Error> {
+        let mut res = Vec::new();
+        for assignment in assignments {
+            let mut split = assignment.splitn(2, '=');
+            let path = split.next().unwrap();
+            let value = split
+                .next()
+                .ok_or_else(|| failure::format_err!("Expected key=value: {}", assignment))
+                .no_code()?;
+            crate::ensure_code!(
+                !path.is_empty() && path.split('.').all(|k| !k.is_empty()),
+                crate::error::GENERAL_ERROR,
+                "Invalid Path: {:?}",
+                path
+            );
+            res.push((
+                path.to_owned(),
+                serde_yaml::from_str(value).with_code(crate::error::SERDE_ERROR)?,
+            ));
+        }
+        Ok(ConfigPatch::Set(res))
+    }
+
+    pub fn apply(&self, config: &Config) -> Result<Config, Error> {
+        match self {
+            ConfigPatch::Json(patch) => {
+                let mut doc = serde_json::to_value(config).with_code(crate::error::SERDE_ERROR)?;
+                json_patch::patch(&mut doc, patch).with_code(crate::error::CFG_SPEC_VIOLATION)?;
+                serde_json::from_value(doc).with_code(crate::error::CFG_SPEC_VIOLATION)
+            }
+            ConfigPatch::Set(assignments) => {
+                let mut res = config.clone();
+                for (path, value) in assignments {
+                    set(&mut res, path, value.clone())?;
+                }
+                Ok(res)
+            }
+        }
+    }
+}
+
+// sets the value at a dotted path, creating intermediate objects that are missing or null
+pub(super) fn set(config: &mut Config, path: &str, value: Value) -> Result<(), Error> {
+    let mut keys = path.split('.').peekable();
+    let mut obj = config;
+    while let Some(key) = keys.next() {
+        if keys.peek().is_none() {
+            obj.0.insert(key.to_owned(), value);
+            break;
+        }
+        let next = obj
+            .0
+            .entry(key.to_owned())
+            .or_insert_with(|| Value::Object(Config(LinearMap::new())));
+        if let Value::Null = next {
+            *next = Value::Object(Config(LinearMap::new()));
+        }
+        obj = match next {
+            Value::Object(o) => o,
+            a => {
+                return Err(failure::format_err!(
+                    "Cannot Set {}: {} is a {}",
+                    path,
+                    key,
+                    a.type_of()
+                ))
+                .with_code(crate::error::CFG_SPEC_VIOLATION)
+            }
+        };
+    }
+    Ok(())
+}
+
+/// Applies `patch` on top of the current config of `name` (or its defaults if it has never been
+/// configured), then runs the normal configure cascade with the result.
+pub async fn patch(
+    name: &str,
+    patch: &ConfigPatch,
+    timeout: Option<Duration>,
+    dry_run: bool,
+) -> Result<ConfigurationRes, Error> {
+    let app_config = crate::apps::config(name).await?;
+    let current = match app_config.config {
+        Some(a) => a,
+        None => app_config
+            .spec
+            .gen(&mut rand::rngs::StdRng::from_entropy(), &timeout)
+            .with_code(crate::error::CFG_SPEC_VIOLATION)?,
+    };
+    super::configure(name, Some(patch.apply(&current)?), timeout, dry_run).await
+}
+
+/// Builds a patch that sets each of `paths` to a freshly generated value, i.e. to rotate a
+/// password. Passing it to `patch` revalidates the config and carries the new values over to
+/// dependents through their pointers.
+pub fn regenerate<R: rand::Rng + rand::CryptoRng + Sync + Send>(
+    spec: &ConfigSpec,
+    paths: &[&str],
+    rng: &mut R,
+    timeout: &Option<Duration>,
+) -> Result<ConfigPatch, Error> {
+    let mut res = Vec::new();
+    for path in paths {
+        let field = spec
+            .get_path(path)
+            .ok_or_else(|| {
+                failure::format_err!(
+                    "Cannot Regenerate {}: not a field of the spec outside of a list or union",
+                    path
+                )
+            })
+            .with_code(crate::error::NOT_FOUND)?;
+        res.push((
+            (*path).to_owned(),
+            field
+                .gen(rng, timeout)
+                .with_code(crate::error::CFG_SPEC_VIOLATION)?,
+        ));
+    }
+    Ok(ConfigPatch::Set(res))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+pruning: disabled
+rpc:
+  enable: false
+  port: 8332
+"#,
+        )
+        .unwrap();
+        let expected: Config = serde_yaml::from_str(
+            r#"
+pruning: disabled
+rpc:
+  enable: true
+  port: 8333
+advanced:
+  peers: 4
+"#,
+        )
+        .unwrap();
+        let set = ConfigPatch::from_assignments(vec![
+            "rpc.enable=true",
+            "rpc.port=8333",
+            "advanced.peers=4",
+        ])
+        .unwrap();
+        assert!(set.apply(&config).unwrap().diff(&expected).is_empty());
+        let json = ConfigPatch::from_json_slice(
+            br#"[
+                { "op": "replace", "path": "/rpc/enable", "value": true },
+                { "op": "replace", "path": "/rpc/port", "value": 8333 },
+                { "op": "add", "path": "/advanced", "value": { "peers": 4 } }
+            ]"#,
+        )
+        .unwrap();
+        assert!(json.apply(&config).unwrap().diff(&expected).is_empty());
+        assert!(ConfigPatch::from_assignments(vec!["pruning.mode=manual"])
+            .unwrap()
+            .apply(&config)
+            .is_err());
+    }
+
+    #[test]
+    fn test_regenerate() {
+        use crate::config::{EntropyProvider, SeededEntropy};
+
+        let spec: ConfigSpec = serde_json::from_str(
+            r#"{
+                "rpc": {
+                    "name": "RPC Settings",
+                    "type": "object",
+                    "nullable": false,
+                    "nullByDefault": false,
+                    "spec": {
+                        "username": {
+                            "name": "RPC Username",
+                            "type": "string",
+                            "nullable": false,
+                            "default": "bitcoin"
+                        },
+                        "password": {
+                            "name": "RPC Password",
+                            "type": "string",
+                            "nullable": false,
+                            "default": {
+                                "charset": "a-z,A-Z,2-9",
+                                "len": 20
+                            }
+                        }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let config: Config = serde_yaml::from_str(
+            r#"
+rpc:
+  username: satoshi
+  password: hunter2
+"#,
+        )
+        .unwrap();
+        let patch = regenerate(
+            &spec,
+            &["rpc.password"],
+            &mut SeededEntropy(1).rng("bitcoind"),
+            &None,
+        )
+        .unwrap();
+        let diff = config.diff(&patch.apply(&config).unwrap());
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].path(), "rpc.password");
+        assert!(regenerate(
+            &spec,
+            &["rpc.port"],
+            &mut SeededEntropy(1).rng("bitcoind"),
+            &None
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use linear_map::{set::LinearSet, LinearMap};
+
+use super::value::Value;
+use super::Config;
+
+/// Where a value in a resolved config came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValueSource {
+    /// Passed to `configure` by the caller.
+    Provided,
+    /// Kept from the config that was already committed.
+    Previous,
+    /// Generated from the default in the spec.
+    Default,
+    /// Resolved from a pointer, i.e. another app's config or tor address. These are re-resolved
+    /// on every configure, whatever the caller passed.
+    Pointer,
+}
+
+/// The source of every value in a config, by dotted path. Objects are broken down into their
+/// fields, everything else (including lists) is a single entry.
+pub type ConfigProvenance = LinearMap<String, ValueSource>;
+
+/// Explains `config`, where every value not under one of `pointers` came from `base`.
+pub fn explain(
+    config: &Config,
+    base: ValueSource,
+    pointers: &LinearSet<String>,
+) -> ConfigProvenance {
+    fn explain_rec(
+        config: &Config,
+        prefix: &str,
+        base: ValueSource,
+        pointers: &LinearSet<String>,
+        res: &mut ConfigProvenance,
+    ) {
+        for (key, value) in config.0.iter() {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            if let Value::Object(o) = value {
+                explain_rec(o, &path, base, pointers, res);
+                continue;
+            }
+            let nested = format!("{}.", path);
+            let source = if pointers
+                .iter()
+                .any(|p| p == &path || p.starts_with(&nested))
+            {
+                ValueSource::Pointer
+            } else {
+                base
+            };
+            res.insert(path, source);
+        }
+    }
+    let mut res = LinearMap::new();
+    explain_rec(config, "", base, pointers, &mut res);
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        let config: Config = serde_yaml::from_str(
+            r#"
+rpc:
+  username: bitcoin
+  password: hunter2
+bitcoind-address: 172.18.0.2
+peers:
+  - tor-address: abc.onion
+"#,
+        )
+        .unwrap();
+        let pointers = vec![
+            "bitcoind-address".to_owned(),
+            "peers.tor-address".to_owned(),
+        ]
+        .into_iter()
+        .collect();
+        let res = explain(&config, ValueSource::Previous, &pointers);
+        assert_eq!(res.len(), 4);
+        assert_eq!(res.get("rpc.username"), Some(&ValueSource::Previous));
+        assert_eq!(res.get("rpc.password"), Some(&ValueSource::Previous));
+        assert_eq!(res.get("bitcoind-address"), Some(&ValueSource::Pointer));
+        assert_eq!(res.get("peers"), Some(&ValueSource::Pointer));
+    }
+}
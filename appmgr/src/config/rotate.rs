@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use linear_map::LinearMap;
+
+use super::{ConfigurationRes, EntropyProvider, OsEntropy};
+use crate::Error;
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RotationRes {
+    /// The fields that were regenerated.
+    pub rotated: Vec<String>,
+    #[serde(flatten)]
+    pub config: ConfigurationRes,
+    /// Apps restarted to pick up the new credentials, in the order they were restarted. On a dry
+    /// run, the ones that would be.
+    pub restarted: Vec<String>,
+    pub failed: LinearMap<String, String>,
+}
+
+/// Regenerates every secret of `name` (see `ConfigSpec::secret_paths`), carries the new values
+/// over to the dependents that point at them, and restarts the affected apps that are running,
+/// each one before its dependents so they never connect with stale credentials. A failed restart
+/// does not stop the others.
+pub async fn rotate_credentials(
+    name: &str,
+    timeout: Option<Duration>,
+    dry_run: bool,
+) -> Result<RotationRes, Error> {
+    let spec = crate::apps::config(name).await?.spec;
+    let rotated: Vec<String> = spec.secret_paths().into_iter().collect();
+    crate::ensure_code!(
+        !rotated.is_empty(),
+        crate::error::NOT_FOUND,
+        "{} has no generated secrets",
+        name
+    );
+    let paths: Vec<&str> = rotated.iter().map(|p| p.as_str()).collect();
+    let patch = super::patch::regenerate(&spec, &paths, &mut OsEntropy.rng(name), &timeout)?;
+    let config = super::patch::patch(name, &patch, timeout, dry_run).await?;
+    let mut res = RotationRes {
+        rotated,
+        config,
+        ..Default::default()
+    };
+    // the cascade marks an app after its dependents, so reversed, each comes before them
+    let mut order: Vec<String> = res
+        .config
+        .needs_restart
+        .iter()
+        .filter(|app| !res.config.stopped.contains_key(*app))
+        .cloned()
+        .collect();
+    order.reverse();
+    for app in order {
+        if dry_run {
+            res.restarted.push(app);
+            continue;
+        }
+        log::info!("Restarting {} with rotated credentials.", app);
+        match crate::control::restart_app(&app).await {
+            Ok(()) => res.restarted.push(app),
+            Err(e) => {
+                log::error!("Restart of {} failed: {}", app, e.failure);
+                res.failed.insert(app, format!("{}", e.failure));
+            }
+        }
+    }
+    Ok(res)
+}
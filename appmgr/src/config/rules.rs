@@ -2,17 +2,28 @@ use std::borrow::Cow;
 use std::sync::Arc;
 
 use linear_map::LinearMap;
-use pest::iterators::Pairs;
+use pest::iterators::{Pair, Pairs};
 use pest::Parser;
 use rand::SeedableRng;
 
+use super::spec::{ValueSpecAny, ValueSpecList};
 use super::util::STATIC_NULL;
 use super::value::{Config, Value};
+use super::ConfigSpec;
 
 #[derive(Parser)]
 #[grammar = "config/rule_parser.pest"]
 struct RuleParser;
 
+/// The newest version of the rule grammar this build understands. Every version is a superset of
+/// the last, so rules written against an older one parse unchanged. A rule may pin the version it
+/// was written against with a `v<N>:` prefix, in which case constructs from later versions are
+/// rejected, and so is a version newer than this one.
+///
+/// 1. The original grammar.
+/// 2. `%`, `len(<string>)`, `count(<list>)` and `<value> IN <list>`.
+pub const GRAMMAR_VERSION: u64 = 2;
+
 lazy_static::lazy_static! {
     static ref NUM_PREC_CLIMBER: pest::prec_climber::PrecClimber<Rule> = {
         use pest::prec_climber::*;
@@ -21,7 +32,7 @@ lazy_static::lazy_static! {
 
         PrecClimber::new(vec![
             Operator::new(add, Left) | Operator::new(sub, Left),
-            Operator::new(mul, Left) | Operator::new(div, Left),
+            Operator::new(mul, Left) | Operator::new(div, Left) | Operator::new(rem, Left),
             Operator::new(pow, Right)
         ])
     };
@@ -294,12 +305,42 @@ impl Suggestion {
             _ => self.variant.apply(id, cfg, cfgs),
         }
     }
+    pub fn applies(&self, cfg: &Config, cfgs: &LinearMap<&str, Cow<Config>>) -> bool {
+        match &self.condition {
+            Some(condition) => (condition.compiled)(cfg, cfgs),
+            None => true,
+        }
+    }
+}
+impl std::fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.variant {
+            SuggestionVariant::Set { var, to, .. } => match to {
+                SetVariant::To(expr) => write!(f, "set {} to {}", var, expr),
+                SetVariant::ToValue(value) => write!(
+                    f,
+                    "set {} to {}",
+                    var,
+                    serde_json::to_string(value).map_err(|_| std::fmt::Error)?
+                ),
+                SetVariant::ToEntropy(_) => write!(f, "set {} to a random value", var),
+            },
+            SuggestionVariant::Delete { src, .. } => write!(f, "delete {}", src),
+            SuggestionVariant::Push { to, value, .. } => write!(
+                f,
+                "add {} to {}",
+                serde_json::to_string(value).map_err(|_| std::fmt::Error)?,
+                to
+            ),
+        }
+    }
 }
 #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ConfigRuleEntryWithSuggestions {
     #[serde(flatten)]
     pub entry: ConfigRuleEntry,
+    #[serde(default)]
     pub suggestions: Vec<Suggestion>,
 }
 impl ConfigRuleEntryWithSuggestions {
@@ -804,6 +845,8 @@ fn compile_num_expr(pairs: Pairs<Rule>) -> CompiledExpr<VarRes<f64>> {
         |pair| match pair.as_rule() {
             Rule::num_var => compile_num_var(pair.into_inner()),
             Rule::num => compile_num(pair.as_str()),
+            Rule::len_fn => compile_len_fn(pair.into_inner()),
+            Rule::count_fn => compile_count_fn(pair.into_inner()),
             Rule::num_expr => compile_num_expr(pair.into_inner()),
             _ => unreachable!(),
         },
@@ -820,6 +863,9 @@ fn compile_num_expr(pairs: Pairs<Rule>) -> CompiledExpr<VarRes<f64>> {
             Rule::div => Box::new(move |cfg, cfgs| {
                 lhs(cfg, cfgs).and_then(|lhs| rhs(cfg, cfgs).map(|rhs| lhs / rhs))
             }),
+            Rule::rem => Box::new(move |cfg, cfgs| {
+                lhs(cfg, cfgs).and_then(|lhs| rhs(cfg, cfgs).map(|rhs| lhs % rhs))
+            }),
             Rule::pow => Box::new(move |cfg, cfgs| {
                 lhs(cfg, cfgs).and_then(|lhs| rhs(cfg, cfgs).map(|rhs| lhs.powf(rhs)))
             }),
@@ -828,6 +874,27 @@ fn compile_num_expr(pairs: Pairs<Rule>) -> CompiledExpr<VarRes<f64>> {
     )
 }
 
+fn compile_len_fn(mut pairs: Pairs<Rule>) -> CompiledExpr<VarRes<f64>> {
+    let expr = compile_str_expr(pairs.next().unwrap().into_inner());
+    Box::new(move |cfg, cfgs| {
+        expr(cfg, cfgs).map(|s| match s {
+            Some(s) => s.chars().count() as f64,
+            None => std::f64::NAN,
+        })
+    })
+}
+
+fn compile_count_fn(mut pairs: Pairs<Rule>) -> CompiledExpr<VarRes<f64>> {
+    let var = compile_var(pairs.next().unwrap().into_inner());
+    Box::new(move |cfg, cfgs| {
+        var(cfg, cfgs).map(|a| match a {
+            Value::List(l) => l.len() as f64,
+            Value::Null => 0.0,
+            _ => std::f64::NAN,
+        })
+    })
+}
+
 fn compile_num_cmp_expr(mut pairs: Pairs<Rule>) -> CompiledRule {
     let lhs = compile_num_expr(pairs.next().unwrap().into_inner());
     let op = pairs.next().unwrap();
@@ -1005,6 +1072,42 @@ fn compile_str_cmp_expr(mut pairs: Pairs<Rule>) -> CompiledRule {
     }
 }
 
+fn compile_num_in_expr(mut pairs: Pairs<Rule>) -> CompiledRule {
+    let lhs = compile_num_expr(pairs.next().unwrap().into_inner());
+    let list = compile_var(pairs.next().unwrap().into_inner());
+    Box::new(move |cfg, cfgs| {
+        lhs(cfg, cfgs)
+            .and_then(|lhs| {
+                list(cfg, cfgs).map(|list| match list {
+                    Value::List(l) => l.iter().any(|a| match a {
+                        Value::Number(n) => *n == lhs,
+                        _ => false,
+                    }),
+                    _ => false,
+                })
+            })
+            .resolve()
+    })
+}
+
+fn compile_str_in_expr(mut pairs: Pairs<Rule>) -> CompiledRule {
+    let lhs = compile_str_expr(pairs.next().unwrap().into_inner());
+    let list = compile_var(pairs.next().unwrap().into_inner());
+    Box::new(move |cfg, cfgs| {
+        lhs(cfg, cfgs)
+            .and_then(|lhs| {
+                list(cfg, cfgs).map(|list| match (&lhs, list) {
+                    (Some(lhs), Value::List(l)) => l.iter().any(|a| match a {
+                        Value::String(s) => s == lhs,
+                        _ => false,
+                    }),
+                    _ => false,
+                })
+            })
+            .resolve()
+    })
+}
+
 fn compile_inv_bool_expr(mut pairs: Pairs<Rule>) -> CompiledRule {
     let expr = compile_bool_expr(pairs.next().unwrap().into_inner());
     Box::new(move |cfg, cfgs| !expr(cfg, cfgs))
@@ -1019,6 +1122,8 @@ fn compile_bool_expr(pairs: Pairs<Rule>) -> CompiledRule {
             Rule::inv_bool_expr => compile_inv_bool_expr(pair.into_inner()),
             Rule::num_cmp_expr => compile_num_cmp_expr(pair.into_inner()),
             Rule::str_cmp_expr => compile_str_cmp_expr(pair.into_inner()),
+            Rule::num_in_expr => compile_num_in_expr(pair.into_inner()),
+            Rule::str_in_expr => compile_str_in_expr(pair.into_inner()),
             _ => unreachable!(),
         },
         |lhs, op, rhs| -> CompiledRule {
@@ -1126,16 +1231,265 @@ fn compile_set_action(var: &str, to: &SetVariant) -> Result<Mutator, failure::Er
     })
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum VarKind {
+    Bool,
+    Number,
+    String,
+    Object,
+    List,
+    Unknown,
+}
+impl std::fmt::Display for VarKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VarKind::Bool => write!(f, "boolean"),
+            VarKind::Number => write!(f, "number"),
+            VarKind::String => write!(f, "string"),
+            VarKind::Object => write!(f, "object"),
+            VarKind::List => write!(f, "list"),
+            VarKind::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+// where a variable points in a config spec, as far as it can be known statically
+#[derive(Clone)]
+enum SpecNode<'a> {
+    // the fields of an object, or of every variant of a union
+    Fields(Vec<&'a ConfigSpec>, Option<&'a str>),
+    Value(&'a ValueSpecAny),
+    Element(&'a ValueSpecList),
+    Leaf(VarKind),
+}
+impl<'a> SpecNode<'a> {
+    fn kind(&self) -> VarKind {
+        match self {
+            SpecNode::Fields(..) => VarKind::Object,
+            SpecNode::Value(v) => match v {
+                ValueSpecAny::Boolean(_) => VarKind::Bool,
+                ValueSpecAny::Enum(_) | ValueSpecAny::String(_) => VarKind::String,
+                ValueSpecAny::Number(_) | ValueSpecAny::Duration(_) | ValueSpecAny::Bytes(_) => {
+                    VarKind::Number
+                }
+                ValueSpecAny::List(_) => VarKind::List,
+                ValueSpecAny::Object(_) | ValueSpecAny::Union(_) => VarKind::Object,
+                ValueSpecAny::Pointer(_) => VarKind::Unknown,
+            },
+            SpecNode::Element(l) => match l {
+                ValueSpecList::Enum(_) | ValueSpecList::String(_) => VarKind::String,
+                ValueSpecList::Number(_) => VarKind::Number,
+                ValueSpecList::Object(_) | ValueSpecList::Union(_) => VarKind::Object,
+            },
+            SpecNode::Leaf(k) => *k,
+        }
+    }
+
+    fn fields(&self) -> Option<SpecNode<'a>> {
+        match self {
+            SpecNode::Fields(..) => Some(self.clone()),
+            SpecNode::Value(v) => match *v {
+                ValueSpecAny::Object(o) => Some(SpecNode::Fields(vec![&o.inner.inner.spec], None)),
+                ValueSpecAny::Union(u) => Some(SpecNode::Fields(
+                    u.inner.inner.variants.values().collect(),
+                    Some(u.inner.inner.tag.id.as_str()),
+                )),
+                _ => None,
+            },
+            SpecNode::Element(l) => match *l {
+                ValueSpecList::Object(l) => {
+                    Some(SpecNode::Fields(vec![&l.inner.inner.spec.spec], None))
+                }
+                ValueSpecList::Union(l) => Some(SpecNode::Fields(
+                    l.inner.inner.spec.inner.variants.values().collect(),
+                    Some(l.inner.inner.spec.inner.tag.id.as_str()),
+                )),
+                _ => None,
+            },
+            SpecNode::Leaf(_) => None,
+        }
+    }
+
+    fn key(&self, path: &str, key: &str) -> Result<SpecNode<'a>, String> {
+        if self.kind() == VarKind::Unknown {
+            return Ok(SpecNode::Leaf(VarKind::Unknown));
+        }
+        match self.fields() {
+            Some(SpecNode::Fields(specs, tag)) => {
+                if tag == Some(key) {
+                    return Ok(SpecNode::Leaf(VarKind::String));
+                }
+                specs
+                    .into_iter()
+                    .find_map(|spec| spec.0.get(key))
+                    .map(SpecNode::Value)
+                    .ok_or_else(|| format!("{} does not exist", join_path(path, key)))
+            }
+            _ => Err(format!("{} is a {}, not an object", path, self.kind())),
+        }
+    }
+
+    fn element(&self, path: &str) -> Result<SpecNode<'a>, String> {
+        if let SpecNode::Value(v) = self {
+            if let ValueSpecAny::List(l) = *v {
+                return Ok(SpecNode::Element(l));
+            }
+        }
+        match self.kind() {
+            // `*` and `&` also iterate over the values of objects
+            VarKind::Object | VarKind::Unknown => Ok(SpecNode::Leaf(VarKind::Unknown)),
+            kind => Err(format!("{} is a {}, not a list", path, kind)),
+        }
+    }
+}
+
+fn join_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn validate_var(var: Pairs<Rule>, spec: &ConfigSpec, expected: VarKind) -> Result<(), String> {
+    let mut path = String::new();
+    let mut node = SpecNode::Fields(vec![spec], None);
+    for seg in var {
+        match seg.as_rule() {
+            // other apps' configs are not known at pack time
+            Rule::app_id => return Ok(()),
+            Rule::sub_ident_regular => {
+                let key = seg.into_inner().next().unwrap();
+                if key.as_rule() != Rule::sub_ident_regular_base {
+                    return Ok(());
+                }
+                node = node.key(&path, key.as_str())?;
+                path = join_path(&path, key.as_str());
+            }
+            Rule::sub_ident_index
+            | Rule::sub_ident_any
+            | Rule::sub_ident_all
+            | Rule::sub_ident_fn => {
+                node = node.element(&path)?;
+                path = format!(
+                    "{}[{}]",
+                    path,
+                    seg.as_str().trim_matches(|c| c == '[' || c == ']')
+                );
+            }
+            _ => (),
+        }
+    }
+    let kind = node.kind();
+    let compatible = match expected {
+        VarKind::Number | VarKind::String => kind != VarKind::Object && kind != VarKind::List,
+        VarKind::List => kind == VarKind::List || kind == VarKind::Unknown,
+        _ => true,
+    };
+    if compatible {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} is a {}, but is used as a {}",
+            path, kind, expected
+        ))
+    }
+}
+
+fn validate_vars_rec(pairs: Pairs<Rule>, spec: &ConfigSpec) -> Result<(), String> {
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::bool_var => validate_var(pair.into_inner(), spec, VarKind::Bool)?,
+            Rule::num_var => validate_var(pair.into_inner(), spec, VarKind::Number)?,
+            Rule::str_var => validate_var(pair.into_inner(), spec, VarKind::String)?,
+            Rule::any_var => validate_var(pair.into_inner(), spec, VarKind::Unknown)?,
+            Rule::count_fn | Rule::num_in_expr | Rule::str_in_expr => {
+                for arg in pair.into_inner() {
+                    if arg.as_rule() == Rule::any_var {
+                        validate_var(arg.into_inner(), spec, VarKind::List)?;
+                    } else {
+                        validate_vars_rec(arg.into_inner(), spec)?;
+                    }
+                }
+            }
+            _ => validate_vars_rec(pair.into_inner(), spec)?,
+        }
+    }
+    Ok(())
+}
+
+/// Checks that every variable a rule reads from its own app's config exists in `spec` and has a
+/// type the rule can use. Variables behind computed keys, and those of other apps, are not checked.
+pub fn validate_vars(rule: &str, spec: &ConfigSpec) -> Result<(), failure::Error> {
+    let pairs = RuleParser::parse(Rule::rule, rule)?;
+    validate_vars_rec(pairs, spec).map_err(|e| format_err!("{}: {}", rule, e))
+}
+
 pub fn validate_key(key: &str) -> Result<(), pest::error::Error<Rule>> {
     RuleParser::parse(Rule::obj_key, key)?;
     Ok(())
 }
 
+// the grammar version a rule was added in
+fn introduced_in(r: Rule) -> u64 {
+    match r {
+        Rule::rem | Rule::len_fn | Rule::count_fn | Rule::num_in_expr | Rule::str_in_expr => 2,
+        _ => 1,
+    }
+}
+
+fn find_newer(pairs: Pairs<Rule>, version: u64) -> Option<Pair<Rule>> {
+    for pair in pairs {
+        if introduced_in(pair.as_rule()) > version {
+            return Some(pair);
+        }
+        if let Some(newer) = find_newer(pair.clone().into_inner(), version) {
+            return Some(newer);
+        }
+    }
+    None
+}
+
+// consumes the version prefix, if there is one, and checks the rest is valid in that version
+fn check_version(pairs: &mut Pairs<Rule>) -> Result<(), pest::error::Error<Rule>> {
+    let version = match pairs.peek() {
+        Some(pair) if pair.as_rule() == Rule::version => pairs.next().unwrap(),
+        _ => return Ok(()),
+    };
+    let custom = |message: String, span| {
+        pest::error::Error::new_from_span(pest::error::ErrorVariant::CustomError { message }, span)
+    };
+    let v: u64 = version.as_str()[1..version.as_str().len() - 1]
+        .parse()
+        .map_err(|_| custom("invalid grammar version".to_owned(), version.as_span()))?;
+    if v == 0 || v > GRAMMAR_VERSION {
+        return Err(custom(
+            format!(
+                "grammar version {} is not supported, expected 1 through {}",
+                v, GRAMMAR_VERSION
+            ),
+            version.as_span(),
+        ));
+    }
+    if let Some(newer) = find_newer(pairs.clone(), v) {
+        return Err(custom(
+            format!(
+                "requires grammar version {}, but the rule is pinned to {}",
+                introduced_in(newer.as_rule()),
+                v
+            ),
+            newer.as_span(),
+        ));
+    }
+    Ok(())
+}
+
 pub fn parse_and<T, F: FnOnce(Pairs<Rule>) -> T>(
     rule: &str,
     f: F,
 ) -> Result<T, pest::error::Error<Rule>> {
     let mut parsed = RuleParser::parse(Rule::rule, rule)?;
+    check_version(&mut parsed)?;
     let pairs = parsed.next().unwrap().into_inner();
     Ok(f(pairs))
 }
@@ -1145,7 +1499,9 @@ pub fn compile(rule: &str) -> Result<CompiledRule, failure::Error> {
 }
 
 pub fn compile_expr(expr: &str) -> Result<CompiledExpr<Value>, failure::Error> {
-    let compiled = compile_value_expr(RuleParser::parse(Rule::value, expr)?);
+    let mut parsed = RuleParser::parse(Rule::value, expr)?;
+    check_version(&mut parsed)?;
+    let compiled = compile_value_expr(parsed);
     Ok(Box::new(move |cfg, cfgs| match compiled(cfg, cfgs) {
         VarRes::Exactly(v) => v,
         _ => Value::Null,
@@ -1164,6 +1520,63 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_validate_vars() {
+        let spec: ConfigSpec = serde_json::from_value(serde_json::json!({
+            "testnet": {
+                "name": "Testnet",
+                "type": "boolean",
+                "default": false
+            },
+            "peers": {
+                "name": "Peers",
+                "type": "list",
+                "subtype": "number",
+                "spec": {
+                    "type": "number",
+                    "integral": true,
+                    "range": "[0,65535]"
+                },
+                "range": "[0,10]",
+                "default": []
+            },
+            "rpc": {
+                "name": "RPC Settings",
+                "type": "object",
+                "nullable": false,
+                "nullByDefault": false,
+                "spec": {
+                    "port": {
+                        "name": "Port",
+                        "type": "number",
+                        "integral": true,
+                        "nullable": false,
+                        "default": 8332,
+                        "range": "[0,65535]"
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        for rule in &[
+            "testnet? OR #rpc.port = 8332",
+            "#peers.0 < 100 AND #peers.* > 1",
+            "'rpc.port = \"8332\"",
+            "#[other-app].foo.bar = 3",
+        ] {
+            validate_vars(rule, &spec).unwrap();
+        }
+        for rule in &[
+            "mainnet?",
+            "#rpc.host = 3",
+            "#rpc = 3",
+            "#testnet.foo = 1",
+            "#rpc.port.0 = 1",
+        ] {
+            assert!(validate_vars(rule, &spec).is_err(), "{}", rule);
+        }
+    }
+
     #[test]
     fn test_access_expr() {
         let mut cfg = Config::default();
@@ -1230,6 +1643,48 @@ mod test {
             .expect("compile failed"))(&cfg, &cfgs));
     }
 
+    #[test]
+    fn test_functions() {
+        let cfg: Config = serde_yaml::from_str(
+            r#"
+name: satoshi
+port: 8333
+peers: [8333, 18333]
+tags: [a, b]
+"#,
+        )
+        .unwrap();
+        let cfgs = LinearMap::new();
+        for rule in &[
+            "#port % 1000 = 333",
+            "len('name) = 7 AND len('name + \"!\") = 8",
+            "count(peers) = 2 AND count(missing) = 0",
+            "#port IN peers AND \"b\" IN tags",
+            "!(\"c\" IN tags) AND !(#port + 1 IN peers)",
+            "v2: #port IN peers",
+            "v1: #port = 8333",
+        ] {
+            assert!(
+                (compile(rule)
+                    .map_err(|e| eprintln!("{}", e))
+                    .expect("compile failed"))(&cfg, &cfgs),
+                "{}",
+                rule
+            );
+        }
+        assert_eq!(
+            (compile_expr("len('name) * 2").unwrap())(&cfg, &cfgs),
+            Value::Number(14.0)
+        );
+        for rule in &[
+            "v1: #port % 1000 = 333",
+            "v1: count(peers) = 2",
+            "v3: #port = 1",
+        ] {
+            assert!(compile(rule).is_err(), "{}", rule);
+        }
+    }
+
     #[test]
     fn test_app_id() {
         let mut dependent_cfg = Config::default();
@@ -18,6 +18,7 @@ use super::{MatchError, NoMatchWithPath, TimeoutError};
 use crate::config::ConfigurationError;
 use crate::manifest::ManifestLatest;
 use crate::util::PersistencePath;
+use crate::ResultExt as _;
 
 // Config Value Specifications
 #[async_trait]
@@ -220,6 +221,12 @@ pub struct WithDescription<T> {
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub change_warning: Option<String>,
+    /// A rule over the sibling fields, i.e. `proxy?` or `'mode = "proxy"`. Front ends hide the
+    /// field while it does not hold. It is only a hint: hidden fields are still validated and saved.
+    #[serde(default)]
+    #[serde(alias = "visible-if")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub visible_if: Option<String>,
 }
 #[async_trait]
 impl<T> ValueSpec for WithDescription<T>
@@ -284,6 +291,8 @@ pub enum ValueSpecAny {
     Enum(WithDescription<WithDefault<ValueSpecEnum>>),
     List(ValueSpecList),
     Number(WithDescription<WithDefault<WithNullable<ValueSpecNumber>>>),
+    Duration(WithDescription<WithDefault<WithNullable<ValueSpecDuration>>>),
+    Bytes(WithDescription<WithDefault<WithNullable<ValueSpecBytes>>>),
     Object(WithDescription<WithNullable<ValueSpecObject>>),
     String(WithDescription<WithDefault<WithNullable<ValueSpecString>>>),
     Union(WithDescription<WithDefault<ValueSpecUnion>>),
@@ -302,12 +311,54 @@ impl ValueSpecAny {
                 ValueSpecList::Union(u) => u.name.as_str(),
             },
             ValueSpecAny::Number(n) => n.name.as_str(),
+            ValueSpecAny::Duration(d) => d.name.as_str(),
+            ValueSpecAny::Bytes(b) => b.name.as_str(),
             ValueSpecAny::Object(o) => o.name.as_str(),
             ValueSpecAny::Pointer(p) => p.name.as_str(),
             ValueSpecAny::String(s) => s.name.as_str(),
             ValueSpecAny::Union(u) => u.name.as_str(),
         }
     }
+    pub fn visible_if(&self) -> Option<&str> {
+        match self {
+            ValueSpecAny::Boolean(b) => b.visible_if.as_deref(),
+            ValueSpecAny::Enum(e) => e.visible_if.as_deref(),
+            ValueSpecAny::List(l) => match l {
+                ValueSpecList::Enum(e) => e.visible_if.as_deref(),
+                ValueSpecList::Number(n) => n.visible_if.as_deref(),
+                ValueSpecList::Object(o) => o.visible_if.as_deref(),
+                ValueSpecList::String(s) => s.visible_if.as_deref(),
+                ValueSpecList::Union(u) => u.visible_if.as_deref(),
+            },
+            ValueSpecAny::Number(n) => n.visible_if.as_deref(),
+            ValueSpecAny::Duration(d) => d.visible_if.as_deref(),
+            ValueSpecAny::Bytes(b) => b.visible_if.as_deref(),
+            ValueSpecAny::Object(o) => o.visible_if.as_deref(),
+            ValueSpecAny::Pointer(p) => p.visible_if.as_deref(),
+            ValueSpecAny::String(s) => s.visible_if.as_deref(),
+            ValueSpecAny::Union(u) => u.visible_if.as_deref(),
+        }
+    }
+    pub fn description(&self) -> Option<&str> {
+        match self {
+            ValueSpecAny::Boolean(b) => b.description.as_deref(),
+            ValueSpecAny::Enum(e) => e.description.as_deref(),
+            ValueSpecAny::List(l) => match l {
+                ValueSpecList::Enum(e) => e.description.as_deref(),
+                ValueSpecList::Number(n) => n.description.as_deref(),
+                ValueSpecList::Object(o) => o.description.as_deref(),
+                ValueSpecList::String(s) => s.description.as_deref(),
+                ValueSpecList::Union(u) => u.description.as_deref(),
+            },
+            ValueSpecAny::Number(n) => n.description.as_deref(),
+            ValueSpecAny::Duration(d) => d.description.as_deref(),
+            ValueSpecAny::Bytes(b) => b.description.as_deref(),
+            ValueSpecAny::Object(o) => o.description.as_deref(),
+            ValueSpecAny::Pointer(p) => p.description.as_deref(),
+            ValueSpecAny::String(s) => s.description.as_deref(),
+            ValueSpecAny::Union(u) => u.description.as_deref(),
+        }
+    }
 }
 #[async_trait]
 impl ValueSpec for ValueSpecAny {
@@ -317,6 +368,8 @@ impl ValueSpec for ValueSpecAny {
             ValueSpecAny::Enum(a) => a.matches(value),
             ValueSpecAny::List(a) => a.matches(value),
             ValueSpecAny::Number(a) => a.matches(value),
+            ValueSpecAny::Duration(a) => a.matches(value),
+            ValueSpecAny::Bytes(a) => a.matches(value),
             ValueSpecAny::Object(a) => a.matches(value),
             ValueSpecAny::String(a) => a.matches(value),
             ValueSpecAny::Union(a) => a.matches(value),
@@ -329,6 +382,8 @@ impl ValueSpec for ValueSpecAny {
             ValueSpecAny::Enum(a) => a.validate(manifest),
             ValueSpecAny::List(a) => a.validate(manifest),
             ValueSpecAny::Number(a) => a.validate(manifest),
+            ValueSpecAny::Duration(a) => a.validate(manifest),
+            ValueSpecAny::Bytes(a) => a.validate(manifest),
             ValueSpecAny::Object(a) => a.validate(manifest),
             ValueSpecAny::String(a) => a.validate(manifest),
             ValueSpecAny::Union(a) => a.validate(manifest),
@@ -341,6 +396,8 @@ impl ValueSpec for ValueSpecAny {
             ValueSpecAny::Enum(a) => a.update(value).await,
             ValueSpecAny::List(a) => a.update(value).await,
             ValueSpecAny::Number(a) => a.update(value).await,
+            ValueSpecAny::Duration(a) => a.update(value).await,
+            ValueSpecAny::Bytes(a) => a.update(value).await,
             ValueSpecAny::Object(a) => a.update(value).await,
             ValueSpecAny::String(a) => a.update(value).await,
             ValueSpecAny::Union(a) => a.update(value).await,
@@ -353,6 +410,8 @@ impl ValueSpec for ValueSpecAny {
             ValueSpecAny::Enum(a) => a.requires(id, value),
             ValueSpecAny::List(a) => a.requires(id, value),
             ValueSpecAny::Number(a) => a.requires(id, value),
+            ValueSpecAny::Duration(a) => a.requires(id, value),
+            ValueSpecAny::Bytes(a) => a.requires(id, value),
             ValueSpecAny::Object(a) => a.requires(id, value),
             ValueSpecAny::String(a) => a.requires(id, value),
             ValueSpecAny::Union(a) => a.requires(id, value),
@@ -365,6 +424,8 @@ impl ValueSpec for ValueSpecAny {
             ValueSpecAny::Enum(a) => a.eq(lhs, rhs),
             ValueSpecAny::List(a) => a.eq(lhs, rhs),
             ValueSpecAny::Number(a) => a.eq(lhs, rhs),
+            ValueSpecAny::Duration(a) => a.eq(lhs, rhs),
+            ValueSpecAny::Bytes(a) => a.eq(lhs, rhs),
             ValueSpecAny::Object(a) => a.eq(lhs, rhs),
             ValueSpecAny::String(a) => a.eq(lhs, rhs),
             ValueSpecAny::Union(a) => a.eq(lhs, rhs),
@@ -385,6 +446,8 @@ impl Defaultable for ValueSpecAny {
             ValueSpecAny::Enum(a) => a.gen(rng, timeout).map_err(crate::util::absurd),
             ValueSpecAny::List(a) => a.gen(rng, timeout),
             ValueSpecAny::Number(a) => a.gen(rng, timeout).map_err(crate::util::absurd),
+            ValueSpecAny::Duration(a) => a.gen(rng, timeout).map_err(crate::util::absurd),
+            ValueSpecAny::Bytes(a) => a.gen(rng, timeout).map_err(crate::util::absurd),
             ValueSpecAny::Object(a) => a.gen(rng, timeout),
             ValueSpecAny::String(a) => a.gen(rng, timeout).map_err(ConfigurationError::from),
             ValueSpecAny::Union(a) => a.gen(rng, timeout),
@@ -442,6 +505,11 @@ impl DefaultableWith for ValueSpecBoolean {
 pub struct ValueSpecEnum {
     pub values: LinearSet<String>,
     pub value_names: LinearMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub other: Option<EnumOther>,
+    /// Set whenever `other` is, so UIs know to render a combo box rather than a select.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub combobox: bool,
 }
 impl<'de> serde::de::Deserialize<'de> for ValueSpecEnum {
     fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
@@ -451,6 +519,8 @@ impl<'de> serde::de::Deserialize<'de> for ValueSpecEnum {
             pub values: LinearSet<String>,
             #[serde(default)]
             pub value_names: LinearMap<String, String>,
+            #[serde(default)]
+            pub other: Option<EnumOther>,
         }
 
         let mut r#enum = _ValueSpecEnum::deserialize(deserializer)?;
@@ -462,9 +532,28 @@ impl<'de> serde::de::Deserialize<'de> for ValueSpecEnum {
         Ok(ValueSpecEnum {
             values: r#enum.values,
             value_names: r#enum.value_names,
+            combobox: r#enum.other.is_some(),
+            other: r#enum.other,
         })
     }
 }
+
+/// Free-form values an enum accepts besides its fixed choices, i.e. a custom fee estimator URL
+/// alongside the known ones.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnumOther {
+    #[serde(flatten)]
+    pub pattern: Pattern,
+    /// The label of the free-form entry, i.e. `Custom URL`.
+    #[serde(default = "EnumOther::default_name")]
+    pub name: String,
+}
+impl EnumOther {
+    fn default_name() -> String {
+        "Other".to_owned()
+    }
+}
 #[async_trait]
 impl ValueSpec for ValueSpecEnum {
     fn matches(&self, val: &Value) -> Result<(), NoMatchWithPath> {
@@ -472,6 +561,15 @@ impl ValueSpec for ValueSpecEnum {
             Value::String(b) => {
                 if self.values.contains(b) {
                     Ok(())
+                } else if let Some(other) = &self.other {
+                    if other.pattern.pattern.is_match(b) {
+                        Ok(())
+                    } else {
+                        Err(NoMatchWithPath::new(MatchError::Pattern(
+                            b.clone(),
+                            other.pattern.pattern.clone(),
+                        )))
+                    }
                 } else {
                     Err(NoMatchWithPath::new(MatchError::Enum(
                         b.clone(),
@@ -520,6 +618,42 @@ impl DefaultableWith for ValueSpecEnum {
 pub struct ListSpec<T> {
     pub spec: T,
     pub range: NumRange<usize>,
+    /// The fields of the objects in the list that must be unique. Takes precedence over the
+    /// `uniqueBy` of the objects themselves, and names the conflicting fields when violated.
+    #[serde(rename = "uniqueBy", alias = "unique-by")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "UniqueBy::is_not_unique")]
+    pub unique_by: UniqueBy,
+}
+impl<T> ListSpec<T>
+where
+    T: ValueSpec,
+{
+    fn check_unique(&self, l: &[Value], i: usize) -> Result<(), NoMatchWithPath> {
+        let v = &l[i];
+        for (i2, v2) in l.iter().enumerate() {
+            if i == i2 {
+                continue;
+            }
+            match (v, v2) {
+                (Value::Object(lhs), Value::Object(rhs)) if !self.unique_by.is_not_unique() => {
+                    if let Some(keys) = self.unique_by.conflict(lhs, rhs) {
+                        return Err(NoMatchWithPath::new(MatchError::ListUniquenessViolationBy(
+                            keys, i2,
+                        ))
+                        .prepend(format!("{}", i)));
+                    }
+                }
+                _ => {
+                    if self.spec.eq(v, v2) {
+                        return Err(NoMatchWithPath::new(MatchError::ListUniquenessViolation)
+                            .prepend(format!("{}", i)));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
 }
 #[async_trait]
 impl<T> ValueSpec for ListSpec<T>
@@ -542,15 +676,7 @@ where
                             self.spec
                                 .matches(v)
                                 .map_err(|e| e.prepend(format!("{}", i)))?;
-                            if l.iter()
-                                .enumerate()
-                                .any(|(i2, v2)| i != i2 && self.spec.eq(v, v2))
-                            {
-                                Err(NoMatchWithPath::new(MatchError::ListUniquenessViolation)
-                                    .prepend(format!("{}", i)))
-                            } else {
-                                Ok(())
-                            }
+                            self.check_unique(l, i)
                         })
                         .collect()
                 }
@@ -650,11 +776,31 @@ impl ValueSpec for ValueSpecList {
         }
     }
     fn validate(&self, manifest: &ManifestLatest) -> Result<(), NoMatchWithPath> {
+        // only objects have fields to be unique by
+        let unique_by = |unique_by: &UniqueBy, subtype| {
+            if unique_by.is_not_unique() {
+                Ok(())
+            } else {
+                Err(
+                    NoMatchWithPath::new(MatchError::InvalidType("object", subtype))
+                        .prepend("uniqueBy".to_owned()),
+                )
+            }
+        };
         match self {
-            ValueSpecList::Enum(a) => a.validate(manifest),
-            ValueSpecList::Number(a) => a.validate(manifest),
+            ValueSpecList::Enum(a) => {
+                unique_by(&a.inner.inner.unique_by, "enum")?;
+                a.validate(manifest)
+            }
+            ValueSpecList::Number(a) => {
+                unique_by(&a.inner.inner.unique_by, "number")?;
+                a.validate(manifest)
+            }
             ValueSpecList::Object(a) => a.validate(manifest),
-            ValueSpecList::String(a) => a.validate(manifest),
+            ValueSpecList::String(a) => {
+                unique_by(&a.inner.inner.unique_by, "string")?;
+                a.validate(manifest)
+            }
             ValueSpecList::Union(a) => a.validate(manifest),
         }
     }
@@ -731,11 +877,11 @@ impl Defaultable for ValueSpecList {
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct ValueSpecNumber {
-    range: Option<NumRange<f64>>,
+    pub range: Option<NumRange<f64>>,
     #[serde(default)]
-    integral: bool,
+    pub integral: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
-    units: Option<String>,
+    pub units: Option<String>,
 }
 #[async_trait]
 impl ValueSpec for ValueSpecNumber {
@@ -841,6 +987,190 @@ impl DefaultableWith for ValueSpecNumber {
     }
 }
 
+pub trait Unit: Clone + Copy + Debug + Send + Sync {
+    const NAME: &'static str;
+    // multipliers to the base unit, keyed by lowercase suffix
+    const UNITS: &'static [(&'static str, f64)];
+
+    /// Parses strings like "1h30m" or "512MB" into a number of base units. A bare number is
+    /// taken to already be in base units.
+    fn parse(s: &str) -> Option<f64> {
+        let s = s.trim();
+        if let Ok(n) = s.parse::<f64>() {
+            return Some(n);
+        }
+        let mut res = 0.0;
+        let mut rest = s;
+        while !rest.is_empty() {
+            let num_len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let num: f64 = rest[..num_len].parse().ok()?;
+            rest = rest[num_len..].trim_start();
+            let unit_len = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            let unit = rest[..unit_len].to_lowercase();
+            rest = rest[unit_len..].trim_start();
+            let (_, mult) = Self::UNITS.iter().find(|(u, _)| *u == unit)?;
+            res += num * mult;
+        }
+        Some(res)
+    }
+}
+
+/// Durations are stored in seconds.
+#[derive(Clone, Copy, Debug)]
+pub struct DurationUnit;
+impl Unit for DurationUnit {
+    const NAME: &'static str = "duration";
+    const UNITS: &'static [(&'static str, f64)] = &[
+        ("ms", 0.001),
+        ("s", 1.0),
+        ("m", 60.0),
+        ("h", 3600.0),
+        ("d", 86400.0),
+        ("w", 604800.0),
+    ];
+}
+
+/// Byte sizes are stored in bytes.
+#[derive(Clone, Copy, Debug)]
+pub struct BytesUnit;
+impl Unit for BytesUnit {
+    const NAME: &'static str = "byte size";
+    const UNITS: &'static [(&'static str, f64)] = &[
+        ("b", 1.0),
+        ("kb", 1e3),
+        ("mb", 1e6),
+        ("gb", 1e9),
+        ("tb", 1e12),
+        ("kib", 1024.0),
+        ("mib", 1048576.0),
+        ("gib", 1073741824.0),
+        ("tib", 1099511627776.0),
+    ];
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Quantity<U>(pub f64, std::marker::PhantomData<U>);
+impl<'de, U: Unit> serde::de::Deserialize<'de> for Quantity<U> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::de::Deserializer<'de>,
+    {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum QuantityIF {
+            Number(Number),
+            String(String),
+        }
+        match QuantityIF::deserialize(deserializer)? {
+            QuantityIF::Number(n) => Ok(Quantity(n.0, std::marker::PhantomData)),
+            QuantityIF::String(s) => U::parse(&s)
+                .map(|n| Quantity(n, std::marker::PhantomData))
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid {}: {:?}", U::NAME, s))),
+        }
+    }
+}
+impl<U> serde::ser::Serialize for Quantity<U> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::ser::Serializer,
+    {
+        serde::ser::Serialize::serialize(&Value::Number(self.0), serializer)
+    }
+}
+
+/// A number with units, which can be given either in base units or as a human friendly string
+/// such as "30s" or "512MB". Strings are normalized to base units on update.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(bound = "")]
+pub struct ValueSpecQuantity<U: Unit> {
+    #[serde(default)]
+    min: Option<Quantity<U>>,
+    #[serde(default)]
+    max: Option<Quantity<U>>,
+}
+pub type ValueSpecDuration = ValueSpecQuantity<DurationUnit>;
+pub type ValueSpecBytes = ValueSpecQuantity<BytesUnit>;
+impl<U: Unit> ValueSpecQuantity<U> {
+    fn range(&self) -> NumRange<f64> {
+        use std::ops::Bound;
+        NumRange((
+            self.min
+                .map(|q| Bound::Included(q.0))
+                .unwrap_or(Bound::Unbounded),
+            self.max
+                .map(|q| Bound::Included(q.0))
+                .unwrap_or(Bound::Unbounded),
+        ))
+    }
+    fn to_base(&self, value: &Value) -> Result<f64, NoMatchWithPath> {
+        match value {
+            Value::Number(n) => Ok(*n),
+            Value::String(s) => U::parse(s).ok_or_else(|| {
+                NoMatchWithPath::new(MatchError::InvalidQuantity(s.clone(), U::NAME))
+            }),
+            Value::Null => Err(NoMatchWithPath::new(MatchError::NotNullable)),
+            a => Err(NoMatchWithPath::new(MatchError::InvalidType(
+                U::NAME,
+                a.type_of(),
+            ))),
+        }
+    }
+}
+#[async_trait]
+impl<U: Unit> ValueSpec for ValueSpecQuantity<U> {
+    fn matches(&self, value: &Value) -> Result<(), NoMatchWithPath> {
+        let n = self.to_base(value)?;
+        let range = self.range();
+        if n < 0.0 || !range.contains(&n) {
+            return Err(NoMatchWithPath::new(MatchError::OutOfRange(range, n)));
+        }
+        Ok(())
+    }
+    fn validate(&self, _manifest: &ManifestLatest) -> Result<(), NoMatchWithPath> {
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min.0 > max.0 {
+                return Err(NoMatchWithPath::new(MatchError::OutOfRange(
+                    self.range(),
+                    min.0,
+                )));
+            }
+        }
+        Ok(())
+    }
+    async fn update(&self, value: &mut Value) -> Result<(), ConfigurationError> {
+        if let Value::String(_) = value {
+            *value = Value::Number(self.to_base(value)?);
+        }
+        Ok(())
+    }
+    fn requires(&self, _id: &str, _value: &Value) -> bool {
+        false
+    }
+    fn eq(&self, lhs: &Value, rhs: &Value) -> bool {
+        match (self.to_base(lhs), self.to_base(rhs)) {
+            (Ok(lhs), Ok(rhs)) => lhs == rhs,
+            _ => false,
+        }
+    }
+}
+impl<U: Unit> DefaultableWith for ValueSpecQuantity<U> {
+    type DefaultSpec = Option<Quantity<U>>;
+    type Error = crate::util::Never;
+
+    fn gen_with<R: Rng + CryptoRng + Sync + Send>(
+        &self,
+        spec: &Self::DefaultSpec,
+        _rng: &mut R,
+        _timeout: &Option<Duration>,
+    ) -> Result<Value, Self::Error> {
+        Ok(spec.map(|s| Value::Number(s.0)).unwrap_or(Value::Null))
+    }
+}
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ValueSpecObject {
@@ -937,6 +1267,38 @@ impl ConfigSpec {
         Ok(())
     }
 
+    /// Like `matches`, but carries on past the first error. Descends into objects and the
+    /// selected variant of unions, so each of their fields is reported on its own.
+    pub fn matches_all(&self, value: &Config) -> Vec<NoMatchWithPath> {
+        let mut res = Vec::new();
+        for (key, val) in self.0.iter() {
+            let v = value.0.get(key).unwrap_or(&Value::Null);
+            let errors = match (val, v) {
+                (ValueSpecAny::Object(o), Value::Object(obj)) => {
+                    o.inner.inner.spec.matches_all(obj)
+                }
+                (ValueSpecAny::Union(u), Value::Object(obj)) => {
+                    let u = &u.inner.inner;
+                    let variant = match obj.0.get(&u.tag.id) {
+                        Some(Value::String(tag)) => u.variants.get(tag),
+                        _ => None,
+                    };
+                    match variant {
+                        Some(variant) => {
+                            let mut without_tag = obj.clone();
+                            without_tag.0.remove(&u.tag.id);
+                            variant.matches_all(&without_tag)
+                        }
+                        None => val.matches(v).err().into_iter().collect(),
+                    }
+                }
+                _ => val.matches(v).err().into_iter().collect(),
+            };
+            res.extend(errors.into_iter().map(|e| e.prepend(key.clone())));
+        }
+        res
+    }
+
     pub fn gen<R: Rng + CryptoRng + Sync + Send>(
         &self,
         rng: &mut R,
@@ -946,7 +1308,51 @@ impl ConfigSpec {
         for (key, val) in self.0.iter() {
             res.insert(key.clone(), val.gen(rng, timeout)?);
         }
-        Ok(Config(res))
+        let mut res = Config(res);
+        self.derive(&mut res)?;
+        Ok(res)
+    }
+
+    /// Fills in the fields of a freshly generated config that have a derived default, which
+    /// `gen` of the field alone leaves null. Does not descend, since objects derive their own.
+    pub fn derive(&self, cfg: &mut Config) -> Result<(), ConfigurationError> {
+        let mut key = None;
+        for (name, val) in self.0.iter() {
+            let (from, len) = match val {
+                ValueSpecAny::String(s) => match &s.inner.default {
+                    Some(DefaultString::Generator(Generator::Derived { from, len })) => {
+                        (from, *len)
+                    }
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            if !matches!(cfg.0.get(name), None | Some(Value::Null)) {
+                continue;
+            }
+            let input = match cfg.0.get(from) {
+                Some(Value::String(s)) => s.clone(),
+                Some(Value::Number(n)) => n.to_string(),
+                _ => {
+                    return Err(ConfigurationError::NoMatch(
+                        NoMatchWithPath::new(MatchError::InvalidDerivation(format!(
+                            "{} is not a string",
+                            from
+                        )))
+                        .prepend(name.clone()),
+                    ))
+                }
+            };
+            if key.is_none() {
+                key = Some(
+                    crate::encryption::derivation_key().map_err(ConfigurationError::SystemError)?,
+                );
+            }
+            let value = hmac_hex(key.as_ref().unwrap(), &input, len)
+                .map_err(ConfigurationError::SystemError)?;
+            cfg.0.insert(name.clone(), Value::String(value));
+        }
+        Ok(())
     }
 
     pub fn validate(&self, manifest: &ManifestLatest) -> Result<(), NoMatchWithPath> {
@@ -956,6 +1362,29 @@ impl ConfigSpec {
                     name.to_owned(),
                 )));
             }
+            if let Some(rule) = val.visible_if() {
+                super::rules::compile(rule)
+                    .and_then(|_| super::rules::validate_vars(rule, self))
+                    .map_err(|e| {
+                        NoMatchWithPath::new(MatchError::InvalidVisibleIf(format!("{}", e)))
+                            .prepend(name.clone())
+                    })?;
+            }
+            if let ValueSpecAny::String(s) = val {
+                if let Some(DefaultString::Generator(Generator::Derived { from, .. })) =
+                    &s.inner.default
+                {
+                    match self.0.get(from) {
+                        Some(ValueSpecAny::String(_)) | Some(ValueSpecAny::Number(_)) => (),
+                        _ => {
+                            return Err(NoMatchWithPath::new(MatchError::InvalidDerivation(
+                                format!("{} is not a string or number field", from),
+                            ))
+                            .prepend(name.clone()))
+                        }
+                    }
+                }
+            }
             val.validate(manifest)
                 .map_err(|e| e.prepend(name.clone()))?;
         }
@@ -981,6 +1410,127 @@ impl ConfigSpec {
             .iter()
             .any(|(k, v)| v.requires(id, cfg.0.get(k).unwrap_or(&STATIC_NULL)))
     }
+
+    /// Dotted paths of every masked string in the spec. The fields of all union variants are
+    /// included, and list elements share the path of their list.
+    pub fn masked_paths(&self) -> LinearSet<String> {
+        fn masked_paths_rec(spec: &ConfigSpec, prefix: &str, res: &mut LinearSet<String>) {
+            for (key, val) in spec.0.iter() {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                match val {
+                    ValueSpecAny::String(s) if s.inner.inner.inner.masked => {
+                        res.insert(path);
+                    }
+                    ValueSpecAny::List(ValueSpecList::String(l)) if l.inner.inner.spec.masked => {
+                        res.insert(path);
+                    }
+                    ValueSpecAny::Object(o) => masked_paths_rec(&o.inner.inner.spec, &path, res),
+                    ValueSpecAny::List(ValueSpecList::Object(l)) => {
+                        masked_paths_rec(&l.inner.inner.spec.spec, &path, res)
+                    }
+                    ValueSpecAny::Union(u) => {
+                        for variant in u.inner.inner.variants.values() {
+                            masked_paths_rec(variant, &path, res)
+                        }
+                    }
+                    ValueSpecAny::List(ValueSpecList::Union(l)) => {
+                        for variant in l.inner.inner.spec.inner.variants.values() {
+                            masked_paths_rec(variant, &path, res)
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let mut res = LinearSet::new();
+        masked_paths_rec(self, "", &mut res);
+        res
+    }
+
+    /// Dotted paths of every masked string with a generated default, i.e. passwords. Only fields
+    /// that `get_path` can reach are included, so each can be regenerated on its own.
+    pub fn secret_paths(&self) -> LinearSet<String> {
+        fn secret_paths_rec(spec: &ConfigSpec, prefix: &str, res: &mut LinearSet<String>) {
+            for (key, val) in spec.0.iter() {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                match val {
+                    ValueSpecAny::String(s)
+                        if s.inner.inner.inner.masked
+                            && matches!(s.inner.default, Some(DefaultString::Entropy(_))) =>
+                    {
+                        res.insert(path);
+                    }
+                    ValueSpecAny::Object(o) => secret_paths_rec(&o.inner.inner.spec, &path, res),
+                    _ => (),
+                }
+            }
+        }
+        let mut res = LinearSet::new();
+        secret_paths_rec(self, "", &mut res);
+        res
+    }
+
+    /// The spec of the field at a dotted path. Only descends into objects, since the fields of
+    /// lists and unions do not have a single spec.
+    pub fn get_path(&self, path: &str) -> Option<&ValueSpecAny> {
+        let mut keys = path.split('.').peekable();
+        let mut spec = self;
+        while let Some(key) = keys.next() {
+            let val = spec.0.get(key)?;
+            if keys.peek().is_none() {
+                return Some(val);
+            }
+            spec = match val {
+                ValueSpecAny::Object(o) => &o.inner.inner.spec,
+                _ => return None,
+            };
+        }
+        None
+    }
+
+    /// Dotted paths of every pointer in the spec, with the same conventions as `masked_paths`.
+    pub fn pointer_paths(&self) -> LinearSet<String> {
+        fn pointer_paths_rec(spec: &ConfigSpec, prefix: &str, res: &mut LinearSet<String>) {
+            for (key, val) in spec.0.iter() {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                match val {
+                    ValueSpecAny::Pointer(_) => {
+                        res.insert(path);
+                    }
+                    ValueSpecAny::Object(o) => pointer_paths_rec(&o.inner.inner.spec, &path, res),
+                    ValueSpecAny::List(ValueSpecList::Object(l)) => {
+                        pointer_paths_rec(&l.inner.inner.spec.spec, &path, res)
+                    }
+                    ValueSpecAny::Union(u) => {
+                        for variant in u.inner.inner.variants.values() {
+                            pointer_paths_rec(variant, &path, res)
+                        }
+                    }
+                    ValueSpecAny::List(ValueSpecList::Union(l)) => {
+                        for variant in l.inner.inner.spec.inner.variants.values() {
+                            pointer_paths_rec(variant, &path, res)
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let mut res = LinearSet::new();
+        pointer_paths_rec(self, "", &mut res);
+        res
+    }
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -1054,7 +1604,11 @@ impl DefaultableWith for ValueSpecString {
         if let Some(spec) = spec {
             let now = timeout.as_ref().map(|_| std::time::Instant::now());
             loop {
-                let candidate = spec.gen(rng);
+                let candidate = match spec.gen(rng) {
+                    Some(a) => a,
+                    // filled in by `ConfigSpec::derive` once the field it derives from exists
+                    None => return Ok(Value::Null),
+                };
                 match (spec, &self.pattern) {
                     (DefaultString::Entropy(_), Some(pattern))
                         if !pattern.pattern.is_match(&candidate) =>
@@ -1081,13 +1635,17 @@ impl DefaultableWith for ValueSpecString {
 #[serde(untagged)]
 pub enum DefaultString {
     Literal(String),
+    // before `Entropy`, which would otherwise accept a generator that has a `len`
+    Generator(Generator),
     Entropy(Entropy),
 }
 impl DefaultString {
-    pub fn gen<R: Rng + CryptoRng + Sync + Send>(&self, rng: &mut R) -> String {
+    /// `None` for a derived default, which depends on another field.
+    pub fn gen<R: Rng + CryptoRng + Sync + Send>(&self, rng: &mut R) -> Option<String> {
         match self {
-            DefaultString::Literal(s) => s.clone(),
-            DefaultString::Entropy(e) => e.gen(rng),
+            DefaultString::Literal(s) => Some(s.clone()),
+            DefaultString::Generator(g) => g.gen(rng),
+            DefaultString::Entropy(e) => Some(e.gen(rng)),
         }
     }
 }
@@ -1095,11 +1653,14 @@ impl DefaultString {
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Entropy {
     pub charset: Option<CharSet>,
-    pub len: usize,
+    pub len: EntropyLen,
 }
 impl Entropy {
     pub fn gen<R: Rng + CryptoRng + Sync + Send>(&self, rng: &mut R) -> String {
-        let len = self.len;
+        let len = match self.len {
+            EntropyLen::Exact(len) => len,
+            EntropyLen::Range { min, max } => rng.gen_range(min, max.max(min) + 1),
+        };
         let set = self
             .charset
             .as_ref()
@@ -1109,6 +1670,79 @@ impl Entropy {
     }
 }
 
+/// The length of a generated string: exact, or picked uniformly from an inclusive range.
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum EntropyLen {
+    Exact(usize),
+    Range { min: usize, max: usize },
+}
+
+/// Defaults that are not drawn from a charset.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "generator")]
+#[serde(rename_all = "kebab-case")]
+pub enum Generator {
+    /// A random (v4) UUID, i.e. `8c1e0b4a-5f0e-4c3b-9a51-0d6f4e2b7c19`.
+    Uuid,
+    /// Seconds since the epoch, at the time the default is generated.
+    Timestamp,
+    /// The hex HMAC-SHA256, under a key derived from the device, of the sibling field `from`,
+    /// truncated to `len` characters. Stable on a device, and different on every other.
+    Derived {
+        from: String,
+        #[serde(default)]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        len: Option<usize>,
+    },
+}
+impl Generator {
+    /// `None` for `Derived`.
+    pub fn gen<R: Rng + CryptoRng + Sync + Send>(&self, rng: &mut R) -> Option<String> {
+        match self {
+            Generator::Uuid => {
+                let mut bytes: [u8; 16] = rng.gen();
+                bytes[6] = (bytes[6] & 0x0f) | 0x40;
+                bytes[8] = (bytes[8] & 0x3f) | 0x80;
+                let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                Some(format!(
+                    "{}-{}-{}-{}-{}",
+                    &hex[0..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..32]
+                ))
+            }
+            Generator::Timestamp => Some(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+                    .to_string(),
+            ),
+            Generator::Derived { .. } => None,
+        }
+    }
+}
+
+fn hmac_hex(key: &[u8], input: &str, len: Option<usize>) -> Result<String, crate::Error> {
+    let key = openssl::pkey::PKey::hmac(key).no_code()?;
+    let mut signer =
+        openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key).no_code()?;
+    signer.update(input.as_bytes()).no_code()?;
+    let mut res: String = signer
+        .sign_to_vec()
+        .no_code()?
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    if let Some(len) = len {
+        res.truncate(len);
+    }
+    Ok(res)
+}
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UnionTag {
@@ -1529,11 +2163,19 @@ impl serde::ser::Serialize for ConfigPointer {
     }
 }
 
+/// Properties of the device itself, resolved on every configure.
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
 #[serde(tag = "target")]
 pub enum SystemPointerSpec {
+    /// The address of the host on the docker network.
     HostIp,
+    /// The tor address of the device, the one its own UI is served on.
+    TorAddress,
+    /// The `.local` hostname of the device.
+    LanAddress,
+    /// The id of the device, i.e. `start9-1a2b3c4d`.
+    ServerId,
 }
 impl fmt::Display for SystemPointerSpec {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -1542,6 +2184,9 @@ impl fmt::Display for SystemPointerSpec {
             "[SYSTEM].{}",
             match self {
                 SystemPointerSpec::HostIp => "HOST_IP",
+                SystemPointerSpec::TorAddress => "TOR_ADDRESS",
+                SystemPointerSpec::LanAddress => "LAN_ADDRESS",
+                SystemPointerSpec::ServerId => "SERVER_ID",
             }
         )
     }
@@ -1552,6 +2197,22 @@ impl SystemPointerSpec {
             SystemPointerSpec::HostIp => {
                 Value::String(format!("{}", std::net::Ipv4Addr::from(crate::HOST_IP)))
             }
+            SystemPointerSpec::TorAddress => Value::String(
+                crate::tor::read_hidden_service_hostname("agent", None)
+                    .await
+                    .map_err(ConfigurationError::SystemError)?,
+            ),
+            SystemPointerSpec::LanAddress => Value::String(format!(
+                "{}.local",
+                crate::identity::server_id()
+                    .await
+                    .map_err(ConfigurationError::SystemError)?
+            )),
+            SystemPointerSpec::ServerId => Value::String(
+                crate::identity::server_id()
+                    .await
+                    .map_err(ConfigurationError::SystemError)?,
+            ),
         })
     }
 }
@@ -1838,6 +2499,8 @@ mod test {
                 description: None,
                 mount_public: false,
                 mount_shared: false,
+                match_mode: false,
+                min_sync: None,
                 optional: Some("Could be external.".to_owned()),
                 config: Vec::new(),
             },
@@ -1858,15 +2521,30 @@ mod test {
             public: None,
             shared: None,
             has_instructions: false,
+            instructions_format: Default::default(),
+            system: false,
+            modes: None,
             os_version_required: ">=0.2.5".parse().unwrap(),
             os_version_recommended: ">=0.2.5".parse().unwrap(),
             assets: Vec::new(),
             hidden_service_version: crate::tor::HiddenServiceVersion::V3,
             dependencies: deps,
+            actions: Vec::new(),
+            launch: Vec::new(),
+            hooks: Default::default(),
+            env_map: LinearMap::new(),
+            config_format: Default::default(),
+            plaintext_config: false,
+            install_prompts: None,
+            config_migrations: Vec::new(),
+            health_checks: LinearMap::new(),
+            provenance: Default::default(),
+            requirements: Default::default(),
             extra: LinearMap::new(),
             install_alert: None,
             restore_alert: None,
             uninstall_alert: None,
+            start_alert: None,
         })
         .unwrap();
         let config = spec
@@ -1874,4 +2552,302 @@ mod test {
             .unwrap();
         spec.matches(&config).unwrap();
     }
+
+    #[test]
+    fn test_system_pointer() {
+        let spec: ValueSpecAny = serde_json::from_value(serde_json::json!({
+            "name": "Device Hostname",
+            "type": "pointer",
+            "subtype": "system",
+            "target": "lan-address",
+            "description": null
+        }))
+        .unwrap();
+        match spec {
+            ValueSpecAny::Pointer(p) => assert_eq!(format!("{}", p.inner), "[SYSTEM].LAN_ADDRESS"),
+            _ => panic!("not a pointer"),
+        }
+    }
+
+    #[test]
+    fn test_enum_other() {
+        let spec: ValueSpecAny = serde_json::from_value(serde_json::json!({
+            "name": "Fee Estimator",
+            "type": "enum",
+            "values": ["mempool", "bitcoind"],
+            "other": {
+                "name": "Custom URL",
+                "pattern": "^https?://.+$",
+                "patternDescription": "Must be an http(s) URL"
+            },
+            "default": "mempool"
+        }))
+        .unwrap();
+        assert!(spec.matches(&Value::String("bitcoind".to_owned())).is_ok());
+        assert!(spec
+            .matches(&Value::String("https://fees.example.com".to_owned()))
+            .is_ok());
+        assert!(spec.matches(&Value::String("fees".to_owned())).is_err());
+        let json = serde_json::to_value(&spec).unwrap();
+        assert_eq!(json["combobox"], serde_json::json!(true));
+        assert_eq!(json["other"]["name"], serde_json::json!("Custom URL"));
+    }
+
+    #[test]
+    fn test_generators() {
+        let spec: ConfigSpec = serde_json::from_str(
+            r#"{
+                "id": {
+                    "name": "Node ID",
+                    "type": "string",
+                    "nullable": false,
+                    "default": { "generator": "uuid" }
+                },
+                "password": {
+                    "name": "Password",
+                    "type": "string",
+                    "nullable": false,
+                    "masked": true,
+                    "default": { "charset": "a-z", "len": { "min": 12, "max": 16 } }
+                },
+                "macaroon": {
+                    "name": "Macaroon Key",
+                    "type": "string",
+                    "nullable": false,
+                    "default": { "generator": "derived", "from": "password", "len": 32 }
+                }
+            }"#,
+        )
+        .unwrap();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(2);
+        let id = spec.0.get("id").unwrap().gen(&mut rng, &None).unwrap();
+        match id {
+            Value::String(id) => {
+                assert_eq!(id.len(), 36);
+                assert_eq!(&id[14..15], "4");
+            }
+            a => panic!("not a string: {:?}", a),
+        }
+        for _ in 0..20 {
+            match spec
+                .0
+                .get("password")
+                .unwrap()
+                .gen(&mut rng, &None)
+                .unwrap()
+            {
+                Value::String(p) => assert!(p.len() >= 12 && p.len() <= 16),
+                a => panic!("not a string: {:?}", a),
+            }
+        }
+        assert_eq!(
+            spec.0
+                .get("macaroon")
+                .unwrap()
+                .gen(&mut rng, &None)
+                .unwrap(),
+            Value::Null
+        );
+        assert_eq!(
+            hmac_hex(b"Jefe", "what do ya want for nothing?", Some(16)).unwrap(),
+            "5bdcc146bf60754e"
+        );
+    }
+
+    #[test]
+    fn test_secret_paths() {
+        let spec: ConfigSpec = serde_json::from_str(
+            r#"{
+                "rpc": {
+                    "name": "RPC Settings",
+                    "type": "object",
+                    "nullable": false,
+                    "nullByDefault": false,
+                    "spec": {
+                        "username": {
+                            "name": "RPC Username",
+                            "type": "string",
+                            "nullable": false,
+                            "masked": true,
+                            "default": "bitcoin"
+                        },
+                        "password": {
+                            "name": "RPC Password",
+                            "type": "string",
+                            "nullable": false,
+                            "masked": true,
+                            "default": {
+                                "charset": "a-z,A-Z,2-9",
+                                "len": 20
+                            }
+                        }
+                    }
+                },
+                "seed": {
+                    "name": "Seed",
+                    "type": "string",
+                    "nullable": false,
+                    "default": {
+                        "len": 32
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        let paths = spec.secret_paths();
+        assert_eq!(paths.len(), 1);
+        assert!(paths.contains("rpc.password"));
+    }
+
+    #[test]
+    fn test_quantity() {
+        assert_eq!(DurationUnit::parse("30s"), Some(30.0));
+        assert_eq!(DurationUnit::parse("1h30m"), Some(5400.0));
+        assert_eq!(DurationUnit::parse("250ms"), Some(0.25));
+        assert_eq!(DurationUnit::parse("90"), Some(90.0));
+        assert_eq!(DurationUnit::parse("3 fortnights"), None);
+        assert_eq!(BytesUnit::parse("512MB"), Some(512e6));
+        assert_eq!(BytesUnit::parse("2 GiB"), Some(2147483648.0));
+        assert_eq!(BytesUnit::parse("MB"), None);
+        let spec: ValueSpecDuration =
+            serde_json::from_value(serde_json::json!({ "min": "1s", "max": "1h" })).unwrap();
+        spec.matches(&Value::String("30m".to_owned())).unwrap();
+        spec.matches(&Value::Number(60.0)).unwrap();
+        assert!(spec.matches(&Value::String("2h".to_owned())).is_err());
+        assert!(spec.eq(&Value::String("1m".to_owned()), &Value::Number(60.0)));
+    }
+
+    #[test]
+    fn test_unique_by() {
+        let spec: ValueSpecList = serde_json::from_value(serde_json::json!({
+            "subtype": "object",
+            "description": null,
+            "name": "Peers",
+            "range": "[0,10]",
+            "default": [],
+            "uniqueBy": ["name", "addr.port"],
+            "spec": {
+                "spec": {
+                    "name": {
+                        "type": "string",
+                        "name": "Name",
+                        "nullable": false,
+                        "default": "peer"
+                    },
+                    "addr": {
+                        "type": "object",
+                        "name": "Address",
+                        "nullable": false,
+                        "spec": {
+                            "port": {
+                                "type": "number",
+                                "name": "Port",
+                                "nullable": false,
+                                "integral": true,
+                                "range": "[0,65535]",
+                                "default": 8333
+                            }
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let peers = |ports: &[(&str, u16)]| {
+            serde_json::from_value::<Value>(serde_json::Value::Array(
+                ports
+                    .iter()
+                    .map(|(name, port)| {
+                        serde_json::json!({ "name": name, "addr": { "port": port } })
+                    })
+                    .collect(),
+            ))
+            .unwrap()
+        };
+        spec.matches(&peers(&[("a", 8333), ("b", 8334)])).unwrap();
+        let err = spec
+            .matches(&peers(&[("a", 8333), ("b", 8333)]))
+            .unwrap_err();
+        assert_eq!(err.path, vec!["0".to_owned()]);
+        match err.error {
+            MatchError::ListUniquenessViolationBy(keys, idx) => {
+                assert_eq!(keys, "addr.port");
+                assert_eq!(idx, 1);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_spec_violations() {
+        let spec: ConfigSpec = serde_json::from_value(serde_json::json!({
+            "rpc": {
+                "name": "RPC",
+                "type": "object",
+                "nullable": false,
+                "spec": {
+                    "user": {
+                        "name": "User",
+                        "type": "string",
+                        "nullable": false,
+                        "default": "bitcoin"
+                    }
+                }
+            },
+            "pruning": {
+                "name": "Pruning",
+                "type": "boolean",
+                "default": false
+            }
+        }))
+        .unwrap();
+        let config: Config = serde_yaml::from_str("rpc:\n  user: 1\npruning: null\n").unwrap();
+        let err = crate::config::SpecViolations::check(&spec, &config).unwrap_err();
+        // the order of the fields of a spec built with json! is up to serde_json
+        let sorted = |v: serde_json::Value| {
+            v.as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<std::collections::BTreeSet<_>>()
+        };
+        assert_eq!(
+            sorted(serde_json::to_value(&err).unwrap()),
+            sorted(serde_json::json!([
+                { "path": ["rpc", "user"], "error": "Invalid Type: expected string, actual: number" },
+                { "path": ["pruning"], "error": "Field Is Not Nullable" },
+            ]))
+        );
+        let config: Config =
+            serde_yaml::from_str("rpc:\n  user: bitcoin\npruning: true\n").unwrap();
+        crate::config::SpecViolations::check(&spec, &config).unwrap();
+    }
+
+    #[test]
+    fn test_visible_if() {
+        let manifest: crate::manifest::ManifestV0 = serde_yaml::from_str("id: test-app\nversion: 0.1.0\ntitle: Test App\ndescription:\n  short: A test app.\n  long: A super cool test app for testing\nrelease-notes: Some things changed\nports: []\nimage:\n  type: tar\nmount: /root\n").unwrap();
+        let mut hidden: ConfigSpec = serde_json::from_value(serde_json::json!({
+            "proxy": {
+                "name": "Proxy",
+                "type": "boolean",
+                "default": false
+            },
+            "proxyAddress": {
+                "name": "Proxy Address",
+                "type": "string",
+                "nullable": true,
+                "visibleIf": "proxy?"
+            }
+        }))
+        .unwrap();
+        hidden.validate(&manifest).unwrap();
+        assert_eq!(
+            serde_json::to_value(&hidden).unwrap()["proxyAddress"]["visibleIf"],
+            "proxy?"
+        );
+        if let Some(ValueSpecAny::String(s)) = hidden.0.get_mut("proxyAddress") {
+            s.visible_if = Some("proxies?".to_owned());
+        }
+        assert!(hidden.validate(&manifest).is_err());
+    }
 }
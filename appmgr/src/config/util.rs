@@ -4,7 +4,7 @@ use std::ops::RangeInclusive;
 
 use rand::{distributions::Distribution, Rng};
 
-use super::value::Config;
+use super::value::{Config, Value};
 
 pub const STATIC_NULL: super::value::Value = super::value::Value::Null;
 
@@ -277,13 +277,49 @@ pub enum UniqueBy {
     Exactly(String),
     NotUnique,
 }
+// follows a dotted path into nested objects
+pub(crate) fn get_path<'a>(config: &'a Config, path: &str) -> Option<&'a Value> {
+    let mut keys = path.split('.');
+    let mut value = config.0.get(keys.next()?)?;
+    for key in keys {
+        value = match value {
+            Value::Object(o) => o.0.get(key)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
 impl UniqueBy {
     pub fn eq(&self, lhs: &Config, rhs: &Config) -> bool {
+        self.conflict(lhs, rhs).is_some()
+    }
+
+    /// The fields `lhs` and `rhs` conflict on, if they are not unique. Keys may be dotted paths
+    /// into nested objects.
+    pub fn conflict(&self, lhs: &Config, rhs: &Config) -> Option<String> {
+        match self {
+            UniqueBy::Any(any) => any.iter().find_map(|u| u.conflict(lhs, rhs)),
+            UniqueBy::All(all) => all
+                .iter()
+                .map(|u| u.conflict(lhs, rhs))
+                .collect::<Option<Vec<_>>>()
+                .map(|keys| keys.join(" and ")),
+            UniqueBy::Exactly(key) => {
+                if get_path(lhs, key) == get_path(rhs, key) {
+                    Some(key.clone())
+                } else {
+                    None
+                }
+            }
+            UniqueBy::NotUnique => None,
+        }
+    }
+
+    pub fn is_not_unique(&self) -> bool {
         match self {
-            UniqueBy::Any(any) => any.iter().any(|u| u.eq(lhs, rhs)),
-            UniqueBy::All(all) => all.iter().all(|u| u.eq(lhs, rhs)),
-            UniqueBy::Exactly(key) => lhs.0.get(key) == rhs.0.get(key),
-            UniqueBy::NotUnique => false,
+            UniqueBy::NotUnique => true,
+            _ => false,
         }
     }
 }
@@ -298,7 +334,21 @@ impl<'de> serde::de::Deserialize<'de> for UniqueBy {
         impl<'de> serde::de::Visitor<'de> for Visitor {
             type Value = UniqueBy;
             fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-                write!(formatter, "a key, an \"any\" object, or an \"all\" object")
+                write!(
+                    formatter,
+                    "a key, a list of keys, an \"any\" object, or an \"all\" object"
+                )
+            }
+            // every key on its own
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut any = Vec::new();
+                while let Some(elem) = seq.next_element()? {
+                    any.push(elem);
+                }
+                Ok(UniqueBy::Any(any))
             }
             fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
                 Ok(UniqueBy::Exactly(v.to_owned()))
@@ -0,0 +1,112 @@
+use std::borrow::Cow;
+
+use linear_map::LinearMap;
+
+use super::{Config, ConfigRuleEntry, ConfigSpec};
+use crate::Error;
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ValidationReport {
+    pub spec_errors: Vec<String>,
+    pub rule_violations: Vec<String>,
+}
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.spec_errors.is_empty() && self.rule_violations.is_empty()
+    }
+}
+
+/// Checks `config` against `spec` and every rule, reporting each failure rather than the first.
+/// Pointers are resolved first if the config matches, since rules may read them.
+pub async fn check(
+    spec: &ConfigSpec,
+    rules: &[ConfigRuleEntry],
+    id: &str,
+    mut config: Config,
+) -> ValidationReport {
+    let mut spec_errors: Vec<String> = spec
+        .matches_all(&config)
+        .into_iter()
+        .map(|e| format!("{}", e))
+        .collect();
+    if spec_errors.is_empty() {
+        if let Err(e) = spec.update(&mut config).await {
+            spec_errors.push(format!("{}", e));
+        }
+    }
+    let mut cfgs = LinearMap::new();
+    cfgs.insert(id, Cow::Borrowed(&config));
+    let rule_violations = rules
+        .iter()
+        .filter_map(|rule| rule.check(&config, &cfgs).err())
+        .map(|e| format!("{}", e))
+        .collect();
+    ValidationReport {
+        spec_errors,
+        rule_violations,
+    }
+}
+
+/// Validates a candidate config for an installed app, without resolving its dependents or
+/// writing anything.
+pub async fn validate(id: &str, config: Config) -> Result<ValidationReport, Error> {
+    let app_config = crate::apps::config(id).await?;
+    Ok(check(&app_config.spec, &app_config.rules, id, config).await)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check() {
+        let spec: ConfigSpec = serde_json::from_value(serde_json::json!({
+            "rpc": {
+                "name": "RPC",
+                "type": "object",
+                "nullable": false,
+                "spec": {
+                    "user": {
+                        "name": "User",
+                        "type": "string",
+                        "nullable": false,
+                        "default": "bitcoin"
+                    },
+                    "port": {
+                        "name": "Port",
+                        "type": "number",
+                        "nullable": false,
+                        "range": "[1,65535]",
+                        "integral": true,
+                        "default": 8332
+                    }
+                }
+            },
+            "pruning": {
+                "name": "Pruning",
+                "type": "boolean",
+                "default": false
+            }
+        }))
+        .unwrap();
+        let rules: Vec<ConfigRuleEntry> =
+            serde_yaml::from_str("- rule: \"pruning?\"\n  description: Pruning must be enabled\n")
+                .unwrap();
+        let config: Config =
+            serde_yaml::from_str("rpc:\n  user: null\n  port: 0.5\npruning: 1\n").unwrap();
+        let report = check(&spec, &rules, "bitcoind", config).await;
+        // the order of the fields of a spec built with json! is up to serde_json
+        let mut paths: Vec<&str> = report
+            .spec_errors
+            .iter()
+            .map(|e| e.split(": ").next().unwrap())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["pruning", "rpc.port", "rpc.user"]);
+        assert_eq!(report.rule_violations, vec!["Pruning must be enabled"]);
+        let config: Config =
+            serde_yaml::from_str("rpc:\n  user: bitcoin\n  port: 8332\npruning: true\n").unwrap();
+        assert!(check(&spec, &rules, "bitcoind", config).await.is_ok());
+    }
+}
@@ -1,6 +1,10 @@
-use linear_map::LinearMap;
+use linear_map::{set::LinearSet, LinearMap};
+
+/// What masked config values are replaced with in output.
+pub const MASK: &'static str = "********";
 
 #[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
 pub struct Config(pub LinearMap<String, Value>);
 
 impl Config {
@@ -28,6 +32,123 @@ impl Config {
             }
         }
     }
+
+    /// Lists the keys that are added, removed, or changed going from `self` to `new`.
+    /// Objects are compared key by key, everything else is compared as a whole.
+    pub fn diff(&self, new: &Config) -> Vec<ConfigChange> {
+        fn diff_rec(prefix: &str, old: &Config, new: &Config, res: &mut Vec<ConfigChange>) {
+            let path = |key: &str| {
+                if prefix.is_empty() {
+                    key.to_owned()
+                } else {
+                    format!("{}.{}", prefix, key)
+                }
+            };
+            for (key, old_val) in old.0.iter() {
+                match (old_val, new.0.get(key)) {
+                    (_, None) => res.push(ConfigChange::Removed {
+                        path: path(key),
+                        value: old_val.clone(),
+                    }),
+                    (Value::Object(old_obj), Some(Value::Object(new_obj))) => {
+                        diff_rec(&path(key), old_obj, new_obj, res)
+                    }
+                    (_, Some(new_val)) if old_val != new_val => res.push(ConfigChange::Changed {
+                        path: path(key),
+                        old: old_val.clone(),
+                        new: new_val.clone(),
+                    }),
+                    _ => (),
+                }
+            }
+            for (key, new_val) in new.0.iter() {
+                if !old.0.contains_key(key) {
+                    res.push(ConfigChange::Added {
+                        path: path(key),
+                        value: new_val.clone(),
+                    })
+                }
+            }
+        }
+        let mut res = Vec::new();
+        diff_rec("", self, new, &mut res);
+        res
+    }
+
+    /// Replaces the values at the dotted paths in `masked` (see `ConfigSpec::masked_paths`).
+    pub fn redact(&mut self, masked: &LinearSet<String>) {
+        for path in masked {
+            let path: Vec<&str> = path.split('.').collect();
+            if let Some(val) = self.0.get_mut(path[0]) {
+                val.redact(&path[1..]);
+            }
+        }
+    }
+
+    /// Puts back the values from `old` wherever a masked path still holds the mask, so a
+    /// redacted config can be submitted without overwriting the secrets in it.
+    pub fn unmask(&mut self, old: &Config, masked: &LinearSet<String>) {
+        for path in masked {
+            let path: Vec<&str> = path.split('.').collect();
+            if let (Some(val), Some(old_val)) = (self.0.get_mut(path[0]), old.0.get(path[0])) {
+                val.unmask(old_val, &path[1..]);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(tag = "op")]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfigChange {
+    Added {
+        path: String,
+        value: Value,
+    },
+    Removed {
+        path: String,
+        value: Value,
+    },
+    Changed {
+        path: String,
+        old: Value,
+        new: Value,
+    },
+}
+impl ConfigChange {
+    pub fn path(&self) -> &str {
+        match self {
+            ConfigChange::Added { path, .. } => path,
+            ConfigChange::Removed { path, .. } => path,
+            ConfigChange::Changed { path, .. } => path,
+        }
+    }
+
+    /// Replaces the values at the dotted paths in `masked`, whether the change is to a masked
+    /// value itself or to an object containing one.
+    pub fn redact(&mut self, masked: &LinearSet<String>) {
+        for masked_path in masked {
+            let rest: Vec<&str> = if masked_path == self.path() {
+                Vec::new()
+            } else if let Some(rest) = masked_path
+                .strip_prefix(self.path())
+                .and_then(|rest| rest.strip_prefix('.'))
+            {
+                rest.split('.').collect()
+            } else {
+                continue;
+            };
+            match self {
+                ConfigChange::Added { value, .. } | ConfigChange::Removed { value, .. } => {
+                    value.redact(&rest)
+                }
+                ConfigChange::Changed { old, new, .. } => {
+                    old.redact(&rest);
+                    new.redact(&rest);
+                }
+            }
+        }
+    }
 }
 
 fn serialize_num<S: serde::Serializer>(num: &f64, serializer: S) -> Result<S::Ok, S::Error> {
@@ -63,4 +184,154 @@ impl Value {
             Value::Null => "null",
         }
     }
+
+    /// Masks the strings at `path`, relative to this value. Lists are redacted element by
+    /// element.
+    pub fn redact(&mut self, path: &[&str]) {
+        match (self, path.split_first()) {
+            (Value::List(l), _) => {
+                for val in l {
+                    val.redact(path);
+                }
+            }
+            (Value::String(s), None) => *s = MASK.to_owned(),
+            (Value::Object(o), Some((key, rest))) => {
+                if let Some(val) = o.0.get_mut(*key) {
+                    val.redact(rest);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Reverses `redact` using `old`. Lists are matched up element by element.
+    pub fn unmask(&mut self, old: &Value, path: &[&str]) {
+        match (self, old, path.split_first()) {
+            (Value::List(l), Value::List(old_l), _) => {
+                for (val, old_val) in l.iter_mut().zip(old_l) {
+                    val.unmask(old_val, path);
+                }
+            }
+            (Value::String(s), Value::String(old_s), None) if *s == MASK => *s = old_s.clone(),
+            (Value::Object(o), Value::Object(old_o), Some((key, rest))) => {
+                if let (Some(val), Some(old_val)) = (o.0.get_mut(*key), old_o.0.get(*key)) {
+                    val.unmask(old_val, rest);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old: Config = serde_yaml::from_str(
+            r#"
+rpc:
+  enable: true
+  username: alice
+  password: hunter2
+pruning: disabled
+peers: []
+"#,
+        )
+        .unwrap();
+        let new: Config = serde_yaml::from_str(
+            r#"
+rpc:
+  enable: true
+  username: bob
+pruning: disabled
+peers: ["abc.onion"]
+zmq: true
+"#,
+        )
+        .unwrap();
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            vec![
+                ConfigChange::Changed {
+                    path: "rpc.username".to_owned(),
+                    old: Value::String("alice".to_owned()),
+                    new: Value::String("bob".to_owned()),
+                },
+                ConfigChange::Removed {
+                    path: "rpc.password".to_owned(),
+                    value: Value::String("hunter2".to_owned()),
+                },
+                ConfigChange::Changed {
+                    path: "peers".to_owned(),
+                    old: Value::List(vec![]),
+                    new: Value::List(vec![Value::String("abc.onion".to_owned())]),
+                },
+                ConfigChange::Added {
+                    path: "zmq".to_owned(),
+                    value: Value::Bool(true),
+                },
+            ]
+        );
+        assert!(new.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut config: Config = serde_yaml::from_str(
+            r#"
+rpc:
+  username: alice
+  password: hunter2
+peers:
+  - address: abc.onion
+    password: swordfish
+"#,
+        )
+        .unwrap();
+        let masked: LinearSet<String> =
+            vec!["rpc.password".to_owned(), "peers.password".to_owned()]
+                .into_iter()
+                .collect();
+        let mut change = ConfigChange::Added {
+            path: "rpc".to_owned(),
+            value: config.0.get("rpc").unwrap().clone(),
+        };
+        config.redact(&masked);
+        change.redact(&masked);
+        let expected: Config = serde_yaml::from_str(
+            r#"
+rpc:
+  username: alice
+  password: "********"
+peers:
+  - address: abc.onion
+    password: "********"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config, expected);
+        assert_eq!(
+            change,
+            ConfigChange::Added {
+                path: "rpc".to_owned(),
+                value: expected.0.get("rpc").unwrap().clone(),
+            }
+        );
+        let old: Config = serde_yaml::from_str(
+            r#"
+rpc:
+  username: alice
+  password: hunter2
+peers:
+  - address: abc.onion
+    password: swordfish
+"#,
+        )
+        .unwrap();
+        config.unmask(&old, &masked);
+        assert_eq!(config, old);
+    }
 }
@@ -0,0 +1,72 @@
+use linear_map::{set::LinearSet, LinearMap};
+
+use super::{Config, ConfigSpec, ConfigurationRes};
+use crate::util::{PersistencePath, YamlUpdateHandle};
+use crate::Error;
+
+pub const POINTERS_YAML: &'static str = "pointers.yaml";
+
+/// Maps each app to the apps whose committed configs contain pointers to it.
+pub type PointerEdges = LinearMap<String, LinearSet<String>>;
+
+async fn edges_mut() -> Result<YamlUpdateHandle<PointerEdges>, Error> {
+    YamlUpdateHandle::new_or_default(PersistencePath::from_ref(POINTERS_YAML)).await
+}
+
+pub async fn edges() -> Result<PointerEdges, Error> {
+    let path = PersistencePath::from_ref(POINTERS_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await,
+        None => Ok(LinearMap::new()),
+    }
+}
+
+/// Replaces the pointer edges of `name` with the ones present in its newly committed config.
+pub async fn record(name: &str, spec: &ConfigSpec, config: &Config) -> Result<(), Error> {
+    let installed = crate::apps::list_info().await?;
+    let mut edges = edges_mut().await?;
+    for (_, watchers) in edges.iter_mut() {
+        watchers.remove(name);
+    }
+    for (target, _) in installed {
+        if target != name && spec.requires(&target, config) {
+            edges
+                .entry(target)
+                .or_insert_with(LinearSet::new)
+                .insert(name.to_owned());
+        }
+    }
+    edges.retain(|_, watchers| !watchers.is_empty());
+    edges.commit().await
+}
+
+/// Removes an app from the registry, both as a target and as a watcher.
+pub async fn forget(name: &str) -> Result<(), Error> {
+    let mut edges = edges_mut().await?;
+    edges.remove(name);
+    for (_, watchers) in edges.iter_mut() {
+        watchers.remove(name);
+    }
+    edges.retain(|_, watchers| !watchers.is_empty());
+    edges.commit().await
+}
+
+pub async fn watchers(target: &str) -> Result<LinearSet<String>, Error> {
+    Ok(edges().await?.remove(target).unwrap_or_default())
+}
+
+/// Re-resolves the configs of every app pointing at `target`, i.e. after its tor address or key
+/// changed outside of `configure`. Apps whose configs resolve to the same values are left alone.
+pub async fn notify(target: &str) -> Result<ConfigurationRes, Error> {
+    let mut res = ConfigurationRes::default();
+    for watcher in watchers(target).await? {
+        log::info!("Reconfiguring {} for changes to {}.", watcher, target);
+        let watcher_res = super::configure(&watcher, None, None, false).await?;
+        res.changed.extend(watcher_res.changed);
+        res.diffs.extend(watcher_res.diffs);
+        res.needs_restart.extend(watcher_res.needs_restart);
+        res.stopped.extend(watcher_res.stopped);
+        res.provenance.extend(watcher_res.provenance);
+    }
+    Ok(res)
+}
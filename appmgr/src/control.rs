@@ -3,9 +3,11 @@ use std::path::Path;
 use futures::future::{BoxFuture, FutureExt};
 use linear_map::{set::LinearSet, LinearMap};
 
+use crate::backend::Backend;
 use crate::dependencies::{DependencyError, TaggedDependencyError};
 use crate::util::{from_yaml_async_reader, PersistencePath, YamlUpdateHandle};
 use crate::Error;
+use crate::ResultExt as _;
 
 pub async fn start_app(name: &str, update_metadata: bool) -> Result<(), Error> {
     let lock = crate::util::lock_file(
@@ -26,24 +28,18 @@ pub async fn start_app(name: &str, update_metadata: bool) -> Result<(), Error> {
             crate::config::configure(name, None, None, false).await?;
             crate::dependencies::update_binds(name).await?;
         }
+        sync_env(name).await?;
         crate::apps::set_needs_restart(name, false).await?;
         let mut running = YamlUpdateHandle::<LinearSet<String>>::new_or_default(
             PersistencePath::from_ref("running.yaml"),
         )
         .await?;
-        let output = tokio::process::Command::new("docker")
-            .args(&["start", name])
-            .stdout(std::process::Stdio::null())
-            .output()
-            .await?;
-        crate::ensure_code!(
-            output.status.success(),
-            crate::error::DOCKER_ERROR,
-            "Failed to Start Application: {}",
-            std::str::from_utf8(&output.stderr).unwrap_or("Unknown Error")
-        );
+        Backend::of_app(name).start(name).await?;
         running.insert(name.to_owned());
         running.commit().await?;
+        crate::mqtt::app_status(name, crate::apps::DockerStatus::Running).await;
+        crate::events::emit(crate::events::EventKind::Started, name).await;
+        crate::hooks::post_install(name).await?;
     } else if status == crate::apps::DockerStatus::Paused {
         resume_app(name).await?;
     }
@@ -51,6 +47,73 @@ pub async fn start_app(name: &str, update_metadata: bool) -> Result<(), Error> {
     Ok(())
 }
 
+/// Recreates the stopped container of `name` if its environment differs from the one rendered
+/// from its config, since docker only sets it at creation. The unit of a static app reads it at
+/// every start instead.
+async fn sync_env(name: &str) -> Result<(), Error> {
+    let manifest = crate::apps::manifest(name).await?;
+    if manifest.env_map.is_empty() || Backend::of_app(name) == Backend::Systemd {
+        return Ok(());
+    }
+    let output = tokio::process::Command::new("docker")
+        .args(&["inspect", name, "--format", "{{json .}}"])
+        .output()
+        .await?;
+    crate::ensure_code!(
+        output.status.success(),
+        crate::error::DOCKER_ERROR,
+        "{}: Docker Error: {}",
+        name,
+        std::str::from_utf8(&output.stderr).unwrap_or("Unknown Error")
+    );
+    let info: serde_json::Value =
+        serde_json::from_slice(&output.stdout).with_code(crate::error::SERDE_ERROR)?;
+    let current: LinearSet<String> = info["Config"]["Env"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|a| a.as_str())
+        .map(|a| a.to_owned())
+        .collect();
+    // everything not set from the config, i.e. the tor address, is kept
+    let mut wanted: LinearSet<String> = current
+        .iter()
+        .filter(|a| {
+            let var = a.split('=').next().unwrap_or_default();
+            !manifest.env_map.contains_key(var)
+        })
+        .cloned()
+        .collect();
+    wanted.extend(crate::config::env::read(name).await?);
+    if wanted == current {
+        return Ok(());
+    }
+    let image = info["Config"]["Image"]
+        .as_str()
+        .ok_or_else(|| failure::format_err!("{}: Container Has No Image", name))
+        .with_code(crate::error::DOCKER_ERROR)?;
+    let ip: std::net::Ipv4Addr = info["NetworkSettings"]["Networks"]["start9"]["IPAMConfig"]
+        ["IPv4Address"]
+        .as_str()
+        .and_then(|a| a.parse().ok())
+        .ok_or_else(|| failure::format_err!("{}: Container Has No IP Address", name))
+        .with_code(crate::error::DOCKER_ERROR)?;
+    log::info!("Recreating docker container {} with new environment.", name);
+    let output = tokio::process::Command::new("docker")
+        .args(&["rm", name])
+        .stdout(std::process::Stdio::null())
+        .output()
+        .await?;
+    crate::ensure_code!(
+        output.status.success(),
+        crate::error::DOCKER_ERROR,
+        "Failed to Remove Container: {}",
+        std::str::from_utf8(&output.stderr).unwrap_or("Unknown Error")
+    );
+    let env: Vec<String> = wanted.into_iter().collect();
+    crate::install::create_container(&manifest, image, ip, &env)
+}
+
 pub async fn stop_app(
     name: &str,
     cascade: bool,
@@ -78,19 +141,11 @@ pub async fn stop_app(
         )
         .await?;
         log::info!("Stopping {}", name);
-        let output = tokio::process::Command::new("docker")
-            .args(&["stop", "-t", "25", name])
-            .stdout(std::process::Stdio::null())
-            .output()
-            .await?;
-        crate::ensure_code!(
-            output.status.success(),
-            crate::error::DOCKER_ERROR,
-            "Failed to Stop Application: {}",
-            std::str::from_utf8(&output.stderr).unwrap_or("Unknown Error")
-        );
+        Backend::of_app(name).stop(name).await?;
         running.remove(name);
         running.commit().await?;
+        crate::mqtt::app_status(name, crate::apps::DockerStatus::Stopped).await;
+        crate::events::emit(crate::events::EventKind::Stopped, name).await;
         crate::util::unlock(lock).await?;
     }
     Ok(res)
@@ -161,17 +216,8 @@ pub async fn pause_app(name: &str) -> Result<(), Error> {
         true,
     )
     .await?;
-    let output = tokio::process::Command::new("docker")
-        .args(&["pause", name])
-        .stdout(std::process::Stdio::null())
-        .output()
-        .await?;
-    crate::ensure_code!(
-        output.status.success(),
-        crate::error::DOCKER_ERROR,
-        "Failed to Pause Application: {}",
-        std::str::from_utf8(&output.stderr).unwrap_or("Unknown Error")
-    );
+    Backend::of_app(name).pause(name).await?;
+    crate::mqtt::app_status(name, crate::apps::DockerStatus::Paused).await;
 
     crate::util::unlock(lock).await?;
     Ok(())
@@ -190,17 +236,8 @@ pub async fn resume_app(name: &str) -> Result<(), Error> {
         true,
     )
     .await?;
-    let output = tokio::process::Command::new("docker")
-        .args(&["unpause", name])
-        .stdout(std::process::Stdio::null())
-        .output()
-        .await?;
-    crate::ensure_code!(
-        output.status.success(),
-        crate::error::DOCKER_ERROR,
-        "Failed to Resume Application: {}",
-        std::str::from_utf8(&output.stderr).unwrap_or("Unknown Error")
-    );
+    Backend::of_app(name).resume(name).await?;
+    crate::mqtt::app_status(name, crate::apps::DockerStatus::Running).await;
     crate::util::unlock(lock).await?;
     Ok(())
 }
@@ -210,11 +247,14 @@ pub async fn repair_app_status() -> Result<(), Error> {
         .maybe_read(false)
         .await
         .transpose()?;
-    let running: Vec<String> = if let Some(f) = running_file.as_mut() {
+    let mut running: Vec<String> = if let Some(f) = running_file.as_mut() {
         from_yaml_async_reader::<_, &mut tokio::fs::File>(f).await?
     } else {
         Vec::new()
     };
+    // system packages first, the rest of the stack may rely on them
+    let info = crate::apps::list_info().await?;
+    running.sort_by_key(|name| !info.get(name).map(|i| i.system).unwrap_or(false));
     for name in running {
         let lock = crate::util::lock_file(
             format!(
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use failure::ResultExt as _;
+use openssl::sha::{sha256, Sha256};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::Error;
+use crate::ResultExt as _;
+
+/// A delta is this, the sha256 of the package it applies to and of the one it produces, then
+/// ops until the end of the file: `0` with an offset and a length to copy from the old package,
+/// or `1` with a length and the bytes to insert. Numbers are big endian u64s.
+pub const MAGIC: &'static [u8; 8] = b"s9pkdlt1";
+const COPY: u8 = 0;
+const INSERT: u8 = 1;
+// small enough that a changed file inside an image costs little, large enough that the index of
+// a large package stays small
+const BLOCK_LEN: usize = 16 * 1024;
+// bytes that match nothing are written out in inserts of at most this, so that they are never
+// all held at once
+const MAX_INSERT: usize = 64 * BLOCK_LEN;
+
+/// The rsync rolling checksum of a window, which can be moved along by a byte at a time.
+struct Rolling {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+impl Rolling {
+    fn new(window: &[u8]) -> Self {
+        let mut res = Rolling {
+            a: 0,
+            b: 0,
+            len: window.len() as u32,
+        };
+        for (i, byte) in window.iter().enumerate() {
+            res.a = res.a.wrapping_add(*byte as u32);
+            res.b = res.b.wrapping_add((window.len() - i) as u32 * *byte as u32);
+        }
+        res
+    }
+    fn roll(&mut self, out: u8, into: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(into as u32);
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(out as u32))
+            .wrapping_add(self.a);
+    }
+    fn digest(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+/// The blocks of the old package by their rolling checksum, each with its sha256 to rule out
+/// collisions.
+struct Index(HashMap<u32, Vec<(u64, [u8; 32])>>);
+impl Index {
+    /// Reads `from` a block at a time, and also returns the sha256 of all of it.
+    async fn build<R: AsyncRead + Unpin>(mut from: R) -> Result<(Self, [u8; 32]), Error> {
+        let mut blocks: HashMap<u32, Vec<(u64, [u8; 32])>> = HashMap::new();
+        let mut hasher = Sha256::new();
+        let mut block = vec![0; BLOCK_LEN];
+        let mut offset = 0;
+        loop {
+            let mut n = 0;
+            while n < BLOCK_LEN {
+                match from.read(&mut block[n..]).await? {
+                    0 => break,
+                    read => n += read,
+                }
+            }
+            hasher.update(&block[..n]);
+            if n < BLOCK_LEN {
+                break;
+            }
+            blocks
+                .entry(Rolling::new(&block).digest())
+                .or_default()
+                .push((offset, sha256(&block)));
+            offset += BLOCK_LEN as u64;
+        }
+        Ok((Index(blocks), hasher.finish()))
+    }
+
+    fn find(&self, digest: u32, window: &[u8]) -> Option<u64> {
+        let candidates = self.0.get(&digest)?;
+        let hash = sha256(window);
+        candidates
+            .iter()
+            .find(|(_, h)| *h == hash)
+            .map(|(offset, _)| *offset)
+    }
+}
+
+/// Writes ops in the delta format, merging copies that pick up where the last one left off.
+struct OpWriter<W> {
+    out: W,
+    len: u64,
+    copy: Option<(u64, u64)>,
+}
+impl<W: AsyncWrite + Unpin> OpWriter<W> {
+    fn new(out: W) -> Self {
+        OpWriter {
+            out,
+            len: 0,
+            copy: None,
+        }
+    }
+
+    async fn copy(&mut self, offset: u64, len: u64) -> Result<(), Error> {
+        if let Some((o, l)) = &mut self.copy {
+            if *o + *l == offset {
+                *l += len;
+                return Ok(());
+            }
+        }
+        self.write_copy().await?;
+        self.copy = Some((offset, len));
+        Ok(())
+    }
+
+    async fn insert(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        self.write_copy().await?;
+        self.out.write_u8(INSERT).await?;
+        self.out.write_u64(data.len() as u64).await?;
+        self.out.write_all(data).await?;
+        self.len += 9 + data.len() as u64;
+        Ok(())
+    }
+
+    async fn write_copy(&mut self) -> Result<(), Error> {
+        if let Some((offset, len)) = self.copy.take() {
+            self.out.write_u8(COPY).await?;
+            self.out.write_u64(offset).await?;
+            self.out.write_u64(len).await?;
+            self.len += 17;
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self) -> Result<(W, u64), Error> {
+        self.write_copy().await?;
+        self.out.flush().await?;
+        Ok((self.out, self.len))
+    }
+}
+
+/// Writes the ops that build `to` out of the blocks in `index`, and returns the sha256 of `to`.
+/// `to` is scanned for them at every offset, so data that only moved is still copied.
+async fn diff<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    index: &Index,
+    mut to: R,
+    ops: &mut OpWriter<W>,
+) -> Result<[u8; 32], Error> {
+    let mut hasher = Sha256::new();
+    let mut chunk = vec![0; 64 * 1024];
+    // what has been read of `to` but not written: bytes that matched nothing, then the window at
+    // `pos`
+    let mut buf = Vec::new();
+    let mut pos = 0;
+    let mut eof = false;
+    let mut rolling: Option<Rolling> = None;
+    loop {
+        // the window, and the byte after it to roll in
+        while !eof && buf.len() <= pos + BLOCK_LEN {
+            let n = to.read(&mut chunk).await?;
+            hasher.update(&chunk[..n]);
+            buf.extend_from_slice(&chunk[..n]);
+            eof = n == 0;
+        }
+        if buf.len() < pos + BLOCK_LEN {
+            break;
+        }
+        let window = rolling.get_or_insert_with(|| Rolling::new(&buf[pos..pos + BLOCK_LEN]));
+        if let Some(offset) = index.find(window.digest(), &buf[pos..pos + BLOCK_LEN]) {
+            ops.insert(&buf[..pos]).await?;
+            ops.copy(offset, BLOCK_LEN as u64).await?;
+            buf.drain(..pos + BLOCK_LEN);
+            pos = 0;
+            rolling = None;
+        } else if buf.len() > pos + BLOCK_LEN {
+            window.roll(buf[pos], buf[pos + BLOCK_LEN]);
+            pos += 1;
+            if pos == MAX_INSERT {
+                ops.insert(&buf[..pos]).await?;
+                buf.drain(..pos);
+                pos = 0;
+            }
+        } else {
+            break;
+        }
+    }
+    ops.insert(&buf).await?;
+    Ok(hasher.finish())
+}
+
+/// Writes a delta from the package at `from` to the one at `to`, and returns its length. Only
+/// the index of `from` is held in memory, not either package.
+pub async fn create<P0: AsRef<Path>, P1: AsRef<Path>, P2: AsRef<Path>>(
+    from: P0,
+    to: P1,
+    output: P2,
+) -> Result<u64, Error> {
+    let open = |path: &Path| {
+        let path = path.to_owned();
+        async move {
+            tokio::fs::File::open(&path)
+                .await
+                .with_context(|e| format!("{}: {}", path.display(), e))
+                .with_code(crate::error::FILESYSTEM_ERROR)
+        }
+    };
+    let (index, from_hash) =
+        Index::build(tokio::io::BufReader::new(open(from.as_ref()).await?)).await?;
+    let to = open(to.as_ref()).await?;
+    let output = output.as_ref();
+    let f = tokio::fs::File::create(output)
+        .await
+        .with_context(|e| format!("{}: {}", output.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let mut out = tokio::io::BufWriter::new(f);
+    out.write_all(MAGIC).await?;
+    // the checksum of `to` is only known once it has been read: both are filled in at the end
+    out.write_all(&[0; 64]).await?;
+    let mut ops = OpWriter::new(out);
+    let to_hash = diff(&index, to, &mut ops).await?;
+    let (out, len) = ops.finish().await?;
+    let mut f = out.into_inner();
+    f.seek(SeekFrom::Start(MAGIC.len() as u64)).await?;
+    f.write_all(&from_hash).await?;
+    f.write_all(&to_hash).await?;
+    f.flush().await?;
+    Ok((MAGIC.len() + 64) as u64 + len)
+}
+
+/// Rebuilds a package from the one at `from` and the delta at `delta`, into `output`. Fails if
+/// the delta was made against a different package, or if what it produces is not what it was
+/// made from.
+pub async fn apply<P0: AsRef<Path>, P1: AsRef<Path>, P2: AsRef<Path>>(
+    from: P0,
+    delta: P1,
+    output: P2,
+) -> Result<(), Error> {
+    let (from, delta, output) = (from.as_ref(), delta.as_ref(), output.as_ref());
+    let f = tokio::fs::File::open(delta)
+        .await
+        .with_context(|e| format!("{}: {}", delta.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let mut delta_reader = tokio::io::BufReader::new(f);
+    let mut magic = [0; 8];
+    let mut from_hash = [0; 32];
+    let mut to_hash = [0; 32];
+    delta_reader.read_exact(&mut magic).await?;
+    crate::ensure_code!(
+        &magic == MAGIC,
+        crate::error::GENERAL_ERROR,
+        "{} Is Not A Delta",
+        delta.display()
+    );
+    delta_reader.read_exact(&mut from_hash).await?;
+    delta_reader.read_exact(&mut to_hash).await?;
+    let mut from_file = tokio::fs::File::open(from)
+        .await
+        .with_context(|e| format!("{}: {}", from.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    crate::ensure_code!(
+        crate::checksums::hash(&mut from_file).await? == from_hash,
+        crate::error::VERSION_INCOMPATIBLE,
+        "Delta Does Not Apply To {}",
+        from.display()
+    );
+    let f = tokio::fs::File::create(output)
+        .await
+        .with_context(|e| format!("{}: {}", output.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let mut out = tokio::io::BufWriter::new(f);
+    let mut op = [0];
+    while delta_reader.read(&mut op).await? > 0 {
+        let (len, copied) = match op[0] {
+            COPY => {
+                let offset = delta_reader.read_u64().await?;
+                let len = delta_reader.read_u64().await?;
+                from_file.seek(SeekFrom::Start(offset)).await?;
+                let copied = tokio::io::copy(&mut (&mut from_file).take(len), &mut out).await?;
+                (len, copied)
+            }
+            INSERT => {
+                let len = delta_reader.read_u64().await?;
+                let copied = tokio::io::copy(&mut (&mut delta_reader).take(len), &mut out).await?;
+                (len, copied)
+            }
+            a => {
+                return Err(failure::format_err!(
+                    "Delta Invalid or Corrupted: Unknown Op {}",
+                    a
+                ))
+                .with_code(crate::error::GENERAL_ERROR)
+            }
+        };
+        crate::ensure_code!(
+            copied == len,
+            crate::error::GENERAL_ERROR,
+            "Delta Invalid or Corrupted: Unexpected End"
+        );
+    }
+    out.flush().await?;
+    drop(out);
+    let f = tokio::fs::File::open(output).await?;
+    if crate::checksums::hash(f).await? != to_hash {
+        tokio::fs::remove_file(output).await?;
+        return Err(failure::format_err!(
+            "Package Rebuilt From Delta Does Not Match Its Checksum"
+        ))
+        .with_code(crate::error::GENERAL_ERROR);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Eq)]
+    enum Op {
+        Copy { offset: u64, len: u64 },
+        Insert { len: u64 },
+    }
+
+    async fn delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+        let (index, _) = Index::build(from).await.unwrap();
+        let mut ops = OpWriter::new(Vec::new());
+        diff(&index, to, &mut ops).await.unwrap();
+        ops.finish().await.unwrap().0
+    }
+
+    fn num(delta: &mut &[u8]) -> u64 {
+        let (n, rest) = delta.split_at(8);
+        *delta = rest;
+        u64::from_be_bytes(std::convert::TryInto::try_into(n).unwrap())
+    }
+
+    // the ops in `delta`, and what they build out of `from`
+    fn patch(from: &[u8], mut delta: &[u8]) -> (Vec<Op>, Vec<u8>) {
+        let mut ops = Vec::new();
+        let mut res = Vec::new();
+        while let Some((op, rest)) = delta.split_first() {
+            delta = rest;
+            match *op {
+                COPY => {
+                    let offset = num(&mut delta);
+                    let len = num(&mut delta);
+                    res.extend_from_slice(&from[offset as usize..(offset + len) as usize]);
+                    ops.push(Op::Copy { offset, len });
+                }
+                INSERT => {
+                    let len = num(&mut delta);
+                    let (data, rest) = delta.split_at(len as usize);
+                    delta = rest;
+                    res.extend_from_slice(data);
+                    ops.push(Op::Insert { len });
+                }
+                op => panic!("unknown op {}", op),
+            }
+        }
+        (ops, res)
+    }
+
+    #[tokio::test]
+    async fn test_diff() {
+        let from: Vec<u8> = (0..BLOCK_LEN * 4).map(|i| (i * 7 % 251) as u8).collect();
+        // a header that grew, an unchanged block that moved, and a changed tail
+        let mut to = b"new header".to_vec();
+        to.extend_from_slice(&from[BLOCK_LEN..BLOCK_LEN * 3]);
+        to.extend_from_slice(&from[..BLOCK_LEN]);
+        to.extend_from_slice(b"new tail");
+        let (ops, res) = patch(&from, &delta(&from, &to).await);
+        assert_eq!(res, to);
+        let block = BLOCK_LEN as u64;
+        assert_eq!(
+            ops,
+            vec![
+                Op::Insert { len: 10 },
+                Op::Copy {
+                    offset: block,
+                    len: block * 2
+                },
+                Op::Copy {
+                    offset: 0,
+                    len: block
+                },
+                Op::Insert { len: 8 },
+            ]
+        );
+        assert_eq!(
+            patch(&from, &delta(&from, b"short").await),
+            (vec![Op::Insert { len: 5 }], b"short".to_vec())
+        );
+        // nothing in common: split up rather than held in one insert
+        let to: Vec<u8> = (0..MAX_INSERT + BLOCK_LEN + 5)
+            .map(|i| (i * 13 % 241) as u8)
+            .collect();
+        let (ops, res) = patch(&from, &delta(&from, &to).await);
+        assert_eq!(res, to);
+        assert_eq!(
+            ops,
+            vec![
+                Op::Insert {
+                    len: MAX_INSERT as u64
+                },
+                Op::Insert {
+                    len: BLOCK_LEN as u64 + 5
+                },
+            ]
+        );
+    }
+}
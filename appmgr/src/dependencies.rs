@@ -11,7 +11,7 @@ use crate::Error;
 use crate::ResultExt as _;
 
 #[derive(Clone, Debug, Fail, serde::Serialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(into = "DependencyErrorRepr")]
 pub enum DependencyError {
     NotInstalled, // "not-installed"
     NotRunning,   // "not-running"
@@ -19,8 +19,17 @@ pub enum DependencyError {
         expected: VersionRange,
         received: Version,
     }, // { "incorrect-version": { "expected": "0.1.0", "received": "^0.2.0" } }
+    ModeMismatch {
+        expected: String,
+        received: String,
+    }, // { "other": "Mode Mismatch: Expected testnet, Received mainnet" }
     ConfigUnsatisfied(Vec<String>), // { "config-unsatisfied": ["Bitcoin Core must have pruning set to manual."] }
     PointerUpdateError(String), // { "pointer-update-error": "Bitcoin Core RPC Port must not be 18332" }
+    Syncing {
+        progress: f64,
+        required: f64,
+        eta: Option<u64>,
+    }, // { "other": "Waiting For Sync: 72% Synced, About 1h 0m Left" }
     Other(String),              // { "other": "Well fuck." }
 }
 impl std::fmt::Display for DependencyError {
@@ -34,15 +43,68 @@ impl std::fmt::Display for DependencyError {
                 "Incorrect Version: Expected {}, Received {}",
                 expected, received
             ),
+            ModeMismatch { expected, received } => write!(
+                f,
+                "Mode Mismatch: Expected {}, Received {}",
+                expected, received
+            ),
             ConfigUnsatisfied(rules) => {
                 write!(f, "Configuration Rule(s) Violated: {}", rules.join(", "))
             }
             PointerUpdateError(e) => write!(f, "Pointer Update Caused {}", e),
+            Syncing {
+                progress,
+                required,
+                eta,
+            } => {
+                write!(f, "Waiting For Sync: {:.0}% Synced", progress.floor())?;
+                if *required < 100.0 {
+                    write!(f, ", Needs {:.0}%", required)?;
+                }
+                if let Some(eta) = eta {
+                    write!(f, ", About {}h {}m Left", eta / 3600, eta % 3600 / 60)?;
+                }
+                Ok(())
+            }
             Other(e) => write!(f, "System Error: {}", e),
         }
     }
 }
 
+/// The shape of `DependencyError` the agent parses. Errors it has no shape for are sent as `other`.
+#[derive(serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum DependencyErrorRepr {
+    NotInstalled,
+    NotRunning,
+    IncorrectVersion {
+        expected: VersionRange,
+        received: Version,
+    },
+    ConfigUnsatisfied(Vec<String>),
+    PointerUpdateError(String),
+    Other(String),
+}
+impl From<DependencyError> for DependencyErrorRepr {
+    fn from(e: DependencyError) -> Self {
+        match e {
+            DependencyError::NotInstalled => DependencyErrorRepr::NotInstalled,
+            DependencyError::NotRunning => DependencyErrorRepr::NotRunning,
+            DependencyError::IncorrectVersion { expected, received } => {
+                DependencyErrorRepr::IncorrectVersion { expected, received }
+            }
+            DependencyError::ConfigUnsatisfied(rules) => {
+                DependencyErrorRepr::ConfigUnsatisfied(rules)
+            }
+            DependencyError::PointerUpdateError(e) => DependencyErrorRepr::PointerUpdateError(e),
+            e @ DependencyError::ModeMismatch { .. } | e @ DependencyError::Syncing { .. } => {
+                DependencyErrorRepr::Other(e.to_string())
+            }
+            DependencyError::Other(e) => DependencyErrorRepr::Other(e),
+        }
+    }
+}
+
 #[derive(Clone, Debug, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct TaggedDependencyError {
@@ -68,6 +130,14 @@ pub struct DepInfo {
     pub mount_public: bool,
     #[serde(default)]
     pub mount_shared: bool,
+    /// The dependency must run in the same mode (i.e. testnet) as the dependent.
+    #[serde(default)]
+    pub match_mode: bool,
+    /// The sync progress, from 0 to 100, the dependency must report before the dependent can
+    /// use it. Dependencies that report no progress are not waited for.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_sync: Option<f64>,
     #[serde(default)]
     pub config: Vec<ConfigRuleEntryWithSuggestions>,
 }
@@ -79,7 +149,8 @@ impl DepInfo {
         dependent_id: &str,
         dependent_config: &Config,
     ) -> Result<Result<(), DependencyError>, Error> {
-        let info = if let Some(info) = crate::apps::list_info().await?.remove(dependency_id) {
+        let mut installed = crate::apps::list_info().await?;
+        let info = if let Some(info) = installed.remove(dependency_id) {
             info
         } else {
             return Ok(Err(DependencyError::NotInstalled));
@@ -90,6 +161,14 @@ impl DepInfo {
                 received: info.version.clone(),
             }));
         }
+        if self.match_mode {
+            let expected = installed.get(dependent_id).and_then(|i| i.mode.clone());
+            if let (Some(expected), Some(received)) = (expected, info.mode) {
+                if expected != received {
+                    return Ok(Err(DependencyError::ModeMismatch { expected, received }));
+                }
+            }
+        }
         let dependency_config = if let Some(cfg) = dependency_config {
             cfg
         } else {
@@ -103,27 +182,147 @@ impl DepInfo {
                     .unwrap_or_default()
             }
         };
+        if let Err(e) = self.check_config(
+            dependency_id,
+            &dependency_config,
+            dependent_id,
+            dependent_config,
+        ) {
+            return Ok(Err(e));
+        }
+        if crate::apps::status(dependency_id, false).await?.status
+            != crate::apps::DockerStatus::Running
+        {
+            return Ok(Err(DependencyError::NotRunning));
+        }
+        Ok(Ok(()))
+    }
+
+    /// Whether the dependency has synced as far as this requires. Kept apart from `satisfied`,
+    /// so a dependency that falls behind does not stop the dependents already using it.
+    pub async fn synced(&self, dependency_id: &str) -> Result<Result<(), DependencyError>, Error> {
+        let required = match self.min_sync {
+            Some(required) => required,
+            None => return Ok(Ok(())),
+        };
+        Ok(match crate::logs::sync_progress(dependency_id).await? {
+            Some(sync) if sync.progress < required => Err(DependencyError::Syncing {
+                progress: sync.progress,
+                required,
+                eta: sync.eta,
+            }),
+            _ => Ok(()),
+        })
+    }
+
+    fn check_config(
+        &self,
+        dependency_id: &str,
+        dependency_config: &Config,
+        dependent_id: &str,
+        dependent_config: &Config,
+    ) -> Result<(), DependencyError> {
         let mut errors = Vec::new();
         let mut cfgs = LinearMap::with_capacity(2);
-        cfgs.insert(dependency_id, Cow::Borrowed(&dependency_config));
+        cfgs.insert(dependency_id, Cow::Borrowed(dependency_config));
         cfgs.insert(dependent_id, Cow::Borrowed(dependent_config));
         for rule in self.config.iter() {
-            if !(rule.entry.rule.compiled)(&dependency_config, &cfgs) {
+            if !(rule.entry.rule.compiled)(dependency_config, &cfgs) {
                 errors.push(rule.entry.description.clone());
             }
         }
-        if !errors.is_empty() {
-            return Ok(Err(DependencyError::ConfigUnsatisfied(errors)));
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(DependencyError::ConfigUnsatisfied(errors))
         }
-        if crate::apps::status(dependency_id, false).await?.status
-            != crate::apps::DockerStatus::Running
-        {
-            return Ok(Err(DependencyError::NotRunning));
+    }
+
+    /// Checks a dependency at `dependency_version` with `dependency_config` against this, without
+    /// looking at the device. Whether the dependency is installed, running, or in the right mode
+    /// is not checked.
+    pub fn satisfied_by(
+        &self,
+        dependency_id: &str,
+        dependency_version: &Version,
+        dependency_config: &Config,
+        dependent_id: &str,
+        dependent_config: &Config,
+    ) -> Result<(), DependencyError> {
+        if !dependency_version.satisfies(&self.version) {
+            return Err(DependencyError::IncorrectVersion {
+                expected: self.version.clone(),
+                received: dependency_version.clone(),
+            });
         }
-        Ok(Ok(()))
+        self.check_config(
+            dependency_id,
+            dependency_config,
+            dependent_id,
+            dependent_config,
+        )
     }
 }
 
+/// A fabricated dependency and dependent config, so package authors can test the config rules
+/// of their dependencies against many versions before release.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DependencyFixture {
+    pub dependency: String,
+    pub version: Version,
+    #[serde(default)]
+    pub dependency_config: Config,
+    #[serde(default)]
+    pub dependent_config: Config,
+    /// Whether the dependency is expected to be satisfied.
+    pub satisfied: bool,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FixtureRes {
+    #[serde(flatten)]
+    pub fixture: DependencyFixture,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<DependencyError>,
+}
+
+/// Runs `fixtures` against the dependencies of `manifest`.
+pub fn check_fixtures(
+    manifest: &ManifestLatest,
+    fixtures: Vec<DependencyFixture>,
+) -> Result<Vec<FixtureRes>, Error> {
+    fixtures
+        .into_iter()
+        .map(|fixture| {
+            let dep_info = manifest
+                .dependencies
+                .0
+                .get(&fixture.dependency)
+                .ok_or_else(|| {
+                    format_err!("{} Does Not Depend On {}", manifest.id, fixture.dependency)
+                })
+                .with_code(crate::error::NOT_FOUND)?;
+            let error = dep_info
+                .satisfied_by(
+                    &fixture.dependency,
+                    &fixture.version,
+                    &fixture.dependency_config,
+                    &manifest.id,
+                    &fixture.dependent_config,
+                )
+                .err();
+            Ok(FixtureRes {
+                passed: error.is_none() == fixture.satisfied,
+                fixture,
+                error,
+            })
+        })
+        .collect()
+}
+
 #[derive(Debug, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct AppDepInfo {
@@ -147,10 +346,13 @@ pub async fn check_dependencies(
     for (dependency_id, dependency_info) in manifest.dependencies.0.into_iter() {
         let required = dependency_info.optional.is_none()
             || dependent_config_spec.requires(&dependency_id, dependent_config);
-        let error = dependency_info
+        let error = match dependency_info
             .satisfied(&dependency_id, None, &manifest.id, dependent_config)
             .await?
-            .err();
+        {
+            Ok(()) => dependency_info.synced(&dependency_id).await?.err(),
+            Err(e) => Some(e),
+        };
         let app_dep_info = AppDepInfo {
             error,
             required,
@@ -258,3 +460,86 @@ pub async fn update_binds(dependent_id: &str) -> Result<(), Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_satisfied_by() {
+        let dep_info: DepInfo = serde_yaml::from_str(
+            r#"
+version: ">=0.20.0"
+optional: ~
+description: ~
+config:
+  - rule: "txindex? AND '[lnd].network = 'network"
+    description: Must have txindex enabled on the same network
+    suggestions: []
+"#,
+        )
+        .unwrap();
+        let dependent: Config = serde_yaml::from_str("network: mainnet").unwrap();
+        let good: Config = serde_yaml::from_str("txindex: true\nnetwork: mainnet").unwrap();
+        let bad: Config = serde_yaml::from_str("txindex: true\nnetwork: testnet").unwrap();
+        let v = |s: &str| s.parse::<Version>().unwrap();
+        dep_info
+            .satisfied_by("bitcoind", &v("0.21.0"), &good, "lnd", &dependent)
+            .unwrap();
+        match dep_info.satisfied_by("bitcoind", &v("0.21.0"), &bad, "lnd", &dependent) {
+            Err(DependencyError::ConfigUnsatisfied(rules)) => assert_eq!(rules.len(), 1),
+            e => panic!("unexpected result: {:?}", e),
+        }
+        match dep_info.satisfied_by("bitcoind", &v("0.19.1"), &good, "lnd", &dependent) {
+            Err(DependencyError::IncorrectVersion { .. }) => (),
+            e => panic!("unexpected result: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_syncing() {
+        let waiting = DependencyError::Syncing {
+            progress: 72.9,
+            required: 100.0,
+            eta: Some(7500),
+        };
+        assert_eq!(
+            format!("{}", waiting),
+            "Waiting For Sync: 72% Synced, About 2h 5m Left"
+        );
+        let partial = DependencyError::Syncing {
+            progress: 10.0,
+            required: 50.0,
+            eta: None,
+        };
+        assert_eq!(
+            format!("{}", partial),
+            "Waiting For Sync: 10% Synced, Needs 50%"
+        );
+    }
+
+    #[test]
+    fn test_agent_shape() {
+        let mismatch = DependencyError::ModeMismatch {
+            expected: "testnet".to_owned(),
+            received: "mainnet".to_owned(),
+        };
+        assert_eq!(
+            serde_json::to_value(&mismatch).unwrap(),
+            serde_json::json!({ "other": "Mode Mismatch: Expected testnet, Received mainnet" })
+        );
+        let waiting = DependencyError::Syncing {
+            progress: 72.5,
+            required: 100.0,
+            eta: Some(3600),
+        };
+        assert_eq!(
+            serde_json::to_value(&waiting).unwrap(),
+            serde_json::json!({ "other": "Waiting For Sync: 72% Synced, About 1h 0m Left" })
+        );
+        assert_eq!(
+            serde_json::to_value(&DependencyError::NotRunning).unwrap(),
+            serde_json::json!("not-running")
+        );
+    }
+}
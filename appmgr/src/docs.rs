@@ -0,0 +1,376 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use failure::ResultExt as _;
+use linear_map::LinearMap;
+
+use crate::config::spec::{ValueSpecAny, ValueSpecList};
+use crate::config::ConfigSpec;
+use crate::util::PersistencePath;
+use crate::Error;
+use crate::ResultExt as _;
+
+/// Where the docs are published, for the web server to serve on the LAN and over tor, so help is
+/// at hand without the clearnet docs site.
+pub const DOCS_DIR: &'static str = "/var/www/docs";
+
+pub const INSTRUCTIONS_PAGE: &'static str = "instructions";
+pub const CONFIG_PAGE: &'static str = "config";
+pub const RELEASE_NOTES_PAGE: &'static str = "release-notes";
+
+/// A markdown page of the docs of an app.
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Page {
+    pub name: String,
+    pub title: String,
+    #[serde(skip)]
+    pub markdown: String,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppDocs {
+    pub title: String,
+    pub version: emver::Version,
+    pub description: String,
+    pub pages: Vec<Page>,
+}
+
+/// One section of a page, which is what a search matches.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SearchEntry {
+    pub app: String,
+    pub page: String,
+    pub heading: String,
+    pub text: String,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Docs {
+    pub generated_at: u64,
+    pub apps: LinearMap<String, AppDocs>,
+    pub search: Vec<SearchEntry>,
+}
+
+fn field_kind(spec: &ValueSpecAny) -> String {
+    match spec {
+        ValueSpecAny::Boolean(_) => "boolean".to_owned(),
+        ValueSpecAny::Enum(_) => "enum".to_owned(),
+        ValueSpecAny::List(l) => format!(
+            "list of {}",
+            match l {
+                ValueSpecList::Enum(_) => "enum",
+                ValueSpecList::Number(_) => "number",
+                ValueSpecList::Object(_) => "object",
+                ValueSpecList::String(_) => "string",
+                ValueSpecList::Union(_) => "union",
+            }
+        ),
+        ValueSpecAny::Number(n) => match &n.inner.inner.inner.units {
+            Some(units) => format!("number ({})", units),
+            None => "number".to_owned(),
+        },
+        ValueSpecAny::Duration(_) => "duration".to_owned(),
+        ValueSpecAny::Bytes(_) => "byte size".to_owned(),
+        ValueSpecAny::Object(_) => "object".to_owned(),
+        ValueSpecAny::String(s) if s.inner.inner.inner.masked => "string, masked".to_owned(),
+        ValueSpecAny::String(_) => "string".to_owned(),
+        ValueSpecAny::Union(_) => "union".to_owned(),
+        ValueSpecAny::Pointer(_) => "set automatically".to_owned(),
+    }
+}
+
+fn config_reference_rec(spec: &ConfigSpec, prefix: &str, depth: usize, res: &mut String) {
+    let hashes = "#".repeat(depth.min(6));
+    for (key, val) in spec.0.iter() {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        res.push_str(&format!("{} {}\n\n", hashes, val.name()));
+        res.push_str(&format!("`{}` · {}\n\n", path, field_kind(val)));
+        if let Some(description) = val.description() {
+            res.push_str(description);
+            res.push_str("\n\n");
+        }
+        match val {
+            ValueSpecAny::Enum(e) => {
+                let e = &e.inner.inner;
+                for value in &e.values {
+                    let name = e.value_names.get(value).unwrap_or(value);
+                    res.push_str(&format!("- `{}`: {}\n", value, name));
+                }
+                if let Some(other) = &e.other {
+                    res.push_str(&format!(
+                        "- {}: {}\n",
+                        other.name, other.pattern.pattern_description
+                    ));
+                }
+                res.push('\n');
+            }
+            ValueSpecAny::Number(n) => {
+                if let Some(range) = &n.inner.inner.inner.range {
+                    res.push_str(&format!("Range: {}\n\n", range));
+                }
+            }
+            ValueSpecAny::String(s) => {
+                if let Some(pattern) = &s.inner.inner.inner.pattern {
+                    res.push_str(&format!("Format: {}\n\n", pattern.pattern_description));
+                }
+            }
+            ValueSpecAny::Object(o) => {
+                config_reference_rec(&o.inner.inner.spec, &path, depth + 1, res);
+            }
+            ValueSpecAny::List(ValueSpecList::Object(o)) => {
+                config_reference_rec(
+                    &o.inner.inner.spec.spec,
+                    &format!("{}.*", path),
+                    depth + 1,
+                    res,
+                );
+            }
+            ValueSpecAny::Union(u) => {
+                let u = &u.inner.inner;
+                for (variant, variant_spec) in &u.variants {
+                    let name = u.tag.variant_names.get(variant).unwrap_or(variant);
+                    res.push_str(&format!(
+                        "{}# {}: {}\n\n`{}.{}` = `{}`\n\n",
+                        hashes, u.tag.name, name, path, u.tag.id, variant
+                    ));
+                    config_reference_rec(variant_spec, &path, depth + 2, res);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// A markdown reference of every config field of an app, from its spec.
+pub fn config_reference(spec: &ConfigSpec) -> String {
+    let mut res = "# Config Reference\n\n".to_owned();
+    config_reference_rec(spec, "", 2, &mut res);
+    res
+}
+
+/// Splits a markdown page into its sections, each under the heading it starts with.
+pub fn sections(app: &str, page: &Page) -> Vec<SearchEntry> {
+    let mut res = Vec::new();
+    let mut heading = page.title.clone();
+    let mut text = String::new();
+    for line in page.markdown.lines() {
+        if line.starts_with('#') {
+            if !text.trim().is_empty() {
+                res.push(SearchEntry {
+                    app: app.to_owned(),
+                    page: page.name.clone(),
+                    heading: heading.clone(),
+                    text: text.trim().to_owned(),
+                });
+            }
+            heading = line.trim_start_matches('#').trim().to_owned();
+            text.clear();
+        } else {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+    if !text.trim().is_empty() {
+        res.push(SearchEntry {
+            app: app.to_owned(),
+            page: page.name.clone(),
+            heading,
+            text: text.trim().to_owned(),
+        });
+    }
+    res
+}
+
+/// The sections that contain every word of `query`, ignoring case.
+pub fn search<'a>(entries: &'a [SearchEntry], query: &str) -> Vec<&'a SearchEntry> {
+    let words: Vec<String> = query.split_whitespace().map(|w| w.to_lowercase()).collect();
+    entries
+        .iter()
+        .filter(|entry| {
+            let haystack = format!("{}\n{}", entry.heading, entry.text).to_lowercase();
+            words.iter().all(|w| haystack.contains(w.as_str()))
+        })
+        .collect()
+}
+
+async fn app_docs(id: &str) -> Result<AppDocs, Error> {
+    let manifest = crate::apps::manifest(id).await?;
+    let mut pages = Vec::new();
+    if let Some(f) = PersistencePath::from_ref("apps")
+        .join(id)
+        .join("instructions.md")
+        .maybe_read(false)
+        .await
+    {
+        use tokio::io::AsyncReadExt;
+        let mut markdown = String::new();
+        f?.read_to_string(&mut markdown).await?;
+        pages.push(Page {
+            name: INSTRUCTIONS_PAGE.to_owned(),
+            title: "Instructions".to_owned(),
+            markdown,
+        });
+    }
+    let spec = crate::apps::config(id).await?.spec;
+    if !spec.0.is_empty() {
+        pages.push(Page {
+            name: CONFIG_PAGE.to_owned(),
+            title: "Config Reference".to_owned(),
+            markdown: config_reference(&spec),
+        });
+    }
+    pages.push(Page {
+        name: RELEASE_NOTES_PAGE.to_owned(),
+        title: format!("Release Notes ({})", manifest.version),
+        markdown: manifest.release_notes.clone(),
+    });
+    Ok(AppDocs {
+        title: manifest.title,
+        version: manifest.version,
+        description: manifest.description.long,
+        pages,
+    })
+}
+
+/// Builds the docs of every installed app.
+pub async fn render() -> Result<Docs, Error> {
+    let mut res = Docs {
+        generated_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        ..Default::default()
+    };
+    for (id, _) in crate::apps::list_info().await? {
+        let docs = app_docs(&id).await?;
+        for page in &docs.pages {
+            res.search.extend(sections(&id, page));
+        }
+        res.apps.insert(id, docs);
+    }
+    Ok(res)
+}
+
+async fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, contents)
+        .await
+        .with_context(|e| format!("{}: {}", tmp.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(())
+}
+
+/// Writes the docs to `DOCS_DIR`: `<app>/<page>.md` for each page, `index.json` listing the apps
+/// and their pages, and `search.json` with every section for the docs route to search. Pages of
+/// apps that are no longer installed are removed.
+pub async fn publish() -> Result<Docs, Error> {
+    let docs = render().await?;
+    let dir = Path::new(DOCS_DIR);
+    tokio::fs::create_dir_all(dir).await?;
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        if entry.file_type().await?.is_dir()
+            && name.to_str().map_or(true, |a| !docs.apps.contains_key(a))
+        {
+            tokio::fs::remove_dir_all(entry.path()).await?;
+        }
+    }
+    for (id, app) in &docs.apps {
+        let app_dir = dir.join(id);
+        tokio::fs::create_dir_all(&app_dir).await?;
+        for page in &app.pages {
+            write_atomic(
+                &app_dir.join(format!("{}.md", page.name)),
+                page.markdown.as_bytes(),
+            )
+            .await?;
+        }
+    }
+    write_atomic(
+        &dir.join("index.json"),
+        &serde_json::to_vec(&docs.apps).with_code(crate::error::SERDE_ERROR)?,
+    )
+    .await?;
+    write_atomic(
+        &dir.join("search.json"),
+        &serde_json::to_vec(&docs.search).with_code(crate::error::SERDE_ERROR)?,
+    )
+    .await?;
+    Ok(docs)
+}
+
+/// Republishes the docs after an app is installed or removed. A failure is only logged, since the
+/// docs are a convenience.
+pub async fn refresh() {
+    if let Err(e) = publish().await {
+        log::warn!("Failed to publish docs: {}", e.failure);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_config_reference() {
+        // yaml, so the fields come out in the order they are written
+        let spec: ConfigSpec = serde_yaml::from_str(
+            r#"
+rpc:
+  name: RPC Settings
+  type: object
+  nullable: false
+  description: How other apps reach the node
+  spec:
+    port:
+      name: Port
+      type: number
+      nullable: false
+      range: "[1,65535]"
+      integral: true
+      default: 8332
+network:
+  name: Network
+  type: enum
+  values: [mainnet, testnet]
+  valueNames:
+    mainnet: Main Network
+  default: mainnet
+"#,
+        )
+        .unwrap();
+        let page = Page {
+            name: CONFIG_PAGE.to_owned(),
+            title: "Config Reference".to_owned(),
+            markdown: config_reference(&spec),
+        };
+        assert!(page
+            .markdown
+            .contains("### Port\n\n`rpc.port` · number\n\nRange: [1,65535]"));
+        assert!(page.markdown.contains("- `mainnet`: Main Network\n"));
+        let entries = sections("bitcoind", &page);
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| e.heading.as_str())
+                .collect::<Vec<_>>(),
+            vec!["RPC Settings", "Port", "Network"]
+        );
+        let found = search(&entries, "TESTNET network");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].heading, "Network");
+    }
+}
@@ -0,0 +1,199 @@
+use failure::ResultExt as _;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+use crate::util::{PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const ENCRYPTION_YAML: &'static str = "encryption.yaml";
+/// The device key is derived from this. It never leaves the device, and is not part of backups.
+pub const PRODUCT_KEY_PATH: &'static str = "/root/agent/product_key";
+/// Where the board's serial number is read from. It is burned into the SoC rather than stored
+/// on the SD card.
+pub const BOARD_SERIAL_PATH: &'static str = "/sys/firmware/devicetree/base/serial-number";
+pub const CPUINFO_PATH: &'static str = "/proc/cpuinfo";
+
+// marks a sealed file, and is authenticated along with it, so the format can change later
+const MAGIC: &'static [u8] = b"appmgr-sealed-v1\n";
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// The serial number of the board, from the device tree or, failing that, `/proc/cpuinfo`.
+async fn board_serial() -> Result<String, Error> {
+    let serial = match tokio::fs::read_to_string(BOARD_SERIAL_PATH).await {
+        Ok(serial) => serial,
+        Err(_) => tokio::fs::read_to_string(CPUINFO_PATH)
+            .await
+            .with_context(|e| format!("{}: {}", CPUINFO_PATH, e))
+            .with_code(crate::error::NOT_FOUND)?
+            .lines()
+            .find_map(|line| {
+                let mut split = line.splitn(2, ':');
+                if split.next()?.trim() == "Serial" {
+                    split.next().map(|s| s.to_owned())
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default(),
+    };
+    let serial = serial.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    crate::ensure_code!(
+        serial.chars().any(|c| c != '0'),
+        crate::error::NOT_FOUND,
+        "Board Has No Serial Number"
+    );
+    Ok(serial.to_owned())
+}
+
+/// Keys sealed files. It is derived from the product key together with the board's serial
+/// number, so the SD card alone is not enough to derive it. This protects a card that is pulled
+/// from the device, not a device that is taken whole, since anyone holding the board can read its
+/// serial. Sealed files only open on the board that sealed them: after moving the card to another
+/// board, restore configs from a backup, which holds them opened.
+pub async fn device_key() -> Result<[u8; 32], Error> {
+    let product_key = tokio::fs::read_to_string(PRODUCT_KEY_PATH)
+        .await
+        .with_context(|e| format!("{}: {}", PRODUCT_KEY_PATH, e))
+        .with_code(crate::error::NOT_FOUND)?;
+    let mut input = b"appmgr config encryption:".to_vec();
+    input.extend_from_slice(product_key.trim().as_bytes());
+    input.push(b':');
+    input.extend_from_slice(board_serial().await?.as_bytes());
+    Ok(openssl::sha::sha256(&input))
+}
+
+/// Keys the derived defaults of configs (see `config::spec::Generator::Derived`). Separate from
+/// the device key, and read synchronously since defaults are generated synchronously.
+pub fn derivation_key() -> Result<[u8; 32], Error> {
+    let product_key = std::fs::read_to_string(PRODUCT_KEY_PATH)
+        .with_context(|e| format!("{}: {}", PRODUCT_KEY_PATH, e))
+        .with_code(crate::error::NOT_FOUND)?;
+    let mut input = b"appmgr config derivation:".to_vec();
+    input.extend_from_slice(product_key.trim().as_bytes());
+    Ok(openssl::sha::sha256(&input))
+}
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EncryptionSettings {
+    /// Whether app configs are sealed under the device key at rest.
+    #[serde(default)]
+    pub configs: bool,
+}
+
+pub async fn settings() -> Result<EncryptionSettings, Error> {
+    let path = PersistencePath::from_ref(ENCRYPTION_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await,
+        None => Ok(Default::default()),
+    }
+}
+
+pub async fn configs_encrypted() -> Result<bool, Error> {
+    Ok(settings().await?.configs)
+}
+
+/// Turns encryption of configs at rest on or off, and rewrites the configs of every installed app
+/// to match.
+pub async fn set_configs_encrypted(enabled: bool) -> Result<(), Error> {
+    if enabled {
+        // fail before anything is sealed if there is no key to seal with
+        device_key().await?;
+    }
+    let mut settings: YamlUpdateHandle<EncryptionSettings> =
+        YamlUpdateHandle::new_or_default(PersistencePath::from_ref(ENCRYPTION_YAML)).await?;
+    settings.configs = enabled;
+    settings.commit().await?;
+    for (id, _) in crate::apps::list_info().await? {
+        crate::config::rewrite(&id).await?;
+    }
+    Ok(())
+}
+
+pub fn is_sealed(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Encrypts `data` with AES-256-GCM, under a fresh nonce.
+pub fn seal(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut nonce = [0; NONCE_LEN];
+    openssl::rand::rand_bytes(&mut nonce).no_code()?;
+    let mut tag = [0; TAG_LEN];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(&nonce),
+        MAGIC,
+        data,
+        &mut tag,
+    )
+    .no_code()?;
+    let mut res = Vec::with_capacity(MAGIC.len() + NONCE_LEN + TAG_LEN + ciphertext.len());
+    res.extend_from_slice(MAGIC);
+    res.extend_from_slice(&nonce);
+    res.extend_from_slice(&tag);
+    res.extend(ciphertext);
+    Ok(res)
+}
+
+pub fn open(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, Error> {
+    crate::ensure_code!(
+        is_sealed(data) && data.len() >= MAGIC.len() + NONCE_LEN + TAG_LEN,
+        crate::error::SERDE_ERROR,
+        "Not A Sealed File"
+    );
+    let data = &data[MAGIC.len()..];
+    let (nonce, data) = data.split_at(NONCE_LEN);
+    let (tag, ciphertext) = data.split_at(TAG_LEN);
+    decrypt_aead(
+        Cipher::aes_256_gcm(),
+        key,
+        Some(nonce),
+        MAGIC,
+        ciphertext,
+        tag,
+    )
+    .map_err(|_| failure::format_err!("Sealed File Is Corrupt Or From Another Device"))
+    .with_code(crate::error::SERDE_ERROR)
+}
+
+/// Seals `data` under the device key if configs are encrypted at rest, otherwise leaves it as is.
+pub async fn seal_if_enabled(data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if configs_encrypted().await? {
+        seal(&device_key().await?, &data)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Opens `data` if it is sealed. Files written before encryption was turned on (or after it was
+/// turned off) are plaintext, and are returned as is.
+pub async fn open_if_sealed(data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if is_sealed(&data) {
+        open(&device_key().await?, &data)
+    } else {
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_seal() {
+        let key = openssl::sha::sha256(b"product key");
+        let data = b"rpc:\n  password: hunter2\n";
+        let sealed = seal(&key, data).unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.windows(7).any(|w| w == b"hunter2"));
+        assert_eq!(open(&key, &sealed).unwrap(), data);
+        assert_ne!(seal(&key, data).unwrap(), sealed);
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open(&key, &tampered).is_err());
+        assert!(open(&openssl::sha::sha256(b"other key"), &sealed).is_err());
+        assert!(!is_sealed(data));
+    }
+}
@@ -11,6 +11,10 @@ pub const VERSION_INCOMPATIBLE: i32 = 8;
 pub const NETWORK_ERROR: i32 = 9;
 pub const REGISTRY_ERROR: i32 = 10;
 pub const SERDE_ERROR: i32 = 11;
+pub const TIMEOUT_ERROR: i32 = 12;
+pub const HOOK_ABORTED: i32 = 13;
+pub const SIGNATURE_ERROR: i32 = 14;
+pub const RESOURCE_INSUFFICIENT: i32 = 15;
 
 #[derive(Debug, Fail)]
 #[fail(display = "{}", _0)]
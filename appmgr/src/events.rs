@@ -0,0 +1,222 @@
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use failure::ResultExt as _;
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::logs::Notification;
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const EVENTS_LOG: &'static str = "events.log";
+/// Once the log grows past this, the older half of it is dropped.
+pub const MAX_LOG_LEN: u64 = 1024 * 1024;
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    Installed,
+    Removed,
+    Started,
+    Stopped,
+    ConfigChanged,
+    BackupComplete,
+    Unhealthy,
+    /// An app that was unhealthy passes its health checks again.
+    Healthy,
+    /// The security audit found something. The event carries the notification it raised.
+    SecurityAlert,
+}
+
+/// Something that happened to an app, one json object per line of the event log.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Event {
+    /// In milliseconds since the epoch.
+    pub time: u64,
+    pub kind: EventKind,
+    /// Left out for events about the device as a whole.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notification: Option<Notification>,
+}
+impl Event {
+    /// The event as a server-sent event, with its time as the id to resume from.
+    pub fn to_sse(&self) -> Result<String, Error> {
+        Ok(format!(
+            "id: {}\nevent: {}\ndata: {}\n\n",
+            self.time,
+            serde_json::to_value(self.kind)
+                .with_code(crate::error::SERDE_ERROR)?
+                .as_str()
+                .unwrap_or_default(),
+            serde_json::to_string(self).with_code(crate::error::SERDE_ERROR)?
+        ))
+    }
+}
+
+fn path() -> PathBuf {
+    Path::new(crate::PERSISTENCE_DIR).join(EVENTS_LOG)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+async fn append(event: &Event) -> Result<(), Error> {
+    let path = path();
+    let mut line = serde_json::to_string(event).with_code(crate::error::SERDE_ERROR)?;
+    line.push('\n');
+    let mut f = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    f.write_all(line.as_bytes()).await?;
+    f.flush().await?;
+    if f.metadata().await?.len() > MAX_LOG_LEN {
+        drop(f);
+        let contents = tokio::fs::read_to_string(&path).await?;
+        let half = contents.len() / 2;
+        let keep = match contents.as_bytes()[half..].iter().position(|b| *b == b'\n') {
+            Some(idx) => &contents[half + idx + 1..],
+            None => "",
+        };
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, keep).await?;
+        tokio::fs::rename(&tmp, &path)
+            .await
+            .with_context(|e| format!("{}: {}", path.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+    }
+    Ok(())
+}
+
+/// Records an event. Like publishing to MQTT, a failure is logged and otherwise ignored, so it
+/// never fails the operation it reports on.
+pub async fn emit(kind: EventKind, app: &str) {
+    let event = Event {
+        time: now(),
+        kind,
+        app: Some(app.to_owned()),
+        notification: None,
+    };
+    if let Err(e) = append(&event).await {
+        log::warn!("Failed to record {:?} event for {}: {}", kind, app, e);
+    }
+}
+
+/// Records a notification raised by the security audit, about `app` if it concerns one.
+pub async fn emit_alert(app: Option<&str>, notification: &Notification) {
+    let event = Event {
+        time: now(),
+        kind: EventKind::SecurityAlert,
+        app: app.map(|a| a.to_owned()),
+        notification: Some(notification.clone()),
+    };
+    if let Err(e) = append(&event).await {
+        log::warn!("Failed to record security alert {}: {}", notification, e);
+    }
+}
+
+fn parse(line: &str) -> Option<Event> {
+    match serde_json::from_str(line) {
+        Ok(event) => Some(event),
+        Err(e) => {
+            log::warn!("Skipping invalid event {:?}: {}", line, e);
+            None
+        }
+    }
+}
+
+/// The recorded events after `since`, oldest first.
+pub async fn read(since: Option<u64>) -> Result<Vec<Event>, Error> {
+    let path = path();
+    let contents = match tokio::fs::read_to_string(&path).await {
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        a => a,
+    }
+    .with_context(|e| format!("{}: {}", path.display(), e))
+    .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(contents
+        .lines()
+        .filter(|line| !line.is_empty())
+        .filter_map(parse)
+        .filter(|event| since.map_or(true, |since| event.time > since))
+        .collect())
+}
+
+/// Calls `f` with every event after `since`, then with every event recorded from then on. Does
+/// not return unless `f` fails.
+pub async fn follow<F: FnMut(Event) -> Result<(), Error>>(
+    since: Option<u64>,
+    mut f: F,
+) -> Result<(), Error> {
+    let mut last = since;
+    for event in read(since).await? {
+        last = Some(event.time);
+        f(event)?;
+    }
+    let path = path();
+    let mut offset = match tokio::fs::metadata(&path).await {
+        Ok(m) => m.len(),
+        Err(_) => 0,
+    };
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let len = match tokio::fs::metadata(&path).await {
+            Ok(m) => m.len(),
+            Err(_) => continue,
+        };
+        // the log was trimmed: start over, skipping what was already seen
+        let rescan = len < offset;
+        if rescan {
+            offset = 0;
+        }
+        if len == offset {
+            continue;
+        }
+        let mut file = tokio::fs::File::open(&path).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut lines = tokio::io::BufReader::new(file).lines();
+        while let Some(line) = lines.next_line().await? {
+            offset += line.len() as u64 + 1;
+            match parse(&line) {
+                Some(event) if !rescan || last.map_or(true, |last| event.time > last) => {
+                    last = Some(event.time);
+                    f(event)?;
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_to_sse() {
+        let event = Event {
+            time: 1600000000000,
+            kind: EventKind::ConfigChanged,
+            app: Some("bitcoind".to_owned()),
+            notification: None,
+        };
+        assert_eq!(
+            event.to_sse().unwrap(),
+            "id: 1600000000000\nevent: config-changed\ndata: {\"time\":1600000000000,\"kind\":\"config-changed\",\"app\":\"bitcoind\"}\n\n"
+        );
+    }
+}
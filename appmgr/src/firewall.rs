@@ -0,0 +1,389 @@
+use std::net::IpAddr;
+
+use linear_map::set::LinearSet;
+
+use crate::util::{Invoke, PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const FIREWALL_YAML: &'static str = "firewall.yaml";
+pub const OUTPUT_CHAIN: &'static str = "APPMGR-TOR-ONLY-OUT";
+pub const FORWARD_CHAIN: &'static str = "APPMGR-TOR-ONLY-FWD";
+pub const BLOCKLIST_CHAIN: &'static str = "APPMGR-BLOCKLIST";
+pub const DOCKER_SUBNET: &'static str = "172.18.0.0/16";
+pub const TOR_USER: &'static str = "debian-tor";
+/// Reapplies the rules at boot, since iptables does not keep them.
+pub const BOOT_UNIT: &'static str = "/etc/systemd/system/appmgr-firewall.service";
+const LOCAL_RANGES: &'static [&'static str] = &[
+    "10.0.0.0/8",
+    "127.0.0.0/8",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+];
+const LOCAL_RANGES_V6: &'static [&'static str] = &["::1/128", "fc00::/7", "fe80::/10", "ff02::/16"];
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FirewallSettings {
+    pub tor_only: bool,
+    #[serde(default)]
+    pub exemptions: LinearSet<String>,
+    #[serde(default)]
+    pub blocked: LinearSet<IpAddr>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FirewallStatus {
+    #[serde(flatten)]
+    pub settings: FirewallSettings,
+    pub active: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Family {
+    V4,
+    V6,
+}
+impl Family {
+    fn command(self) -> &'static str {
+        match self {
+            Family::V4 => "iptables",
+            Family::V6 => "ip6tables",
+        }
+    }
+}
+
+async fn xtables(family: Family, args: &[&str]) -> Result<(), Error> {
+    tokio::process::Command::new(family.command())
+        .args(args)
+        .invoke("IPTables")
+        .await
+        .with_code(crate::error::NETWORK_ERROR)?;
+    Ok(())
+}
+
+async fn chain_exists(family: Family, chain: &str) -> bool {
+    xtables(family, &["-n", "-L", chain]).await.is_ok()
+}
+
+async fn is_hooked(family: Family, parent: &str, chain: &str) -> bool {
+    xtables(family, &["-C", parent, "-j", chain]).await.is_ok()
+}
+
+// the name a chain is built under before it is swapped in
+fn staging_name(chain: &str) -> String {
+    format!("{}-NEW", chain)
+}
+
+async fn discard(family: Family, parent: &str, chain: &str) {
+    while xtables(family, &["-D", parent, "-j", chain]).await.is_ok() {}
+    if chain_exists(family, chain).await {
+        if let Err(e) = xtables(family, &["-F", chain]).await {
+            log::warn!("Failed to flush {}: {}", chain, e);
+        }
+        if let Err(e) = xtables(family, &["-X", chain]).await {
+            log::warn!("Failed to delete {}: {}", chain, e);
+        }
+    }
+}
+
+async fn teardown() {
+    for &(family, parent, chain) in &[
+        (Family::V4, "OUTPUT", OUTPUT_CHAIN),
+        (Family::V4, "DOCKER-USER", FORWARD_CHAIN),
+        (Family::V6, "OUTPUT", OUTPUT_CHAIN),
+    ] {
+        discard(family, parent, &staging_name(chain)).await;
+        discard(family, parent, chain).await;
+    }
+}
+
+/// Builds `rules` into a new chain and swaps it in for `chain`. The new chain is hooked into
+/// `parent` before the old one is removed, so a complete rule set is in effect throughout.
+async fn swap(
+    family: Family,
+    parent: &str,
+    chain: &str,
+    rules: &[Vec<String>],
+) -> Result<(), Error> {
+    let staging = staging_name(chain);
+    if chain_exists(family, &staging).await {
+        if is_hooked(family, parent, &staging).await && !chain_exists(family, chain).await {
+            // an earlier swap stopped short of the rename, so this is the rule set in effect
+            xtables(family, &["-E", staging.as_str(), chain]).await?;
+        } else {
+            discard(family, parent, &staging).await;
+        }
+    }
+    let built = async {
+        xtables(family, &["-N", staging.as_str()]).await?;
+        for r in rules {
+            let mut args = vec!["-A", staging.as_str()];
+            args.extend(r.iter().map(|a| a.as_str()));
+            xtables(family, &args).await?;
+        }
+        xtables(family, &["-I", parent, "-j", staging.as_str()]).await
+    }
+    .await;
+    if let Err(e) = built {
+        discard(family, parent, &staging).await;
+        return Err(e);
+    }
+    discard(family, parent, chain).await;
+    xtables(family, &["-E", staging.as_str(), chain]).await
+}
+
+fn rule(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| (*a).to_owned()).collect()
+}
+
+// the addresses of the exempted apps
+async fn exempt_ips(settings: &FirewallSettings) -> Result<Vec<IpAddr>, Error> {
+    let services =
+        crate::tor::services_map(&PersistencePath::from_ref(crate::SERVICES_YAML)).await?;
+    let mut res = Vec::new();
+    for app_id in &settings.exemptions {
+        if let Some(service) = services.map.get(app_id) {
+            res.push(service.ip.into());
+        } else {
+            log::warn!("{} has no network address, skipping exemption.", app_id);
+        }
+    }
+    Ok(res)
+}
+
+// appmgr reaches the registry through Tor (see `registry::client`), so nothing else is let out
+fn output_rules(local_ranges: &[&str]) -> Vec<Vec<String>> {
+    let mut output = vec![
+        rule(&["-o", "lo", "-j", "RETURN"]),
+        rule(&["-m", "owner", "--uid-owner", TOR_USER, "-j", "RETURN"]),
+        rule(&[
+            "-m",
+            "conntrack",
+            "--ctstate",
+            "ESTABLISHED,RELATED",
+            "-j",
+            "RETURN",
+        ]),
+    ];
+    for &range in local_ranges {
+        output.push(rule(&["-d", range, "-j", "RETURN"]));
+    }
+    output.push(rule(&["-j", "REJECT"]));
+    output
+}
+
+async fn install(exempt: &[IpAddr]) -> Result<(), Error> {
+    let mut forward = Vec::new();
+    for ip in exempt {
+        let ip = ip.to_string();
+        forward.push(rule(&["-s", ip.as_str(), "-j", "RETURN"]));
+    }
+    forward.push(rule(&[
+        "-m",
+        "conntrack",
+        "--ctstate",
+        "ESTABLISHED,RELATED",
+        "-j",
+        "RETURN",
+    ]));
+    for &range in LOCAL_RANGES {
+        forward.push(rule(&["-s", DOCKER_SUBNET, "-d", range, "-j", "RETURN"]));
+    }
+    forward.push(rule(&["-s", DOCKER_SUBNET, "-j", "REJECT"]));
+
+    swap(
+        Family::V4,
+        "OUTPUT",
+        OUTPUT_CHAIN,
+        &output_rules(LOCAL_RANGES),
+    )
+    .await?;
+    // docker gives apps no IPv6 addresses, so only the host's own egress needs blocking there
+    swap(
+        Family::V6,
+        "OUTPUT",
+        OUTPUT_CHAIN,
+        &output_rules(LOCAL_RANGES_V6),
+    )
+    .await?;
+    swap(Family::V4, "DOCKER-USER", FORWARD_CHAIN, &forward).await
+}
+
+async fn apply_settings(settings: &FirewallSettings) -> Result<(), Error> {
+    if settings.tor_only {
+        log::info!("Blocking clearnet egress.");
+        let res = match exempt_ips(settings).await {
+            Ok(exempt) => install(&exempt).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            // fail closed: whatever went wrong, nothing but Tor may leave
+            log::error!(
+                "Failed to apply firewall rules, blocking all clearnet egress: {}",
+                e
+            );
+            if let Err(e) = install(&[]).await {
+                log::error!("Failed to block clearnet egress: {}", e);
+            }
+            return Err(e);
+        }
+    } else {
+        log::info!("Allowing clearnet egress.");
+        teardown().await;
+    }
+    Ok(())
+}
+
+pub async fn settings() -> Result<FirewallSettings, Error> {
+    let path = PersistencePath::from_ref(FIREWALL_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await,
+        None => Ok(Default::default()),
+    }
+}
+
+async fn apply_blocklist(blocked: &LinearSet<IpAddr>) -> Result<(), Error> {
+    for &family in &[Family::V4, Family::V6] {
+        if !chain_exists(family, BLOCKLIST_CHAIN).await {
+            xtables(family, &["-N", BLOCKLIST_CHAIN]).await?;
+        }
+        if !is_hooked(family, "INPUT", BLOCKLIST_CHAIN).await {
+            xtables(family, &["-I", "INPUT", "-j", BLOCKLIST_CHAIN]).await?;
+        }
+        xtables(family, &["-F", BLOCKLIST_CHAIN]).await?;
+        for ip in blocked {
+            if ip.is_ipv6() != (family == Family::V6) {
+                continue;
+            }
+            let ip = ip.to_string();
+            xtables(
+                family,
+                &["-A", BLOCKLIST_CHAIN, "-s", ip.as_str(), "-j", "DROP"],
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Reapplies the persisted firewall settings, i.e. after a reboot.
+pub async fn apply() -> Result<(), Error> {
+    let settings = settings().await?;
+    apply_blocklist(&settings.blocked).await?;
+    apply_settings(&settings).await
+}
+
+/// Reapplies the rules if clearnet egress is blocked, for when the addresses they name have
+/// changed, i.e. an app was given a new ip.
+pub async fn refresh() -> Result<(), Error> {
+    let settings = settings().await?;
+    if settings.tor_only {
+        apply_settings(&settings).await?;
+    }
+    Ok(())
+}
+
+async fn systemctl(args: &[&str]) -> Result<(), Error> {
+    tokio::process::Command::new("systemctl")
+        .args(args)
+        .invoke("Systemd")
+        .await
+        .with_code(crate::error::GENERAL_ERROR)?;
+    Ok(())
+}
+
+// installs or removes the unit that reapplies the rules at boot, after docker has set up its
+// own chains
+async fn set_boot_unit(enabled: bool) -> Result<(), Error> {
+    let path = std::path::Path::new(BOOT_UNIT);
+    let name = path
+        .file_name()
+        .and_then(|a| a.to_str())
+        .unwrap_or_default();
+    if enabled {
+        let exe = std::env::current_exe()?;
+        tokio::fs::write(
+            path,
+            format!(
+                "[Unit]\nDescription=Apply the appmgr firewall rules\nWants=network-online.target\nAfter=network-online.target docker.service tor.service\n\n[Service]\nType=oneshot\nExecStart={} firewall apply\n\n[Install]\nWantedBy=multi-user.target\n",
+                exe.display()
+            ),
+        )
+        .await?;
+        systemctl(&["daemon-reload"]).await?;
+        systemctl(&["enable", name]).await?;
+    } else if path.exists() {
+        systemctl(&["disable", name]).await?;
+        tokio::fs::remove_file(path).await?;
+        systemctl(&["daemon-reload"]).await?;
+    }
+    Ok(())
+}
+
+// the rules only need reapplying at boot if there are any
+async fn sync_boot_unit() -> Result<(), Error> {
+    let settings = settings().await?;
+    set_boot_unit(settings.tor_only || !settings.blocked.is_empty()).await
+}
+
+// the new settings are kept even if applying them fails, since a failure while blocking leaves
+// all clearnet egress blocked rather than reverting to the old rules
+async fn update<F: FnOnce(&mut FirewallSettings)>(f: F) -> Result<(), Error> {
+    let mut settings: YamlUpdateHandle<FirewallSettings> =
+        YamlUpdateHandle::new_or_default(PersistencePath::from_ref(FIREWALL_YAML)).await?;
+    f(&mut *settings);
+    let res = apply_settings(&*settings).await;
+    settings.commit().await?;
+    res
+}
+
+pub async fn set_tor_only(tor_only: bool) -> Result<(), Error> {
+    let res = update(|s| s.tor_only = tor_only).await;
+    sync_boot_unit().await?;
+    res
+}
+
+pub async fn set_exempt(app_id: &str, exempt: bool) -> Result<(), Error> {
+    crate::ensure_code!(
+        crate::apps::list_info().await?.contains_key(app_id),
+        crate::error::NOT_FOUND,
+        "App Not Installed: {}",
+        app_id
+    );
+    update(|s| {
+        if exempt {
+            s.exemptions.insert(app_id.to_owned());
+        } else {
+            s.exemptions.remove(app_id);
+        }
+    })
+    .await
+}
+
+pub async fn status() -> Result<FirewallStatus, Error> {
+    Ok(FirewallStatus {
+        settings: settings().await?,
+        active: chain_exists(Family::V4, OUTPUT_CHAIN).await,
+    })
+}
+
+pub async fn block_source(ip: IpAddr) -> Result<(), Error> {
+    let mut settings: YamlUpdateHandle<FirewallSettings> =
+        YamlUpdateHandle::new_or_default(PersistencePath::from_ref(FIREWALL_YAML)).await?;
+    log::info!("Blocking inbound traffic from {}.", ip);
+    settings.blocked.insert(ip);
+    apply_blocklist(&settings.blocked).await?;
+    settings.commit().await?;
+    sync_boot_unit().await
+}
+
+pub async fn unblock_source(ip: IpAddr) -> Result<(), Error> {
+    let mut settings: YamlUpdateHandle<FirewallSettings> =
+        YamlUpdateHandle::new_or_default(PersistencePath::from_ref(FIREWALL_YAML)).await?;
+    settings.blocked.remove(&ip);
+    apply_blocklist(&settings.blocked).await?;
+    settings.commit().await?;
+    sync_boot_unit().await
+}
@@ -0,0 +1,241 @@
+use std::time::Duration;
+
+use emver::VersionRange;
+use linear_map::{set::LinearSet, LinearMap};
+
+use crate::config::{Config, ConfigurationRes};
+use crate::dependencies::TaggedDependencyError;
+use crate::util::{PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const GROUPS_YAML: &'static str = "groups.yaml";
+
+/// A set of apps meant to be run together, i.e. a "Lightning stack" of bitcoind, lnd and RTL.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Group {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub apps: LinearMap<String, GroupMember>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GroupMember {
+    #[serde(default = "VersionRange::any")]
+    pub version: VersionRange,
+    /// The config to give the app, instead of the one it has or its defaults.
+    #[serde(default)]
+    pub preset: Option<Config>,
+    /// The answers to its install prompts.
+    #[serde(default)]
+    pub answers: Option<Config>,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GroupInstallRes {
+    pub installed: Vec<String>,
+    pub configured: ConfigurationRes,
+    pub started: Vec<String>,
+}
+
+async fn groups_mut() -> Result<YamlUpdateHandle<LinearMap<String, Group>>, Error> {
+    YamlUpdateHandle::new_or_default(PersistencePath::from_ref(GROUPS_YAML)).await
+}
+
+/// The groups defined on the device.
+pub async fn list() -> Result<LinearMap<String, Group>, Error> {
+    let path = PersistencePath::from_ref(GROUPS_YAML);
+    Ok(match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await?,
+        None => LinearMap::new(),
+    })
+}
+
+/// The group named `name`, as defined on the device, or else as shipped by the registry.
+pub async fn get(name: &str) -> Result<Group, Error> {
+    if let Some(group) = list().await?.remove(name) {
+        return Ok(group);
+    }
+    crate::registry::group(name).await
+}
+
+pub async fn define(name: &str, group: Group) -> Result<(), Error> {
+    crate::ensure_code!(
+        !group.apps.is_empty(),
+        crate::error::GENERAL_ERROR,
+        "Group {} Has No Apps",
+        name
+    );
+    let mut groups = groups_mut().await?;
+    groups.insert(name.to_owned(), group);
+    groups.commit().await?;
+    Ok(())
+}
+
+pub async fn undefine(name: &str) -> Result<(), Error> {
+    let mut groups = groups_mut().await?;
+    groups
+        .remove(name)
+        .ok_or_else(|| failure::format_err!("No Group Named {}", name))
+        .with_code(crate::error::NOT_FOUND)?;
+    groups.commit().await?;
+    Ok(())
+}
+
+/// Orders `apps` so that each comes after the apps of the group it depends on. Apps in a
+/// dependency cycle keep the order they were given in.
+pub fn dependency_order(
+    mut apps: Vec<String>,
+    deps: &LinearMap<String, LinearSet<String>>,
+) -> Vec<String> {
+    let mut res = Vec::with_capacity(apps.len());
+    let mut done = LinearSet::new();
+    while !apps.is_empty() {
+        let ready = apps
+            .iter()
+            .position(|id| {
+                deps.get(id).map_or(true, |deps| {
+                    deps.iter()
+                        .all(|dep| done.contains(dep) || !apps.contains(dep))
+                })
+            })
+            .unwrap_or(0);
+        let id = apps.remove(ready);
+        done.insert(id.clone());
+        res.push(id);
+    }
+    res
+}
+
+// the dependencies of the installed apps are taken from their manifests, those of the rest from
+// the registry
+async fn order(group: &Group) -> Result<Vec<String>, Error> {
+    let installed = crate::apps::list_info().await?;
+    let mut deps = LinearMap::new();
+    for (id, member) in group.apps.iter() {
+        let manifest = match installed.get(id) {
+            Some(info) if info.version.satisfies(&member.version) => {
+                crate::apps::manifest(id).await?
+            }
+            _ => crate::registry::manifest(id, &member.version).await?,
+        };
+        deps.insert(
+            id.clone(),
+            manifest
+                .dependencies
+                .0
+                .into_iter()
+                .map(|(dep, _)| dep)
+                .filter(|dep| dep != id)
+                .collect::<LinearSet<String>>(),
+        );
+    }
+    Ok(dependency_order(
+        group.apps.keys().cloned().collect(),
+        &deps,
+    ))
+}
+
+/// Installs the apps of a group that are missing, configures all of them with their presets in
+/// one transaction, then starts them, each after the apps it depends on. Apps that were already
+/// running are left to be restarted, as with `configure`.
+pub async fn install(group: &Group, timeout: Option<Duration>) -> Result<GroupInstallRes, Error> {
+    let order = order(group).await?;
+    let mut res = GroupInstallRes::default();
+    for (id, member) in order
+        .iter()
+        .filter_map(|id| Some((id, group.apps.get(id)?)))
+    {
+        let installed = crate::apps::list_info()
+            .await?
+            .get(id)
+            .map_or(false, |info| info.version.satisfies(&member.version));
+        if installed {
+            continue;
+        }
+        log::info!("Installing {}.", id);
+        crate::install_name(
+            &format!("{}@{}", id, member.version),
+            true,
+            member.answers.clone(),
+        )
+        .await?;
+        res.installed.push(id.clone());
+    }
+    res.configured = crate::config::configure_many(
+        order
+            .iter()
+            .map(|id| {
+                let preset = group.apps.get(id).and_then(|member| member.preset.clone());
+                (id.clone(), preset)
+            })
+            .collect(),
+        timeout,
+        false,
+    )
+    .await?;
+    for id in &order {
+        let status = crate::apps::status(id, false).await?.status;
+        if status == crate::apps::DockerStatus::Stopped {
+            crate::start_app(id, true).await?;
+            res.started.push(id.clone());
+        }
+    }
+    Ok(res)
+}
+
+/// Starts the stopped apps of a group, each after the apps it depends on.
+pub async fn start(group: &Group) -> Result<Vec<String>, Error> {
+    let mut res = Vec::new();
+    for id in order(group).await? {
+        if crate::apps::status(&id, false).await?.status == crate::apps::DockerStatus::Stopped {
+            crate::start_app(&id, true).await?;
+            res.push(id);
+        }
+    }
+    Ok(res)
+}
+
+/// Stops the apps of a group, each before the apps it depends on, along with their dependents
+/// outside of the group.
+pub async fn stop(
+    group: &Group,
+    dry_run: bool,
+) -> Result<LinearMap<String, TaggedDependencyError>, Error> {
+    let mut res = LinearMap::new();
+    for id in order(group).await?.into_iter().rev() {
+        if crate::apps::status(&id, false).await?.status != crate::apps::DockerStatus::Stopped {
+            res.extend(crate::stop_app(&id, true, dry_run).await?);
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dependency_order() {
+        let mut deps = LinearMap::new();
+        deps.insert(
+            "rtl".to_owned(),
+            vec!["lnd".to_owned()].into_iter().collect::<LinearSet<_>>(),
+        );
+        deps.insert(
+            "lnd".to_owned(),
+            vec!["bitcoind".to_owned(), "tor".to_owned()]
+                .into_iter()
+                .collect::<LinearSet<_>>(),
+        );
+        let order = dependency_order(
+            vec!["rtl".to_owned(), "lnd".to_owned(), "bitcoind".to_owned()],
+            &deps,
+        );
+        assert_eq!(order, vec!["bitcoind", "lnd", "rtl"]);
+    }
+}
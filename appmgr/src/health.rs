@@ -0,0 +1,422 @@
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use linear_map::LinearMap;
+use tokio_compat_02::FutureExt;
+
+use crate::apps::DockerStatus;
+use crate::util::{Invoke, PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const HEALTH_YAML: &'static str = "health.yaml";
+/// Runs the monitor while any installed app declares health checks.
+pub const MONITOR_UNIT: &'static str = "/etc/systemd/system/appmgr-health.service";
+/// How often the monitor looks for checks that are due.
+pub const TICK: Duration = Duration::from_secs(1);
+
+fn default_interval() -> u64 {
+    30
+}
+fn default_timeout() -> u64 {
+    10
+}
+fn default_retries() -> u32 {
+    3
+}
+fn default_path() -> String {
+    "/".to_owned()
+}
+
+/// How a health check probes an app.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "kebab-case")]
+pub enum Probe {
+    /// A GET of `path` on the app, which passes unless it answers with an error status.
+    Http {
+        port: u16,
+        #[serde(default = "default_path")]
+        path: String,
+    },
+    /// Passes if the app accepts a connection on `port`.
+    Tcp { port: u16 },
+    /// Run in the container of the app, and passes if it exits successfully.
+    Command { command: Vec<String> },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HealthCheck {
+    #[serde(flatten)]
+    pub probe: Probe,
+    /// Seconds between probes.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Seconds before a probe that has not passed fails.
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /// Probes that must fail in a row before the app is unhealthy.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Health {
+    /// Not probed yet, or failing fewer times than it is allowed to since it started.
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
+/// The result of the probes of a check so far.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CheckState {
+    pub health: Health,
+    /// Failed probes in a row.
+    pub failures: u32,
+    /// In seconds since the epoch.
+    pub checked_at: u64,
+    /// Why the last probe failed.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The health of an app as a whole: unhealthy if any of its checks are, healthy once all of them
+/// are.
+pub fn overall<'a, I: IntoIterator<Item = &'a CheckState>>(states: I) -> Option<Health> {
+    let mut res = None;
+    for state in states {
+        res = match (res, state.health) {
+            (_, Health::Unhealthy) | (Some(Health::Unhealthy), _) => Some(Health::Unhealthy),
+            (_, Health::Starting) | (Some(Health::Starting), _) => Some(Health::Starting),
+            _ => Some(Health::Healthy),
+        };
+    }
+    res
+}
+
+/// The state of a check after a probe. A failure only makes the check unhealthy once it has
+/// failed `retries` times in a row, until then it stays as it was.
+pub fn record(
+    prev: Option<&CheckState>,
+    retries: u32,
+    result: Result<(), String>,
+    now: u64,
+) -> CheckState {
+    match result {
+        Ok(()) => CheckState {
+            health: Health::Healthy,
+            failures: 0,
+            checked_at: now,
+            error: None,
+        },
+        Err(e) => {
+            let failures = prev.map_or(0, |s| s.failures) + 1;
+            CheckState {
+                health: if failures >= retries {
+                    Health::Unhealthy
+                } else {
+                    prev.map_or(Health::Starting, |s| s.health)
+                },
+                failures,
+                checked_at: now,
+                error: Some(e),
+            }
+        }
+    }
+}
+
+fn path(id: &str) -> PersistencePath {
+    PersistencePath::from_ref("apps").join(id).join(HEALTH_YAML)
+}
+
+/// The state of every check of an app, as of the last time the monitor probed it. Empty unless
+/// the app is running.
+pub async fn get(id: &str) -> Result<LinearMap<String, CheckState>, Error> {
+    match path(id).maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await,
+        None => Ok(LinearMap::new()),
+    }
+}
+
+async fn clear(id: &str) -> Result<(), Error> {
+    let path = path(id);
+    if path.exists().await {
+        path.delete().await?;
+    }
+    Ok(())
+}
+
+async fn probe(id: &str, ip: Option<Ipv4Addr>, check: &HealthCheck) -> Result<(), String> {
+    let run = async {
+        match &check.probe {
+            Probe::Http { port, path } => {
+                let ip = ip.ok_or_else(|| format!("{} Has No IP Address", id))?;
+                reqwest::get(&format!("http://{}:{}{}", ip, port, path))
+                    .compat()
+                    .await
+                    .and_then(|res| res.error_for_status())
+                    .map_err(|e| format!("{}", e))?;
+                Ok(())
+            }
+            Probe::Tcp { port } => {
+                let ip = ip.ok_or_else(|| format!("{} Has No IP Address", id))?;
+                tokio::net::TcpStream::connect((ip, *port))
+                    .await
+                    .map_err(|e| format!("{}:{}: {}", ip, port, e))?;
+                Ok(())
+            }
+            Probe::Command { command } => {
+                let output = tokio::process::Command::new("docker")
+                    .arg("exec")
+                    .arg(id)
+                    .args(command)
+                    .kill_on_drop(true)
+                    .output()
+                    .await
+                    .map_err(|e| format!("{}", e))?;
+                if output.status.success() {
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Exited With {}: {}",
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ))
+                }
+            }
+        }
+    };
+    match tokio::time::timeout(Duration::from_secs(check.timeout), run).await {
+        Ok(res) => res,
+        Err(_) => Err(format!("Timed Out After {}s", check.timeout)),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Probes the checks of an app that are due, and records their state. `due` is when each of its
+/// checks is next due, and is cleared when the app is not running.
+async fn check_app(
+    id: &str,
+    ip: Option<Ipv4Addr>,
+    due: &mut HashMap<String, Instant>,
+) -> Result<(), Error> {
+    let manifest = crate::apps::manifest(id).await?;
+    if manifest.health_checks.is_empty() {
+        return Ok(());
+    }
+    if crate::apps::status(id, false).await?.status != DockerStatus::Running {
+        due.clear();
+        return clear(id).await;
+    }
+    let now_instant = Instant::now();
+    let checks: Vec<_> = manifest
+        .health_checks
+        .iter()
+        .filter(|(check_id, _)| due.get(*check_id).map_or(true, |t| *t <= now_instant))
+        .collect();
+    if checks.is_empty() {
+        return Ok(());
+    }
+    let results =
+        futures::future::join_all(checks.iter().map(|(_, check)| probe(id, ip, check))).await;
+    let mut states: YamlUpdateHandle<LinearMap<String, CheckState>> =
+        YamlUpdateHandle::new_or_default(path(id)).await?;
+    let before = overall(states.values());
+    // checks the manifest no longer declares are dropped
+    let mut next: LinearMap<String, CheckState> = manifest
+        .health_checks
+        .keys()
+        .filter_map(|check_id| Some((check_id.clone(), states.get(check_id)?.clone())))
+        .collect();
+    for ((check_id, check), result) in checks.into_iter().zip(results) {
+        due.insert(
+            check_id.clone(),
+            now_instant + Duration::from_secs(check.interval),
+        );
+        if let Err(e) = &result {
+            log::info!("{}: Health Check {} Failed: {}", id, check_id, e);
+        }
+        let state = record(next.get(check_id), check.retries, result, now());
+        next.insert(check_id.clone(), state);
+    }
+    let after = overall(next.values());
+    *states = next;
+    states.commit().await?;
+    match (before, after) {
+        (Some(Health::Unhealthy), Some(Health::Unhealthy)) => (),
+        (_, Some(Health::Unhealthy)) => {
+            log::warn!("{} Is Unhealthy", id);
+            crate::events::emit(crate::events::EventKind::Unhealthy, id).await;
+        }
+        (Some(Health::Unhealthy), Some(Health::Healthy)) => {
+            log::info!("{} Is Healthy Again", id);
+            crate::events::emit(crate::events::EventKind::Healthy, id).await;
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+async fn systemctl(args: &[&str]) -> Result<(), Error> {
+    tokio::process::Command::new("systemctl")
+        .args(args)
+        .invoke("Systemd")
+        .await
+        .with_code(crate::error::GENERAL_ERROR)?;
+    Ok(())
+}
+
+/// Installs and starts the unit that runs the monitor if any installed app declares health
+/// checks, and stops and removes it otherwise.
+pub async fn sync_monitor_unit() -> Result<(), Error> {
+    let mut enabled = false;
+    for (id, _) in crate::apps::list_info().await? {
+        if !crate::apps::manifest(&id).await?.health_checks.is_empty() {
+            enabled = true;
+            break;
+        }
+    }
+    let path = std::path::Path::new(MONITOR_UNIT);
+    let name = path
+        .file_name()
+        .and_then(|a| a.to_str())
+        .unwrap_or_default();
+    if enabled {
+        if path.exists() {
+            return Ok(());
+        }
+        let exe = std::env::current_exe()?;
+        tokio::fs::write(
+            path,
+            format!(
+                "[Unit]\nDescription=Probe the health checks of running apps\nAfter=docker.service\n\n[Service]\nExecStart={} health monitor\nRestart=always\nRestartSec=5\n\n[Install]\nWantedBy=multi-user.target\n",
+                exe.display()
+            ),
+        )
+        .await?;
+        systemctl(&["daemon-reload"]).await?;
+        systemctl(&["enable", "--now", name]).await?;
+    } else if path.exists() {
+        systemctl(&["disable", "--now", name]).await?;
+        tokio::fs::remove_file(path).await?;
+        systemctl(&["daemon-reload"]).await?;
+    }
+    Ok(())
+}
+
+// hands an app back to the monitor once its probe task ends, even if the task panics
+struct Done {
+    id: String,
+    due: HashMap<String, Instant>,
+    send: std::sync::mpsc::Sender<(String, HashMap<String, Instant>)>,
+}
+impl Drop for Done {
+    fn drop(&mut self) {
+        self.send
+            .send((std::mem::take(&mut self.id), std::mem::take(&mut self.due)))
+            .unwrap_or_default();
+    }
+}
+
+/// Probes the health checks of every running app as they come due, and records their state for
+/// the app list. The state of an app that is not running is dropped, so it starts over when it
+/// is started again. Each app is probed on its own task, so a slow probe only holds up its own
+/// app, and an app that cannot be checked is logged and skipped. Does not return.
+pub async fn monitor() -> Result<(), Error> {
+    let mut due: HashMap<String, HashMap<String, Instant>> = HashMap::new();
+    // apps being probed, which are not probed again until they are done
+    let mut in_flight: HashSet<String> = HashSet::new();
+    let (done_send, done_recv) = std::sync::mpsc::channel();
+    loop {
+        while let Ok((id, app_due)) = done_recv.try_recv() {
+            in_flight.remove(&id);
+            due.insert(id, app_due);
+        }
+        let services = match crate::tor::services_map(&PersistencePath::from_ref(
+            crate::SERVICES_YAML,
+        ))
+        .await
+        {
+            Ok(a) => a,
+            Err(e) => {
+                log::warn!("Could not read services: {}", e.failure);
+                tokio::time::sleep(TICK).await;
+                continue;
+            }
+        };
+        let apps = match crate::apps::list_info().await {
+            Ok(a) => a,
+            Err(e) => {
+                log::warn!("Could not read app list: {}", e.failure);
+                tokio::time::sleep(TICK).await;
+                continue;
+            }
+        };
+        due.retain(|id, _| apps.contains_key(id));
+        for (id, _) in apps {
+            if in_flight.contains(&id) {
+                continue;
+            }
+            let ip = services.map.get(&id).map(|s| s.ip);
+            let mut done = Done {
+                due: due.remove(&id).unwrap_or_default(),
+                id: id.clone(),
+                send: done_send.clone(),
+            };
+            in_flight.insert(id);
+            tokio::spawn(crate::util::app_lock_scope(async move {
+                if let Err(e) = check_app(&done.id, ip, &mut done.due).await {
+                    log::warn!("Could not check health of {}: {}", done.id, e.failure);
+                }
+            }));
+        }
+        tokio::time::sleep(TICK).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let check: HealthCheck =
+            serde_yaml::from_str("type: http\nport: 8080\nretries: 2\n").unwrap();
+        assert_eq!(
+            check.probe,
+            Probe::Http {
+                port: 8080,
+                path: "/".to_owned()
+            }
+        );
+        assert_eq!(check.interval, 30);
+        let failed = record(None, check.retries, Err("refused".to_owned()), 1);
+        assert_eq!(failed.health, Health::Starting);
+        let failed = record(Some(&failed), check.retries, Err("refused".to_owned()), 2);
+        assert_eq!(failed.health, Health::Unhealthy);
+        assert_eq!(failed.failures, 2);
+        let passed = record(Some(&failed), check.retries, Ok(()), 3);
+        assert_eq!(passed.health, Health::Healthy);
+        assert_eq!(passed.failures, 0);
+        let failed = record(Some(&passed), check.retries, Err("refused".to_owned()), 4);
+        assert_eq!(failed.health, Health::Healthy);
+        assert_eq!(overall(vec![&passed, &failed]), Some(Health::Healthy));
+        assert_eq!(
+            overall(vec![&passed, &record(None, 2, Err(String::new()), 5)]),
+            Some(Health::Starting)
+        );
+        assert_eq!(overall(Vec::new()), None);
+    }
+}
@@ -0,0 +1,202 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use crate::apps::DockerStatus;
+use crate::util::PersistencePath;
+use crate::Error;
+use crate::ResultExt as _;
+
+/// Marks an app whose post-install hook has not run successfully yet.
+pub const POST_INSTALL_PENDING: &'static str = "post-install-pending";
+
+/// Commands run in the container of an app at points in its lifecycle.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Hooks {
+    /// Run once, the first time the app is started after being installed. Retried on every start
+    /// until it succeeds.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub post_install: Option<Hook>,
+    /// Run before the app is removed, i.e. to close channels gracefully. Exiting with an error
+    /// aborts the removal, with what the command printed as the reason.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pre_remove: Option<Hook>,
+}
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.post_install.is_none() && self.pre_remove.is_none()
+    }
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Hook {
+    pub command: Vec<String>,
+    /// Seconds the command may run before it is killed.
+    #[serde(default = "Hook::default_timeout")]
+    pub timeout: u64,
+}
+impl Hook {
+    fn default_timeout() -> u64 {
+        60
+    }
+}
+
+#[derive(Debug, Fail, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[fail(
+    display = "{} Hook Of {} Aborted (exit {}): {}",
+    hook, app, code, reason
+)]
+pub struct HookAborted {
+    pub app: String,
+    pub hook: &'static str,
+    pub code: i32,
+    pub reason: String,
+}
+
+/// Runs `hook` in the container of `id`: with `docker exec` if it is running, otherwise in a
+/// fresh container with its volume mounted. Returns what the command printed.
+async fn run(id: &str, name: &'static str, hook: &Hook) -> Result<String, Error> {
+    let entrypoint = hook
+        .command
+        .get(0)
+        .ok_or_else(|| failure::format_err!("{} Hook Command Cannot Be Empty", name))
+        .with_code(crate::error::GENERAL_ERROR)?;
+    let status = crate::apps::status(id, false).await?.status;
+    let container = format!("{}_{}", id, name);
+    let mut cmd = tokio::process::Command::new("docker");
+    if status == DockerStatus::Running {
+        cmd.arg("exec").arg(id).args(&hook.command);
+    } else {
+        let manifest = crate::apps::manifest(id).await?;
+        cmd.arg("run")
+            .arg("--rm")
+            .arg("--name")
+            .arg(&container)
+            .arg("--mount")
+            .arg(format!(
+                "type=bind,src={}/{},dst={}",
+                crate::VOLUMES,
+                id,
+                manifest.mount.display()
+            ))
+            .arg("--entrypoint")
+            .arg(entrypoint)
+            .arg(format!("start9/{}", id))
+            .args(&hook.command[1..]);
+    }
+    log::info!("Running {} hook of {}.", name, id);
+    let child = cmd
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let output =
+        match tokio::time::timeout(Duration::from_secs(hook.timeout), child.wait_with_output())
+            .await
+        {
+            Ok(output) => output?,
+            Err(_) => {
+                if status != DockerStatus::Running {
+                    tokio::process::Command::new("docker")
+                        .args(&["rm", "-f", &container])
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .status()
+                        .await?;
+                }
+                return Err(failure::format_err!(
+                    "{} Hook Of {} Timed Out After {}s",
+                    name,
+                    id,
+                    hook.timeout
+                ))
+                .with_code(crate::error::TIMEOUT_ERROR);
+            }
+        };
+    if output.status.success() {
+        log::info!("{} hook of {} succeeded.", name, id);
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = if stderr.trim().is_empty() {
+        stdout.trim()
+    } else {
+        stderr.trim()
+    };
+    Err(Error::new(
+        HookAborted {
+            app: id.to_owned(),
+            hook: name,
+            code: output.status.code().unwrap_or(-1),
+            reason: reason.to_owned(),
+        },
+        Some(crate::error::HOOK_ABORTED),
+    ))
+}
+
+fn pending_path(id: &str) -> PersistencePath {
+    PersistencePath::from_ref("apps")
+        .join(id)
+        .join(POST_INSTALL_PENDING)
+}
+
+/// Called by install, so that the post-install hook runs on the next start.
+pub async fn set_post_install_pending(id: &str) -> Result<(), Error> {
+    let f = pending_path(id).write(None).await?;
+    f.commit().await
+}
+
+/// Runs the post-install hook of `id` if it has not succeeded yet. A failure is logged rather
+/// than returned, since the app is already running, and the hook is tried again on the next start.
+pub async fn post_install(id: &str) -> Result<(), Error> {
+    let path = pending_path(id);
+    if !path.path().exists() {
+        return Ok(());
+    }
+    let hook = match crate::apps::manifest(id).await?.hooks.post_install {
+        Some(a) => a,
+        None => return path.delete().await,
+    };
+    match run(id, "post-install", &hook).await {
+        Ok(_) => path.delete().await,
+        Err(e) => {
+            log::error!("{}", e.failure);
+            Ok(())
+        }
+    }
+}
+
+/// Runs the pre-remove hook of `id`, if it has one. An error aborts the removal.
+pub async fn pre_remove(id: &str) -> Result<(), Error> {
+    match crate::apps::manifest(id).await?.hooks.pre_remove {
+        Some(hook) => run(id, "pre-remove", &hook).await.map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hooks() {
+        let hooks: Hooks = serde_yaml::from_str(
+            r#"
+pre-remove:
+  command: ["lncli", "closeallchannels"]
+  timeout: 300
+post-install:
+  command: ["/usr/local/bin/init-wallet.sh"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(hooks.pre_remove.unwrap().timeout, 300);
+        assert_eq!(hooks.post_install.unwrap().timeout, 60);
+        assert!(Hooks::default().is_empty());
+    }
+}
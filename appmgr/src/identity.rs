@@ -0,0 +1,234 @@
+use failure::ResultExt as _;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::X509;
+
+use crate::util::{Invoke, PersistencePath};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const IDENTITY_DIR: &'static str = "identity";
+pub const INT_CA_CONF: &'static str = "/root/agent/ca/intermediate/openssl.conf";
+pub const INT_CA_KEY: &'static str = "/root/agent/ca/intermediate/private/embassy-int-ca.key.pem";
+pub const INT_CA_CERT: &'static str = "/root/agent/ca/intermediate/certs/embassy-int-ca.crt.pem";
+pub const ROOT_CA_CERT: &'static str = "/root/agent/ca/certs/embassy-root-ca.cert.pem";
+/// The API certificate is reissued when it expires in fewer days than this.
+pub const RENEW_DAYS: u32 = 30;
+
+fn path(file: &str) -> PersistencePath {
+    PersistencePath::from_ref(IDENTITY_DIR).join(file)
+}
+
+// as the agent computes it: the first 4 bytes of the hash of the product key, in hex
+fn server_id_from_product_key(product_key: &str) -> String {
+    let hash = openssl::sha::sha256(product_key.as_bytes());
+    let mut res = "start9-".to_owned();
+    for b in &hash[..4] {
+        res.push_str(&format!("{:02x}", b));
+    }
+    res
+}
+
+/// The id of this device, which is also its hostname on the LAN.
+pub async fn server_id() -> Result<String, Error> {
+    let product_key = tokio::fs::read_to_string(crate::encryption::PRODUCT_KEY_PATH)
+        .await
+        .with_context(|e| format!("{}: {}", crate::encryption::PRODUCT_KEY_PATH, e))
+        .with_code(crate::error::NOT_FOUND)?;
+    Ok(server_id_from_product_key(
+        product_key.lines().next().unwrap_or_default(),
+    ))
+}
+
+/// The keypair of the device, generated the first time it is needed and never replaced, so that
+/// clients can pin it across certificate renewals.
+pub async fn device_key() -> Result<PKey<Private>, Error> {
+    let key_path = path("device.key.pem");
+    if let Some(mut f) = key_path.maybe_read(false).await.transpose()? {
+        use tokio::io::AsyncReadExt;
+        let mut pem = Vec::new();
+        f.read_to_end(&mut pem).await?;
+        return PKey::private_key_from_pem(&pem).with_code(crate::error::SERDE_ERROR);
+    }
+    log::info!("Generating device identity key.");
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).no_code()?;
+    let key = PKey::from_ec_key(EcKey::generate(&group).no_code()?).no_code()?;
+    let pem = key.private_key_to_pem_pkcs8().no_code()?;
+    let mut f = key_path.write(None).await?;
+    {
+        use tokio::io::AsyncWriteExt;
+        f.write_all(&pem).await?;
+    }
+    tokio::fs::set_permissions(
+        key_path.tmp(),
+        std::os::unix::fs::PermissionsExt::from_mode(0o600),
+    )
+    .await?;
+    f.commit().await?;
+    Ok(key)
+}
+
+fn hex_fingerprint(digest: &[u8]) -> String {
+    digest
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// What a client needs to pin the device.
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Fingerprints {
+    pub hostname: String,
+    /// SHA-256 of the certificate, as browsers show it.
+    pub cert_sha256: String,
+    /// Base64 SHA-256 of the public key (as in HPKP), which survives renewals.
+    pub spki_sha256: String,
+    pub not_after: String,
+}
+
+fn fingerprints(hostname: String, cert: &X509) -> Result<Fingerprints, Error> {
+    let spki = cert.public_key().no_code()?.public_key_to_der().no_code()?;
+    Ok(Fingerprints {
+        hostname,
+        cert_sha256: hex_fingerprint(&cert.digest(MessageDigest::sha256()).no_code()?),
+        spki_sha256: openssl::base64::encode_block(&openssl::sha::sha256(&spki)),
+        not_after: format!("{}", cert.not_after()),
+    })
+}
+
+async fn read_cert() -> Result<Option<X509>, Error> {
+    match path("api.crt.pem").maybe_read(false).await.transpose()? {
+        Some(mut f) => {
+            use tokio::io::AsyncReadExt;
+            let mut pem = Vec::new();
+            f.read_to_end(&mut pem).await?;
+            Ok(Some(
+                X509::from_pem(&pem).with_code(crate::error::SERDE_ERROR)?,
+            ))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Issues a certificate for the management API from the on-device CA, for the device key and
+/// `<server id>.local`. Does nothing if the current one matches the key and is not about to
+/// expire, unless `force` is set.
+pub async fn issue(force: bool) -> Result<Fingerprints, Error> {
+    let hostname = format!("{}.local", server_id().await?);
+    let key = device_key().await?;
+    if let Some(cert) = read_cert().await? {
+        let current = cert.public_key().no_code()?.public_eq(&key)
+            && cert.not_after() > openssl::asn1::Asn1Time::days_from_now(RENEW_DAYS).no_code()?;
+        if current && !force {
+            return fingerprints(hostname, &cert);
+        }
+    }
+    log::info!("Issuing API certificate for {}.", hostname);
+    let conf_path = path("api.csr.conf").path();
+    let req_path = path("api.csr").path();
+    let cert_path = path("api.crt.pem").path();
+    tokio::fs::write(
+        &conf_path,
+        format!(
+            include_str!("cert-local.csr.conf.template"),
+            hostname = hostname.trim_end_matches(".local")
+        ),
+    )
+    .await?;
+    tokio::process::Command::new("openssl")
+        .arg("req")
+        .arg("-config")
+        .arg(&conf_path)
+        .arg("-key")
+        .arg(path("device.key.pem").path())
+        .arg("-new")
+        .arg("-addext")
+        .arg(format!("subjectAltName=DNS:{}", hostname))
+        .arg("-out")
+        .arg(&req_path)
+        .invoke("OpenSSL Req")
+        .await?;
+    tokio::process::Command::new("openssl")
+        .arg("ca")
+        .arg("-batch")
+        .arg("-config")
+        .arg(INT_CA_CONF)
+        .arg("-rand_serial")
+        .arg("-keyfile")
+        .arg(INT_CA_KEY)
+        .arg("-cert")
+        .arg(INT_CA_CERT)
+        .arg("-extensions")
+        .arg("server_cert")
+        .arg("-days")
+        .arg("365")
+        .arg("-notext")
+        .arg("-in")
+        .arg(&req_path)
+        .arg("-out")
+        .arg(&cert_path)
+        .invoke("OpenSSL CA")
+        .await?;
+    let mut fullchain = tokio::fs::read(&cert_path).await?;
+    for ca in &[INT_CA_CERT, ROOT_CA_CERT] {
+        fullchain.extend(
+            tokio::fs::read(ca)
+                .await
+                .with_context(|e| format!("{}: {}", ca, e))
+                .with_code(crate::error::FILESYSTEM_ERROR)?,
+        );
+    }
+    let mut f = path("api.fullchain.crt.pem").write(None).await?;
+    {
+        use tokio::io::AsyncWriteExt;
+        f.write_all(&fullchain).await?;
+    }
+    f.commit().await?;
+    let cert = read_cert()
+        .await?
+        .ok_or_else(|| failure::format_err!("API Certificate Missing After Issue"))
+        .no_code()?;
+    fingerprints(hostname, &cert)
+}
+
+pub async fn show() -> Result<Fingerprints, Error> {
+    let hostname = format!("{}.local", server_id().await?);
+    let cert = read_cert()
+        .await?
+        .ok_or_else(|| failure::format_err!("No API Certificate, Run `identity issue` First"))
+        .with_code(crate::error::NOT_FOUND)?;
+    fingerprints(hostname, &cert)
+}
+
+/// The PEM of the API certificate, or of its full chain up to the root CA.
+pub async fn export(chain: bool) -> Result<String, Error> {
+    let file = if chain {
+        "api.fullchain.crt.pem"
+    } else {
+        "api.crt.pem"
+    };
+    let path = path(file).path();
+    tokio::fs::read_to_string(&path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::NOT_FOUND)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_server_id() {
+        assert_eq!(server_id_from_product_key("0a1b2c3d"), "start9-2510026a");
+    }
+
+    #[test]
+    fn test_hex_fingerprint() {
+        assert_eq!(hex_fingerprint(&[0x0a, 0xff, 0x10]), "0A:FF:10");
+    }
+}
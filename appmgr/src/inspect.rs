@@ -1,12 +1,10 @@
 use std::path::Path;
 
-use failure::ResultExt as _;
-use futures::stream::StreamExt;
-use tokio_tar as tar;
-
 use crate::config::{ConfigRuleEntry, ConfigSpec};
-use crate::manifest::{Manifest, ManifestLatest};
-use crate::util::from_cbor_async_reader;
+use crate::instructions::OutputFormat;
+use crate::manifest::{ManifestLatest, Provenance};
+use crate::s9pk::S9pkReader;
+use crate::signing::SignatureInfo;
 use crate::version::VersionT;
 use crate::Error;
 use crate::ResultExt as _;
@@ -20,6 +18,8 @@ pub struct AppInfoFull {
     pub manifest: Option<ManifestLatest>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub config: Option<AppConfig>,
+    /// Unset if the package is not signed.
+    pub signature: Option<SignatureInfo>,
 }
 
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -27,6 +27,9 @@ pub struct AppInfoFull {
 pub struct AppInfo {
     pub title: String,
     pub version: emver::Version,
+    #[serde(default)]
+    #[serde(flatten)]
+    pub provenance: Provenance,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -42,28 +45,13 @@ pub async fn info_full<P: AsRef<Path>>(
     with_config: bool,
 ) -> Result<AppInfoFull, Error> {
     let p = path.as_ref();
-    log::info!("Opening file.");
-    let r = tokio::fs::File::open(p)
-        .await
-        .with_context(|e| format!("{}: {}", p.display(), e))
-        .with_code(crate::error::FILESYSTEM_ERROR)?;
-    log::info!("Extracting archive.");
-    let mut pkg = tar::Archive::new(r);
-    let mut entries = pkg.entries()?;
+    log::info!("Checking signature.");
+    let signature = crate::signing::check(p).await?;
+    crate::checksums::check(p).await?;
+    log::info!("Indexing archive.");
+    let mut pkg = S9pkReader::open(p).await?;
     log::info!("Opening manifest from archive.");
-    let manifest = entries
-        .next()
-        .await
-        .ok_or(crate::install::Error::CorruptedPkgFile("missing manifest"))
-        .no_code()??;
-    crate::ensure_code!(
-        manifest.path()?.to_str() == Some("manifest.cbor"),
-        crate::error::GENERAL_ERROR,
-        "Package File Invalid or Corrupted"
-    );
-    log::trace!("Deserializing manifest.");
-    let manifest: Manifest = from_cbor_async_reader(manifest).await?;
-    let manifest = manifest.into_latest();
+    let manifest = pkg.manifest().await?;
     crate::ensure_code!(
         crate::version::Current::new()
             .semver()
@@ -76,70 +64,31 @@ pub async fn info_full<P: AsRef<Path>>(
         info: AppInfo {
             title: manifest.title.clone(),
             version: manifest.version.clone(),
+            provenance: manifest.provenance.clone(),
         },
         manifest: if with_manifest { Some(manifest) } else { None },
         config: if with_config {
             log::info!("Opening config spec from archive.");
-            let spec = entries
-                .next()
-                .await
-                .ok_or(crate::install::Error::CorruptedPkgFile(
-                    "missing config spec",
-                ))
-                .no_code()??;
-            crate::ensure_code!(
-                spec.path()?.to_str() == Some("config_spec.cbor"),
-                crate::error::GENERAL_ERROR,
-                "Package File Invalid or Corrupted"
-            );
-            log::trace!("Deserializing config spec.");
-            let spec = from_cbor_async_reader(spec).await?;
+            let spec = pkg.config_spec().await?;
             log::info!("Opening config rules from archive.");
-            let rules = entries
-                .next()
-                .await
-                .ok_or(crate::install::Error::CorruptedPkgFile(
-                    "missing config rules",
-                ))
-                .no_code()??;
-            crate::ensure_code!(
-                rules.path()?.to_str() == Some("config_rules.cbor"),
-                crate::error::GENERAL_ERROR,
-                "Package File Invalid or Corrupted"
-            );
-            log::trace!("Deserializing config rules.");
-            let rules = from_cbor_async_reader(rules).await?;
+            let rules = pkg.config_rules().await?;
             Some(AppConfig { spec, rules })
         } else {
             None
         },
+        signature,
     })
 }
 
-pub async fn print_instructions<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+pub async fn print_instructions<P: AsRef<Path>>(
+    path: P,
+    format: OutputFormat,
+) -> Result<(), Error> {
     let p = path.as_ref();
-    log::info!("Opening file.");
-    let r = tokio::fs::File::open(p)
-        .await
-        .with_context(|e| format!("{}: {}", p.display(), e))
-        .with_code(crate::error::FILESYSTEM_ERROR)?;
-    log::info!("Extracting archive.");
-    let mut pkg = tar::Archive::new(r);
-    let mut entries = pkg.entries()?;
+    log::info!("Indexing archive.");
+    let mut pkg = S9pkReader::open(p).await?;
     log::info!("Opening manifest from archive.");
-    let manifest = entries
-        .next()
-        .await
-        .ok_or(crate::install::Error::CorruptedPkgFile("missing manifest"))
-        .no_code()??;
-    crate::ensure_code!(
-        manifest.path()?.to_str() == Some("manifest.cbor"),
-        crate::error::GENERAL_ERROR,
-        "Package File Invalid or Corrupted"
-    );
-    log::trace!("Deserializing manifest.");
-    let manifest: Manifest = from_cbor_async_reader(manifest).await?;
-    let manifest = manifest.into_latest();
+    let manifest = pkg.manifest().await?;
     crate::ensure_code!(
         crate::version::Current::new()
             .semver()
@@ -148,44 +97,10 @@ pub async fn print_instructions<P: AsRef<Path>>(path: P) -> Result<(), Error> {
         "AppMgr Version Not Compatible: needs {}",
         manifest.os_version_required
     );
-    entries
-        .next()
-        .await
-        .ok_or(crate::install::Error::CorruptedPkgFile(
-            "missing config spec",
-        ))
-        .no_code()??;
-    entries
-        .next()
-        .await
-        .ok_or(crate::install::Error::CorruptedPkgFile(
-            "missing config rules",
-        ))
-        .no_code()??;
 
     if manifest.has_instructions {
-        use tokio::io::AsyncWriteExt;
-
-        let mut instructions = entries
-            .next()
-            .await
-            .ok_or(crate::install::Error::CorruptedPkgFile(
-                "missing instructions",
-            ))
-            .no_code()??;
-
-        let mut stdout = tokio::io::stdout();
-        tokio::io::copy(&mut instructions, &mut stdout)
-            .await
-            .with_code(crate::error::FILESYSTEM_ERROR)?;
-        stdout
-            .flush()
-            .await
-            .with_code(crate::error::FILESYSTEM_ERROR)?;
-        stdout
-            .shutdown()
-            .await
-            .with_code(crate::error::FILESYSTEM_ERROR)?;
+        let text = pkg.instructions().await?;
+        crate::instructions::print(&text, manifest.instructions_format, format).await?;
     } else {
         return Err(failure::format_err!("No instructions for {}", p.display()))
             .with_code(crate::error::NOT_FOUND);
@@ -4,12 +4,11 @@ use std::marker::Unpin;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::{
-    atomic::{self, AtomicBool, AtomicU64},
+    atomic::{self, AtomicU64},
     Arc,
 };
 use std::task::Context;
 use std::task::Poll;
-use std::time::Duration;
 
 use failure::ResultExt as _;
 use futures::stream::StreamExt;
@@ -19,9 +18,17 @@ use tokio::io::{AsyncRead, ReadBuf};
 use tokio_compat_02::FutureExt;
 use tokio_tar as tar;
 
-use crate::config::{ConfigRuleEntry, ConfigSpec};
+use crate::backend::Backend;
+use crate::config::{
+    Config, ConfigRuleEntryWithSuggestions, ConfigSpec, EntropyProvider, OsEntropy,
+};
 use crate::manifest::{ImageConfig, Manifest, ManifestV0};
-use crate::util::{from_cbor_async_reader, to_yaml_async_writer, AsyncCompat, PersistencePath};
+use crate::progress::{ProgressGuard, Unit};
+use crate::s9pk::reader::next_entry;
+use crate::s9pk::{CONFIG_RULES_CBOR, CONFIG_SPEC_CBOR, INSTRUCTIONS_MD, MANIFEST_CBOR};
+use crate::util::{
+    from_cbor_async_reader, to_yaml_async_writer, AsyncCompat, Invoke, PersistencePath,
+};
 use crate::version::VersionT;
 use crate::ResultExt as _;
 
@@ -33,25 +40,14 @@ pub enum Error {
     InvalidFileName,
 }
 
-pub async fn install_name(name_version: &str, use_cache: bool) -> Result<(), crate::Error> {
+pub async fn install_name(
+    name_version: &str,
+    use_cache: bool,
+    answers: Option<Config>,
+) -> Result<(), crate::Error> {
     let name = name_version.split("@").next().unwrap();
-    let tmp_path = Path::new(crate::TMP_DIR).join(format!("{}.s9pk", name));
-    if !use_cache || !tmp_path.exists() {
-        download_name(name_version).await?;
-    }
-    install_path(
-        &tmp_path
-            .as_os_str()
-            .to_str()
-            .ok_or(Error::InvalidFileName)
-            .with_code(crate::error::FILESYSTEM_ERROR)?,
-        Some(name),
-    )
-    .await?;
-    tokio::fs::remove_file(&tmp_path)
-        .await
-        .with_context(|e| format!("{}: {}", tmp_path.display(), e))
-        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let path = fetch_name(name_version, use_cache).await?;
+    install_path(&path, Some(name), answers).await?;
     Ok(())
 }
 
@@ -76,29 +72,113 @@ where
     }
 }
 
+/// Downloads the package of `name_version`, or reuses the cached one if the version it resolves
+/// to was downloaded before.
 pub async fn download_name(name_version: &str) -> Result<PathBuf, crate::Error> {
+    fetch_name(name_version, true).await
+}
+
+// with `use_cache` unset, the package is downloaded again and replaces the cached one. If the
+// registry cannot be reached, the newest cached version in range is used.
+async fn fetch_name(name_version: &str, use_cache: bool) -> Result<PathBuf, crate::Error> {
     let mut split = name_version.split("@");
     let name = split.next().unwrap();
-    let req: Option<emver::VersionRange> = split.next().map(|a| a.parse()).transpose().no_code()?;
-    if let Some(req) = req {
-        download(
-            &format!("{}/{}.s9pk?spec={}", &*crate::APP_REGISTRY_URL, name, req),
-            Some(name),
-        )
-        .await
-    } else {
-        download(
-            &format!("{}/{}.s9pk", &*crate::APP_REGISTRY_URL, name),
-            Some(name),
-        )
-        .await
+    let req: emver::VersionRange = split
+        .next()
+        .map(|a| a.parse())
+        .transpose()
+        .no_code()?
+        .unwrap_or_else(emver::VersionRange::any);
+    let version = match crate::registry::version(name, &req).await {
+        Ok(version) => version,
+        Err(e) if use_cache && e.code == Some(crate::error::NETWORK_ERROR) => {
+            match crate::cache::newest(name, &req).await? {
+                Some(path) => {
+                    log::warn!("{}: Using Cached {}.", e.failure, path.display());
+                    return Ok(path);
+                }
+                None => return Err(e),
+            }
+        }
+        Err(e) => return Err(e),
+    };
+    if use_cache {
+        if let Some(path) = crate::cache::get(name, &version).await? {
+            log::info!("Using cached {} v{}.", name, version);
+            return Ok(path);
+        }
+        if let Some(path) = fetch_delta(name, &version).await? {
+            return crate::cache::insert(name, &version, &path).await;
+        }
+    }
+    let download_path = download(
+        &format!(
+            "{}/{}.s9pk?spec=={}",
+            &*crate::APP_REGISTRY_URL,
+            name,
+            version
+        ),
+        Some(name),
+    )
+    .await?;
+    crate::cache::insert(name, &version, &download_path).await
+}
+
+// Rebuilds `version` of an installed app from the cached package of its installed version and a
+// delta from the registry, which is much less to download than a whole package. Any failure
+// falls back to downloading the whole package.
+async fn fetch_delta(
+    name: &str,
+    version: &emver::Version,
+) -> Result<Option<PathBuf>, crate::Error> {
+    let installed = match crate::apps::list_info().await?.get(name) {
+        Some(info) if &info.version != version => info.version.clone(),
+        _ => return Ok(None),
+    };
+    let base = match crate::cache::get(name, &installed).await? {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+    let delta_path = match download(
+        &format!(
+            "{}/{}.s9pk.delta?from=={}&spec=={}",
+            &*crate::APP_REGISTRY_URL,
+            name,
+            installed,
+            version
+        ),
+        Some(&format!("{}.delta", name)),
+    )
+    .await
+    {
+        Ok(a) => a,
+        Err(e) => {
+            log::info!("No Delta From {} v{}: {}", name, installed, e);
+            return Ok(None);
+        }
+    };
+    let path = Path::new(crate::TMP_DIR).join(format!("{}.s9pk", name));
+    let res = crate::delta::apply(&base, &delta_path, &path).await;
+    tokio::fs::remove_file(&delta_path).await?;
+    match res {
+        Ok(()) => {
+            log::info!("Rebuilt {} v{} From v{}.", name, version, installed);
+            Ok(Some(path))
+        }
+        Err(e) => {
+            log::warn!("Could Not Apply Delta: {}", e);
+            Ok(None)
+        }
     }
 }
 
 pub async fn download(url: &str, name: Option<&str>) -> Result<PathBuf, crate::Error> {
     let url = reqwest::Url::parse(url).no_code()?;
     log::info!("Downloading {}.", url.as_str());
-    let response = reqwest::get(url)
+    let response = crate::registry::client()
+        .await?
+        .get(url)
+        .send()
         .compat()
         .await
         .with_code(crate::error::NETWORK_ERROR)?
@@ -112,8 +192,7 @@ pub async fn download(url: &str, name: Option<&str>) -> Result<PathBuf, crate::E
         log::info!("{}KiB to download.", a / 1024);
         a
     });
-    let done = Arc::new(AtomicBool::new(false));
-    let counter = Arc::new(AtomicU64::new(0));
+    let progress = ProgressGuard::start("Downloading", Unit::Bytes, len);
     let mut reader = CountingReader(
         AsyncCompat(
             response
@@ -121,42 +200,24 @@ pub async fn download(url: &str, name: Option<&str>) -> Result<PathBuf, crate::E
                 .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
                 .into_async_read(),
         ),
-        counter.clone(),
+        progress.progress.counter(),
     );
-    let done_handle = done.clone();
-    let download_handle = tokio::spawn(async move {
-        let res = tokio::io::copy(&mut reader, &mut f).await;
-        done_handle.store(true, atomic::Ordering::SeqCst);
-        res
-    });
-    let poll_handle = tokio::spawn(async move {
-        loop {
-            let is_done = done.load(atomic::Ordering::SeqCst);
-            let downloaded_bytes = counter.load(atomic::Ordering::SeqCst);
-            if !*crate::QUIET.read().await {
-                if let Some(len) = len {
-                    print!("\rDownloading... {}%", downloaded_bytes * 100 / len);
-                } else {
-                    print!("\rDownloading... {}KiB", downloaded_bytes / 1024);
-                }
-            }
-            if is_done {
-                break;
-            }
-            tokio::time::sleep(Duration::from_millis(10)).await;
-        }
-        if !*crate::QUIET.read().await {
-            println!("\rDownloading... 100%");
-        }
-    });
-    download_handle.await.unwrap()?;
-    poll_handle.await.unwrap();
+    tokio::io::copy(&mut reader, &mut f).await?;
+    progress.finish().await;
     Ok(tmp_file_path)
 }
 
-pub async fn install_url(url: &str, name: Option<&str>) -> Result<(), crate::Error> {
+pub async fn install_url(
+    url: &str,
+    name: Option<&str>,
+    answers: Option<Config>,
+) -> Result<(), crate::Error> {
     let tmp_file_path = download(url, name).await?;
-    install_path(&tmp_file_path, name).await?;
+    if let Err(e) = crate::signing::verify_trusted(&tmp_file_path).await {
+        tokio::fs::remove_file(&tmp_file_path).await?;
+        return Err(e);
+    }
+    install_path(&tmp_file_path, name, answers).await?;
     tokio::fs::remove_file(&tmp_file_path)
         .await
         .with_context(|e| format!("{}: {}", tmp_file_path.display(), e))
@@ -164,7 +225,11 @@ pub async fn install_url(url: &str, name: Option<&str>) -> Result<(), crate::Err
     Ok(())
 }
 
-pub async fn install_path<P: AsRef<Path>>(p: P, name: Option<&str>) -> Result<(), crate::Error> {
+pub async fn install_path<P: AsRef<Path>>(
+    p: P,
+    name: Option<&str>,
+    answers: Option<Config>,
+) -> Result<(), crate::Error> {
     let path = p.as_ref();
     log::info!(
         "Starting install of {}.",
@@ -173,37 +238,16 @@ pub async fn install_path<P: AsRef<Path>>(p: P, name: Option<&str>) -> Result<()
             .ok_or(Error::InvalidFileName)
             .no_code()?
     );
+    crate::checksums::check(path).await?;
     let file = tokio::fs::File::open(&path)
         .await
         .with_context(|e| format!("{}: {}", path.display(), e))
         .with_code(crate::error::FILESYSTEM_ERROR)?;
     let len = file.metadata().await?.len();
-    let done = Arc::new(AtomicBool::new(false));
-    let counter = Arc::new(AtomicU64::new(0));
-    let done_handle = done.clone();
-    let name_clone = name.map(|a| a.to_owned());
-    let counter_clone = counter.clone();
-    let poll_handle = tokio::spawn(async move {
-        loop {
-            let is_done = done.load(atomic::Ordering::SeqCst);
-            let installed_bytes = counter.load(atomic::Ordering::SeqCst);
-            if !*crate::QUIET.read().await {
-                print!("\rInstalling... {}%", installed_bytes * 100 / len);
-            }
-            if is_done {
-                break;
-            }
-            tokio::time::sleep(Duration::from_millis(10)).await;
-        }
-        if !*crate::QUIET.read().await {
-            println!("\rInstalling... 100%");
-        }
-    });
-    let reader = CountingReader(file, counter_clone);
-    let res = install(reader, name_clone.as_ref().map(|a| a.as_str())).await;
-    done_handle.store(true, atomic::Ordering::SeqCst);
-    res?;
-    poll_handle.await.unwrap();
+    let progress = ProgressGuard::start("Installing", Unit::Bytes, Some(len));
+    let reader = CountingReader(file, progress.progress.counter());
+    install(reader, name, answers).await?;
+    progress.finish().await;
     if !*crate::QUIET.read().await {
         println!("Complete.");
     }
@@ -213,33 +257,74 @@ pub async fn install_path<P: AsRef<Path>>(p: P, name: Option<&str>) -> Result<()
 pub async fn install<R: AsyncRead + Unpin + Send + Sync>(
     r: R,
     name: Option<&str>,
+    answers: Option<Config>,
 ) -> Result<(), crate::Error> {
     log::info!("Extracting archive.");
+    crate::progress::phase("Extracting archive");
     let mut pkg = tar::Archive::new(r);
     let mut entries = pkg.entries()?;
     log::info!("Opening manifest from archive.");
-    let manifest = entries
-        .next()
-        .await
-        .ok_or(Error::CorruptedPkgFile("missing manifest"))
-        .no_code()??;
-    crate::ensure_code!(
-        manifest.path()?.to_str() == Some("manifest.cbor"),
-        crate::error::GENERAL_ERROR,
-        "Package File Invalid or Corrupted"
-    );
+    let manifest = next_entry(&mut entries, MANIFEST_CBOR).await?;
     log::trace!("Deserializing manifest.");
     let manifest: Manifest = from_cbor_async_reader(manifest).await.no_code()?;
     match manifest {
-        Manifest::V0(m) => install_v0(m, entries, name).await?,
+        Manifest::V0(m) => install_v0(m, entries, name, answers).await?,
     };
     Ok(())
 }
 
+/// Pulls an image by its digest and tags it as `tag`. Docker checks the content it pulls against
+/// the digest; the check here is that it did pull by digest.
+async fn pull_image(reference: &str, digest: &str, tag: &str) -> Result<(), crate::Error> {
+    crate::ensure_code!(
+        crate::manifest::is_valid_digest(digest),
+        crate::error::GENERAL_ERROR,
+        "Invalid Image Digest: {}",
+        digest
+    );
+    let pinned = format!("{}@{}", reference, digest);
+    log::info!("Pulling docker image {}.", pinned);
+    crate::progress::phase("Pulling image");
+    tokio::process::Command::new("docker")
+        .arg("pull")
+        .arg(&pinned)
+        .invoke("Docker Pull")
+        .await
+        .with_code(crate::error::DOCKER_ERROR)?;
+    let repo_digests = tokio::process::Command::new("docker")
+        .arg("image")
+        .arg("inspect")
+        .arg("--format")
+        .arg("{{json .RepoDigests}}")
+        .arg(&pinned)
+        .invoke("Docker Inspect")
+        .await
+        .with_code(crate::error::DOCKER_ERROR)?;
+    let repo_digests: Vec<String> =
+        serde_json::from_slice(&repo_digests).with_code(crate::error::SERDE_ERROR)?;
+    crate::ensure_code!(
+        repo_digests
+            .iter()
+            .any(|d| d.ends_with(&format!("@{}", digest))),
+        crate::error::DOCKER_ERROR,
+        "Pulled Image Does Not Match Digest {}",
+        digest
+    );
+    tokio::process::Command::new("docker")
+        .arg("tag")
+        .arg(&pinned)
+        .arg(tag)
+        .invoke("Docker Tag")
+        .await
+        .with_code(crate::error::DOCKER_ERROR)?;
+    Ok(())
+}
+
 pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
     manifest: ManifestV0,
     mut entries: tar::Entries<R>,
     name: Option<&str>,
+    answers: Option<Config>,
 ) -> Result<(), crate::Error> {
     crate::ensure_code!(
         crate::version::Current::new()
@@ -249,6 +334,22 @@ pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
         "OS Version Not Compatible: need {}",
         manifest.os_version_required
     );
+    let image_tar = match &manifest.image {
+        ImageConfig::Registry { .. } => None,
+        image => Some(
+            image
+                .tar_for(std::env::consts::ARCH)
+                .ok_or_else(|| {
+                    format_err!(
+                        "{} Has No Image For {}",
+                        manifest.id,
+                        std::env::consts::ARCH
+                    )
+                })
+                .with_code(crate::error::VERSION_INCOMPATIBLE)?,
+        ),
+    };
+    crate::backend::validate(&manifest)?;
     if let Some(name) = name {
         crate::ensure_code!(
             manifest.id == name,
@@ -257,6 +358,7 @@ pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
         );
     }
 
+    let _lock = crate::util::lock_app(&manifest.id).await?;
     log::info!(
         "Creating metadata directory: {}/apps/{}",
         crate::PERSISTENCE_DIR,
@@ -264,6 +366,52 @@ pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
     );
     let app_dir = PersistencePath::from_ref("apps").join(&manifest.id);
     let app_dir_path = app_dir.path();
+    let fresh = !app_dir_path.exists();
+    crate::system::check(&manifest.id, &manifest.requirements, fresh)?;
+    let previous = if app_dir_path.exists() {
+        crate::config::migration::previous(&manifest.id)
+            .await
+            .unwrap_or_else(|e| {
+                log::warn!("Could not read previous config: {}", e.failure);
+                None
+            })
+    } else {
+        None
+    };
+    let answers = match &manifest.install_prompts {
+        Some(prompts) => {
+            // answers kept from the installed copy, for the prompts this version still has
+            let answers = match answers {
+                Some(a) => a,
+                None if app_dir_path.exists() => crate::config::answers::get(&manifest.id)
+                    .await?
+                    .map(|a| {
+                        Config(
+                            a.0.into_iter()
+                                .filter(|(k, _)| prompts.0.contains_key(k))
+                                .collect(),
+                        )
+                    })
+                    .unwrap_or_default(),
+                None => Config::default(),
+            };
+            Some(crate::config::answers::resolve(
+                prompts,
+                answers,
+                &mut OsEntropy.rng(&manifest.id),
+                &None,
+            )?)
+        }
+        None => {
+            crate::ensure_code!(
+                answers.is_none(),
+                crate::error::GENERAL_ERROR,
+                "{} Has No Install Prompts",
+                manifest.id
+            );
+            None
+        }
+    };
     if app_dir_path.exists() {
         tokio::fs::remove_dir_all(&app_dir_path).await?;
     }
@@ -281,24 +429,22 @@ pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
     let recoverable = Path::new(crate::VOLUMES).join(&manifest.id).exists();
 
     log::info!("Creating volume {}/{}.", crate::VOLUMES, manifest.id);
+    crate::progress::phase("Saving metadata");
     tokio::fs::create_dir_all(Path::new(crate::VOLUMES).join(&manifest.id)).await?;
 
-    let _lock = app_dir.lock(true).await?;
     log::info!("Saving manifest.");
     let mut manifest_out = app_dir.join("manifest.yaml").write(None).await?;
     to_yaml_async_writer(&mut *manifest_out, &Manifest::V0(manifest.clone())).await?;
     manifest_out.commit().await?;
+    if let Some(answers) = &answers {
+        log::info!("Saving install answers.");
+        crate::config::answers::save(&manifest.id, answers).await?;
+    }
+    if fresh && manifest.hooks.post_install.is_some() {
+        crate::hooks::set_post_install_pending(&manifest.id).await?;
+    }
     log::info!("Opening config spec from archive.");
-    let config_spec = entries
-        .next()
-        .await
-        .ok_or(Error::CorruptedPkgFile("missing config spec"))
-        .no_code()??;
-    crate::ensure_code!(
-        config_spec.path()?.to_str() == Some("config_spec.cbor"),
-        crate::error::GENERAL_ERROR,
-        "Package File Invalid or Corrupted"
-    );
+    let config_spec = next_entry(&mut entries, CONFIG_SPEC_CBOR).await?;
     log::trace!("Deserializing config spec.");
     let config_spec: ConfigSpec = from_cbor_async_reader(config_spec).await?;
     log::info!("Saving config spec.");
@@ -306,34 +452,17 @@ pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
     to_yaml_async_writer(&mut *config_spec_out, &config_spec).await?;
     config_spec_out.commit().await?;
     log::info!("Opening config rules from archive.");
-    let config_rules = entries
-        .next()
-        .await
-        .ok_or(Error::CorruptedPkgFile("missing config rules"))
-        .no_code()??;
-    crate::ensure_code!(
-        config_rules.path()?.to_str() == Some("config_rules.cbor"),
-        crate::error::GENERAL_ERROR,
-        "Package File Invalid or Corrupted"
-    );
+    let config_rules = next_entry(&mut entries, CONFIG_RULES_CBOR).await?;
     log::trace!("Deserializing config rules.");
-    let config_rules: Vec<ConfigRuleEntry> = from_cbor_async_reader(config_rules).await?;
+    let config_rules: Vec<ConfigRuleEntryWithSuggestions> =
+        from_cbor_async_reader(config_rules).await?;
     log::info!("Saving config rules.");
     let mut config_rules_out = app_dir.join("config_rules.yaml").write(None).await?;
     to_yaml_async_writer(&mut *config_rules_out, &config_rules).await?;
     config_rules_out.commit().await?;
     if manifest.has_instructions {
         log::info!("Opening instructions from archive.");
-        let mut instructions = entries
-            .next()
-            .await
-            .ok_or(Error::CorruptedPkgFile("missing config rules"))
-            .no_code()??;
-        crate::ensure_code!(
-            instructions.path()?.to_str() == Some("instructions.md"),
-            crate::error::GENERAL_ERROR,
-            "Package File Invalid or Corrupted"
-        );
+        let mut instructions = next_entry(&mut entries, INSTRUCTIONS_MD).await?;
         log::info!("Saving instructions.");
         let mut instructions_out = app_dir.join("instructions.md").write(None).await?;
         tokio::io::copy(&mut instructions, &mut *instructions_out)
@@ -343,6 +472,7 @@ pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
     }
 
     log::info!("Copying over assets.");
+    crate::progress::phase("Copying assets");
     for asset in manifest.assets.iter() {
         let dst_path = Path::new(crate::VOLUMES)
             .join(&manifest.id)
@@ -398,61 +528,73 @@ pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
         }
     }
 
-    let tag = match &manifest.image {
-        ImageConfig::Tar => {
-            let image_name = format!("start9/{}", manifest.id);
-            let tag = format!("{}:latest", image_name);
-            if tokio::process::Command::new("docker")
-                .arg("images")
-                .arg("-q")
-                .arg(&image_name)
-                .output()
-                .await?
-                .stdout
-                .len()
-                > 0
-            {
-                tokio::process::Command::new("docker")
-                    .arg("stop")
-                    .arg(&manifest.id)
-                    .spawn()?
-                    .wait()
-                    .await?;
+    let tag = {
+        let image_name = format!("start9/{}", manifest.id);
+        let tag = format!("{}:latest", image_name);
+        if tokio::process::Command::new("docker")
+            .arg("images")
+            .arg("-q")
+            .arg(&image_name)
+            .output()
+            .await?
+            .stdout
+            .len()
+            > 0
+        {
+            tokio::process::Command::new("docker")
+                .arg("stop")
+                .arg(&manifest.id)
+                .spawn()?
+                .wait()
+                .await?;
+            tokio::process::Command::new("docker")
+                .arg("rm")
+                .arg(&manifest.id)
+                .spawn()?
+                .wait()
+                .await?;
+            crate::ensure_code!(
                 tokio::process::Command::new("docker")
-                    .arg("rm")
-                    .arg(&manifest.id)
-                    .spawn()?
-                    .wait()
-                    .await?;
-                crate::ensure_code!(
-                    tokio::process::Command::new("docker")
-                        .arg("rmi")
-                        .arg(&image_name)
-                        .output()
-                        .await?
-                        .status
-                        .success(),
-                    crate::error::DOCKER_ERROR,
-                    "Failed to Remove Existing Image"
-                )
-            }
-            log::info!("Opening image.tar from archive.");
+                    .arg("rmi")
+                    .arg(&image_name)
+                    .output()
+                    .await?
+                    .status
+                    .success(),
+                crate::error::DOCKER_ERROR,
+                "Failed to Remove Existing Image"
+            )
+        }
+        for tar_name in manifest.image.tar_names() {
+            log::info!("Opening {} from archive.", tar_name);
             let mut image = entries
                 .next()
                 .await
-                .ok_or(Error::CorruptedPkgFile("missing image.tar"))
+                .ok_or(Error::CorruptedPkgFile("missing image"))
                 .no_code()??;
             let image_path = image.path()?;
-            if image_path != Path::new("image.tar") {
+            if image_path != Path::new(&tar_name) {
                 return Err(crate::Error::from(format_err!(
-                    "Package File Invalid or Corrupted: expected image.tar, got {}",
+                    "Package File Invalid or Corrupted: expected {}, got {}",
+                    tar_name,
                     image_path.display()
                 )));
             }
+            if Some(&tar_name) != image_tar.as_ref() {
+                continue;
+            }
+            if let ImageConfig::Static { .. } = &manifest.image {
+                log::info!("Installing {} from archive.", tar_name);
+                crate::progress::phase("Installing binary");
+                crate::backend::install_binary(&manifest, &mut image).await?;
+                continue;
+            }
             log::info!(
-                "Loading docker image start9/{} from image.tar.",
-                manifest.id
+                "Loading docker image start9/{} from {}.",
+                manifest.id,
+                tar_name
             );
+            crate::progress::phase("Loading image");
             let mut child = tokio::process::Command::new("docker")
                 .arg("load")
                 .stdin(std::process::Stdio::piped())
@@ -472,72 +614,43 @@ pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
                 crate::error::DOCKER_ERROR,
                 "Failed to Load Docker Image From Tar"
             );
-            tag
         }
+        if let ImageConfig::Registry { reference, digest } = &manifest.image {
+            pull_image(reference, digest, &tag).await?;
+        }
+        tag
     };
-    log::info!("Creating docker container: {} from {}.", manifest.id, tag);
-    let volume_arg = format!(
-        "type=bind,src={}/{},dst={}",
-        crate::VOLUMES,
-        manifest.id,
-        manifest.mount.display()
-    );
-    let mut args = vec![
-        Cow::Borrowed(OsStr::new("create")),
-        Cow::Borrowed(OsStr::new("--restart")),
-        Cow::Borrowed(OsStr::new("no")),
-        Cow::Borrowed(OsStr::new("--name")),
-        Cow::Borrowed(OsStr::new(&manifest.id)),
-        Cow::Borrowed(OsStr::new("--mount")),
-        Cow::Borrowed(OsStr::new(&volume_arg)),
-        Cow::Borrowed(OsStr::new("--net")),
-        Cow::Borrowed(OsStr::new("start9")),
-        Cow::Borrowed(OsStr::new("--ip")),
-        Cow::Owned(OsString::from(format!("{}", ip))),
-    ];
+    let mut env = Vec::new();
     if let (Some(ref tor_addr), Some(ref tor_key)) = (&tor_addr, &tor_key) {
-        args.extend(
-            std::iter::empty()
-                .chain(std::iter::once(Cow::Borrowed(OsStr::new("--env"))))
-                .chain(std::iter::once(Cow::Owned(OsString::from(format!(
-                    "TOR_ADDRESS={}",
-                    tor_addr
-                )))))
-                .chain(std::iter::once(Cow::Borrowed(OsStr::new("--env"))))
-                .chain(std::iter::once(Cow::Owned(OsString::from(format!(
-                    "TOR_KEY={}",
-                    tor_key
-                ))))),
-        );
+        env.push(format!("TOR_ADDRESS={}", tor_addr));
+        env.push(format!("TOR_KEY={}", tor_key));
     }
-    if let Some(shm_size_mb) = manifest.shm_size_mb {
-        args.push(Cow::Borrowed(OsStr::new("--shm-size")));
-        args.push(Cow::Owned(OsString::from(format!("{}m", shm_size_mb))));
+    match Backend::of(&manifest.image) {
+        Backend::Docker => {
+            log::info!("Creating docker container: {} from {}.", manifest.id, tag);
+            crate::progress::phase("Creating container");
+            create_container(&manifest, &tag, ip, &env)?;
+        }
+        Backend::Systemd => {
+            log::info!(
+                "Writing systemd unit: {}.",
+                crate::backend::unit_name(&manifest.id)
+            );
+            crate::progress::phase("Creating service");
+            crate::backend::write_unit(&manifest, ip, &env).await?;
+        }
     }
-    args.push(Cow::Borrowed(OsStr::new(&tag)));
-    crate::ensure_code!(
-        std::process::Command::new("docker")
-            .args(&args)
-            .stdout(std::process::Stdio::null())
-            .stderr(match log::max_level() {
-                log::LevelFilter::Error => std::process::Stdio::null(),
-                _ => std::process::Stdio::inherit(),
-            })
-            .status()?
-            .success(),
-        crate::error::DOCKER_ERROR,
-        "Failed to Create Docker Container"
-    );
     tokio::fs::create_dir_all(Path::new(crate::VOLUMES).join(&manifest.id).join("start9")).await?;
-    if let Some(public) = manifest.public {
+    if let Some(public) = &manifest.public {
         tokio::fs::create_dir_all(Path::new(crate::VOLUMES).join(&manifest.id).join(public))
             .await?;
     }
-    if let Some(shared) = manifest.shared {
+    if let Some(shared) = &manifest.shared {
         tokio::fs::create_dir_all(Path::new(crate::VOLUMES).join(&manifest.id).join(shared))
             .await?;
     }
     log::info!("Updating app list.");
+    crate::progress::phase("Configuring");
     crate::apps::add(
         &manifest.id,
         crate::apps::AppInfo {
@@ -547,9 +660,33 @@ pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
             configured: false,
             recoverable,
             needs_restart: false,
+            pinned: false,
+            system: manifest.system,
+            mode: crate::modes::current(&manifest).await?,
+            provenance: manifest.provenance.clone(),
         },
     )
     .await?;
+    if let Some((version, cfg)) = previous {
+        let cfg = match crate::config::migration::migrate(
+            &manifest,
+            &version,
+            cfg.clone(),
+            answers.as_ref(),
+        )
+        .await
+        {
+            Ok(a) => a,
+            Err(e) => {
+                log::error!(
+                    "Config migration failed, keeping previous config: {}",
+                    e.failure
+                );
+                cfg
+            }
+        };
+        app_dir.join("config.yaml").write_sealed(&cfg).await?;
+    }
     let config = crate::apps::config(&manifest.id).await?;
     if let Some(cfg) = config.config {
         if config.spec.matches(&cfg).is_ok() {
@@ -574,6 +711,63 @@ pub async fn install_v0<R: AsyncRead + Unpin + Send + Sync>(
             }
         }
     }
+    crate::docs::refresh().await;
+    if let Err(e) = crate::health::sync_monitor_unit().await {
+        log::warn!("Failed to set up the health monitor: {}", e.failure);
+    }
+    crate::events::emit(crate::events::EventKind::Installed, &manifest.id).await;
+
+    Ok(())
+}
 
+/// Creates the container of an app from `tag`, with its volume mounted and `env` set. Also used to
+/// recreate the container when its environment changes, since docker fixes it at creation.
+pub(crate) fn create_container(
+    manifest: &ManifestV0,
+    tag: &str,
+    ip: std::net::Ipv4Addr,
+    env: &[String],
+) -> Result<(), crate::Error> {
+    let volume_arg = format!(
+        "type=bind,src={}/{},dst={}",
+        crate::VOLUMES,
+        manifest.id,
+        manifest.mount.display()
+    );
+    let mut args = vec![
+        Cow::Borrowed(OsStr::new("create")),
+        Cow::Borrowed(OsStr::new("--restart")),
+        Cow::Borrowed(OsStr::new("no")),
+        Cow::Borrowed(OsStr::new("--name")),
+        Cow::Borrowed(OsStr::new(&manifest.id)),
+        Cow::Borrowed(OsStr::new("--mount")),
+        Cow::Borrowed(OsStr::new(&volume_arg)),
+        Cow::Borrowed(OsStr::new("--net")),
+        Cow::Borrowed(OsStr::new("start9")),
+        Cow::Borrowed(OsStr::new("--ip")),
+        Cow::Owned(OsString::from(format!("{}", ip))),
+    ];
+    for var in env {
+        args.push(Cow::Borrowed(OsStr::new("--env")));
+        args.push(Cow::Borrowed(OsStr::new(var)));
+    }
+    if let Some(shm_size_mb) = manifest.shm_size_mb {
+        args.push(Cow::Borrowed(OsStr::new("--shm-size")));
+        args.push(Cow::Owned(OsString::from(format!("{}m", shm_size_mb))));
+    }
+    args.push(Cow::Borrowed(OsStr::new(tag)));
+    crate::ensure_code!(
+        std::process::Command::new("docker")
+            .args(&args)
+            .stdout(std::process::Stdio::null())
+            .stderr(match log::max_level() {
+                log::LevelFilter::Error => std::process::Stdio::null(),
+                _ => std::process::Stdio::inherit(),
+            })
+            .status()?
+            .success(),
+        crate::error::DOCKER_ERROR,
+        "Failed to Create Docker Container"
+    );
     Ok(())
 }
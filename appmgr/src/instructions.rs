@@ -0,0 +1,284 @@
+use tokio::io::AsyncWriteExt;
+
+use crate::Error;
+use crate::ResultExt as _;
+
+const BOLD: &'static str = "\x1b[1m";
+const NO_BOLD: &'static str = "\x1b[22m";
+const DIM: &'static str = "\x1b[2m";
+const ITALIC: &'static str = "\x1b[3m";
+const NO_ITALIC: &'static str = "\x1b[23m";
+const UNDERLINE: &'static str = "\x1b[4m";
+const NO_UNDERLINE: &'static str = "\x1b[24m";
+const CODE: &'static str = "\x1b[36m";
+const DEFAULT_FG: &'static str = "\x1b[39m";
+const RESET: &'static str = "\x1b[0m";
+
+/// What the instructions of a package are written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InstructionsFormat {
+    Markdown,
+    Html,
+    Plaintext,
+}
+impl Default for InstructionsFormat {
+    fn default() -> Self {
+        InstructionsFormat::Markdown
+    }
+}
+
+/// How to print instructions: as they are, for the UI to render, or rendered for a terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Raw,
+    Ansi,
+}
+impl std::str::FromStr for OutputFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(OutputFormat::Raw),
+            "ansi" => Ok(OutputFormat::Ansi),
+            _ => Err(failure::format_err!("Unknown Output Format: {}", s))
+                .with_code(crate::error::GENERAL_ERROR),
+        }
+    }
+}
+
+fn find(chars: &[char], from: usize, c: char) -> Option<usize> {
+    chars[from..]
+        .iter()
+        .position(|a| *a == c)
+        .map(|idx| from + idx)
+}
+
+fn is_word(c: Option<&char>) -> bool {
+    c.map_or(false, |c| c.is_alphanumeric())
+}
+
+// `[text](url)` starting at `start`, as the text, the url, and the index after it
+fn link(chars: &[char], start: usize) -> Option<(String, String, usize)> {
+    let close = find(chars, start + 1, ']')?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let end = find(chars, close + 2, ')')?;
+    Some((
+        chars[start + 1..close].iter().collect(),
+        chars[close + 2..end].iter().collect(),
+        end + 1,
+    ))
+}
+
+fn inline(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut res = String::new();
+    let mut bold = false;
+    let mut italic = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let prev = if i > 0 { chars.get(i - 1) } else { None };
+        if c == '\\' && i + 1 < chars.len() {
+            res.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+        if c == '`' {
+            if let Some(end) = find(&chars, i + 1, '`') {
+                res.push_str(CODE);
+                res.extend(&chars[i + 1..end]);
+                res.push_str(DEFAULT_FG);
+                i = end + 1;
+                continue;
+            }
+        }
+        if c == '[' || (c == '!' && chars.get(i + 1) == Some(&'[')) {
+            let start = if c == '!' { i + 1 } else { i };
+            if let Some((text, url, end)) = link(&chars, start) {
+                res.push_str(UNDERLINE);
+                res.push_str(&inline(&text));
+                res.push_str(NO_UNDERLINE);
+                res.push_str(&format!(" ({})", url));
+                i = end;
+                continue;
+            }
+        }
+        let emphasis = match c {
+            '*' => true,
+            // underscores only emphasize at the edge of a word, so snake_case is left alone
+            '_' => {
+                let run = if chars.get(i + 1) == Some(&'_') { 2 } else { 1 };
+                !is_word(prev) || !is_word(chars.get(i + run))
+            }
+            _ => false,
+        };
+        if emphasis && chars.get(i + 1) == Some(&c) {
+            bold = !bold;
+            res.push_str(if bold { BOLD } else { NO_BOLD });
+            i += 2;
+            continue;
+        }
+        if emphasis {
+            italic = !italic;
+            res.push_str(if italic { ITALIC } else { NO_ITALIC });
+            i += 1;
+            continue;
+        }
+        res.push(c);
+        i += 1;
+    }
+    if bold || italic {
+        res.push_str(RESET);
+    }
+    res
+}
+
+/// Renders markdown for a terminal, line by line. Covers what instructions use: headings,
+/// lists, quotes, code, emphasis and links.
+pub fn markdown_to_ansi(text: &str) -> String {
+    let mut res = String::new();
+    let mut in_code = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            res.push_str(&format!("    {}{}{}\n", DIM, line, RESET));
+            continue;
+        }
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        if level > 0 && level <= 6 && trimmed[level..].starts_with(' ') {
+            let heading = inline(trimmed[level..].trim());
+            if level == 1 {
+                res.push_str(&format!("{}{}{}{}\n", BOLD, UNDERLINE, heading, RESET));
+            } else {
+                res.push_str(&format!("{}{}{}\n", BOLD, heading, RESET));
+            }
+        } else if trimmed.len() >= 3
+            && (trimmed.chars().all(|c| c == '-') || trimmed.chars().all(|c| c == '*'))
+        {
+            res.push_str(&format!("{}\n", "─".repeat(40)));
+        } else if trimmed.starts_with("- ")
+            || trimmed.starts_with("* ")
+            || trimmed.starts_with("+ ")
+        {
+            let indent = &line[..line.len() - trimmed.len()];
+            res.push_str(&format!("{}  • {}\n", indent, inline(&trimmed[2..])));
+        } else if trimmed.starts_with('>') {
+            res.push_str(&format!(
+                "  {}│{} {}\n",
+                DIM,
+                RESET,
+                inline(trimmed[1..].trim_start())
+            ));
+        } else {
+            res.push_str(&inline(line));
+            res.push('\n');
+        }
+    }
+    res
+}
+
+/// Reduces html to its text for a terminal, keeping line breaks and list items.
+pub fn html_to_text(text: &str) -> String {
+    let mut res = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        res.push_str(&rest[..start]);
+        let end = match rest[start..].find('>') {
+            Some(a) => start + a,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let tag = rest[start + 1..end].trim().to_lowercase();
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or("");
+        match (tag.starts_with('/'), name) {
+            (false, "br") => res.push('\n'),
+            (false, "li") => res.push_str("  • "),
+            (true, "p") | (true, "li") | (true, "div") | (true, "tr") => res.push('\n'),
+            (true, h) if h.len() == 2 && h.starts_with('h') => res.push('\n'),
+            _ => (),
+        }
+        rest = &rest[end + 1..];
+    }
+    res.push_str(rest);
+    res.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+pub fn render(text: &[u8], source: InstructionsFormat, format: OutputFormat) -> Vec<u8> {
+    match (format, source) {
+        (OutputFormat::Raw, _) | (OutputFormat::Ansi, InstructionsFormat::Plaintext) => {
+            text.to_vec()
+        }
+        (OutputFormat::Ansi, InstructionsFormat::Markdown) => {
+            markdown_to_ansi(&String::from_utf8_lossy(text)).into_bytes()
+        }
+        (OutputFormat::Ansi, InstructionsFormat::Html) => {
+            html_to_text(&String::from_utf8_lossy(text)).into_bytes()
+        }
+    }
+}
+
+pub async fn print(
+    text: &[u8],
+    source: InstructionsFormat,
+    format: OutputFormat,
+) -> Result<(), Error> {
+    let mut stdout = tokio::io::stdout();
+    stdout
+        .write_all(&render(text, source, format))
+        .await
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    stdout
+        .flush()
+        .await
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    stdout
+        .shutdown()
+        .await
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_markdown_to_ansi() {
+        assert_eq!(
+            markdown_to_ansi("# Setup\nRun `bitcoin-cli` with **care** and *patience*.\n- see [docs](https://docs.start9labs.com)\nkeep snake_case_names\n"),
+            format!(
+                "{b}{u}Setup{r}\nRun {c}bitcoin-cli{fg} with {b}care{nb} and {i}patience{ni}.\n  • see {u}docs{nu} (https://docs.start9labs.com)\nkeep snake_case_names\n",
+                b = BOLD,
+                u = UNDERLINE,
+                r = RESET,
+                c = CODE,
+                fg = DEFAULT_FG,
+                nb = NO_BOLD,
+                i = ITALIC,
+                ni = NO_ITALIC,
+                nu = NO_UNDERLINE,
+            )
+        );
+        assert_eq!(
+            html_to_text("<h1>Setup</h1><p>Use <b>Tor</b> &amp; LAN</p><ul><li>one</li></ul>"),
+            "Setup\nUse Tor & LAN\n  • one\n"
+        );
+    }
+}
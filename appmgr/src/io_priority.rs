@@ -0,0 +1,302 @@
+use std::path::{Path, PathBuf};
+
+use crate::util::{PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const IO_PRIORITY_YAML: &'static str = "io-priority.yaml";
+pub const CGROUP_ROOT: &'static str = "/sys/fs/cgroup";
+
+/// The background jobs that yield disk I/O to the apps, so they do not starve i.e. an initial
+/// block download.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobClass {
+    Backup,
+    Gc,
+    Download,
+}
+impl JobClass {
+    pub const ALL: [JobClass; 3] = [JobClass::Backup, JobClass::Gc, JobClass::Download];
+}
+impl std::fmt::Display for JobClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobClass::Backup => write!(f, "backup"),
+            JobClass::Gc => write!(f, "gc"),
+            JobClass::Download => write!(f, "download"),
+        }
+    }
+}
+impl std::str::FromStr for JobClass {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "backup" => Ok(JobClass::Backup),
+            "gc" => Ok(JobClass::Gc),
+            "download" => Ok(JobClass::Download),
+            _ => Err(failure::format_err!("Unknown Job Class: {}", s))
+                .with_code(crate::error::GENERAL_ERROR),
+        }
+    }
+}
+
+/// Relative disk I/O weights, on the scale of the cgroup `io.weight` (1 to 10000), where
+/// everything else, the apps included, runs at 100.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct IoWeights {
+    #[serde(default = "IoWeights::default_backup")]
+    pub backup: u16,
+    #[serde(default = "IoWeights::default_gc")]
+    pub gc: u16,
+    #[serde(default = "IoWeights::default_download")]
+    pub download: u16,
+}
+impl IoWeights {
+    fn default_backup() -> u16 {
+        50
+    }
+    fn default_gc() -> u16 {
+        25
+    }
+    fn default_download() -> u16 {
+        50
+    }
+    pub fn get(&self, class: JobClass) -> u16 {
+        match class {
+            JobClass::Backup => self.backup,
+            JobClass::Gc => self.gc,
+            JobClass::Download => self.download,
+        }
+    }
+    fn get_mut(&mut self, class: JobClass) -> &mut u16 {
+        match class {
+            JobClass::Backup => &mut self.backup,
+            JobClass::Gc => &mut self.gc,
+            JobClass::Download => &mut self.download,
+        }
+    }
+}
+impl Default for IoWeights {
+    fn default() -> Self {
+        IoWeights {
+            backup: IoWeights::default_backup(),
+            gc: IoWeights::default_gc(),
+            download: IoWeights::default_download(),
+        }
+    }
+}
+
+pub async fn get() -> Result<IoWeights, Error> {
+    let path = PersistencePath::from_ref(IO_PRIORITY_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await,
+        None => Ok(IoWeights::default()),
+    }
+}
+
+pub async fn set(class: JobClass, weight: u16) -> Result<(), Error> {
+    crate::ensure_code!(
+        (1..=10000).contains(&weight),
+        crate::error::GENERAL_ERROR,
+        "I/O Weight Must Be Between 1 And 10000"
+    );
+    let mut weights: YamlUpdateHandle<IoWeights> =
+        YamlUpdateHandle::new_or_default(PersistencePath::from_ref(IO_PRIORITY_YAML)).await?;
+    *weights.get_mut(class) = weight;
+    weights.commit().await
+}
+
+/// An ionice scheduling class, with its level where it has one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IoNice {
+    BestEffort(u8),
+    Idle,
+}
+impl IoNice {
+    /// The ionice equivalent of a cgroup weight: 100 is the default best-effort level of 4, and
+    /// every halving of the weight is one level lower, down to idle below 10.
+    pub fn from_weight(weight: u16) -> Self {
+        if weight < 10 {
+            return IoNice::Idle;
+        }
+        let level = 4.0 - (weight as f64 / 100.0).log2().round();
+        IoNice::BestEffort(level.max(0.0).min(7.0) as u8)
+    }
+    // see linux/ioprio.h
+    fn ioprio(self) -> libc::c_int {
+        const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+        match self {
+            IoNice::BestEffort(level) => (2 << IOPRIO_CLASS_SHIFT) | level as libc::c_int,
+            IoNice::Idle => 3 << IOPRIO_CLASS_SHIFT,
+        }
+    }
+}
+impl std::fmt::Display for IoNice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IoNice::BestEffort(level) => write!(f, "best-effort {}", level),
+            IoNice::Idle => write!(f, "idle"),
+        }
+    }
+}
+
+// the io priority of every thread of this process, so that whichever one spawns a child passes
+// it on
+fn set_ioprio(ioprio: libc::c_int) -> std::io::Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    for task in std::fs::read_dir("/proc/self/task")? {
+        let tid: libc::c_int = match task?.file_name().to_str().and_then(|a| a.parse().ok()) {
+            Some(a) => a,
+            None => continue,
+        };
+        if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, ioprio) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn get_ioprio() -> std::io::Result<libc::c_int> {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    let res = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, 0) };
+    if res < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(res as libc::c_int)
+    }
+}
+
+// the cgroup v2 this process is in, relative to the root
+fn current_cgroup() -> std::io::Result<Option<PathBuf>> {
+    Ok(std::fs::read_to_string("/proc/self/cgroup")?
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| PathBuf::from(path.trim_start_matches('/'))))
+}
+
+fn move_to_cgroup(cgroup: &Path) -> std::io::Result<()> {
+    std::fs::write(
+        Path::new(CGROUP_ROOT).join(cgroup).join("cgroup.procs"),
+        std::process::id().to_string(),
+    )
+}
+
+// creates the cgroup for `class` with `weight`, and moves this process into it
+fn enter_cgroup(class: JobClass, weight: u16) -> std::io::Result<PathBuf> {
+    let root = Path::new(CGROUP_ROOT);
+    let controllers = std::fs::read_to_string(root.join("cgroup.controllers"))?;
+    if !controllers.split_whitespace().any(|c| c == "io") {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "io controller unavailable",
+        ));
+    }
+    std::fs::write(root.join("cgroup.subtree_control"), "+io")?;
+    let cgroup = PathBuf::from(format!("appmgr-{}", class));
+    std::fs::create_dir_all(root.join(&cgroup))?;
+    std::fs::write(
+        root.join(&cgroup).join("io.weight"),
+        format!("default {}", weight),
+    )?;
+    move_to_cgroup(&cgroup)?;
+    Ok(cgroup)
+}
+
+/// The priority a job runs at, as applied.
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppliedPriority {
+    pub class: JobClass,
+    pub weight: u16,
+    /// `None` if it could not be set.
+    pub ionice: Option<IoNice>,
+    /// `None` if cgroup v2 or its io controller is unavailable.
+    pub cgroup: Option<PathBuf>,
+}
+impl std::fmt::Display for AppliedPriority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at I/O weight {}", self.class, self.weight)?;
+        if let Some(ionice) = &self.ionice {
+            write!(f, ", ionice {}", ionice)?;
+        }
+        if let Some(cgroup) = &self.cgroup {
+            write!(f, ", cgroup {}", cgroup.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs this process, and whatever it spawns, at the priority of a job class until dropped.
+pub struct IoPriorityGuard {
+    pub applied: AppliedPriority,
+    prev_ioprio: Option<libc::c_int>,
+    prev_cgroup: Option<PathBuf>,
+}
+impl Drop for IoPriorityGuard {
+    fn drop(&mut self) {
+        if let Some(ioprio) = self.prev_ioprio {
+            if let Err(e) = set_ioprio(ioprio) {
+                log::warn!("Could not restore I/O priority: {}", e);
+            }
+        }
+        if let Some(cgroup) = &self.prev_cgroup {
+            if let Err(e) = move_to_cgroup(cgroup) {
+                log::warn!("Could not leave cgroup {}: {}", cgroup.display(), e);
+            }
+        }
+    }
+}
+
+/// Lowers the I/O priority of this process to the weight configured for `class`, through both
+/// ionice and the cgroup io controller, since which of them is honored depends on the I/O
+/// scheduler. Either failing is logged rather than returned: the job still runs, only at full
+/// priority. I/O done on behalf of the job by the docker daemon is not covered.
+pub async fn enter(class: JobClass) -> Result<IoPriorityGuard, Error> {
+    let weight = get().await?.get(class);
+    let mut guard = IoPriorityGuard {
+        applied: AppliedPriority {
+            class,
+            weight,
+            ionice: None,
+            cgroup: None,
+        },
+        prev_ioprio: None,
+        prev_cgroup: None,
+    };
+    let ionice = IoNice::from_weight(weight);
+    match get_ioprio().and_then(|prev| set_ioprio(ionice.ioprio()).map(|_| prev)) {
+        Ok(prev) => {
+            guard.prev_ioprio = Some(prev);
+            guard.applied.ionice = Some(ionice);
+        }
+        Err(e) => log::warn!("Could not set I/O priority of {}: {}", class, e),
+    }
+    match current_cgroup().and_then(|prev| enter_cgroup(class, weight).map(|c| (prev, c))) {
+        Ok((prev, cgroup)) => {
+            guard.prev_cgroup = prev;
+            guard.applied.cgroup = Some(cgroup);
+        }
+        Err(e) => log::warn!("Could not set I/O weight of {}: {}", class, e),
+    }
+    log::info!("Running {}.", guard.applied);
+    Ok(guard)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_weight() {
+        assert_eq!(IoNice::from_weight(100), IoNice::BestEffort(4));
+        assert_eq!(IoNice::from_weight(50), IoNice::BestEffort(5));
+        assert_eq!(IoNice::from_weight(25), IoNice::BestEffort(6));
+        assert_eq!(IoNice::from_weight(12), IoNice::BestEffort(7));
+        assert_eq!(IoNice::from_weight(9), IoNice::Idle);
+        assert_eq!(IoNice::from_weight(10000), IoNice::BestEffort(0));
+        assert_eq!(IoNice::ioprio(IoNice::BestEffort(4)), 0x4004);
+    }
+}
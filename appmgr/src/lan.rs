@@ -34,6 +34,7 @@ pub async fn enable_lan() -> Result<(), Error> {
         hostname_buf[0] = 15; // set the prefix length to 15 for the main address
         hostname_buf[16] = 5; // set the prefix length to 5 for "local"
 
+        let mut tor_addresses = Vec::new();
         for (app_id, app_info) in app_list {
             let man = crate::apps::manifest(&app_id).await?;
             if man
@@ -45,11 +46,17 @@ pub async fn enable_lan() -> Result<(), Error> {
             {
                 continue;
             }
-            let tor_address = if let Some(addr) = app_info.tor_address {
-                addr
-            } else {
-                continue;
-            };
+            if let Some(addr) = app_info.tor_address {
+                tor_addresses.push(addr);
+            }
+        }
+        for (_, share) in crate::shares::list().await? {
+            if let Some(addr) = share.tor_address {
+                tor_addresses.push(addr);
+            }
+        }
+
+        for tor_address in tor_addresses {
             let lan_address = tor_address
                 .strip_suffix(".onion")
                 .ok_or_else(|| failure::format_err!("Invalid Tor Address: {:?}", tor_address))?
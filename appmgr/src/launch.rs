@@ -0,0 +1,89 @@
+use crate::manifest::Network;
+use crate::tor::LanOptions;
+use crate::Error;
+use crate::ResultExt as _;
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LaunchUrl {
+    pub name: String,
+    pub network: Network,
+    pub url: String,
+}
+
+/// Picks the best URL for each launchable interface of an app that a client on `client` can
+/// reach, preferring LAN over Tor when both are available.
+pub async fn launch(app_id: &str, client: Network) -> Result<Vec<LaunchUrl>, Error> {
+    let info = crate::apps::list_info()
+        .await?
+        .remove(app_id)
+        .ok_or_else(|| failure::format_err!("App Not Installed: {}", app_id))
+        .with_code(crate::error::NOT_FOUND)?;
+    let tor_address = info
+        .tor_address
+        .ok_or_else(|| failure::format_err!("{} has no Tor address", app_id))
+        .with_code(crate::error::NOT_FOUND)?;
+    let lan_hostname = tor_address
+        .strip_suffix(".onion")
+        .ok_or_else(|| failure::format_err!("Invalid Tor Address: {:?}", tor_address))
+        .no_code()?
+        .to_owned()
+        + ".local";
+    let manifest = crate::apps::manifest(app_id).await?;
+    let mut res = Vec::new();
+    for interface in manifest.launch {
+        let port = match manifest
+            .ports
+            .iter()
+            .find(|p| p.internal == interface.internal)
+        {
+            Some(a) => a,
+            None => {
+                log::warn!(
+                    "{} declares launch interface {} on undeclared port {}.",
+                    app_id,
+                    interface.name,
+                    interface.internal
+                );
+                continue;
+            }
+        };
+        let lan_url = match (client, port.lan) {
+            (Network::Lan, Some(LanOptions::Standard)) => {
+                Some(format!("https://{}{}", lan_hostname, interface.path))
+            }
+            (Network::Lan, Some(LanOptions::Custom { port })) => Some(format!(
+                "http://{}:{}{}",
+                lan_hostname, port, interface.path
+            )),
+            _ => None,
+        };
+        let tor_url = match port.tor {
+            80 => format!("http://{}{}", tor_address, interface.path),
+            tor_port => format!("http://{}:{}{}", tor_address, tor_port, interface.path),
+        };
+        let url = match (interface.requires, lan_url) {
+            (Some(Network::Tor), _) | (None, None) => Some((Network::Tor, tor_url)),
+            (_, Some(lan_url)) => Some((Network::Lan, lan_url)),
+            (Some(Network::Lan), None) => None,
+        };
+        if let Some((network, url)) = url {
+            res.push(LaunchUrl {
+                name: interface.name,
+                network,
+                url,
+            });
+        }
+    }
+    crate::ensure_code!(
+        !res.is_empty(),
+        crate::error::NOT_FOUND,
+        "{} has no interface reachable over {}",
+        app_id,
+        match client {
+            Network::Lan => "LAN",
+            Network::Tor => "Tor",
+        }
+    );
+    Ok(res)
+}
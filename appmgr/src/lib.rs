@@ -18,26 +18,59 @@ lazy_static::lazy_static! {
     pub static ref SYS_REGISTRY_URL: String = format!("{}/sys", *REGISTRY_URL);
     pub static ref APP_REGISTRY_URL: String = format!("{}/apps", *REGISTRY_URL);
     pub static ref QUIET: tokio::sync::RwLock<bool> = tokio::sync::RwLock::new(!std::env::var("APPMGR_QUIET").map(|a| a == "0").unwrap_or(true));
+    /// Reads back and checksums critical writes after they are synced, to catch SD cards that
+    /// silently corrupt data. Off by default, as it roughly doubles the IO of every commit.
+    pub static ref PARANOID_WRITES: bool = std::env::var("APPMGR_PARANOID_WRITES").map(|a| a == "1").unwrap_or(false);
+    /// Encrypts app configs at rest under the device key, so service credentials cannot be read
+    /// off a pulled SD card. Only apps that ask for one get a plaintext copy in their volume.
 }
 
 pub mod actions;
 pub mod apps;
+pub mod backend;
 pub mod backup;
+pub mod cache;
+pub mod checksums;
 pub mod config;
 pub mod control;
+pub mod delta;
 pub mod dependencies;
 pub mod disks;
+pub mod docs;
+pub mod encryption;
 pub mod error;
+pub mod events;
+pub mod firewall;
+pub mod groups;
+pub mod health;
+pub mod hooks;
+pub mod identity;
 pub mod index;
 pub mod inspect;
 pub mod install;
+pub mod instructions;
+pub mod io_priority;
 #[cfg(feature = "avahi")]
 pub mod lan;
+pub mod launch;
 pub mod logs;
 pub mod manifest;
+pub mod modes;
+pub mod mqtt;
 pub mod pack;
+pub mod progress;
+pub mod properties;
 pub mod registry;
 pub mod remove;
+pub mod replication;
+pub mod retention;
+pub mod s9pk;
+pub mod schedule;
+pub mod security;
+pub mod shares;
+pub mod signing;
+pub mod status_page;
+pub mod system;
 pub mod tor;
 pub mod update;
 pub mod util;
@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::ffi::{OsStr, OsString};
 use std::path::Path;
 
@@ -7,11 +8,12 @@ use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
 use itertools::Itertools;
 
+use crate::properties::{Properties, SyncProgress};
 use crate::util::PersistencePath;
 use crate::Error;
 use crate::ResultExt as _;
 
-#[derive(Clone, Copy, Debug, serde::Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Level {
     Error,
@@ -42,7 +44,7 @@ impl std::str::FromStr for Level {
     }
 }
 
-#[derive(Clone, Debug, serde::Serialize)]
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
 pub struct Notification {
     pub time: i64,
     pub level: Level,
@@ -107,6 +109,9 @@ pub async fn logs<A: AsRef<str>, B: AsRef<str>>(
     name: &str,
     options: LogOptions<A, B>,
 ) -> Result<(), Error> {
+    if crate::backend::Backend::of_app(name) == crate::backend::Backend::Systemd {
+        return journal(name, options);
+    }
     let mut args = vec![Cow::Borrowed(OsStr::new("logs"))];
     if options.details {
         args.push(Cow::Borrowed(OsStr::new("--details")));
@@ -141,6 +146,54 @@ pub async fn logs<A: AsRef<str>, B: AsRef<str>>(
     Ok(())
 }
 
+/// The logs of a static app, from the journal of its unit. `since` and `until` are given to
+/// journalctl as they are, and there are no details to show.
+fn journal<A: AsRef<str>, B: AsRef<str>>(
+    name: &str,
+    options: LogOptions<A, B>,
+) -> Result<(), Error> {
+    let unit = crate::backend::unit_name(name);
+    let mut args = vec![
+        Cow::Borrowed(OsStr::new("-u")),
+        Cow::Borrowed(OsStr::new(&unit)),
+        Cow::Borrowed(OsStr::new("--no-pager")),
+        Cow::Borrowed(OsStr::new("-o")),
+        Cow::Borrowed(OsStr::new(if options.timestamps {
+            "short-iso"
+        } else {
+            "cat"
+        })),
+    ];
+    if options.follow {
+        args.push(Cow::Borrowed(OsStr::new("-f")));
+    }
+    if let Some(since) = options.since.as_ref() {
+        args.push(Cow::Borrowed(OsStr::new("--since")));
+        args.push(Cow::Borrowed(OsStr::new(since.as_ref())));
+    }
+    if let Some(until) = options.until.as_ref() {
+        args.push(Cow::Borrowed(OsStr::new("--until")));
+        args.push(Cow::Borrowed(OsStr::new(until.as_ref())));
+    }
+    if let Some(tail) = options.tail {
+        args.push(Cow::Borrowed(OsStr::new("-n")));
+        args.push(Cow::Owned(OsString::from(format!("{}", tail))));
+    }
+    crate::ensure_code!(
+        std::process::Command::new("journalctl")
+            .args(args.into_iter())
+            .status()?
+            .success(),
+        crate::error::GENERAL_ERROR,
+        "Failed to Collect Logs from Journal"
+    );
+    Ok(())
+}
+
+/// The most notifications kept from a single read. When an app writes more than this between
+/// reads, the oldest are dropped and replaced with a single warning saying how many were lost.
+pub const NOTIFICATION_BUFFER_LEN: usize = 1000;
+
 pub async fn notifications(id: &str) -> Result<Vec<Notification>, Error> {
     let p = PersistencePath::from_ref("notifications").join(id).tmp();
     if let Some(parent) = p.parent() {
@@ -164,13 +217,47 @@ pub async fn notifications(id: &str) -> Result<Vec<Notification>, Error> {
         .await
         .with_context(|e| format!("{}: {}", p.display(), e))
         .with_code(crate::error::FILESYSTEM_ERROR)?;
-    tokio::io::AsyncBufReadExt::lines(tokio::io::BufReader::new(f))
-        .map(|a| a.map_err(From::from).and_then(|a| a.parse()))
-        .try_collect()
-        .await
+    let mut lines = tokio::io::AsyncBufReadExt::lines(tokio::io::BufReader::new(f)).map(|a| {
+        a.map_err(Error::from)
+            .and_then(|a| a.parse::<Notification>())
+    });
+    let mut res = VecDeque::with_capacity(NOTIFICATION_BUFFER_LEN);
+    let mut dropped = 0_usize;
+    let mut first_time = None;
+    while let Some(notification) = lines.try_next().await? {
+        first_time.get_or_insert(notification.time);
+        if res.len() == NOTIFICATION_BUFFER_LEN {
+            res.pop_front();
+            dropped += 1;
+        }
+        res.push_back(notification);
+    }
+    if dropped > 0 {
+        let last_time = res.back().map(|n| n.time).unwrap_or(0);
+        let span = (last_time - first_time.unwrap_or(last_time)).max(1);
+        log::warn!(
+            "{} produced {} notifications since last read, dropped {} ({:.2}/s).",
+            id,
+            dropped + res.len(),
+            dropped,
+            dropped as f64 / span as f64
+        );
+        res.push_front(Notification {
+            time: res.front().map(|n| n.time).unwrap_or(last_time),
+            level: Level::Warn,
+            code: 0,
+            title: "Notifications Truncated".to_owned(),
+            message: format!(
+                "{} older notifications were dropped because {} produced more than {} \
+                 since they were last read.",
+                dropped, id, NOTIFICATION_BUFFER_LEN
+            ),
+        });
+    }
+    Ok(res.into())
 }
 
-pub async fn stats(id: &str) -> Result<serde_yaml::Value, Error> {
+pub async fn stats(id: &str) -> Result<Properties, Error> {
     let p = PersistencePath::from_ref("stats").join(id).tmp();
     if let Some(parent) = p.parent() {
         if !parent.exists() {
@@ -187,7 +274,7 @@ pub async fn stats(id: &str) -> Result<serde_yaml::Value, Error> {
     .await
     {
         Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
-            return Ok(serde_yaml::Value::Null)
+            return Ok(Properties::Legacy(serde_yaml::Value::Null))
         }
         a => a,
     }?;
@@ -195,5 +282,29 @@ pub async fn stats(id: &str) -> Result<serde_yaml::Value, Error> {
         .await
         .with_context(|e| format!("{}: {}", p.display(), e))
         .with_code(crate::error::FILESYSTEM_ERROR)?;
-    crate::util::from_yaml_async_reader(f).await.no_code()
+    let value = crate::util::from_yaml_async_reader(f).await.no_code()?;
+    Properties::from_value(value)
+}
+
+/// The sync progress `id` reports in its `stats.yaml`, if it reports any.
+pub async fn sync_progress(id: &str) -> Result<Option<SyncProgress>, Error> {
+    let p = Path::new(crate::VOLUMES)
+        .join(id)
+        .join("start9")
+        .join("stats.yaml");
+    let contents = match tokio::fs::read(&p).await {
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        a => a,
+    }
+    .with_context(|e| format!("{}: {}", p.display(), e))
+    .with_code(crate::error::FILESYSTEM_ERROR)?;
+    // the app may be halfway through writing it
+    let value: serde_yaml::Value = match serde_yaml::from_slice(&contents) {
+        Ok(value) => value,
+        Err(e) => {
+            log::warn!("{}: {}", p.display(), e);
+            return Ok(None);
+        }
+    };
+    Ok(SyncProgress::from_stats(&value))
 }
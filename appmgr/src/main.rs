@@ -3,6 +3,7 @@
 use std::borrow::Cow;
 use std::path::Path;
 
+use appmgrlib::config::ConfigChange;
 use appmgrlib::version::VersionT;
 use appmgrlib::*;
 
@@ -10,7 +11,7 @@ use clap::{App, Arg, SubCommand};
 
 #[tokio::main]
 async fn main() {
-    match inner_main().await {
+    match util::app_lock_scope(inner_main()).await {
         Ok(()) => (),
         Err(e) => {
             eprintln!("{}", e.failure);
@@ -52,9 +53,48 @@ async fn inner_main() -> Result<(), Error> {
         )
         .subcommand(SubCommand::with_name("semver").about("Prints semantic version and exits"))
         .subcommand(SubCommand::with_name("git-info").about("Prints git version info and exits"))
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Prints a shell completion script and exits")
+                .setting(clap::AppSettings::Hidden)
+                .arg(
+                    Arg::with_name("SHELL")
+                        .help("The shell to complete in")
+                        .possible_values(&clap::Shell::variants())
+                        .required(true),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("pack")
                 .about("Creates a new application package")
+                .setting(clap::AppSettings::SubcommandsNegateReqs)
+                .subcommand(
+                    SubCommand::with_name("delta")
+                        .about("Creates a delta that updates one package to another")
+                        .arg(
+                            Arg::with_name("from")
+                                .long("from")
+                                .takes_value(true)
+                                .value_name("S9PK")
+                                .required(true)
+                                .help("The package the delta applies to"),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .takes_value(true)
+                                .value_name("S9PK")
+                                .required(true)
+                                .help("The package the delta produces"),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .short("o")
+                                .long("output")
+                                .takes_value(true)
+                                .default_value("app.s9pk.delta"),
+                        ),
+                )
                 .arg(
                     Arg::with_name("output")
                         .short("o")
@@ -62,6 +102,18 @@ async fn inner_main() -> Result<(), Error> {
                         .takes_value(true)
                         .default_value("app.s9pk"),
                 )
+                .arg(
+                    Arg::with_name("key")
+                        .long("key")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Developer key to sign the package with"),
+                )
+                .arg(
+                    Arg::with_name("deterministic")
+                        .long("deterministic")
+                        .help("Packs without timestamps or owners and in a fixed order, so that rebuilds are byte-identical"),
+                )
                 .arg(
                     Arg::with_name("PATH")
                         .help("Path to the folder containing the application data")
@@ -77,6 +129,104 @@ async fn inner_main() -> Result<(), Error> {
                         .required(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("keys")
+                .about("Manage the keys app packages are signed with")
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .alias("ls")
+                        .about("List the trusted keys")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Trust packages signed with a key")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("Name of the developer the key belongs to")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("KEY")
+                                .help("The public key, as base64")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("remove")
+                        .alias("rm")
+                        .about("Stop trusting a key")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("Name the key is trusted under")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("generate")
+                        .about("Create a developer key to sign packages with, and print its public key")
+                        .arg(
+                            Arg::with_name("FILE")
+                                .help("Path to write the key to")
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("test-dependencies")
+                .about("Tests the dependency config rules of a package against fabricated configs")
+                .arg(
+                    Arg::with_name("MANIFEST")
+                        .help("Path to the manifest.yaml of the package")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("FIXTURES")
+                        .help("Path to a yaml list of dependency versions and configs, and whether each should satisfy the dependency")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .conflicts_with("yaml")
+                        .long("json")
+                        .short("j")
+                        .help("Output as json"),
+                )
+                .arg(
+                    Arg::with_name("pretty")
+                        .requires("json")
+                        .long("pretty")
+                        .short("p")
+                        .help("Pretty print output"),
+                )
+                .arg(
+                    Arg::with_name("yaml")
+                        .conflicts_with("json")
+                        .long("yaml")
+                        .short("y")
+                        .help("Output as yaml"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("inspect")
                 .about("Inspects an application package")
@@ -149,6 +299,52 @@ async fn inner_main() -> Result<(), Error> {
                             Arg::with_name("PATH")
                                 .help("Path to the s9pk file to inspect")
                                 .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("format")
+                                .long("format")
+                                .takes_value(true)
+                                .possible_values(&["raw", "ansi"])
+                                .default_value("raw")
+                                .help("Prints the instructions as written, or rendered for a terminal"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("lint-config")
+                        .about("Checks an app's config spec and rules against random configs")
+                        .arg(
+                            Arg::with_name("PATH")
+                                .help("Path to the s9pk file to inspect")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("samples")
+                                .long("samples")
+                                .short("n")
+                                .takes_value(true)
+                                .default_value("100")
+                                .help("Number of random configs to generate"),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
                         ),
                 ),
         )
@@ -183,6 +379,13 @@ async fn inner_main() -> Result<(), Error> {
                         .long("no-cache")
                         .help("Replace cached download of application"),
                 )
+                .arg(
+                    Arg::with_name("answers")
+                        .long("answers")
+                        .takes_value(true)
+                        .value_name("FILE")
+                        .help("Answers to the install prompts of the app, as yaml or json"),
+                )
                 .arg(
                     Arg::with_name("ID|PATH|URL")
                         .help("The app to install")
@@ -208,6 +411,24 @@ async fn inner_main() -> Result<(), Error> {
                         .long("dry-run")
                         .help("Do not commit result"),
                 )
+                .arg(
+                    Arg::with_name("retain-for")
+                        .long("retain-for")
+                        .takes_value(true)
+                        .value_name("DAYS")
+                        .help("Days to keep the previous version for rollback (0 to disable)"),
+                )
+                .arg(
+                    Arg::with_name("ignore-pin")
+                        .long("ignore-pin")
+                        .help("Update even if the app is pinned to its current version"),
+                )
+                .arg(
+                    Arg::with_name("os-update")
+                        .long("os-update")
+                        .hidden(true)
+                        .help("Allow updating a system package, used by the OS updater"),
+                )
                 .arg(
                     Arg::with_name("json")
                         .conflicts_with("yaml")
@@ -231,19 +452,20 @@ async fn inner_main() -> Result<(), Error> {
                 ),
         )
         .subcommand(
-            SubCommand::with_name("start")
-                .about("Starts an app")
-                .arg(Arg::with_name("ID").help("The app to start").required(true)),
+            SubCommand::with_name("pin")
+                .about("Pins an app to its current version, excluding it from updates")
+                .arg(Arg::with_name("ID").help("The app to pin").required(true)),
         )
         .subcommand(
-            SubCommand::with_name("stop")
-                .about("Stops an app")
-                .arg(Arg::with_name("ID").help("The app to stop").required(true))
-                .arg(
-                    Arg::with_name("dry-run")
-                        .long("dry-run")
-                        .help("Do not commit result"),
-                )
+            SubCommand::with_name("unpin")
+                .about("Unpins an app, allowing it to be updated")
+                .arg(Arg::with_name("ID").help("The app to unpin").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("mode")
+                .about("Shows or switches the network an app runs against")
+                .arg(Arg::with_name("ID").help("The app").required(true))
+                .arg(Arg::with_name("MODE").help("The mode to switch to, i.e. testnet"))
                 .arg(
                     Arg::with_name("json")
                         .conflicts_with("yaml")
@@ -267,47 +489,17 @@ async fn inner_main() -> Result<(), Error> {
                 ),
         )
         .subcommand(
-            SubCommand::with_name("restart")
-                .about("Restarts an app")
-                .arg(
-                    Arg::with_name("ID")
-                        .help("The app to restart")
-                        .required(true),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("configure")
-                .about("Configures an app")
+            SubCommand::with_name("launch")
+                .about("Shows the URLs to open an app's interfaces at")
                 .arg(
                     Arg::with_name("ID")
-                        .help("The app to configure")
+                        .help("The app to launch")
                         .required(true),
                 )
-                .arg(Arg::with_name("FILE").help("The configuration file to use"))
-                .arg(
-                    Arg::with_name("stdin")
-                        .long("stdin")
-                        .help("Use stdin for the config file")
-                        .conflicts_with("FILE"),
-                )
-                .arg(
-                    Arg::with_name("timeout")
-                        .short("t")
-                        .long("timeout")
-                        .help("Max seconds to attempt generating entropy per field")
-                        .default_value("3")
-                        .conflicts_with("no-timeout"),
-                )
                 .arg(
-                    Arg::with_name("no-timeout")
-                        .long("no-timeout")
-                        .help("Disable timeout on entropy generation")
-                        .conflicts_with("timeout"),
-                )
-                .arg(
-                    Arg::with_name("dry-run")
-                        .long("dry-run")
-                        .help("Do not commit result"),
+                    Arg::with_name("tor")
+                        .long("tor")
+                        .help("Only return URLs reachable over Tor"),
                 )
                 .arg(
                     Arg::with_name("json")
@@ -332,16 +524,19 @@ async fn inner_main() -> Result<(), Error> {
                 ),
         )
         .subcommand(
-            SubCommand::with_name("check-dependencies")
-                .about("Check dependencies for an app")
+            SubCommand::with_name("start")
+                .about("Starts an app")
+                .arg(Arg::with_name("ID").help("The app to start").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("stop")
+                .about("Stops an app")
+                .arg(Arg::with_name("ID").help("The app to stop").required(true))
                 .arg(
-                    Arg::with_name("ID")
-                        .help("The app to check dependencies for.")
-                        .required(true),
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Do not commit result"),
                 )
-                .arg(Arg::with_name("local-only").long("local-only").help(
-                    "Disable reaching out to the Start9 registry if the app isn't installed.",
-                ))
                 .arg(
                     Arg::with_name("json")
                         .conflicts_with("yaml")
@@ -365,23 +560,81 @@ async fn inner_main() -> Result<(), Error> {
                 ),
         )
         .subcommand(
-            SubCommand::with_name("autoconfigure-dependency")
-                .about("Automatically configure a dependency")
+            SubCommand::with_name("restart")
+                .about("Restarts an app")
                 .arg(
                     Arg::with_name("ID")
-                        .help("The app to autoconfigure a dependency for.")
+                        .help("The app to restart")
                         .required(true),
-                )
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("configure")
+                .alias("config")
+                .about("Configures an app")
+                .setting(clap::AppSettings::SubcommandsNegateReqs)
                 .arg(
-                    Arg::with_name("DEPENDENCY")
-                        .help("The dependency to autoconfigure.")
+                    Arg::with_name("ID")
+                        .help("The app to configure")
                         .required(true),
                 )
+                .arg(Arg::with_name("FILE").help("The configuration file to use"))
+                .arg(
+                    Arg::with_name("stdin")
+                        .long("stdin")
+                        .help("Use stdin for the config file")
+                        .conflicts_with("FILE"),
+                )
+                .arg(
+                    Arg::with_name("interactive")
+                        .long("interactive")
+                        .short("i")
+                        .help("Offer the suggested fixes of each config rule that fails")
+                        .conflicts_with("stdin"),
+                )
+                .arg(
+                    Arg::with_name("edit")
+                        .long("edit")
+                        .short("e")
+                        .help("Edit the config field by field, following its spec")
+                        .conflicts_with("stdin"),
+                )
+                .arg(
+                    Arg::with_name("timeout")
+                        .short("t")
+                        .long("timeout")
+                        .help("Max seconds to spend configuring, including entropy generation")
+                        .default_value("10")
+                        .conflicts_with("no-timeout"),
+                )
+                .arg(
+                    Arg::with_name("no-timeout")
+                        .long("no-timeout")
+                        .help("Disable timeout on configuring")
+                        .conflicts_with("timeout"),
+                )
                 .arg(
                     Arg::with_name("dry-run")
                         .long("dry-run")
                         .help("Do not commit result"),
                 )
+                .arg(
+                    Arg::with_name("seed")
+                        .long("seed")
+                        .takes_value(true)
+                        .requires("dry-run")
+                        .help("Seed generated defaults deterministically, for reproducing bugs"),
+                )
+                .arg(
+                    Arg::with_name("redact")
+                        .long("redact")
+                        .help("Replace the values of masked config fields with ********"),
+                )
+                .arg(
+                    Arg::with_name("explain")
+                        .long("explain")
+                        .help("Show where each value of the resolved configs came from"),
+                )
                 .arg(
                     Arg::with_name("json")
                         .conflicts_with("yaml")
@@ -402,184 +655,398 @@ async fn inner_main() -> Result<(), Error> {
                         .long("yaml")
                         .short("y")
                         .help("Output as yaml"),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("remove")
-                .alias("rm")
-                .about("Removes an installed app")
-                .arg(
-                    Arg::with_name("purge")
-                        .long("purge")
-                        .help("Deletes all application data"),
-                )
-                .arg(
-                    Arg::with_name("ID")
-                        .help("ID of the application to be removed")
-                        .required(true),
-                )
-                .arg(
-                    Arg::with_name("dry-run")
-                        .long("dry-run")
-                        .help("Do not commit result"),
                 )
-                .arg(
-                    Arg::with_name("json")
-                        .conflicts_with("yaml")
-                        .long("json")
-                        .short("j")
-                        .help("Output as json"),
+                .subcommand(
+                    SubCommand::with_name("history")
+                        .about("Lists previous configurations of an app")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("The app to list configurations for")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("redact")
+                                .long("redact")
+                                .help("Replace the values of masked config fields with ********"),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
                 )
-                .arg(
-                    Arg::with_name("pretty")
-                        .requires("json")
-                        .long("pretty")
-                        .short("p")
-                        .help("Pretty print output"),
+                .subcommand(
+                    SubCommand::with_name("validate")
+                        .about("Checks a config against the spec and rules of an app, without applying it")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("The app to validate the config for")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("FILE")
+                                .help("The config to validate")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
                 )
-                .arg(
-                    Arg::with_name("yaml")
-                        .conflicts_with("json")
-                        .long("yaml")
-                        .short("y")
-                        .help("Output as yaml"),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("tor")
-                .about("Configures tor hidden services")
                 .subcommand(
-                    SubCommand::with_name("show")
-                        .about("Shows the onion address for the hidden service")
+                    SubCommand::with_name("status")
+                        .about("Prints whether an app is configured, and whether it needs a restart")
                         .arg(
                             Arg::with_name("ID")
-                                .help("ID of the application to get the onion address for")
+                                .help("The app to print the config status of")
                                 .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
                         ),
                 )
-                .subcommand(SubCommand::with_name("reload").about("Reloads the tor configuration")),
-        )
-        .subcommand(
-            SubCommand::with_name("info")
-                .about("Prints information about an installed app")
-                .arg(
-                    Arg::with_name("ID")
-                        .help("ID of the application to print information about")
-                        .required(true),
+                .subcommand(
+                    SubCommand::with_name("many")
+                        .about("Configures several apps at once, restarting each affected app only once")
+                        .arg(
+                            Arg::with_name("FILE")
+                                .help("A map of app ids to configurations, null to reuse the current one")
+                                .required_unless("stdin"),
+                        )
+                        .arg(
+                            Arg::with_name("stdin")
+                                .long("stdin")
+                                .help("Use stdin for the map of configurations")
+                                .conflicts_with("FILE"),
+                        )
+                        .arg(
+                            Arg::with_name("timeout")
+                                .short("t")
+                                .long("timeout")
+                                .help("Max seconds to spend configuring, including entropy generation")
+                                .default_value("10")
+                                .conflicts_with("no-timeout"),
+                        )
+                        .arg(
+                            Arg::with_name("no-timeout")
+                                .long("no-timeout")
+                                .help("Disable timeout on configuring")
+                                .conflicts_with("timeout"),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Do not commit result"),
+                        )
+                        .arg(
+                            Arg::with_name("redact")
+                                .long("redact")
+                                .help("Replace the values of masked config fields with ********"),
+                        )
+                        .arg(
+                            Arg::with_name("explain")
+                                .long("explain")
+                                .help("Show where each value of the resolved configs came from"),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
                 )
-                .arg(
-                    Arg::with_name("json")
-                        .conflicts_with("yaml")
-                        .required_unless("yaml")
-                        .long("json")
-                        .short("j")
-                        .help("Output as json"),
+                .subcommand(
+                    SubCommand::with_name("patch")
+                        .about("Updates part of the configuration of an app")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("The app to configure")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("FILE")
+                                .help("A JSON Patch (RFC 6902) to apply")
+                                .required_unless_one(&["stdin", "set"]),
+                        )
+                        .arg(
+                            Arg::with_name("stdin")
+                                .long("stdin")
+                                .help("Use stdin for the JSON Patch")
+                                .conflicts_with_all(&["FILE", "set"]),
+                        )
+                        .arg(
+                            Arg::with_name("set")
+                                .long("set")
+                                .short("s")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .conflicts_with_all(&["FILE", "stdin"])
+                                .help("Sets a dotted path to a value, e.g. rpc.port=8332"),
+                        )
+                        .arg(
+                            Arg::with_name("timeout")
+                                .short("t")
+                                .long("timeout")
+                                .help("Max seconds to spend configuring, including entropy generation")
+                                .default_value("10")
+                                .conflicts_with("no-timeout"),
+                        )
+                        .arg(
+                            Arg::with_name("no-timeout")
+                                .long("no-timeout")
+                                .help("Disable timeout on configuring")
+                                .conflicts_with("timeout"),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Do not commit result"),
+                        )
+                        .arg(
+                            Arg::with_name("redact")
+                                .long("redact")
+                                .help("Replace the values of masked config fields with ********"),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
                 )
-                .arg(
-                    Arg::with_name("pretty")
-                        .requires("json")
-                        .long("pretty")
-                        .short("p")
-                        .help("Pretty print output"),
-                )
-                .arg(
-                    Arg::with_name("yaml")
-                        .conflicts_with("json")
-                        .required_unless("json")
-                        .long("yaml")
-                        .short("y")
-                        .help("Output as yaml"),
-                )
-                .arg(
-                    Arg::with_name("include-status")
-                        .long("include-status")
-                        .short("s"),
-                )
-                .arg(
-                    Arg::with_name("include-manifest")
-                        .long("include-manifest")
-                        .short("m"),
-                )
-                .arg(
-                    Arg::with_name("include-config")
-                        .long("include-config")
-                        .short("c"),
+                .subcommand(
+                    SubCommand::with_name("regenerate")
+                        .about("Regenerates selected fields of the configuration of an app")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("The app to configure")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("paths")
+                                .long("paths")
+                                .takes_value(true)
+                                .use_delimiter(true)
+                                .required(true)
+                                .help("Dotted paths to regenerate, e.g. rpc.password,tor.key"),
+                        )
+                        .arg(
+                            Arg::with_name("timeout")
+                                .short("t")
+                                .long("timeout")
+                                .help("Max seconds to spend configuring, including entropy generation")
+                                .default_value("10")
+                                .conflicts_with("no-timeout"),
+                        )
+                        .arg(
+                            Arg::with_name("no-timeout")
+                                .long("no-timeout")
+                                .help("Disable timeout on configuring")
+                                .conflicts_with("timeout"),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Do not commit result"),
+                        )
+                        .arg(
+                            Arg::with_name("redact")
+                                .long("redact")
+                                .help("Replace the values of masked config fields with ********"),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
                 )
+                .subcommand(
+                    SubCommand::with_name("revert")
+                        .about("Restores a previous configuration of an app")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("The app to revert")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("to")
+                                .long("to")
+                                .takes_value(true)
+                                .required(true)
+                                .help("The revision to restore, 1 being the most recent"),
+                        )
+                        .arg(
+                            Arg::with_name("timeout")
+                                .short("t")
+                                .long("timeout")
+                                .help("Max seconds to spend configuring, including entropy generation")
+                                .default_value("10")
+                                .conflicts_with("no-timeout"),
+                        )
+                        .arg(
+                            Arg::with_name("no-timeout")
+                                .long("no-timeout")
+                                .help("Disable timeout on configuring")
+                                .conflicts_with("timeout"),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Do not commit result"),
+                        )
+                        .arg(
+                            Arg::with_name("redact")
+                                .long("redact")
+                                .help("Replace the values of masked config fields with ********"),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rotate-credentials")
+                .about("Regenerates the secrets of an app and updates the apps using them")
                 .arg(
-                    Arg::with_name("include-dependencies")
-                        .long("include-dependencies")
-                        .short("d"),
+                    Arg::with_name("ID")
+                        .help("The app whose credentials to rotate")
+                        .required(true),
                 )
                 .arg(
-                    Arg::with_name("only-status")
-                        .long("only-status")
-                        .short("S")
-                        .conflicts_with_all(&[
-                            "include-status",
-                            "include-manifest",
-                            "include-config",
-                            "include-dependencies",
-                            "only-manifest",
-                            "only-config",
-                            "only-dependencies",
-                        ]),
+                    Arg::with_name("timeout")
+                        .short("t")
+                        .long("timeout")
+                        .help("Max seconds to spend configuring, including entropy generation")
+                        .default_value("10")
+                        .conflicts_with("no-timeout"),
                 )
                 .arg(
-                    Arg::with_name("only-manifest")
-                        .long("only-manifest")
-                        .short("M")
-                        .conflicts_with_all(&[
-                            "include-status",
-                            "include-manifest",
-                            "include-config",
-                            "include-dependencies",
-                            "only-status",
-                            "only-config",
-                            "only-dependencies",
-                        ]),
+                    Arg::with_name("no-timeout")
+                        .long("no-timeout")
+                        .help("Disable timeout on configuring")
+                        .conflicts_with("timeout"),
                 )
                 .arg(
-                    Arg::with_name("only-config")
-                        .long("only-config")
-                        .short("C")
-                        .conflicts_with_all(&[
-                            "include-status",
-                            "include-manifest",
-                            "include-config",
-                            "include-dependencies",
-                            "only-status",
-                            "only-manifest",
-                            "only-dependencies",
-                        ]),
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Do not commit result or restart anything"),
                 )
-                .arg(
-                    Arg::with_name("only-dependencies")
-                        .long("only-dependencies")
-                        .short("D")
-                        .conflicts_with_all(&[
-                            "include-status",
-                            "include-manifest",
-                            "include-config",
-                            "include-dependencies",
-                            "only-status",
-                            "only-manifest",
-                            "only-config",
-                        ]),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("instructions")
-                .about("Prints instructions for an installed app")
-                .arg(
-                    Arg::with_name("ID")
-                        .help("ID of the application to print instructions for")
-                        .required(true),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("list")
-                .alias("ls")
-                .about("Lists apps successfully installed on the system")
                 .arg(
                     Arg::with_name("json")
                         .conflicts_with("yaml")
@@ -600,95 +1067,19 @@ async fn inner_main() -> Result<(), Error> {
                         .long("yaml")
                         .short("y")
                         .help("Output as yaml"),
-                )
-                .arg(
-                    Arg::with_name("include-status")
-                        .long("include-status")
-                        .short("s"),
-                )
-                .arg(
-                    Arg::with_name("include-manifest")
-                        .long("include-manifest")
-                        .short("m"),
-                )
-                .arg(
-                    Arg::with_name("include-config")
-                        .long("include-config")
-                        .short("c"),
-                )
-                .arg(
-                    Arg::with_name("include-dependencies")
-                        .long("include-dependencies")
-                        .short("d"),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("self-update")
-                .about("Updates appmgr")
-                .arg(
-                    Arg::with_name("VERSION_REQUIREMENT")
-                        .help("Version requirement to update to (i.e. ^0.1.0)"),
-                ),
-        )
-        .subcommand(
-            SubCommand::with_name("logs")
-                .about("Fetch the logs of an app")
-                .arg(
-                    Arg::with_name("ID")
-                        .help("ID of the application to fetch logs for")
-                        .required(true),
-                )
-                .arg(
-                    Arg::with_name("details")
-                        .help("Show extra details provided to logs")
-                        .long("details"),
-                )
-                .arg(
-                    Arg::with_name("follow")
-                        .help("Follow log output")
-                        .long("follow")
-                        .short("f"),
-                )
-                .arg(
-                    Arg::with_name("since")
-                        .help(concat!(
-                            "Show logs since timestamp (e.g. 2013-01-02T13:23:37)",
-                            " or relative (e.g. 42m for 42 minutes)"
-                        ))
-                        .long("since")
-                        .takes_value(true),
-                )
-                .arg(
-                    Arg::with_name("tail")
-                        .help("Number of lines to show from the end of the logs")
-                        .long("tail")
-                        .takes_value(true)
-                        .default_value("all"),
-                )
-                .arg(
-                    Arg::with_name("timestamps")
-                        .help("Show timestamps")
-                        .short("t")
-                        .long("timestamps"),
-                )
-                .arg(
-                    Arg::with_name("until")
-                        .help(concat!(
-                            "Show logs before a timestamp (e.g. 2013-01-02T13:23:37)",
-                            " or relative (e.g. 42m for 42 minutes)"
-                        ))
-                        .long("until")
-                        .takes_value(true),
                 ),
         )
         .subcommand(
-            SubCommand::with_name("notifications")
-                .about("Get notifications broadcast by an app")
+            SubCommand::with_name("check-dependencies")
+                .about("Check dependencies for an app")
                 .arg(
                     Arg::with_name("ID")
-                        .help("ID of the application to get notifications for")
+                        .help("The app to check dependencies for.")
                         .required(true),
                 )
+                .arg(Arg::with_name("local-only").long("local-only").help(
+                    "Disable reaching out to the Start9 registry if the app isn't installed.",
+                ))
                 .arg(
                     Arg::with_name("json")
                         .conflicts_with("yaml")
@@ -712,17 +1103,26 @@ async fn inner_main() -> Result<(), Error> {
                 ),
         )
         .subcommand(
-            SubCommand::with_name("stats")
-                .about("Get stats broadcast by an app")
+            SubCommand::with_name("autoconfigure-dependency")
+                .about("Automatically configure a dependency")
                 .arg(
                     Arg::with_name("ID")
-                        .help("ID of the application to get stats for")
+                        .help("The app to autoconfigure a dependency for.")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("DEPENDENCY")
+                        .help("The dependency to autoconfigure.")
                         .required(true),
                 )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Do not commit result"),
+                )
                 .arg(
                     Arg::with_name("json")
                         .conflicts_with("yaml")
-                        .required_unless("yaml")
                         .long("json")
                         .short("j")
                         .help("Output as json"),
@@ -737,22 +1137,124 @@ async fn inner_main() -> Result<(), Error> {
                 .arg(
                     Arg::with_name("yaml")
                         .conflicts_with("json")
-                        .required_unless("json")
                         .long("yaml")
                         .short("y")
                         .help("Output as yaml"),
                 ),
         )
         .subcommand(
-            SubCommand::with_name("disks")
-                .about("Manage external disks")
-                .subcommand(
-                    SubCommand::with_name("show")
-                        .alias("list")
-                        .alias("ls")
-                        .about("List external drive information")
-                        .arg(
-                            Arg::with_name("json")
+            SubCommand::with_name("remove")
+                .alias("rm")
+                .about("Removes an installed app")
+                .arg(
+                    Arg::with_name("purge")
+                        .long("purge")
+                        .help("Deletes all application data"),
+                )
+                .arg(
+                    Arg::with_name("keep-data")
+                        .long("keep-data")
+                        .conflicts_with("purge")
+                        .help("Deletes everything but the volume of the app, which can be deleted later with purge-data"),
+                )
+                .arg(
+                    Arg::with_name("ID")
+                        .help("ID of the application to be removed")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Remove even if the pre-remove hook of the app fails"),
+                )
+                .arg(
+                    Arg::with_name("cascade")
+                        .long("cascade")
+                        .help("Also remove every app that depends on it"),
+                )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Do not commit result"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .conflicts_with("yaml")
+                        .long("json")
+                        .short("j")
+                        .help("Output as json"),
+                )
+                .arg(
+                    Arg::with_name("pretty")
+                        .requires("json")
+                        .long("pretty")
+                        .short("p")
+                        .help("Pretty print output"),
+                )
+                .arg(
+                    Arg::with_name("yaml")
+                        .conflicts_with("json")
+                        .long("yaml")
+                        .short("y")
+                        .help("Output as yaml"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("orphans")
+                .about("Lists the data left behind by apps removed with --keep-data")
+                .arg(
+                    Arg::with_name("json")
+                        .conflicts_with("yaml")
+                        .long("json")
+                        .short("j")
+                        .help("Output as json"),
+                )
+                .arg(
+                    Arg::with_name("pretty")
+                        .requires("json")
+                        .long("pretty")
+                        .short("p")
+                        .help("Pretty print output"),
+                )
+                .arg(
+                    Arg::with_name("yaml")
+                        .conflicts_with("json")
+                        .long("yaml")
+                        .short("y")
+                        .help("Output as yaml"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("purge-data")
+                .about("Deletes the data left behind by an app that is no longer installed")
+                .arg(
+                    Arg::with_name("ID")
+                        .help("ID of the application to delete the data of")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tor")
+                .about("Configures tor hidden services")
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows the onion address for the hidden service")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("ID of the application to get the onion address for")
+                                .required(true),
+                        ),
+                )
+                .subcommand(SubCommand::with_name("reload").about("Reloads the tor configuration")),
+        )
+        .subcommand(
+            SubCommand::with_name("firewall")
+                .about("Configures egress filtering")
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Shows whether clearnet egress is blocked")
+                        .arg(
+                            Arg::with_name("json")
                                 .conflicts_with("yaml")
                                 .long("json")
                                 .short("j")
@@ -773,106 +1275,2201 @@ async fn inner_main() -> Result<(), Error> {
                                 .help("Output as yaml"),
                         ),
                 )
-                .subcommand(SubCommand::with_name("use")),
+                .subcommand(
+                    SubCommand::with_name("tor-only")
+                        .about("Forces all app and system egress through Tor")
+                        .arg(
+                            Arg::with_name("STATE")
+                                .possible_values(&["on", "off"])
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("exempt")
+                        .about("Allows an app to reach the clearnet while Tor-only is on")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("ID of the application to exempt")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("unexempt")
+                        .about("Removes the clearnet exemption for an app")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("ID of the application to remove the exemption for")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("unblock")
+                        .about("Removes an address from the inbound blocklist")
+                        .arg(
+                            Arg::with_name("IP")
+                                .help("The address to unblock")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("apply").about("Reapplies the saved firewall settings"),
+                ),
         )
         .subcommand(
-            SubCommand::with_name("backup")
-                .about("Manage app data backups")
+            SubCommand::with_name("share")
+                .about("Hosts static files over Tor and LAN without a package")
                 .subcommand(
-                    SubCommand::with_name("create")
-                        .about("Backup current app state")
+                    SubCommand::with_name("list")
+                        .about("Lists shares")
                         .arg(
-                            Arg::with_name("ID")
-                                .help("ID of the application to backup data for")
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("add")
+                        .about("Creates a share and publishes it")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("Name of the share")
                                 .required(true),
                         )
+                        .arg(Arg::with_name("DIR").help("Directory to copy into the share")),
+                )
+                .subcommand(
+                    SubCommand::with_name("upload")
+                        .about("Copies files into a share")
                         .arg(
-                            Arg::with_name("PARTITION")
-                                .help("Logical name of the partition you would like to backup to")
+                            Arg::with_name("NAME")
+                                .help("Name of the share")
                                 .required(true),
                         )
                         .arg(
-                            Arg::with_name("password")
-                                .long("password")
+                            Arg::with_name("DIR")
+                                .help("Directory to copy into the share")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("rm")
+                        .about("Removes a share and its files")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("Name of the share")
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("schedule")
+                .about("Runs apps only during certain hours")
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("Lists app schedules")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
                                 .short("p")
-                                .takes_value(true)
-                                .help("Password to use for encryption of backup file"),
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
                         ),
                 )
                 .subcommand(
-                    SubCommand::with_name("restore")
-                        .about("Restore app state from backup")
+                    SubCommand::with_name("set")
+                        .about("Runs an app only between two times of day, in local time")
                         .arg(
                             Arg::with_name("ID")
-                                .help("ID of the application to restore data for")
+                                .help("The app to schedule")
                                 .required(true),
                         )
                         .arg(
-                            Arg::with_name("PARTITION")
-                                .help("Logical name of the partition you would like to backup to")
-                                .required(true),
+                            Arg::with_name("start")
+                                .long("start")
+                                .takes_value(true)
+                                .value_name("HH:MM")
+                                .required(true)
+                                .help("When to start the app"),
                         )
                         .arg(
-                            Arg::with_name("timestamp")
-                                .long("timestamp")
-                                .short("t")
+                            Arg::with_name("stop")
+                                .long("stop")
                                 .takes_value(true)
-                                .help("Timestamp of the backup to restore"),
+                                .value_name("HH:MM")
+                                .required(true)
+                                .help("When to stop the app"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("rm")
+                        .about("Removes the schedule of an app")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("The app to unschedule")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("enforce")
+                        .about("Starts and stops apps whose windows opened or closed since the last run")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
                         )
                         .arg(
-                            Arg::with_name("password")
-                                .long("password")
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
                                 .short("p")
-                                .takes_value(true)
-                                .help("Password to use for encryption of backup file"),
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
                         ),
                 ),
         )
         .subcommand(
-            SubCommand::with_name("repair-app-status").about("Restarts crashed apps"), // TODO: remove
-        )
-        .subcommand(
-            SubCommand::with_name("actions")
-                .about("Perform an action for a service")
-                .arg(
-                    Arg::with_name("SERVICE")
-                        .help("ID of the service to perform an action on")
-                        .required(true),
-                )
-                .arg(Arg::with_name("ACTION").help("ID of the action to perform")),
-        );
-
-    let matches = app.clone().get_matches();
-
-    log::set_max_level(match matches.occurrences_of("verbosity") {
-        0 => log::LevelFilter::Error,
-        1 => log::LevelFilter::Warn,
-        2 => log::LevelFilter::Info,
-        3 => log::LevelFilter::Debug,
-        _ => log::LevelFilter::Trace,
-    });
-
-    match matches.subcommand() {
-        ("semver", _) => {
-            println!("{}", version);
-        }
-        ("git-info", _) => {
-            println!("{}", git_version);
+            SubCommand::with_name("mqtt")
+                .about("Publishes app status and device metrics to an MQTT broker")
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows the configured broker")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Sets the broker to publish to")
+                        .arg(
+                            Arg::with_name("HOST")
+                                .help("The hostname or IP address of the broker")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("port")
+                                .long("port")
+                                .takes_value(true)
+                                .default_value("1883")
+                                .help("The port of the broker"),
+                        )
+                        .arg(
+                            Arg::with_name("prefix")
+                                .long("prefix")
+                                .takes_value(true)
+                                .default_value("embassy")
+                                .help("Prepended to every topic"),
+                        )
+                        .arg(
+                            Arg::with_name("username")
+                                .long("username")
+                                .short("u")
+                                .takes_value(true)
+                                .help("The username to connect with"),
+                        )
+                        .arg(
+                            Arg::with_name("password")
+                                .long("password")
+                                .takes_value(true)
+                                .requires("username")
+                                .help("The password to connect with"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("clear").about("Stops publishing to the broker"),
+                )
+                .subcommand(
+                    SubCommand::with_name("publish")
+                        .about("Publishes the status and properties of every app, and device metrics"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("status-page")
+                .about("Publishes a read-only status page for selected apps, without authentication")
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows the status page as it would be published")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("allow")
+                        .about("Shows an app on the status page")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("The app to show")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("FIELDS")
+                                .help("status, uptime, version, or the name of a property of the app")
+                                .multiple(true)
+                                .default_value("status,uptime")
+                                .use_delimiter(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("deny")
+                        .about("Removes an app from the status page")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("The app to remove")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("publish").about("Writes the status page for the web server"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("docs")
+                .about("Manages the offline docs of the installed apps")
+                .subcommand(
+                    SubCommand::with_name("publish")
+                        .about("Writes the docs of every installed app for the web server"),
+                )
+                .subcommand(
+                    SubCommand::with_name("search")
+                        .about("Searches the docs of the installed apps")
+                        .arg(
+                            Arg::with_name("QUERY")
+                                .help("The words to look for")
+                                .required(true)
+                                .multiple(true),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("identity")
+                .about("Manages the identity key of the device, and the certificate of its API")
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows the fingerprints clients can pin the device by")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("issue")
+                        .about("Issues the API certificate from the device CA, if it is missing or expiring")
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .help("Reissue even if the current certificate is valid"),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("export")
+                        .about("Prints the API certificate as PEM")
+                        .arg(
+                            Arg::with_name("chain")
+                                .long("chain")
+                                .help("Include the intermediate and root CA certificates"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("io-priority")
+                .about("Manages the disk I/O priority of backups, gc, and update downloads")
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows the I/O weight of each job class")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("set")
+                        .about("Sets the I/O weight of a job class, relative to 100 for the apps")
+                        .arg(
+                            Arg::with_name("CLASS")
+                                .help("The job class")
+                                .possible_values(&["backup", "gc", "download"])
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("WEIGHT")
+                                .help("From 1 to 10000")
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("replication")
+                .about("Keeps a standby device up to date, so it can take over (experimental)")
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows the replication settings of this device")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("pair")
+                        .about("Pairs this device with another one")
+                        .arg(
+                            Arg::with_name("ROLE")
+                                .help("Whether this device ships snapshots or takes over")
+                                .possible_values(&["primary", "standby"])
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("TARGET")
+                                .help("Where snapshots are kept, as a duplicity URL")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("tor")
+                                .long("tor")
+                                .help("Reach the target through tor"),
+                        )
+                        .arg(
+                            Arg::with_name("password")
+                                .long("password")
+                                .takes_value(true)
+                                .help("Password to encrypt snapshots with, the same on both devices"),
+                        ),
+                )
+                .subcommand(SubCommand::with_name("unpair").about("Stops replicating"))
+                .subcommand(
+                    SubCommand::with_name("sync")
+                        .about("Ships a snapshot of every app to the standby")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("failover")
+                        .about("Brings up the apps of the primary on this device")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("security")
+                .about("Monitors the device for intrusions and protects data at rest")
+                .subcommand(
+                    SubCommand::with_name("audit")
+                        .about("Reports anomalies found since the last audit")
+                        .arg(
+                            Arg::with_name("block")
+                                .long("block")
+                                .help("Block LAN addresses with repeated login failures"),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("encrypt-configs")
+                        .about("Seals app configs at rest under the device key")
+                        .arg(
+                            Arg::with_name("STATE")
+                                .possible_values(&["on", "off"])
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("health")
+                .about("Probes the health checks apps declare")
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .about("Shows the state of the health checks of an app")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("ID of the application")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("monitor")
+                        .about("Probes the health checks of running apps as they come due, until stopped"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Prints information about an installed app")
+                .arg(
+                    Arg::with_name("ID")
+                        .help("ID of the application to print information about")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("redact")
+                        .long("redact")
+                        .help("Replace the values of masked config fields with ********"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .conflicts_with("yaml")
+                        .required_unless("yaml")
+                        .long("json")
+                        .short("j")
+                        .help("Output as json"),
+                )
+                .arg(
+                    Arg::with_name("pretty")
+                        .requires("json")
+                        .long("pretty")
+                        .short("p")
+                        .help("Pretty print output"),
+                )
+                .arg(
+                    Arg::with_name("yaml")
+                        .conflicts_with("json")
+                        .required_unless("json")
+                        .long("yaml")
+                        .short("y")
+                        .help("Output as yaml"),
+                )
+                .arg(
+                    Arg::with_name("include-status")
+                        .long("include-status")
+                        .short("s"),
+                )
+                .arg(
+                    Arg::with_name("include-manifest")
+                        .long("include-manifest")
+                        .short("m"),
+                )
+                .arg(
+                    Arg::with_name("include-config")
+                        .long("include-config")
+                        .short("c"),
+                )
+                .arg(
+                    Arg::with_name("include-dependencies")
+                        .long("include-dependencies")
+                        .short("d"),
+                )
+                .arg(
+                    Arg::with_name("only-status")
+                        .long("only-status")
+                        .short("S")
+                        .conflicts_with_all(&[
+                            "include-status",
+                            "include-manifest",
+                            "include-config",
+                            "include-dependencies",
+                            "only-manifest",
+                            "only-config",
+                            "only-dependencies",
+                        ]),
+                )
+                .arg(
+                    Arg::with_name("only-manifest")
+                        .long("only-manifest")
+                        .short("M")
+                        .conflicts_with_all(&[
+                            "include-status",
+                            "include-manifest",
+                            "include-config",
+                            "include-dependencies",
+                            "only-status",
+                            "only-config",
+                            "only-dependencies",
+                        ]),
+                )
+                .arg(
+                    Arg::with_name("only-config")
+                        .long("only-config")
+                        .short("C")
+                        .conflicts_with_all(&[
+                            "include-status",
+                            "include-manifest",
+                            "include-config",
+                            "include-dependencies",
+                            "only-status",
+                            "only-manifest",
+                            "only-dependencies",
+                        ]),
+                )
+                .arg(
+                    Arg::with_name("only-dependencies")
+                        .long("only-dependencies")
+                        .short("D")
+                        .conflicts_with_all(&[
+                            "include-status",
+                            "include-manifest",
+                            "include-config",
+                            "include-dependencies",
+                            "only-status",
+                            "only-manifest",
+                            "only-config",
+                        ]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("instructions")
+                .about("Prints instructions for an installed app")
+                .arg(
+                    Arg::with_name("ID")
+                        .help("ID of the application to print instructions for")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["raw", "ansi"])
+                        .default_value("raw")
+                        .help("Prints the instructions as written, or rendered for a terminal"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .alias("ls")
+                .about("Lists apps successfully installed on the system")
+                .arg(
+                    Arg::with_name("json")
+                        .conflicts_with("yaml")
+                        .long("json")
+                        .short("j")
+                        .help("Output as json"),
+                )
+                .arg(
+                    Arg::with_name("pretty")
+                        .requires("json")
+                        .long("pretty")
+                        .short("p")
+                        .help("Pretty print output"),
+                )
+                .arg(
+                    Arg::with_name("yaml")
+                        .conflicts_with("json")
+                        .long("yaml")
+                        .short("y")
+                        .help("Output as yaml"),
+                )
+                .arg(
+                    Arg::with_name("include-status")
+                        .long("include-status")
+                        .short("s"),
+                )
+                .arg(
+                    Arg::with_name("include-manifest")
+                        .long("include-manifest")
+                        .short("m"),
+                )
+                .arg(
+                    Arg::with_name("include-config")
+                        .long("include-config")
+                        .short("c"),
+                )
+                .arg(
+                    Arg::with_name("include-dependencies")
+                        .long("include-dependencies")
+                        .short("d"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("self-update")
+                .about("Updates appmgr")
+                .arg(
+                    Arg::with_name("VERSION_REQUIREMENT")
+                        .help("Version requirement to update to (i.e. ^0.1.0)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("logs")
+                .about("Fetch the logs of an app")
+                .arg(
+                    Arg::with_name("ID")
+                        .help("ID of the application to fetch logs for")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("details")
+                        .help("Show extra details provided to logs")
+                        .long("details"),
+                )
+                .arg(
+                    Arg::with_name("follow")
+                        .help("Follow log output")
+                        .long("follow")
+                        .short("f"),
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .help(concat!(
+                            "Show logs since timestamp (e.g. 2013-01-02T13:23:37)",
+                            " or relative (e.g. 42m for 42 minutes)"
+                        ))
+                        .long("since")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("tail")
+                        .help("Number of lines to show from the end of the logs")
+                        .long("tail")
+                        .takes_value(true)
+                        .default_value("all"),
+                )
+                .arg(
+                    Arg::with_name("timestamps")
+                        .help("Show timestamps")
+                        .short("t")
+                        .long("timestamps"),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .help(concat!(
+                            "Show logs before a timestamp (e.g. 2013-01-02T13:23:37)",
+                            " or relative (e.g. 42m for 42 minutes)"
+                        ))
+                        .long("until")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("notifications")
+                .about("Get notifications broadcast by an app")
+                .arg(
+                    Arg::with_name("ID")
+                        .help("ID of the application to get notifications for")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .conflicts_with("yaml")
+                        .long("json")
+                        .short("j")
+                        .help("Output as json"),
+                )
+                .arg(
+                    Arg::with_name("pretty")
+                        .requires("json")
+                        .long("pretty")
+                        .short("p")
+                        .help("Pretty print output"),
+                )
+                .arg(
+                    Arg::with_name("yaml")
+                        .conflicts_with("json")
+                        .long("yaml")
+                        .short("y")
+                        .help("Output as yaml"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("events")
+                .about("Print app lifecycle events: installs, starts, stops, config changes, backups")
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .takes_value(true)
+                        .help("Only events after this time, in milliseconds since the epoch"),
+                )
+                .arg(
+                    Arg::with_name("follow")
+                        .long("follow")
+                        .short("f")
+                        .help("Keep printing events as they happen"),
+                )
+                .arg(
+                    Arg::with_name("app")
+                        .long("app")
+                        .takes_value(true)
+                        .help("Only events of this app"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .conflicts_with("sse")
+                        .long("json")
+                        .short("j")
+                        .help("Output one json object per line"),
+                )
+                .arg(
+                    Arg::with_name("sse")
+                        .conflicts_with("json")
+                        .long("sse")
+                        .help("Output as a text/event-stream body"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Get stats broadcast by an app")
+                .arg(
+                    Arg::with_name("ID")
+                        .help("ID of the application to get stats for")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .conflicts_with("yaml")
+                        .required_unless("yaml")
+                        .long("json")
+                        .short("j")
+                        .help("Output as json"),
+                )
+                .arg(
+                    Arg::with_name("pretty")
+                        .requires("json")
+                        .long("pretty")
+                        .short("p")
+                        .help("Pretty print output"),
+                )
+                .arg(
+                    Arg::with_name("yaml")
+                        .conflicts_with("json")
+                        .required_unless("json")
+                        .long("yaml")
+                        .short("y")
+                        .help("Output as yaml"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("disks")
+                .about("Manage external disks")
+                .subcommand(
+                    SubCommand::with_name("show")
+                        .alias("list")
+                        .alias("ls")
+                        .about("List external drive information")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(SubCommand::with_name("use")),
+        )
+        .subcommand(
+            SubCommand::with_name("backup")
+                .about("Manage app data backups")
+                .subcommand(
+                    SubCommand::with_name("create")
+                        .about("Backup current app state")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("ID of the application to backup data for")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("PARTITION")
+                                .help("Logical name of the partition you would like to backup to")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("password")
+                                .long("password")
+                                .short("p")
+                                .takes_value(true)
+                                .help("Password to use for encryption of backup file"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("create-all")
+                        .about("Backup the current state of every app")
+                        .arg(
+                            Arg::with_name("PARTITION")
+                                .help("Logical name of the partition you would like to backup to")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("jobs")
+                                .long("jobs")
+                                .short("j")
+                                .takes_value(true)
+                                .default_value("2")
+                                .help("How many apps to back up at the same time"),
+                        )
+                        .arg(
+                            Arg::with_name("group")
+                                .long("group")
+                                .takes_value(true)
+                                .help("Only back up the apps of this group"),
+                        )
+                        .arg(
+                            Arg::with_name("password")
+                                .long("password")
+                                .short("p")
+                                .takes_value(true)
+                                .help("Password to use for encryption of backup file"),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("restore")
+                        .about("Restore app state from backup")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("ID of the application to restore data for")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("PARTITION")
+                                .help("Logical name of the partition you would like to backup to")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("timestamp")
+                                .long("timestamp")
+                                .short("t")
+                                .takes_value(true)
+                                .help("Timestamp of the backup to restore"),
+                        )
+                        .arg(
+                            Arg::with_name("password")
+                                .long("password")
+                                .short("p")
+                                .takes_value(true)
+                                .help("Password to use for encryption of backup file"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("retained")
+                .alias("retention")
+                .about("Manage previous app versions retained after updates")
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .alias("ls")
+                        .about("List retained app versions and the space they use")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("period")
+                        .about("Set how many days previous versions are retained after updates")
+                        .arg(
+                            Arg::with_name("DAYS")
+                                .help("Days to retain for, 0 to retain nothing")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("gc")
+                        .about("Discard retained versions past their retention period")
+                        .arg(
+                            Arg::with_name("all")
+                                .long("all")
+                                .help("Discard all retained versions"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("discard")
+                        .about("Discard the retained version of an app")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("ID of the application to discard the retained version of")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("rollback")
+                        .about("Put back the retained version of an app, with its volume")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("ID of the application to roll back")
+                                .required(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("group")
+                .about("Manage groups of apps that are run together")
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .alias("ls")
+                        .about("List the groups defined on the device")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("define")
+                        .about("Define a group, or replace its definition")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("Name of the group")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("FILE")
+                                .help("Yaml file with the title and apps of the group")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("undefine")
+                        .about("Remove the definition of a group, leaving its apps as they are")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("Name of the group")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("install")
+                        .about("Install, configure and start every app of a group")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("Name of the group, defined on the device or in the registry")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("timeout")
+                                .short("t")
+                                .long("timeout")
+                                .help("Max seconds to spend configuring, including entropy generation")
+                                .default_value("10")
+                                .conflicts_with("no-timeout"),
+                        )
+                        .arg(
+                            Arg::with_name("no-timeout")
+                                .long("no-timeout")
+                                .help("Disable timeout on configuring")
+                                .conflicts_with("timeout"),
+                        )
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("start")
+                        .about("Start every app of a group in dependency order")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("Name of the group")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("stop")
+                        .about("Stop every app of a group in reverse dependency order")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("Name of the group")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .long("dry-run")
+                                .help("Do not commit result"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .about("Manage the cache of downloaded app packages")
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .alias("ls")
+                        .about("List cached packages and the space they use")
+                        .arg(
+                            Arg::with_name("json")
+                                .conflicts_with("yaml")
+                                .long("json")
+                                .short("j")
+                                .help("Output as json"),
+                        )
+                        .arg(
+                            Arg::with_name("pretty")
+                                .requires("json")
+                                .long("pretty")
+                                .short("p")
+                                .help("Pretty print output"),
+                        )
+                        .arg(
+                            Arg::with_name("yaml")
+                                .conflicts_with("json")
+                                .long("yaml")
+                                .short("y")
+                                .help("Output as yaml"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("clear")
+                        .about("Remove cached packages")
+                        .arg(
+                            Arg::with_name("ID")
+                                .help("ID of the application to remove the cached packages of"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("gc")
+                        .about("Evict the least recently used packages until the cache fits its limit")
+                        .arg(
+                            Arg::with_name("max-size")
+                                .long("max-size")
+                                .takes_value(true)
+                                .help("Size to shrink the cache to instead, in MiB"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("limit")
+                        .about("Show or set the size limit of the cache")
+                        .arg(Arg::with_name("MIB").help("New size limit, in MiB"))
+                        .arg(
+                            Arg::with_name("reset")
+                                .long("reset")
+                                .conflicts_with("MIB")
+                                .help("Go back to the default size limit"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("repair-app-status").about("Restarts crashed apps"), // TODO: remove
+        )
+        .subcommand(
+            SubCommand::with_name("actions")
+                .about("Perform an action for a service")
+                .arg(
+                    Arg::with_name("SERVICE")
+                        .help("ID of the service to perform an action on")
+                        .required(true),
+                )
+                .arg(Arg::with_name("ACTION").help("ID of the action to perform")),
+        );
+
+    let matches = app.clone().get_matches();
+
+    log::set_max_level(match matches.occurrences_of("verbosity") {
+        0 => log::LevelFilter::Error,
+        1 => log::LevelFilter::Warn,
+        2 => log::LevelFilter::Info,
+        3 => log::LevelFilter::Debug,
+        _ => log::LevelFilter::Trace,
+    });
+
+    match matches.subcommand() {
+        ("semver", _) => {
+            println!("{}", version);
+        }
+        ("git-info", _) => {
+            println!("{}", git_version);
+        }
+        ("completions", Some(sub_m)) => {
+            let shell = sub_m
+                .value_of("SHELL")
+                .unwrap()
+                .parse::<clap::Shell>()
+                .map_err(failure::err_msg)
+                .no_code()?;
+            app.gen_completions_to("appmgr", shell, &mut std::io::stdout());
+        }
+        #[cfg(not(feature = "portable"))]
+        ("install", Some(sub_m)) => {
+            let target = sub_m.value_of("ID|PATH|URL").unwrap();
+            let answers = if let Some(path) = sub_m.value_of("answers") {
+                Some(
+                    util::from_yaml_async_reader(tokio::fs::File::open(path).await.with_ctx(
+                        |e| (Some(error::FILESYSTEM_ERROR), format!("{}: {}", path, e)),
+                    )?)
+                    .await?,
+                )
+            } else {
+                None
+            };
+            if target.starts_with("https://") || target.starts_with("http://") {
+                install_url(target, None, answers).await?;
+            } else if target.ends_with(".s9pk") {
+                signing::verify_trusted(target).await?;
+                install_path(target, None, answers).await?;
+            } else {
+                install_name(target, !sub_m.is_present("no-cache"), answers).await?;
+            }
+        }
+        #[cfg(not(feature = "portable"))]
+        ("update", Some(sub_m)) => {
+            let retention = std::time::Duration::from_secs(
+                sub_m
+                    .value_of("retain-for")
+                    .map(|d| d.parse())
+                    .transpose()
+                    .no_code()?
+                    .unwrap_or(retention::settings().await?.days)
+                    * 24
+                    * 60
+                    * 60,
+            );
+            let res = update(
+                sub_m.value_of("ID").unwrap(),
+                sub_m.is_present("dry-run"),
+                retention,
+                sub_m.is_present("ignore-pin"),
+                sub_m.is_present("os-update"),
+            )
+            .await?;
+            if sub_m.is_present("json") {
+                if sub_m.is_present("pretty") {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                }
+            } else if sub_m.is_present("yaml") {
+                println!(
+                    "{}",
+                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                );
+            } else if !res.is_empty() {
+                use prettytable::{Cell, Row, Table};
+                let mut table = Table::new();
+                let heading = vec![
+                    Cell::new("APPLICATION ID"),
+                    Cell::new("STATUS"),
+                    Cell::new("REASON"),
+                ];
+                table.add_row(Row::new(heading));
+                for (name, reason) in res {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&name),
+                        Cell::new("Stopped"),
+                        Cell::new(&format!("{}", reason)),
+                    ]));
+                }
+                table.print(&mut std::io::stdout())?;
+            }
+        }
+        #[cfg(not(feature = "portable"))]
+        ("pin", Some(sub_m)) => {
+            apps::set_pinned(sub_m.value_of("ID").unwrap(), true).await?;
+        }
+        #[cfg(not(feature = "portable"))]
+        ("unpin", Some(sub_m)) => {
+            apps::set_pinned(sub_m.value_of("ID").unwrap(), false).await?;
+        }
+        #[cfg(not(feature = "portable"))]
+        ("mode", Some(sub_m)) => {
+            let id = sub_m.value_of("ID").unwrap();
+            if let Some(mode) = sub_m.value_of("MODE") {
+                let res = modes::set(id, mode).await?;
+                if sub_m.is_present("json") {
+                    if sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else if !res.is_empty() {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("STATUS"),
+                        Cell::new("REASON"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for (name, reason) in res {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&name),
+                            Cell::new("Stopped"),
+                            Cell::new(&format!("{}", reason)),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
+            } else {
+                let res = modes::current(&apps::manifest(id).await?).await?;
+                if sub_m.is_present("json") {
+                    if sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    println!("{}", res.unwrap_or_else(|| "N/A".to_owned()));
+                }
+            }
+        }
+        #[cfg(not(feature = "portable"))]
+        ("launch", Some(sub_m)) => {
+            let client = if sub_m.is_present("tor") {
+                manifest::Network::Tor
+            } else {
+                manifest::Network::Lan
+            };
+            let res = launch::launch(sub_m.value_of("ID").unwrap(), client).await?;
+            if sub_m.is_present("json") {
+                if sub_m.is_present("pretty") {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                }
+            } else if sub_m.is_present("yaml") {
+                println!(
+                    "{}",
+                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                );
+            } else {
+                for url in res {
+                    println!("{}: {}", url.name, url.url);
+                }
+            }
+        }
+        #[cfg(not(feature = "portable"))]
+        ("start", Some(sub_m)) => {
+            start_app(sub_m.value_of("ID").unwrap(), true).await?;
+        }
+        #[cfg(not(feature = "portable"))]
+        ("stop", Some(sub_m)) => {
+            let res = stop_app(
+                sub_m.value_of("ID").unwrap(),
+                true,
+                sub_m.is_present("dry-run"),
+            )
+            .await?;
+            if sub_m.is_present("json") {
+                if sub_m.is_present("pretty") {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                }
+            } else if sub_m.is_present("yaml") {
+                println!(
+                    "{}",
+                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                );
+            } else if !res.is_empty() {
+                use prettytable::{Cell, Row, Table};
+                let mut table = Table::new();
+                let heading = vec![
+                    Cell::new("APPLICATION ID"),
+                    Cell::new("STATUS"),
+                    Cell::new("REASON"),
+                ];
+                table.add_row(Row::new(heading));
+                for (name, reason) in res {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&name),
+                        Cell::new("Stopped"),
+                        Cell::new(&format!("{}", reason)),
+                    ]));
+                }
+                table.print(&mut std::io::stdout())?;
+            }
+        }
+        #[cfg(not(feature = "portable"))]
+        ("restart", Some(sub_m)) => {
+            restart_app(sub_m.value_of("ID").unwrap()).await?;
+        }
+        #[cfg(not(feature = "portable"))]
+        ("configure", Some(sub_m)) | ("config", Some(sub_m)) => {
+            if let ("status", Some(sub_sub_m)) = sub_m.subcommand() {
+                let res = config::status(sub_sub_m.value_of("ID").unwrap()).await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                }
+                return Ok(());
+            }
+            if let ("validate", Some(sub_sub_m)) = sub_m.subcommand() {
+                let p = Path::new(sub_sub_m.value_of("FILE").unwrap());
+                let config: Config = if p.extension() == Some(std::ffi::OsStr::new("json")) {
+                    util::from_json_async_reader(tokio::fs::File::open(p).await?).await?
+                } else {
+                    util::from_yaml_async_reader(tokio::fs::File::open(p).await?).await?
+                };
+                let report =
+                    config::validate::validate(sub_sub_m.value_of("ID").unwrap(), config).await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&report)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&report).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&report).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    for (heading, issues) in &[
+                        ("SPEC ERRORS", &report.spec_errors),
+                        ("RULE VIOLATIONS", &report.rule_violations),
+                    ] {
+                        if !issues.is_empty() {
+                            println!("{}:", heading);
+                            for issue in issues.iter() {
+                                println!("  {}", issue);
+                            }
+                        }
+                    }
+                    if report.is_ok() {
+                        println!("Config is valid.");
+                    }
+                }
+                if !report.spec_errors.is_empty() {
+                    std::process::exit(crate::error::CFG_SPEC_VIOLATION);
+                } else if !report.rule_violations.is_empty() {
+                    std::process::exit(crate::error::CFG_RULES_VIOLATION);
+                }
+                return Ok(());
+            }
+            if let ("history", Some(sub_sub_m)) = sub_m.subcommand() {
+                let id = sub_sub_m.value_of("ID").unwrap();
+                let mut res = config::history::list(id).await?;
+                if sub_sub_m.is_present("redact") {
+                    let masked = apps::config(id).await?.spec.masked_paths();
+                    for rev in res.iter_mut() {
+                        rev.config.redact(&masked);
+                    }
+                }
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![Cell::new("REVISION"), Cell::new("SAVED AT")];
+                    table.add_row(Row::new(heading));
+                    for rev in res {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&format!("{}", rev.revision)),
+                            Cell::new(&format!("{}", rev.saved_at)),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
+                return Ok(());
+            }
+            let (sub_m, revert_to, patch, many) = match sub_m.subcommand() {
+                ("revert", Some(sub_sub_m)) => (
+                    sub_sub_m,
+                    Some(
+                        sub_sub_m
+                            .value_of("to")
+                            .unwrap()
+                            .parse::<usize>()
+                            .no_code()?,
+                    ),
+                    None,
+                    None,
+                ),
+                ("patch", Some(sub_sub_m)) => {
+                    let patch = if let Some(assignments) = sub_sub_m.values_of("set") {
+                        config::patch::ConfigPatch::from_assignments(assignments)?
+                    } else if let Some(path) = sub_sub_m.value_of("FILE") {
+                        config::patch::ConfigPatch::from_json_slice(
+                            &tokio::fs::read(path)
+                                .await
+                                .with_code(crate::error::FILESYSTEM_ERROR)?,
+                        )?
+                    } else {
+                        use tokio::io::AsyncReadExt;
+                        let mut buf = Vec::new();
+                        tokio::io::stdin().read_to_end(&mut buf).await?;
+                        config::patch::ConfigPatch::from_json_slice(&buf)?
+                    };
+                    (sub_sub_m, None, Some(patch), None)
+                }
+                ("regenerate", Some(sub_sub_m)) => {
+                    let timeout = if sub_sub_m.is_present("no-timeout") {
+                        None
+                    } else {
+                        Some(std::time::Duration::from_secs(
+                            sub_sub_m.value_of("timeout").unwrap().parse().no_code()?,
+                        ))
+                    };
+                    let patch = config::patch::regenerate(
+                        &apps::config(sub_sub_m.value_of("ID").unwrap()).await?.spec,
+                        &sub_sub_m.values_of("paths").unwrap().collect::<Vec<_>>(),
+                        &mut config::EntropyProvider::rng(
+                            &config::OsEntropy,
+                            sub_sub_m.value_of("ID").unwrap(),
+                        ),
+                        &timeout,
+                    )?;
+                    (sub_sub_m, None, Some(patch), None)
+                }
+                ("many", Some(sub_sub_m)) => {
+                    let configs: linear_map::LinearMap<String, Option<Config>> =
+                        if let Some(path) = sub_sub_m.value_of("FILE") {
+                            util::from_yaml_async_reader(tokio::fs::File::open(path).await?).await?
+                        } else {
+                            util::from_yaml_async_reader(tokio::io::stdin()).await?
+                        };
+                    (sub_sub_m, None, None, Some(configs))
+                }
+                _ => (sub_m, None, None, None),
+            };
+            let config: Option<Config> = if revert_to.is_some() || patch.is_some() || many.is_some()
+            {
+                None
+            } else if let Some(path) = sub_m.value_of("FILE") {
+                let p = Path::new(path);
+                if p.extension() == Some(std::ffi::OsStr::new("json"))
+                    || (sub_m.is_present("json")
+                        && p.extension() != Some(std::ffi::OsStr::new("yaml")))
+                {
+                    Some(util::from_json_async_reader(tokio::fs::File::open(p).await?).await?)
+                } else {
+                    Some(util::from_yaml_async_reader(tokio::fs::File::open(p).await?).await?)
+                }
+            } else if sub_m.is_present("stdin") {
+                if sub_m.is_present("json") {
+                    Some(util::from_yaml_async_reader(tokio::io::stdin()).await?)
+                } else {
+                    Some(util::from_yaml_async_reader(tokio::io::stdin()).await?)
+                }
+            } else {
+                None
+            };
+            let config = if sub_m.is_present("edit") {
+                Some(config::interactive::edit(sub_m.value_of("ID").unwrap(), config).await?)
+            } else {
+                config
+            };
+            let config = if sub_m.is_present("interactive") {
+                Some(config::interactive::fix(sub_m.value_of("ID").unwrap(), config).await?)
+            } else {
+                config
+            };
+            let timeout = if sub_m.is_present("no-timeout") {
+                None
+            } else if let Some(t) = sub_m.value_of("timeout") {
+                Some(std::time::Duration::from_secs(t.parse().no_code()?))
+            } else {
+                Some(std::time::Duration::from_secs(10))
+            };
+            let progress =
+                progress::ProgressGuard::start("Configuring", progress::Unit::Steps, None);
+            let res = if let Some(many) = many {
+                config::configure_many(
+                    many.into_iter().collect(),
+                    timeout,
+                    sub_m.is_present("dry-run"),
+                )
+                .await
+            } else if let Some(revision) = revert_to {
+                config::history::revert(
+                    sub_m.value_of("ID").unwrap(),
+                    revision,
+                    timeout,
+                    sub_m.is_present("dry-run"),
+                )
+                .await
+            } else if let Some(patch) = &patch {
+                config::patch::patch(
+                    sub_m.value_of("ID").unwrap(),
+                    patch,
+                    timeout,
+                    sub_m.is_present("dry-run"),
+                )
+                .await
+            } else if let Some(seed) = sub_m.value_of("seed") {
+                config::configure_with_entropy(
+                    sub_m.value_of("ID").unwrap(),
+                    config,
+                    timeout,
+                    sub_m.is_present("dry-run"),
+                    &config::SeededEntropy(seed.parse().no_code()?),
+                )
+                .await
+            } else {
+                configure(
+                    sub_m.value_of("ID").unwrap(),
+                    config,
+                    timeout,
+                    sub_m.is_present("dry-run"),
+                )
+                .await
+            };
+            progress.finish().await;
+            let mut res = match res {
+                Ok(res) => res,
+                Err(e) => {
+                    // every spec violation is printed as a list, so a UI can mark each bad field
+                    if let Some(violations) = e.failure.downcast_ref::<config::SpecViolations>() {
+                        if sub_m.is_present("json") {
+                            println!(
+                                "{}",
+                                serde_json::json!({
+                                    "code": crate::error::CFG_SPEC_VIOLATION,
+                                    "message": "Config Does Not Match Spec",
+                                    "violations": violations,
+                                })
+                            );
+                            std::process::exit(crate::error::CFG_SPEC_VIOLATION);
+                        }
+                    }
+                    return Err(e);
+                }
+            };
+            if sub_m.is_present("redact") {
+                res.redact().await?;
+            }
+            if !sub_m.is_present("explain") {
+                res.provenance.clear();
+            }
+            if sub_m.is_present("json") {
+                if sub_m.is_present("pretty") {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                }
+            } else if sub_m.is_present("yaml") {
+                println!(
+                    "{}",
+                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                );
+            } else {
+                use prettytable::{Cell, Row, Table};
+                if sub_m.is_present("dry-run") && res.diffs.values().any(|d| !d.is_empty()) {
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("PATH"),
+                        Cell::new("OLD"),
+                        Cell::new("NEW"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for (name, diff) in &res.diffs {
+                        for change in diff {
+                            let (old, new) = match change {
+                                ConfigChange::Added { value, .. } => (None, Some(value)),
+                                ConfigChange::Removed { value, .. } => (Some(value), None),
+                                ConfigChange::Changed { old, new, .. } => (Some(old), Some(new)),
+                            };
+                            let show = |v: Option<&config::value::Value>| match v {
+                                Some(v) => {
+                                    serde_json::to_string(v).with_code(crate::error::SERDE_ERROR)
+                                }
+                                None => Ok("N/A".to_owned()),
+                            };
+                            table.add_row(Row::new(vec![
+                                Cell::new(name),
+                                Cell::new(change.path()),
+                                Cell::new(&show(old)?),
+                                Cell::new(&show(new)?),
+                            ]));
+                        }
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
+                if !res.provenance.is_empty() {
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("PATH"),
+                        Cell::new("SOURCE"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for (name, provenance) in &res.provenance {
+                        for (path, source) in provenance {
+                            table.add_row(Row::new(vec![
+                                Cell::new(name),
+                                Cell::new(path),
+                                Cell::new(&format!("{:?}", source)),
+                            ]));
+                        }
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
+                if sub_m.is_present("paths") {
+                    // which dependents picked up the regenerated values through their pointers
+                    let id = sub_m.value_of("ID").unwrap();
+                    let mut table = Table::new();
+                    let heading = vec![Cell::new("DEPENDENT"), Cell::new("PATHS CHANGED")];
+                    table.add_row(Row::new(heading));
+                    for (name, diff) in res.diffs.iter().filter(|(n, d)| *n != id && !d.is_empty())
+                    {
+                        table.add_row(Row::new(vec![
+                            Cell::new(name),
+                            Cell::new(
+                                &diff.iter().map(|c| c.path()).collect::<Vec<_>>().join(", "),
+                            ),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
+                if res.needs_restart.is_empty() && res.stopped.is_empty() && res.skipped.is_empty()
+                {
+                    return Ok(());
+                }
+                let mut table = Table::new();
+                let heading = vec![
+                    Cell::new("APPLICATION ID"),
+                    Cell::new("STATUS"),
+                    Cell::new("REASON"),
+                ];
+                table.add_row(Row::new(heading));
+                for name in res.needs_restart {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&name),
+                        Cell::new("Needs Restart"),
+                        Cell::new("Configuration Changed"),
+                    ]));
+                }
+                for (name, reason) in res.stopped {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&name),
+                        Cell::new("Stopped"),
+                        Cell::new(&format!("{}", reason)),
+                    ]));
+                }
+                for (name, reason) in res.skipped {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&name),
+                        Cell::new("Skipped"),
+                        Cell::new(&format!("{}", reason)),
+                    ]));
+                }
+                table.print(&mut std::io::stdout())?;
+            }
+        }
+        #[cfg(not(feature = "portable"))]
+        ("rotate-credentials", Some(sub_m)) => {
+            let id = sub_m.value_of("ID").unwrap();
+            let timeout = if sub_m.is_present("no-timeout") {
+                None
+            } else {
+                Some(std::time::Duration::from_secs(
+                    sub_m.value_of("timeout").unwrap().parse().no_code()?,
+                ))
+            };
+            let mut res =
+                config::rotate::rotate_credentials(id, timeout, sub_m.is_present("dry-run"))
+                    .await?;
+            res.config.redact().await?;
+            res.config.provenance.clear();
+            if sub_m.is_present("json") {
+                if sub_m.is_present("pretty") {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                }
+            } else if sub_m.is_present("yaml") {
+                println!(
+                    "{}",
+                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                );
+            } else {
+                use prettytable::{Cell, Row, Table};
+                let mut table = Table::new();
+                let heading = vec![
+                    Cell::new("APPLICATION ID"),
+                    Cell::new("STATUS"),
+                    Cell::new("REASON"),
+                ];
+                table.add_row(Row::new(heading));
+                table.add_row(Row::new(vec![
+                    Cell::new(id),
+                    Cell::new("Rotated"),
+                    Cell::new(&res.rotated.join(", ")),
+                ]));
+                for (name, diff) in res.config.diffs.iter() {
+                    if name == id || diff.is_empty() {
+                        continue;
+                    }
+                    table.add_row(Row::new(vec![
+                        Cell::new(name),
+                        Cell::new("Reconfigured"),
+                        Cell::new(&diff.iter().map(|c| c.path()).collect::<Vec<_>>().join(", ")),
+                    ]));
+                }
+                for name in &res.restarted {
+                    table.add_row(Row::new(vec![
+                        Cell::new(name),
+                        Cell::new(if sub_m.is_present("dry-run") {
+                            "Needs Restart"
+                        } else {
+                            "Restarted"
+                        }),
+                        Cell::new("Credentials Rotated"),
+                    ]));
+                }
+                for (name, reason) in &res.config.stopped {
+                    table.add_row(Row::new(vec![
+                        Cell::new(name),
+                        Cell::new("Stopped"),
+                        Cell::new(&format!("{}", reason)),
+                    ]));
+                }
+                for (name, reason) in &res.failed {
+                    table.add_row(Row::new(vec![
+                        Cell::new(name),
+                        Cell::new("Restart Failed"),
+                        Cell::new(reason),
+                    ]));
+                }
+                table.print(&mut std::io::stdout())?;
+            }
+            if !res.failed.is_empty() {
+                std::process::exit(crate::error::GENERAL_ERROR);
+            }
         }
         #[cfg(not(feature = "portable"))]
-        ("install", Some(sub_m)) => {
-            let target = sub_m.value_of("ID|PATH|URL").unwrap();
-            if target.starts_with("https://") || target.starts_with("http://") {
-                install_url(target, None).await?;
-            } else if target.ends_with(".s9pk") {
-                install_path(target, None).await?;
+        ("check-dependencies", Some(sub_m)) => {
+            let res = apps::dependencies(
+                sub_m.value_of("ID").unwrap(),
+                sub_m.is_present("local-only"),
+            )
+            .await?;
+            if sub_m.is_present("json") {
+                if sub_m.is_present("pretty") {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                }
+            } else if sub_m.is_present("yaml") {
+                println!(
+                    "{}",
+                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                );
+            } else if !res.0.is_empty() {
+                use prettytable::{Cell, Row, Table};
+                let mut table = Table::new();
+                let heading = vec![
+                    Cell::new("APPLICATION ID"),
+                    Cell::new("REQUIRED"),
+                    Cell::new("VIOLATION"),
+                ];
+                table.add_row(Row::new(heading));
+                for (name, info) in res.0 {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&name),
+                        Cell::new(&format!("{}", info.required)),
+                        Cell::new(&if let Some(error) = info.error {
+                            format!("{}", error)
+                        } else {
+                            "N/A".to_owned()
+                        }),
+                    ]));
+                }
+                table.print(&mut std::io::stdout())?;
             } else {
-                install_name(target, !sub_m.is_present("no-cache")).await?;
+                println!("No dependencies for {}", sub_m.value_of("ID").unwrap());
             }
         }
-        #[cfg(not(feature = "portable"))]
-        ("update", Some(sub_m)) => {
-            let res = update(sub_m.value_of("ID").unwrap(), sub_m.is_present("dry-run")).await?;
+        ("autoconfigure-dependency", Some(sub_m)) => {
+            let res = dependencies::auto_configure(
+                sub_m.value_of("ID").unwrap(),
+                sub_m.value_of("DEPENDENCY").unwrap(),
+                sub_m.is_present("dry-run"),
+            )
+            .await?;
             if sub_m.is_present("json") {
                 if sub_m.is_present("pretty") {
                     println!(
@@ -890,7 +3487,7 @@ async fn inner_main() -> Result<(), Error> {
                     "{}",
                     serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
                 );
-            } else if !res.is_empty() {
+            } else if !res.needs_restart.is_empty() || !res.stopped.is_empty() {
                 use prettytable::{Cell, Row, Table};
                 let mut table = Table::new();
                 let heading = vec![
@@ -899,300 +3496,882 @@ async fn inner_main() -> Result<(), Error> {
                     Cell::new("REASON"),
                 ];
                 table.add_row(Row::new(heading));
-                for (name, reason) in res {
+                for name in res.needs_restart {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&name),
+                        Cell::new("Needs Restart"),
+                        Cell::new("Configuration Changed"),
+                    ]));
+                }
+                for (name, reason) in res.stopped {
                     table.add_row(Row::new(vec![
                         Cell::new(&name),
                         Cell::new("Stopped"),
                         Cell::new(&format!("{}", reason)),
                     ]));
                 }
-                table.print(&mut std::io::stdout())?;
+                table.print(&mut std::io::stdout())?;
+            }
+        }
+        #[cfg(not(feature = "portable"))]
+        ("remove", Some(sub_m)) | ("rm", Some(sub_m)) => {
+            let res = remove(
+                sub_m.value_of("ID").unwrap(),
+                sub_m.is_present("purge"),
+                sub_m.is_present("keep-data"),
+                sub_m.is_present("dry-run"),
+                sub_m.is_present("force"),
+                sub_m.is_present("cascade"),
+            )
+            .await?;
+            // the broken dependents alone, as before
+            if sub_m.is_present("json") {
+                if sub_m.is_present("pretty") {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&res.broken)
+                            .with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&res.broken).with_code(crate::error::SERDE_ERROR)?
+                    );
+                }
+            } else if sub_m.is_present("yaml") {
+                println!(
+                    "{}",
+                    serde_yaml::to_string(&res.broken).with_code(crate::error::SERDE_ERROR)?
+                );
+            } else if !res.broken.is_empty() {
+                use prettytable::{Cell, Row, Table};
+                let mut table = Table::new();
+                let heading = vec![
+                    Cell::new("APPLICATION ID"),
+                    Cell::new("STATUS"),
+                    Cell::new("REASON"),
+                ];
+                table.add_row(Row::new(heading));
+                for (name, reason) in res.broken.iter() {
+                    let status = if res.removed.contains(name) {
+                        "Removed"
+                    } else if res.stopped.contains(name) {
+                        "Stopped"
+                    } else {
+                        "Broken"
+                    };
+                    table.add_row(Row::new(vec![
+                        Cell::new(name),
+                        Cell::new(status),
+                        Cell::new(&format!("{}", reason)),
+                    ]));
+                }
+                table.print(&mut std::io::stdout())?;
+            }
+        }
+        #[cfg(not(feature = "portable"))]
+        ("orphans", Some(sub_m)) => {
+            let res = crate::remove::orphans().await?;
+            if sub_m.is_present("json") {
+                if sub_m.is_present("pretty") {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                }
+            } else if sub_m.is_present("yaml") {
+                println!(
+                    "{}",
+                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                );
+            } else if !res.is_empty() {
+                use prettytable::{Cell, Row, Table};
+                let mut table = Table::new();
+                let heading = vec![Cell::new("APPLICATION ID"), Cell::new("SIZE")];
+                table.add_row(Row::new(heading));
+                for orphan in res {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&orphan.id),
+                        Cell::new(&format!("{}", orphan.size)),
+                    ]));
+                }
+                table.print(&mut std::io::stdout())?;
+            }
+        }
+        #[cfg(not(feature = "portable"))]
+        ("purge-data", Some(sub_m)) => {
+            crate::remove::purge_data(sub_m.value_of("ID").unwrap()).await?;
+        }
+        #[cfg(not(feature = "portable"))]
+        ("tor", Some(sub_m)) => match sub_m.subcommand() {
+            ("show", Some(sub_sub_m)) => {
+                println!(
+                    "{}",
+                    crate::tor::read_tor_address(sub_sub_m.value_of("ID").unwrap(), None).await?
+                );
+            }
+            ("reload", Some(_)) => {
+                crate::tor::reload().await?;
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        #[cfg(not(feature = "portable"))]
+        ("firewall", Some(sub_m)) => match sub_m.subcommand() {
+            ("status", Some(sub_sub_m)) => {
+                let info = firewall::status().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&info)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&info).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&info).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    table.add_row(Row::new(vec![
+                        Cell::new("TOR ONLY"),
+                        Cell::new(&format!("{}", info.settings.tor_only)),
+                    ]));
+                    table.add_row(Row::new(vec![
+                        Cell::new("ACTIVE"),
+                        Cell::new(&format!("{}", info.active)),
+                    ]));
+                    table.add_row(Row::new(vec![
+                        Cell::new("EXEMPTIONS"),
+                        Cell::new(
+                            &info
+                                .settings
+                                .exemptions
+                                .iter()
+                                .cloned()
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        ),
+                    ]));
+                    table.print(&mut std::io::stdout())?;
+                }
+            }
+            ("tor-only", Some(sub_sub_m)) => {
+                firewall::set_tor_only(sub_sub_m.value_of("STATE") == Some("on")).await?;
+            }
+            ("exempt", Some(sub_sub_m)) => {
+                firewall::set_exempt(sub_sub_m.value_of("ID").unwrap(), true).await?;
+            }
+            ("unexempt", Some(sub_sub_m)) => {
+                firewall::set_exempt(sub_sub_m.value_of("ID").unwrap(), false).await?;
+            }
+            ("unblock", Some(sub_sub_m)) => {
+                firewall::unblock_source(sub_sub_m.value_of("IP").unwrap().parse().no_code()?)
+                    .await?;
+            }
+            ("apply", Some(_)) => {
+                firewall::apply().await?;
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        #[cfg(not(feature = "portable"))]
+        ("share", Some(sub_m)) => match sub_m.subcommand() {
+            ("list", Some(sub_sub_m)) => {
+                let info = shares::list().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&info)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&info).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&info).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else if !info.is_empty() {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("NAME"),
+                        Cell::new("TOR ADDRESS"),
+                        Cell::new("PATH"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for (name, share) in info {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&name),
+                            Cell::new(share.tor_address.as_deref().unwrap_or("N/A")),
+                            Cell::new(&format!("{}", share.path.display())),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
+            }
+            ("add", Some(sub_sub_m)) => {
+                let share = shares::add(
+                    sub_sub_m.value_of("NAME").unwrap(),
+                    sub_sub_m.value_of("DIR").map(Path::new),
+                )
+                .await?;
+                if let Some(addr) = share.tor_address {
+                    println!("{}", addr);
+                }
+            }
+            ("upload", Some(sub_sub_m)) => {
+                shares::upload(
+                    sub_sub_m.value_of("NAME").unwrap(),
+                    Path::new(sub_sub_m.value_of("DIR").unwrap()),
+                )
+                .await?;
+            }
+            ("rm", Some(sub_sub_m)) => {
+                shares::remove(sub_sub_m.value_of("NAME").unwrap()).await?;
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        #[cfg(not(feature = "portable"))]
+        ("schedule", Some(sub_m)) => match sub_m.subcommand() {
+            ("list", Some(sub_sub_m)) => {
+                let res = schedule::list().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else if !res.is_empty() {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("START"),
+                        Cell::new("STOP"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for (id, schedule) in res {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&id),
+                            Cell::new(&format!("{}", schedule.start)),
+                            Cell::new(&format!("{}", schedule.stop)),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
             }
-        }
-        #[cfg(not(feature = "portable"))]
-        ("start", Some(sub_m)) => {
-            start_app(sub_m.value_of("ID").unwrap(), true).await?;
-        }
-        #[cfg(not(feature = "portable"))]
-        ("stop", Some(sub_m)) => {
-            let res = stop_app(
-                sub_m.value_of("ID").unwrap(),
-                true,
-                sub_m.is_present("dry-run"),
-            )
-            .await?;
-            if sub_m.is_present("json") {
-                if sub_m.is_present("pretty") {
+            ("set", Some(sub_sub_m)) => {
+                schedule::set(
+                    sub_sub_m.value_of("ID").unwrap(),
+                    sub_sub_m.value_of("start").unwrap().parse()?,
+                    sub_sub_m.value_of("stop").unwrap().parse()?,
+                )
+                .await?;
+            }
+            ("rm", Some(sub_sub_m)) => {
+                schedule::remove(sub_sub_m.value_of("ID").unwrap()).await?;
+            }
+            ("enforce", Some(sub_sub_m)) => {
+                let res = schedule::enforce().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
                     println!(
                         "{}",
-                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
                     );
                 } else {
+                    for id in &res.started {
+                        println!("Started {}", id);
+                    }
+                    for id in &res.stopped {
+                        println!("Stopped {}", id);
+                    }
+                    for (id, error) in &res.stopped_dependents {
+                        println!("Stopped {}: {}", id, error);
+                    }
+                }
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        #[cfg(not(feature = "portable"))]
+        ("mqtt", Some(sub_m)) => match sub_m.subcommand() {
+            ("show", Some(sub_sub_m)) => {
+                let res = mqtt::get().await?.map(|mut config| {
+                    if config.password.is_some() {
+                        config.password = Some(config::value::MASK.to_owned());
+                    }
+                    config
+                });
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
                     println!(
                         "{}",
-                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
                     );
+                } else if let Some(config) = res {
+                    println!("{}:{}/{}", config.host, config.port, config.prefix);
                 }
-            } else if sub_m.is_present("yaml") {
-                println!(
-                    "{}",
-                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
-                );
-            } else if !res.is_empty() {
-                use prettytable::{Cell, Row, Table};
-                let mut table = Table::new();
-                let heading = vec![
-                    Cell::new("APPLICATION ID"),
-                    Cell::new("STATUS"),
-                    Cell::new("REASON"),
-                ];
-                table.add_row(Row::new(heading));
-                for (name, reason) in res {
-                    table.add_row(Row::new(vec![
-                        Cell::new(&name),
-                        Cell::new("Stopped"),
-                        Cell::new(&format!("{}", reason)),
-                    ]));
+            }
+            ("set", Some(sub_sub_m)) => {
+                mqtt::set(&mqtt::MqttConfig {
+                    host: sub_sub_m.value_of("HOST").unwrap().to_owned(),
+                    port: sub_sub_m.value_of("port").unwrap().parse().no_code()?,
+                    prefix: sub_sub_m.value_of("prefix").unwrap().to_owned(),
+                    username: sub_sub_m.value_of("username").map(|a| a.to_owned()),
+                    password: sub_sub_m.value_of("password").map(|a| a.to_owned()),
+                })
+                .await?;
+            }
+            ("clear", _) => {
+                mqtt::clear().await?;
+            }
+            ("publish", _) => {
+                let count = mqtt::publish_all().await?;
+                if !*QUIET.read().await {
+                    println!("Published {} messages.", count);
                 }
-                table.print(&mut std::io::stdout())?;
             }
-        }
-        #[cfg(not(feature = "portable"))]
-        ("restart", Some(sub_m)) => {
-            restart_app(sub_m.value_of("ID").unwrap()).await?;
-        }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
         #[cfg(not(feature = "portable"))]
-        ("configure", Some(sub_m)) => {
-            let config: Option<Config> = if let Some(path) = sub_m.value_of("FILE") {
-                let p = Path::new(path);
-                if p.extension() == Some(std::ffi::OsStr::new("json"))
-                    || (sub_m.is_present("json")
-                        && p.extension() != Some(std::ffi::OsStr::new("yaml")))
-                {
-                    Some(util::from_json_async_reader(tokio::fs::File::open(p).await?).await?)
+        ("identity", Some(sub_m)) => match sub_m.subcommand() {
+            (cmd @ "show", Some(sub_sub_m)) | (cmd @ "issue", Some(sub_sub_m)) => {
+                let res = if cmd == "issue" {
+                    identity::issue(sub_sub_m.is_present("force")).await?
                 } else {
-                    Some(util::from_yaml_async_reader(tokio::fs::File::open(p).await?).await?)
-                }
-            } else if sub_m.is_present("stdin") {
-                if sub_m.is_present("json") {
-                    Some(util::from_yaml_async_reader(tokio::io::stdin()).await?)
+                    identity::show().await?
+                };
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
                 } else {
-                    Some(util::from_yaml_async_reader(tokio::io::stdin()).await?)
+                    println!("Hostname: {}", res.hostname);
+                    println!("Certificate SHA-256: {}", res.cert_sha256);
+                    println!("Public Key SHA-256: {}", res.spki_sha256);
+                    println!("Expires: {}", res.not_after);
                 }
-            } else {
-                None
-            };
-            let timeout = if sub_m.is_present("no-timeout") {
-                None
-            } else if let Some(t) = sub_m.value_of("timeout") {
-                Some(std::time::Duration::from_secs(t.parse().no_code()?))
-            } else {
-                Some(std::time::Duration::from_secs(3))
-            };
-            let res = configure(
-                sub_m.value_of("ID").unwrap(),
-                config,
-                timeout,
-                sub_m.is_present("dry-run"),
-            )
-            .await?;
-            if sub_m.is_present("json") {
-                if sub_m.is_present("pretty") {
+            }
+            ("export", Some(sub_sub_m)) => {
+                print!("{}", identity::export(sub_sub_m.is_present("chain")).await?);
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        #[cfg(not(feature = "portable"))]
+        ("io-priority", Some(sub_m)) => match sub_m.subcommand() {
+            ("show", Some(sub_sub_m)) => {
+                let res = io_priority::get().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
                     println!(
                         "{}",
-                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
                     );
                 } else {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("JOB CLASS"),
+                        Cell::new("WEIGHT"),
+                        Cell::new("IONICE"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for class in io_priority::JobClass::ALL.iter() {
+                        let weight = res.get(*class);
+                        table.add_row(Row::new(vec![
+                            Cell::new(&format!("{}", class)),
+                            Cell::new(&format!("{}", weight)),
+                            Cell::new(&format!("{}", io_priority::IoNice::from_weight(weight))),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
+            }
+            ("set", Some(sub_sub_m)) => {
+                io_priority::set(
+                    sub_sub_m.value_of("CLASS").unwrap().parse()?,
+                    sub_sub_m.value_of("WEIGHT").unwrap().parse().no_code()?,
+                )
+                .await?;
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        #[cfg(not(feature = "portable"))]
+        ("status-page", Some(sub_m)) => match sub_m.subcommand() {
+            ("show", Some(sub_sub_m)) => {
+                let res = status_page::render().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
                     println!(
                         "{}",
-                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
                     );
+                } else {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("FIELD"),
+                        Cell::new("VALUE"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for (id, entry) in res.apps {
+                        let mut fields = Vec::new();
+                        if let Some(status) = entry.status {
+                            fields.push(("status".to_owned(), format!("{:?}", status)));
+                        }
+                        if let Some(uptime) = entry.uptime {
+                            fields.push(("uptime".to_owned(), format!("{}s", uptime)));
+                        }
+                        if let Some(version) = entry.version {
+                            fields.push(("version".to_owned(), format!("{}", version)));
+                        }
+                        fields.extend(entry.properties);
+                        for (field, value) in fields {
+                            table.add_row(Row::new(vec![
+                                Cell::new(&id),
+                                Cell::new(&field),
+                                Cell::new(&value),
+                            ]));
+                        }
+                    }
+                    table.print(&mut std::io::stdout())?;
                 }
-            } else if sub_m.is_present("yaml") {
-                println!(
-                    "{}",
-                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
-                );
-            } else if !res.needs_restart.is_empty() || !res.stopped.is_empty() {
-                use prettytable::{Cell, Row, Table};
-                let mut table = Table::new();
-                let heading = vec![
-                    Cell::new("APPLICATION ID"),
-                    Cell::new("STATUS"),
-                    Cell::new("REASON"),
-                ];
-                table.add_row(Row::new(heading));
-                for name in res.needs_restart {
-                    table.add_row(Row::new(vec![
-                        Cell::new(&name),
-                        Cell::new("Needs Restart"),
-                        Cell::new("Configuration Changed"),
-                    ]));
-                }
-                for (name, reason) in res.stopped {
-                    table.add_row(Row::new(vec![
-                        Cell::new(&name),
-                        Cell::new("Stopped"),
-                        Cell::new(&format!("{}", reason)),
-                    ]));
+            }
+            ("allow", Some(sub_sub_m)) => {
+                status_page::allow(
+                    sub_sub_m.value_of("ID").unwrap(),
+                    sub_sub_m
+                        .values_of("FIELDS")
+                        .unwrap()
+                        .map(|a| a.trim().to_owned())
+                        .collect(),
+                )
+                .await?;
+            }
+            ("deny", Some(sub_sub_m)) => {
+                status_page::deny(sub_sub_m.value_of("ID").unwrap()).await?;
+            }
+            ("publish", _) => {
+                let res = status_page::publish().await?;
+                if !*QUIET.read().await {
+                    println!("Published the status of {} apps.", res.apps.len());
                 }
-                table.print(&mut std::io::stdout())?;
             }
-        }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
         #[cfg(not(feature = "portable"))]
-        ("check-dependencies", Some(sub_m)) => {
-            let res = apps::dependencies(
-                sub_m.value_of("ID").unwrap(),
-                sub_m.is_present("local-only"),
-            )
-            .await?;
-            if sub_m.is_present("json") {
-                if sub_m.is_present("pretty") {
+        ("docs", Some(sub_m)) => match sub_m.subcommand() {
+            ("publish", _) => {
+                let res = docs::publish().await?;
+                if !*QUIET.read().await {
+                    println!("Published the docs of {} apps.", res.apps.len());
+                }
+            }
+            ("search", Some(sub_sub_m)) => {
+                let query = sub_sub_m
+                    .values_of("QUERY")
+                    .unwrap()
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let res = docs::render().await?;
+                let found = docs::search(&res.search, &query);
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&found)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&found).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
                     println!(
                         "{}",
-                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                        serde_yaml::to_string(&found).with_code(crate::error::SERDE_ERROR)?
                     );
                 } else {
+                    for entry in found {
+                        println!("{} / {} / {}", entry.app, entry.page, entry.heading);
+                        for line in entry.text.lines() {
+                            println!("  {}", line);
+                        }
+                        println!();
+                    }
+                }
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        #[cfg(not(feature = "portable"))]
+        ("replication", Some(sub_m)) => match sub_m.subcommand() {
+            ("show", Some(sub_sub_m)) => {
+                let res = replication::get().await?.map(|mut config| {
+                    config.password = config::value::MASK.to_owned();
+                    config
+                });
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
                     println!(
                         "{}",
-                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
                     );
+                } else if let Some(config) = res {
+                    println!("{} of {}", config.role, config.target);
                 }
-            } else if sub_m.is_present("yaml") {
-                println!(
-                    "{}",
-                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
-                );
-            } else if !res.0.is_empty() {
-                use prettytable::{Cell, Row, Table};
-                let mut table = Table::new();
-                let heading = vec![
-                    Cell::new("APPLICATION ID"),
-                    Cell::new("REQUIRED"),
-                    Cell::new("VIOLATION"),
-                ];
-                table.add_row(Row::new(heading));
-                for (name, info) in res.0 {
-                    table.add_row(Row::new(vec![
-                        Cell::new(&name),
-                        Cell::new(&format!("{}", info.required)),
-                        Cell::new(&if let Some(error) = info.error {
-                            format!("{}", error)
-                        } else {
-                            "N/A".to_owned()
-                        }),
-                    ]));
-                }
-                table.print(&mut std::io::stdout())?;
-            } else {
-                println!("No dependencies for {}", sub_m.value_of("ID").unwrap());
             }
-        }
-        ("autoconfigure-dependency", Some(sub_m)) => {
-            let res = dependencies::auto_configure(
-                sub_m.value_of("ID").unwrap(),
-                sub_m.value_of("DEPENDENCY").unwrap(),
-                sub_m.is_present("dry-run"),
-            )
-            .await?;
-            if sub_m.is_present("json") {
-                if sub_m.is_present("pretty") {
+            ("pair", Some(sub_sub_m)) => {
+                replication::pair(
+                    sub_sub_m.value_of("ROLE").unwrap().parse()?,
+                    sub_sub_m.value_of("TARGET").unwrap(),
+                    sub_sub_m.is_present("tor"),
+                    &match sub_sub_m.value_of("password") {
+                        Some(a) => Cow::Borrowed(a),
+                        None => Cow::Owned(rpassword::read_password_from_tty(Some("Password: "))?),
+                    },
+                )
+                .await?;
+            }
+            ("unpair", _) => {
+                replication::unpair().await?;
+            }
+            ("sync", Some(sub_sub_m)) => {
+                let res = replication::sync().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
                     println!(
                         "{}",
-                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
                     );
                 } else {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("STATUS"),
+                        Cell::new("REASON"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for id in &res.succeeded {
+                        table.add_row(Row::new(vec![
+                            Cell::new(id),
+                            Cell::new("Shipped"),
+                            Cell::new(""),
+                        ]));
+                    }
+                    for (id, reason) in &res.failed {
+                        table.add_row(Row::new(vec![
+                            Cell::new(id),
+                            Cell::new("Failed"),
+                            Cell::new(reason),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
+                if !res.failed.is_empty() {
+                    std::process::exit(crate::error::GENERAL_ERROR);
+                }
+            }
+            ("failover", Some(sub_sub_m)) => {
+                let res = replication::failover().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
                     println!(
                         "{}",
-                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
                     );
+                } else {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("STATUS"),
+                        Cell::new("REASON"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for id in &res.succeeded {
+                        table.add_row(Row::new(vec![
+                            Cell::new(id),
+                            Cell::new("Restored"),
+                            Cell::new(""),
+                        ]));
+                    }
+                    for (id, reason) in &res.failed {
+                        table.add_row(Row::new(vec![
+                            Cell::new(id),
+                            Cell::new("Failed"),
+                            Cell::new(reason),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
                 }
-            } else if sub_m.is_present("yaml") {
-                println!(
-                    "{}",
-                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
-                );
-            } else if !res.needs_restart.is_empty() || !res.stopped.is_empty() {
-                use prettytable::{Cell, Row, Table};
-                let mut table = Table::new();
-                let heading = vec![
-                    Cell::new("APPLICATION ID"),
-                    Cell::new("STATUS"),
-                    Cell::new("REASON"),
-                ];
-                table.add_row(Row::new(heading));
-                for name in res.needs_restart {
-                    table.add_row(Row::new(vec![
-                        Cell::new(&name),
-                        Cell::new("Needs Restart"),
-                        Cell::new("Configuration Changed"),
-                    ]));
-                }
-                for (name, reason) in res.stopped {
-                    table.add_row(Row::new(vec![
-                        Cell::new(&name),
-                        Cell::new("Stopped"),
-                        Cell::new(&format!("{}", reason)),
-                    ]));
+                if !res.failed.is_empty() {
+                    std::process::exit(crate::error::GENERAL_ERROR);
                 }
-                table.print(&mut std::io::stdout())?;
             }
-        }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
         #[cfg(not(feature = "portable"))]
-        ("remove", Some(sub_m)) | ("rm", Some(sub_m)) => {
-            let res = remove(
-                sub_m.value_of("ID").unwrap(),
-                sub_m.is_present("purge"),
-                sub_m.is_present("dry-run"),
-            )
-            .await?;
-            if sub_m.is_present("json") {
-                if sub_m.is_present("pretty") {
+        ("security", Some(sub_m)) => match sub_m.subcommand() {
+            ("audit", Some(sub_sub_m)) => {
+                let info = security::audit(sub_sub_m.is_present("block")).await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&info)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&info).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
                     println!(
                         "{}",
-                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                        serde_yaml::to_string(&info).with_code(crate::error::SERDE_ERROR)?
                     );
+                } else if !info.is_empty() {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("LEVEL"),
+                        Cell::new("CODE"),
+                        Cell::new("TITLE"),
+                        Cell::new("MESSAGE"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for note in info {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&format!("{}", note.level)),
+                            Cell::new(&format!("{}", note.code)),
+                            Cell::new(&format!("{}", note.title)),
+                            Cell::new(&format!("{}", note.message)),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
                 } else {
-                    println!(
-                        "{}",
-                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
-                    );
-                }
-            } else if sub_m.is_present("yaml") {
-                println!(
-                    "{}",
-                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
-                );
-            } else if !res.is_empty() {
-                use prettytable::{Cell, Row, Table};
-                let mut table = Table::new();
-                let heading = vec![
-                    Cell::new("APPLICATION ID"),
-                    Cell::new("STATUS"),
-                    Cell::new("REASON"),
-                ];
-                table.add_row(Row::new(heading));
-                for (name, reason) in res {
-                    table.add_row(Row::new(vec![
-                        Cell::new(&name),
-                        Cell::new("Stopped"),
-                        Cell::new(&format!("{}", reason)),
-                    ]));
+                    println!("No anomalies detected");
                 }
-                table.print(&mut std::io::stdout())?;
             }
-        }
+            ("encrypt-configs", Some(sub_sub_m)) => {
+                encryption::set_configs_encrypted(sub_sub_m.value_of("STATE") == Some("on"))
+                    .await?;
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
         #[cfg(not(feature = "portable"))]
-        ("tor", Some(sub_m)) => match sub_m.subcommand() {
+        ("health", Some(sub_m)) => match sub_m.subcommand() {
             ("show", Some(sub_sub_m)) => {
-                println!(
-                    "{}",
-                    crate::tor::read_tor_address(sub_sub_m.value_of("ID").unwrap(), None).await?
-                );
-            }
-            ("reload", Some(_)) => {
-                crate::tor::reload().await?;
+                let info = health::get(sub_sub_m.value_of("ID").unwrap()).await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&info)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&info).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&info).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else if !info.is_empty() {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("CHECK"),
+                        Cell::new("HEALTH"),
+                        Cell::new("FAILURES"),
+                        Cell::new("ERROR"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for (id, state) in info {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&id),
+                            Cell::new(&format!("{:?}", state.health)),
+                            Cell::new(&format!("{}", state.failures)),
+                            Cell::new(state.error.as_deref().unwrap_or("")),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                } else {
+                    println!("No health checks probed");
+                }
             }
+            ("monitor", _) => health::monitor().await?,
             _ => {
                 println!("{}", sub_m.usage());
                 std::process::exit(1);
@@ -1210,7 +4389,7 @@ async fn inner_main() -> Result<(), Error> {
         #[cfg(not(feature = "portable"))]
         ("info", Some(sub_m)) => {
             let name = sub_m.value_of("ID").unwrap();
-            let info = crate::apps::info_full(
+            let mut info = crate::apps::info_full(
                 &name,
                 sub_m.is_present("include-status") || sub_m.is_present("only-status"),
                 sub_m.is_present("include-manifest") || sub_m.is_present("only-manifest"),
@@ -1218,6 +4397,13 @@ async fn inner_main() -> Result<(), Error> {
                 sub_m.is_present("include-dependencies") || sub_m.is_present("only-dependencies"),
             )
             .await?;
+            if sub_m.is_present("redact") {
+                if let Some(cfg) = info.config.as_mut() {
+                    if let Some(config) = cfg.config.as_mut() {
+                        config.redact(&cfg.spec.masked_paths());
+                    }
+                }
+            }
             if sub_m.is_present("json") {
                 if sub_m.is_present("pretty") {
                     if sub_m.is_present("only-status") {
@@ -1316,7 +4502,11 @@ async fn inner_main() -> Result<(), Error> {
         }
         #[cfg(not(feature = "portable"))]
         ("instructions", Some(sub_m)) => {
-            crate::apps::print_instructions(sub_m.value_of("ID").unwrap()).await?;
+            crate::apps::print_instructions(
+                sub_m.value_of("ID").unwrap(),
+                sub_m.value_of("format").unwrap().parse()?,
+            )
+            .await?;
         }
         #[cfg(not(feature = "portable"))]
         ("list", Some(sub_m)) | ("ls", Some(sub_m)) => {
@@ -1353,6 +4543,8 @@ async fn inner_main() -> Result<(), Error> {
                     Cell::new("VERSION"),
                     Cell::new("TOR ADDRESS"),
                     Cell::new("CONFIGURED"),
+                    Cell::new("PINNED"),
+                    Cell::new("MODE"),
                 ];
                 if sub_m.is_present("include-status") {
                     heading.push(Cell::new("STATUS"));
@@ -1372,13 +4564,16 @@ async fn inner_main() -> Result<(), Error> {
                                 info.info.tor_address.unwrap_or_else(|| "N/A".to_owned())
                             )),
                             Cell::new(&format!("{}", info.info.configured)),
+                            Cell::new(&format!("{}", info.info.pinned)),
+                            Cell::new(info.info.mode.as_deref().unwrap_or("N/A")),
                         ]
                         .into_iter()
-                        .chain(
-                            info.status
-                                .into_iter()
-                                .map(|s| Cell::new(&format!("{:?}", s.status))),
-                        )
+                        .chain(info.status.into_iter().map(|s| {
+                            Cell::new(&match s.health {
+                                Some(health) => format!("{:?} ({:?})", s.status, health),
+                                None => format!("{:?}", s.status),
+                            })
+                        }))
                         .chain(info.dependencies.into_iter().map(|s| {
                             Cell::new(&format!(
                                 "{}",
@@ -1470,6 +4665,51 @@ async fn inner_main() -> Result<(), Error> {
             }
         }
         #[cfg(not(feature = "portable"))]
+        ("events", Some(sub_m)) => {
+            let since = sub_m
+                .value_of("since")
+                .map(|s| s.parse())
+                .transpose()
+                .no_code()?;
+            let app = sub_m.value_of("app");
+            let show = |event: events::Event| -> Result<(), Error> {
+                if app.map_or(false, |app| Some(app) != event.app.as_deref()) {
+                    return Ok(());
+                }
+                if sub_m.is_present("json") {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&event).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else if sub_m.is_present("sse") {
+                    print!("{}", event.to_sse()?);
+                    std::io::Write::flush(&mut std::io::stdout())?;
+                } else {
+                    println!(
+                        "{} {} {}{}",
+                        event.time,
+                        serde_json::to_value(event.kind)
+                            .with_code(crate::error::SERDE_ERROR)?
+                            .as_str()
+                            .unwrap_or_default(),
+                        event.app.as_deref().unwrap_or("-"),
+                        event
+                            .notification
+                            .map(|n| format!(" {}", n))
+                            .unwrap_or_default()
+                    );
+                }
+                Ok(())
+            };
+            if sub_m.is_present("follow") {
+                events::follow(since, show).await?;
+            } else {
+                for event in events::read(since).await? {
+                    show(event)?;
+                }
+            }
+        }
+        #[cfg(not(feature = "portable"))]
         ("stats", Some(sub_m)) => {
             let info = stats(sub_m.value_of("ID").unwrap()).await?;
             if sub_m.is_present("json") {
@@ -1489,25 +4729,11 @@ async fn inner_main() -> Result<(), Error> {
                     "{}",
                     serde_yaml::to_string(&info).with_code(crate::error::SERDE_ERROR)?
                 );
-            } else if let serde_yaml::Value::Mapping(map) = info {
+            } else {
                 use prettytable::{Cell, Row, Table};
                 let mut table = Table::new();
-                for (k, v) in map {
-                    let ks = match k {
-                        serde_yaml::Value::Bool(k) => format!("{}", k),
-                        serde_yaml::Value::Null => "null".to_owned(),
-                        serde_yaml::Value::Number(k) => format!("{}", k),
-                        serde_yaml::Value::String(k) => k,
-                        k => serde_yaml::to_string(&k).with_code(crate::error::SERDE_ERROR)?,
-                    };
-                    let vs = match v {
-                        serde_yaml::Value::Bool(v) => format!("{}", v),
-                        serde_yaml::Value::Null => "null".to_owned(),
-                        serde_yaml::Value::Number(v) => format!("{}", v),
-                        serde_yaml::Value::String(v) => v,
-                        v => serde_yaml::to_string(&v).with_code(crate::error::SERDE_ERROR)?,
-                    };
-                    table.add_row(Row::new(vec![Cell::new(&ks), Cell::new(&vs)]));
+                for (k, v) in info.rows()? {
+                    table.add_row(Row::new(vec![Cell::new(&k), Cell::new(&v)]));
                 }
                 table.print(&mut std::io::stdout())?;
             }
@@ -1556,6 +4782,75 @@ async fn inner_main() -> Result<(), Error> {
                 )
                 .await?
             }
+            ("create-all", Some(sub_sub_m)) => {
+                let password = match sub_sub_m.value_of("password") {
+                    Some(a) => Cow::Borrowed(a),
+                    None => Cow::Owned(rpassword::read_password_from_tty(Some("Password: "))?),
+                };
+                let jobs = sub_sub_m.value_of("jobs").unwrap().parse().no_code()?;
+                let res = if let Some(group) = sub_sub_m.value_of("group") {
+                    crate::backup::backup_many_to_partition(
+                        sub_sub_m.value_of("PARTITION").unwrap(),
+                        &password,
+                        groups::get(group).await?.apps.keys().cloned().collect(),
+                        jobs,
+                    )
+                    .await?
+                } else {
+                    crate::backup::backup_all_to_partition(
+                        sub_sub_m.value_of("PARTITION").unwrap(),
+                        &password,
+                        jobs,
+                    )
+                    .await?
+                };
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("STATUS"),
+                        Cell::new("REASON"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for id in &res.succeeded {
+                        table.add_row(Row::new(vec![
+                            Cell::new(id),
+                            Cell::new("Backed Up"),
+                            Cell::new(""),
+                        ]));
+                    }
+                    for (id, reason) in &res.failed {
+                        table.add_row(Row::new(vec![
+                            Cell::new(id),
+                            Cell::new("Failed"),
+                            Cell::new(reason),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
+                if !res.failed.is_empty() {
+                    std::process::exit(crate::error::GENERAL_ERROR);
+                }
+            }
             ("restore", Some(sub_sub_m)) => {
                 crate::backup::restore_from_partition(
                     sub_sub_m.value_of("PARTITION").unwrap(),
@@ -1573,6 +4868,276 @@ async fn inner_main() -> Result<(), Error> {
             }
         },
         #[cfg(not(feature = "portable"))]
+        ("retained", Some(sub_m)) => match sub_m.subcommand() {
+            ("list", Some(sub_sub_m)) | ("ls", Some(sub_sub_m)) => {
+                let info = retention::list().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&info)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&info).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&info).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else if !info.is_empty() {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("VERSION"),
+                        Cell::new("SIZE"),
+                        Cell::new("EXPIRES AT"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for (name, info) in info {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&name),
+                            Cell::new(&format!("{}", info.retained.version)),
+                            Cell::new(&format!("{:.1} MiB", info.size as f64 / 1048576.0)),
+                            Cell::new(&format!("{}", info.retained.expires_at)),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                } else {
+                    println!("No app versions retained");
+                }
+            }
+            ("period", Some(sub_sub_m)) => {
+                retention::set_days(sub_sub_m.value_of("DAYS").unwrap().parse().no_code()?).await?;
+            }
+            ("gc", Some(sub_sub_m)) => {
+                for name in retention::gc(sub_sub_m.is_present("all")).await? {
+                    println!("Discarded retained version of {}", name);
+                }
+            }
+            ("discard", Some(sub_sub_m)) => {
+                retention::discard(sub_sub_m.value_of("ID").unwrap()).await?;
+            }
+            ("rollback", Some(sub_sub_m)) => {
+                retention::rollback(sub_sub_m.value_of("ID").unwrap()).await?;
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        #[cfg(not(feature = "portable"))]
+        ("group", Some(sub_m)) => match sub_m.subcommand() {
+            ("list", Some(sub_sub_m)) | ("ls", Some(sub_sub_m)) => {
+                let res = groups::list().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else if !res.is_empty() {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![Cell::new("NAME"), Cell::new("TITLE"), Cell::new("APPS")];
+                    table.add_row(Row::new(heading));
+                    for (name, group) in res {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&name),
+                            Cell::new(&group.title),
+                            Cell::new(&group.apps.keys().cloned().collect::<Vec<_>>().join(", ")),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                } else {
+                    println!("No groups defined");
+                }
+            }
+            ("define", Some(sub_sub_m)) => {
+                let group: groups::Group = util::from_yaml_async_reader(
+                    tokio::fs::File::open(sub_sub_m.value_of("FILE").unwrap()).await?,
+                )
+                .await?;
+                groups::define(sub_sub_m.value_of("NAME").unwrap(), group).await?;
+            }
+            ("undefine", Some(sub_sub_m)) => {
+                groups::undefine(sub_sub_m.value_of("NAME").unwrap()).await?;
+            }
+            ("install", Some(sub_sub_m)) => {
+                let timeout = if sub_sub_m.is_present("no-timeout") {
+                    None
+                } else {
+                    Some(std::time::Duration::from_secs(
+                        sub_sub_m.value_of("timeout").unwrap().parse().no_code()?,
+                    ))
+                };
+                let group = groups::get(sub_sub_m.value_of("NAME").unwrap()).await?;
+                let res = groups::install(&group, timeout).await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&res)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    for id in &res.installed {
+                        println!("Installed {}", id);
+                    }
+                    for id in &res.started {
+                        println!("Started {}", id);
+                    }
+                    for id in &res.configured.needs_restart {
+                        println!("{} needs to be restarted for its config to apply", id);
+                    }
+                }
+            }
+            ("start", Some(sub_sub_m)) => {
+                let group = groups::get(sub_sub_m.value_of("NAME").unwrap()).await?;
+                for id in groups::start(&group).await? {
+                    println!("Started {}", id);
+                }
+            }
+            ("stop", Some(sub_sub_m)) => {
+                let group = groups::get(sub_sub_m.value_of("NAME").unwrap()).await?;
+                let res = groups::stop(&group, sub_sub_m.is_present("dry-run")).await?;
+                if !res.is_empty() {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("STATUS"),
+                        Cell::new("REASON"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for (name, reason) in res {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&name),
+                            Cell::new("Stopped"),
+                            Cell::new(&format!("{}", reason)),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                }
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        #[cfg(not(feature = "portable"))]
+        ("cache", Some(sub_m)) => match sub_m.subcommand() {
+            ("list", Some(sub_sub_m)) | ("ls", Some(sub_sub_m)) => {
+                let state = cache::state().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&state)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&state).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&state).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else if !state.packages.is_empty() {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![
+                        Cell::new("APPLICATION ID"),
+                        Cell::new("VERSION"),
+                        Cell::new("SIZE"),
+                        Cell::new("LAST USED"),
+                    ];
+                    table.add_row(Row::new(heading));
+                    for cached in state.packages.values() {
+                        table.add_row(Row::new(vec![
+                            Cell::new(&cached.id),
+                            Cell::new(&format!("{}", cached.version)),
+                            Cell::new(&format!("{:.1} MiB", cached.size as f64 / 1048576.0)),
+                            Cell::new(&format!("{}", cached.last_used)),
+                        ]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                    println!(
+                        "{:.1} of {:.1} MiB used",
+                        state.size() as f64 / 1048576.0,
+                        state.max_size() as f64 / 1048576.0
+                    );
+                } else {
+                    println!("No packages cached");
+                }
+            }
+            ("clear", Some(sub_sub_m)) => {
+                for cached in cache::clear(sub_sub_m.value_of("ID")).await? {
+                    println!("Removed cached {} v{}", cached.id, cached.version);
+                }
+            }
+            ("gc", Some(sub_sub_m)) => {
+                let max_size = sub_sub_m
+                    .value_of("max-size")
+                    .map(|s| s.parse::<u64>().map(|mib| mib * 1024 * 1024))
+                    .transpose()
+                    .no_code()?;
+                for cached in cache::gc(max_size).await? {
+                    println!("Evicted cached {} v{}", cached.id, cached.version);
+                }
+            }
+            ("limit", Some(sub_sub_m)) => {
+                let evicted = if let Some(mib) = sub_sub_m.value_of("MIB") {
+                    cache::set_max_size(Some(mib.parse::<u64>().no_code()? * 1024 * 1024)).await?
+                } else if sub_sub_m.is_present("reset") {
+                    cache::set_max_size(None).await?
+                } else {
+                    let state = cache::state().await?;
+                    println!("{:.1} MiB", state.max_size() as f64 / 1048576.0);
+                    Vec::new()
+                };
+                for cached in evicted {
+                    println!("Evicted cached {} v{}", cached.id, cached.version);
+                }
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        #[cfg(not(feature = "portable"))]
         ("repair-app-status", _) => {
             control::repair_app_status().await?;
         }
@@ -1604,14 +5169,154 @@ async fn inner_main() -> Result<(), Error> {
                 .with_code(error::SERDE_ERROR)?
             )
         }
-        ("pack", Some(sub_m)) => {
-            pack(
-                sub_m.value_of("PATH").unwrap(),
-                sub_m.value_of("output").unwrap(),
+        ("pack", Some(sub_m)) => match sub_m.subcommand() {
+            ("delta", Some(sub_sub_m)) => {
+                let len = delta::create(
+                    sub_sub_m.value_of("from").unwrap(),
+                    sub_sub_m.value_of("to").unwrap(),
+                    sub_sub_m.value_of("output").unwrap(),
+                )
+                .await?;
+                log::info!("{}KiB delta written.", len / 1024);
+            }
+            _ => {
+                pack(
+                    sub_m.value_of("PATH").unwrap(),
+                    sub_m.value_of("output").unwrap(),
+                    sub_m.value_of("key"),
+                    sub_m.is_present("deterministic"),
+                )
+                .await?
+            }
+        },
+        ("verify", Some(sub_m)) => verify(sub_m.value_of("PATH").unwrap()).await?,
+        ("keys", Some(sub_m)) => match sub_m.subcommand() {
+            ("list", Some(sub_sub_m)) | ("ls", Some(sub_sub_m)) => {
+                let keys = signing::list().await?;
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&keys)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&keys).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&keys).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else if !keys.is_empty() {
+                    use prettytable::{Cell, Row, Table};
+                    let mut table = Table::new();
+                    let heading = vec![Cell::new("NAME"), Cell::new("PUBLIC KEY")];
+                    table.add_row(Row::new(heading));
+                    for (name, key) in keys.iter() {
+                        table.add_row(Row::new(vec![Cell::new(name), Cell::new(key)]));
+                    }
+                    table.print(&mut std::io::stdout())?;
+                } else {
+                    println!("No keys trusted");
+                }
+            }
+            ("add", Some(sub_sub_m)) => {
+                signing::add(
+                    sub_sub_m.value_of("NAME").unwrap(),
+                    sub_sub_m.value_of("KEY").unwrap(),
+                )
+                .await?;
+            }
+            ("remove", Some(sub_sub_m)) | ("rm", Some(sub_sub_m)) => {
+                signing::remove(sub_sub_m.value_of("NAME").unwrap()).await?;
+            }
+            ("generate", Some(sub_sub_m)) => {
+                println!(
+                    "{}",
+                    signing::generate_key(sub_sub_m.value_of("FILE").unwrap()).await?
+                );
+            }
+            _ => {
+                println!("{}", sub_m.usage());
+                std::process::exit(1);
+            }
+        },
+        ("test-dependencies", Some(sub_m)) => {
+            let manifest_path = sub_m.value_of("MANIFEST").unwrap();
+            let manifest: manifest::Manifest = util::from_yaml_async_reader(
+                tokio::fs::File::open(manifest_path).await.with_ctx(|e| {
+                    (
+                        Some(crate::error::FILESYSTEM_ERROR),
+                        format!("{}: {}", manifest_path, e),
+                    )
+                })?,
+            )
+            .await?;
+            let fixtures_path = sub_m.value_of("FIXTURES").unwrap();
+            let fixtures = util::from_yaml_async_reader(
+                tokio::fs::File::open(fixtures_path).await.with_ctx(|e| {
+                    (
+                        Some(crate::error::FILESYSTEM_ERROR),
+                        format!("{}: {}", fixtures_path, e),
+                    )
+                })?,
             )
-            .await?
+            .await?;
+            let res = dependencies::check_fixtures(&manifest.into_latest(), fixtures)?;
+            if sub_m.is_present("json") {
+                if sub_m.is_present("pretty") {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                    );
+                }
+            } else if sub_m.is_present("yaml") {
+                println!(
+                    "{}",
+                    serde_yaml::to_string(&res).with_code(crate::error::SERDE_ERROR)?
+                );
+            } else {
+                use prettytable::{Cell, Row, Table};
+                let mut table = Table::new();
+                let heading = vec![
+                    Cell::new("DEPENDENCY"),
+                    Cell::new("VERSION"),
+                    Cell::new("EXPECTED"),
+                    Cell::new("RESULT"),
+                    Cell::new("PASSED"),
+                ];
+                table.add_row(Row::new(heading));
+                for fixture_res in &res {
+                    table.add_row(Row::new(vec![
+                        Cell::new(&fixture_res.fixture.dependency),
+                        Cell::new(&format!("{}", fixture_res.fixture.version)),
+                        Cell::new(if fixture_res.fixture.satisfied {
+                            "Satisfied"
+                        } else {
+                            "Unsatisfied"
+                        }),
+                        Cell::new(&match &fixture_res.error {
+                            Some(e) => format!("{}", e),
+                            None => "Satisfied".to_owned(),
+                        }),
+                        Cell::new(&format!("{}", fixture_res.passed)),
+                    ]));
+                }
+                table.print(&mut std::io::stdout())?;
+            }
+            if res.iter().any(|r| !r.passed) {
+                std::process::exit(crate::error::GENERAL_ERROR);
+            }
         }
-        ("verify", Some(sub_m)) => verify(sub_m.value_of("PATH").unwrap()).await?,
         ("inspect", Some(sub_m)) => match sub_m.subcommand() {
             ("info", Some(sub_sub_m)) => {
                 let path = sub_sub_m.value_of("PATH").unwrap();
@@ -1686,8 +5391,68 @@ async fn inner_main() -> Result<(), Error> {
                 }
             }
             ("instructions", Some(sub_sub_m)) => {
-                crate::inspect::print_instructions(Path::new(sub_sub_m.value_of("PATH").unwrap()))
-                    .await?;
+                crate::inspect::print_instructions(
+                    Path::new(sub_sub_m.value_of("PATH").unwrap()),
+                    sub_sub_m.value_of("format").unwrap().parse()?,
+                )
+                .await?;
+            }
+            ("lint-config", Some(sub_sub_m)) => {
+                let info =
+                    crate::inspect::info_full(sub_sub_m.value_of("PATH").unwrap(), true, true)
+                        .await?;
+                let samples = sub_sub_m
+                    .value_of("samples")
+                    .unwrap()
+                    .parse::<usize>()
+                    .no_code()?;
+                let manifest = info.manifest.unwrap();
+                let report = match info.config {
+                    Some(cfg) => config::lint::lint(&manifest, &cfg.spec, &cfg.rules, samples),
+                    None => config::lint::LintReport {
+                        samples,
+                        ..Default::default()
+                    },
+                };
+                if sub_sub_m.is_present("json") {
+                    if sub_sub_m.is_present("pretty") {
+                        println!(
+                            "{}",
+                            serde_json::to_string_pretty(&report)
+                                .with_code(crate::error::SERDE_ERROR)?
+                        );
+                    } else {
+                        println!(
+                            "{}",
+                            serde_json::to_string(&report).with_code(crate::error::SERDE_ERROR)?
+                        );
+                    }
+                } else if sub_sub_m.is_present("yaml") {
+                    println!(
+                        "{}",
+                        serde_yaml::to_string(&report).with_code(crate::error::SERDE_ERROR)?
+                    );
+                } else {
+                    for (heading, issues) in &[
+                        ("SPEC ERRORS", &report.spec_errors),
+                        ("DEFAULT VIOLATIONS", &report.default_violations),
+                        ("UNSATISFIABLE RULES", &report.unsatisfiable_rules),
+                        ("UNREACHABLE VARIANTS", &report.unreachable_variants),
+                    ] {
+                        if !issues.is_empty() {
+                            println!("{}:", heading);
+                            for issue in issues.iter() {
+                                println!("  {}", issue);
+                            }
+                        }
+                    }
+                    if report.is_ok() {
+                        println!("No issues found in {} samples.", report.samples);
+                    }
+                }
+                if !report.is_ok() {
+                    std::process::exit(crate::error::CFG_RULES_VIOLATION);
+                }
             }
             _ => {
                 println!("{}", sub_m.usage());
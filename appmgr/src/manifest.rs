@@ -1,9 +1,16 @@
 use std::path::PathBuf;
 
-use linear_map::LinearMap;
+use itertools::Itertools;
+use linear_map::{set::LinearSet, LinearMap};
 
 use crate::actions::Action;
+use crate::config::migration::ConfigMigration;
+use crate::config::{ConfigFormat, ConfigSpec};
 use crate::dependencies::Dependencies;
+use crate::health::HealthCheck;
+use crate::hooks::Hooks;
+use crate::instructions::InstructionsFormat;
+use crate::modes::Modes;
 use crate::tor::HiddenServiceVersion;
 use crate::tor::PortMapping;
 
@@ -15,11 +22,120 @@ pub struct Description {
     pub long: String,
 }
 
+/// Where a package comes from, for the UI to show next to it. Unset fields are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Provenance {
+    /// The license of the service, as an SPDX expression, i.e. `MIT` or `GPL-3.0-or-later`.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_repo: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marketing_site: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub support_site: Option<String>,
+}
+
+/// What the host needs for the app to run, checked on install and update.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Requirements {
+    /// Total memory, in MiB.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_memory: Option<u64>,
+    /// Free disk, in MiB.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_disk: Option<u64>,
+    /// The architectures the app runs on (as in `std::env::consts::ARCH`), any if empty.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cpu_arch: Vec<String>,
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum ImageConfig {
     Tar,
+    /// One image per target architecture (as in `std::env::consts::ARCH`, i.e. `aarch64` or
+    /// `x86_64`), packed as `image.<arch>.tar` in the order given.
+    MultiArch {
+        arches: Vec<String>,
+    },
+    /// An image in a container registry, pinned by its digest, that install pulls instead of the
+    /// package carrying it.
+    Registry {
+        reference: String,
+        digest: String,
+    },
+    /// A binary run directly on the host as a systemd service instead of in a container, for
+    /// services light enough not to need an image. It is built for one architecture, so the
+    /// manifest should name it in `cpu-arch`. `binary` is its file name in the package.
+    Static {
+        binary: String,
+        #[serde(default)]
+        unit: StaticUnit,
+    },
+}
+/// How the service of a static binary is run. It runs in the volume of the app, and is given the
+/// same environment a container would be.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StaticUnit {
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "LinearMap::is_empty")]
+    pub environment: LinearMap<String, String>,
+    /// Runs as a user of its own unless set.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+/// Whether `digest` is a sha256 content digest, as in `sha256:<64 hex digits>`.
+pub fn is_valid_digest(digest: &str) -> bool {
+    digest.strip_prefix("sha256:").map_or(false, |hex| {
+        hex.len() == 64
+            && hex
+                .chars()
+                .all(|c| c.is_ascii_hexdigit() && !c.is_ascii_uppercase())
+    })
+}
+impl ImageConfig {
+    /// The names of the image tarballs in the package, in the order they are packed. A static
+    /// binary is packed in place of an image.
+    pub fn tar_names(&self) -> Vec<String> {
+        match self {
+            ImageConfig::Tar => vec!["image.tar".to_owned()],
+            ImageConfig::MultiArch { arches } => arches
+                .iter()
+                .map(|arch| format!("image.{}.tar", arch))
+                .collect(),
+            ImageConfig::Registry { .. } => Vec::new(),
+            ImageConfig::Static { binary, .. } => vec![binary.clone()],
+        }
+    }
+    /// The name of the image tarball to load on `arch`, if the package has one for it. A single
+    /// image is assumed to be built for the device. Registry images have no tarball: which image
+    /// to pull for the device is up to docker.
+    pub fn tar_for(&self, arch: &str) -> Option<String> {
+        match self {
+            ImageConfig::Tar => Some("image.tar".to_owned()),
+            ImageConfig::MultiArch { arches } => arches
+                .iter()
+                .find(|a| *a == arch)
+                .map(|arch| format!("image.{}.tar", arch)),
+            ImageConfig::Registry { .. } => None,
+            ImageConfig::Static { binary, .. } => Some(binary.clone()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
@@ -29,6 +145,28 @@ pub struct Asset {
     pub overwrite: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Lan,
+    Tor,
+}
+
+fn root_path() -> String {
+    "/".to_owned()
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LaunchInterface {
+    pub name: String,
+    pub internal: u16,
+    #[serde(default = "root_path")]
+    pub path: String,
+    #[serde(default)]
+    pub requires: Option<Network>,
+}
+
 #[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ManifestV0 {
@@ -47,6 +185,16 @@ pub struct ManifestV0 {
     pub start_alert: Option<String>,
     #[serde(default)]
     pub has_instructions: bool,
+    /// What instructions.md is written in. Markdown unless set.
+    #[serde(default)]
+    pub instructions_format: InstructionsFormat,
+    /// Ships with the OS: cannot be removed, is updated with the OS rather than on its own, and is
+    /// started before other apps.
+    #[serde(default)]
+    pub system: bool,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modes: Option<Modes>,
     #[serde(default = "emver::VersionRange::any")]
     pub os_version_required: emver::VersionRange,
     #[serde(default = "emver::VersionRange::any")]
@@ -68,6 +216,40 @@ pub struct ManifestV0 {
     pub dependencies: Dependencies,
     #[serde(default)]
     pub actions: Vec<Action>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Hooks::is_empty")]
+    pub hooks: Hooks,
+    /// Environment variables of the container, by the dotted path of the config field they are
+    /// set from. The container is recreated when they change.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "LinearMap::is_empty")]
+    pub env_map: LinearMap<String, String>,
+    #[serde(default)]
+    pub launch: Vec<LaunchInterface>,
+    #[serde(default)]
+    pub config_format: ConfigFormat,
+    /// Keep a plaintext copy of the config in the volume even when configs are encrypted at rest.
+    #[serde(default)]
+    pub plaintext_config: bool,
+    /// Asked before install, i.e. whether to restore from an existing seed. The answers seed the
+    /// first config (by top level key), and are given to migration scripts.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install_prompts: Option<ConfigSpec>,
+    /// Applied in order to the config of a previous version when this version is installed over it.
+    #[serde(default)]
+    pub config_migrations: Vec<ConfigMigration>,
+    /// Probed while the app runs by `appmgr health monitor`, by id.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "LinearMap::is_empty")]
+    pub health_checks: LinearMap<String, HealthCheck>,
+    /// `license`, `upstream-repo`, `marketing-site` and `support-site`, unset for packages that
+    /// predate them.
+    #[serde(flatten)]
+    pub provenance: Provenance,
+    /// `min-memory`, `min-disk` and `cpu-arch`.
+    #[serde(flatten)]
+    pub requirements: Requirements,
     #[serde(flatten)]
     pub extra: LinearMap<String, serde_yaml::Value>,
 }
@@ -85,3 +267,311 @@ impl Manifest {
         }
     }
 }
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathSeg {
+    Key(String),
+    Index(usize),
+}
+impl std::fmt::Display for PathSeg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathSeg::Key(k) => write!(f, "{}", k),
+            PathSeg::Index(i) => write!(f, "{}", i),
+        }
+    }
+}
+
+/// Something wrong with a manifest.yaml, with the line it is on when it can be found.
+#[derive(Clone, Debug)]
+pub struct ManifestViolation {
+    pub path: Vec<PathSeg>,
+    pub line: Option<usize>,
+    pub message: String,
+}
+impl std::fmt::Display for ManifestViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "manifest.yaml")?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        if !self.path.is_empty() {
+            write!(f, ": {}", self.path.iter().join("."))?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// Every violation found in a manifest.yaml, so they can all be fixed at once.
+#[derive(Clone, Debug, Fail)]
+pub struct ManifestViolations(pub Vec<ManifestViolation>);
+impl std::fmt::Display for ManifestViolations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.iter().join("\n"))
+    }
+}
+
+/// The 1-based line a path points to in a yaml document, found by following its keys and list
+/// items down the indentation. Only block style is followed.
+pub fn locate(text: &str, path: &[PathSeg]) -> Option<usize> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut pos = 0;
+    let mut parent_indent: isize = -1;
+    let mut in_item = false;
+    for seg in path {
+        let start = if in_item || parent_indent < 0 {
+            pos
+        } else {
+            pos + 1
+        };
+        let mut child_indent = None;
+        let mut seen = 0;
+        let mut found = None;
+        for (idx, line) in lines.iter().enumerate().skip(start) {
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let mut indent = (line.len() - trimmed.len()) as isize;
+            let mut content = trimmed;
+            if in_item && idx == pos {
+                // the first key of a list item is on the line of its dash
+                let rest = content[1..].trim_start();
+                indent += (content.len() - rest.len()) as isize;
+                content = rest;
+            } else if indent < parent_indent
+                || (indent == parent_indent
+                    && !(content.starts_with('-') && matches!(seg, PathSeg::Index(_))))
+            {
+                // out of the parent, though a list may be indented the same as its key
+                break;
+            }
+            if indent != *child_indent.get_or_insert(indent) {
+                continue;
+            }
+            match seg {
+                PathSeg::Key(k) => {
+                    if content.starts_with(k.as_str()) && content[k.len()..].starts_with(':') {
+                        found = Some((idx, indent));
+                        break;
+                    }
+                }
+                PathSeg::Index(i) => {
+                    if content.starts_with('-') {
+                        if seen == *i {
+                            found = Some((idx, indent));
+                            break;
+                        }
+                        seen += 1;
+                    }
+                }
+            }
+        }
+        let (idx, indent) = found?;
+        pos = idx;
+        parent_indent = indent;
+        in_item = matches!(seg, PathSeg::Index(_));
+    }
+    Some(pos + 1)
+}
+
+fn key(k: &str) -> PathSeg {
+    PathSeg::Key(k.to_owned())
+}
+
+fn relative_path_error(path: &str) -> Option<String> {
+    let p = std::path::Path::new(path);
+    if p.is_absolute() {
+        Some(format!("Path Must Be Relative: {}", path))
+    } else if p.components().any(|c| c == std::path::Component::ParentDir) {
+        Some(format!("Path Cannot Leave Its Directory: {}", path))
+    } else {
+        None
+    }
+}
+
+/// Checks a manifest.yaml for everything that would otherwise fail one at a time deep in
+/// deserialization, or not until install: that it deserializes, ports are in range, launch
+/// interface names are unique, paths are relative, and version ranges parse.
+pub fn validate(text: &str) -> Vec<ManifestViolation> {
+    let value: serde_yaml::Value = match serde_yaml::from_str(text) {
+        Ok(a) => a,
+        Err(e) => {
+            return vec![ManifestViolation {
+                path: Vec::new(),
+                line: e.location().map(|l| l.line()),
+                message: format!("{}", e),
+            }]
+        }
+    };
+    let mut res = Vec::new();
+    if let Err(e) = serde_yaml::from_str::<Manifest>(text) {
+        res.push(ManifestViolation {
+            path: Vec::new(),
+            line: e.location().map(|l| l.line()),
+            message: format!("{}", e),
+        });
+    }
+    let mut push = |path: Vec<PathSeg>, message: String| {
+        let line = locate(text, &path);
+        res.push(ManifestViolation {
+            path,
+            line,
+            message,
+        })
+    };
+    for field in &["os-version-required", "os-version-recommended"] {
+        if let Some(range) = value.get(*field).and_then(|r| r.as_str()) {
+            if let Err(e) = range.parse::<emver::VersionRange>() {
+                push(
+                    vec![key(field)],
+                    format!("Invalid Version Range {:?}: {}", range, e),
+                );
+            }
+        }
+    }
+    if let Some(ports) = value.get("ports").and_then(|p| p.as_sequence()) {
+        for (i, port) in ports.iter().enumerate() {
+            for field in &["internal", "tor"] {
+                if let Some(n) = port.get(*field).and_then(|n| n.as_i64()) {
+                    if !(1..=65535).contains(&n) {
+                        push(
+                            vec![key("ports"), PathSeg::Index(i), key(field)],
+                            format!("Port Out Of Range: {}", n),
+                        );
+                    }
+                }
+            }
+        }
+    }
+    if let Some(launch) = value.get("launch").and_then(|l| l.as_sequence()) {
+        let mut names = LinearSet::new();
+        for (i, interface) in launch.iter().enumerate() {
+            if let Some(name) = interface.get("name").and_then(|n| n.as_str()) {
+                if !names.insert(name) {
+                    push(
+                        vec![key("launch"), PathSeg::Index(i), key("name")],
+                        format!("Duplicate Launch Interface Name: {}", name),
+                    );
+                }
+            }
+        }
+    }
+    if let Some(digest) = value
+        .get("image")
+        .filter(|i| i.get("type").and_then(|t| t.as_str()) == Some("registry"))
+        .and_then(|i| i.get("digest"))
+        .and_then(|d| d.as_str())
+    {
+        if !is_valid_digest(digest) {
+            push(
+                vec![key("image"), key("digest")],
+                format!("Invalid Image Digest: {}", digest),
+            );
+        }
+    }
+    for field in &["public", "shared"] {
+        if let Some(path) = value.get(*field).and_then(|p| p.as_str()) {
+            if let Some(e) = relative_path_error(path) {
+                push(vec![key(field)], e);
+            }
+        }
+    }
+    if let Some(assets) = value.get("assets").and_then(|a| a.as_sequence()) {
+        for (i, asset) in assets.iter().enumerate() {
+            for field in &["src", "dst"] {
+                if let Some(path) = asset.get(*field).and_then(|p| p.as_str()) {
+                    if let Some(e) = relative_path_error(path) {
+                        push(vec![key("assets"), PathSeg::Index(i), key(field)], e);
+                    }
+                }
+            }
+        }
+    }
+    if let Some(deps) = value.get("dependencies").and_then(|d| d.as_mapping()) {
+        for (id, dep) in deps {
+            let id = match id.as_str() {
+                Some(a) => a,
+                None => continue,
+            };
+            if let Some(range) = dep.get("version").and_then(|r| r.as_str()) {
+                if let Err(e) = range.parse::<emver::VersionRange>() {
+                    push(
+                        vec![key("dependencies"), key(id), key("version")],
+                        format!("Invalid Version Range {:?}: {}", range, e),
+                    );
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        let text = "id: foo\nports:\n  - internal: 80\n    tor: 80\n  - internal: 8080\n    tor: 0\nlaunch:\n- name: web\n  internal: 80\n- name: web\n";
+        let path = vec![key("ports"), PathSeg::Index(1), key("tor")];
+        assert_eq!(locate(text, &path), Some(6));
+        let path = vec![key("launch"), PathSeg::Index(1), key("name")];
+        assert_eq!(locate(text, &path), Some(10));
+        let path = vec![key("ports"), PathSeg::Index(0), key("lan")];
+        assert_eq!(locate(text, &path), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        let text = "id: foo\nports:\n  - internal: 80\n    tor: 0\nlaunch:\n- name: web\n  internal: 80\n- name: web\n  internal: 80\nassets:\n- src: /etc/passwd\n  dst: ../up\n  overwrite: true\ndependencies:\n  bitcoind:\n    version: \"not a range\"\n";
+        let violations: Vec<String> = validate(text)
+            .iter()
+            .skip(1) // missing fields
+            .map(|v| format!("{}", v))
+            .collect();
+        assert_eq!(
+            violations[..4],
+            [
+                "manifest.yaml:4: ports.0.tor: Port Out Of Range: 0",
+                "manifest.yaml:8: launch.1.name: Duplicate Launch Interface Name: web",
+                "manifest.yaml:11: assets.0.src: Path Must Be Relative: /etc/passwd",
+                "manifest.yaml:12: assets.0.dst: Path Cannot Leave Its Directory: ../up",
+            ]
+        );
+        assert!(violations[4].starts_with(
+            "manifest.yaml:16: dependencies.bitcoind.version: Invalid Version Range \"not a range\": "
+        ));
+    }
+
+    #[test]
+    fn test_provenance() {
+        let manifest: ManifestV0 = serde_yaml::from_str("id: foo\nversion: 0.1.0\ntitle: Foo\ndescription:\n  short: Foo\n  long: Foo\nrelease-notes: First\nports: []\nimage:\n  type: tar\nmount: /root\nlicense: MIT\nupstream-repo: https://github.com/foo/foo\nunknown: 1\n").unwrap();
+        assert_eq!(manifest.provenance.license.as_deref(), Some("MIT"));
+        assert_eq!(
+            manifest.provenance.upstream_repo.as_deref(),
+            Some("https://github.com/foo/foo")
+        );
+        assert_eq!(manifest.provenance.support_site, None);
+        assert_eq!(manifest.extra.keys().collect::<Vec<_>>(), vec!["unknown"]);
+    }
+
+    #[test]
+    fn test_image_tar_for() {
+        let image: ImageConfig =
+            serde_yaml::from_str("type: multi_arch\narches: [aarch64, x86_64]\n").unwrap();
+        assert_eq!(
+            image.tar_names(),
+            vec!["image.aarch64.tar", "image.x86_64.tar"]
+        );
+        assert_eq!(image.tar_for("x86_64").as_deref(), Some("image.x86_64.tar"));
+        assert_eq!(image.tar_for("riscv64"), None);
+        assert!(is_valid_digest(&format!("sha256:{}", "0f".repeat(32))));
+        assert!(!is_valid_digest(&format!("sha256:{}", "0F".repeat(32))));
+        assert!(!is_valid_digest("sha256:0f"));
+        assert_eq!(
+            ImageConfig::Tar.tar_for("riscv64").as_deref(),
+            Some("image.tar")
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+use failure::ResultExt as _;
+use linear_map::{set::LinearSet, LinearMap};
+
+use crate::dependencies::TaggedDependencyError;
+use crate::manifest::ManifestLatest;
+use crate::Error;
+use crate::ResultExt as _;
+
+/// The networks a package can run against, i.e. mainnet, testnet, and regtest.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Modes {
+    pub default: String,
+    pub available: LinearSet<String>,
+    /// Paths under the mount that hold data for a single mode. When switching modes they are
+    /// stashed away under `start9/modes/<old mode>`, and the ones stashed for the new mode (if
+    /// any) are put back.
+    #[serde(default)]
+    pub isolate: Vec<PathBuf>,
+}
+
+fn volume(id: &str) -> PathBuf {
+    Path::new(crate::VOLUMES).join(id)
+}
+
+// readable by the app, so it knows which network to run against
+fn mode_file(id: &str) -> PathBuf {
+    volume(id).join("start9").join("mode")
+}
+
+fn stash_dir(id: &str, mode: &str) -> PathBuf {
+    volume(id).join("start9").join("modes").join(mode)
+}
+
+/// The mode an app is in, as recorded in its volume. Falls back to the default of the manifest
+/// if none is recorded, or the recorded one is no longer available. `None` if the app does not
+/// declare modes.
+pub async fn current(manifest: &ManifestLatest) -> Result<Option<String>, Error> {
+    let modes = match &manifest.modes {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+    let path = mode_file(&manifest.id);
+    let mode = match tokio::fs::read_to_string(&path).await {
+        Ok(a) => a.trim().to_owned(),
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => modes.default.clone(),
+        Err(e) => {
+            return Err(e)
+                .with_context(|e| format!("{}: {}", path.display(), e))
+                .with_code(crate::error::FILESYSTEM_ERROR)
+        }
+    };
+    if modes.available.contains(&mode) {
+        Ok(Some(mode))
+    } else {
+        log::warn!(
+            "{} is in unknown mode {:?}, using {}.",
+            manifest.id,
+            mode,
+            modes.default
+        );
+        Ok(Some(modes.default.clone()))
+    }
+}
+
+async fn rename(from: &Path, to: &Path) -> Result<(), Error> {
+    if let Some(parent) = to.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::rename(from, to)
+        .await
+        .with_context(|e| format!("mv {} {}: {}", from.display(), to.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)
+}
+
+/// Switches an app to another mode. The app and its dependents are stopped first, and the app is
+/// started again afterwards if it was running. Returns the dependents that were stopped.
+pub async fn set(id: &str, mode: &str) -> Result<LinearMap<String, TaggedDependencyError>, Error> {
+    let manifest = crate::apps::manifest(id).await?;
+    let modes = manifest
+        .modes
+        .as_ref()
+        .ok_or_else(|| failure::format_err!("{} does not support modes", id))
+        .with_code(crate::error::GENERAL_ERROR)?;
+    crate::ensure_code!(
+        modes.available.contains(mode),
+        crate::error::NOT_FOUND,
+        "{} has no mode {:?}, expected one of: {}",
+        id,
+        mode,
+        modes
+            .available
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    for path in &modes.isolate {
+        crate::ensure_code!(
+            path.components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)))
+                && !path.starts_with("start9"),
+            crate::error::GENERAL_ERROR,
+            "Invalid Isolated Path: {}",
+            path.display()
+        );
+    }
+    let old = current(&manifest).await?.unwrap_or_default();
+    if old == mode {
+        return Ok(LinearMap::new());
+    }
+    let running =
+        crate::apps::status(id, false).await?.status != crate::apps::DockerStatus::Stopped;
+    let res = if running {
+        crate::control::stop_app(id, true, false).await?
+    } else {
+        LinearMap::new()
+    };
+    log::info!("Switching {} from {} to {}.", id, old, mode);
+    for path in &modes.isolate {
+        // the volume is mounted at the mount point, so paths under it are paths in the volume
+        let live = volume(id).join(path);
+        if live.exists() {
+            rename(&live, &stash_dir(id, &old).join(path)).await?;
+        }
+        let stashed = stash_dir(id, mode).join(path);
+        if stashed.exists() {
+            rename(&stashed, &live).await?;
+        }
+    }
+    tokio::fs::write(mode_file(id), mode)
+        .await
+        .with_context(|e| format!("{}: {}", mode_file(id).display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    crate::apps::set_mode(id, Some(mode.to_owned())).await?;
+    if running {
+        crate::control::start_app(id, true).await?;
+    }
+    Ok(res)
+}
@@ -0,0 +1,282 @@
+use std::time::Duration;
+
+use linear_map::LinearMap;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::apps::DockerStatus;
+use crate::util::PersistencePath;
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const MQTT_YAML: &'static str = "mqtt.yaml";
+pub const MQTT_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_prefix() -> String {
+    "embassy".to_owned()
+}
+
+/// The broker events are published to. Nothing is published unless this is set.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MqttConfig {
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Prepended to every topic, i.e. `embassy/apps/bitcoind/status`.
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    /// Relative to the configured prefix.
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+pub async fn get() -> Result<Option<MqttConfig>, Error> {
+    let path = PersistencePath::from_ref(MQTT_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+pub async fn set(config: &MqttConfig) -> Result<(), Error> {
+    crate::ensure_code!(
+        !config.prefix.is_empty() && !config.prefix.contains(|c| c == '#' || c == '+'),
+        crate::error::GENERAL_ERROR,
+        "Invalid Topic Prefix: {:?}",
+        config.prefix
+    );
+    crate::ensure_code!(
+        config.password.is_none() || config.username.is_some(),
+        crate::error::GENERAL_ERROR,
+        "A password requires a username"
+    );
+    let mut f = PersistencePath::from_ref(MQTT_YAML).write(None).await?;
+    crate::util::to_yaml_async_writer(&mut *f, config).await?;
+    f.commit().await
+}
+
+pub async fn clear() -> Result<(), Error> {
+    let path = PersistencePath::from_ref(MQTT_YAML).path();
+    if path.exists() {
+        tokio::fs::remove_file(&path).await?;
+    }
+    Ok(())
+}
+
+fn encode_len(mut len: usize, buf: &mut Vec<u8>) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn encode_bytes(bytes: &[u8], buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn packet(header: u8, body: Vec<u8>) -> Vec<u8> {
+    let mut res = vec![header];
+    encode_len(body.len(), &mut res);
+    res.extend(body);
+    res
+}
+
+// MQTT 3.1.1, clean session
+fn connect_packet(config: &MqttConfig) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_bytes(b"MQTT", &mut body);
+    body.push(4);
+    let mut flags = 0x02;
+    if config.username.is_some() {
+        flags |= 0x80;
+    }
+    if config.password.is_some() {
+        flags |= 0x40;
+    }
+    body.push(flags);
+    body.extend_from_slice(&60u16.to_be_bytes());
+    encode_bytes(
+        format!("appmgr-{}", std::process::id()).as_bytes(),
+        &mut body,
+    );
+    if let Some(username) = &config.username {
+        encode_bytes(username.as_bytes(), &mut body);
+    }
+    if let Some(password) = &config.password {
+        encode_bytes(password.as_bytes(), &mut body);
+    }
+    packet(0x10, body)
+}
+
+// QoS 0, so there is no packet identifier and nothing to acknowledge
+fn publish_packet(prefix: &str, message: &Message) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_bytes(
+        format!("{}/{}", prefix, message.topic).as_bytes(),
+        &mut body,
+    );
+    body.extend_from_slice(&message.payload);
+    packet(if message.retain { 0x31 } else { 0x30 }, body)
+}
+
+async fn send_inner(config: &MqttConfig, messages: &[Message]) -> Result<(), Error> {
+    let mut stream = tokio::net::TcpStream::connect((config.host.as_str(), config.port)).await?;
+    stream.write_all(&connect_packet(config)).await?;
+    let mut connack = [0; 4];
+    stream.read_exact(&mut connack).await?;
+    crate::ensure_code!(
+        connack[0] == 0x20 && connack[3] == 0,
+        crate::error::GENERAL_ERROR,
+        "MQTT Broker Refused Connection: {}",
+        connack[3]
+    );
+    for message in messages {
+        stream
+            .write_all(&publish_packet(&config.prefix, message))
+            .await?;
+    }
+    stream.write_all(&[0xE0, 0x00]).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Publishes `messages` to the configured broker, if there is one.
+pub async fn send(messages: &[Message]) -> Result<(), Error> {
+    let config = match get().await? {
+        Some(a) => a,
+        None => return Ok(()),
+    };
+    tokio::time::timeout(MQTT_TIMEOUT, send_inner(&config, messages))
+        .await
+        .map_err(|_| failure::format_err!("Timed Out Connecting to MQTT Broker"))
+        .no_code()?
+}
+
+/// Publishes the new status of an app. Errors are logged rather than returned, so an unreachable
+/// broker never gets in the way of starting or stopping apps.
+pub async fn app_status(id: &str, status: DockerStatus) {
+    let message = Message {
+        topic: format!("apps/{}/status", id),
+        payload: serde_json::to_vec(&status).unwrap_or_default(),
+        retain: true,
+    };
+    if let Err(e) = send(&[message]).await {
+        log::warn!("Could not publish status of {}: {}", id, e.failure);
+    }
+}
+
+fn system_metrics() -> Result<LinearMap<&'static str, f64>, Error> {
+    let mut res = LinearMap::new();
+    let loadavg = std::fs::read_to_string("/proc/loadavg")?;
+    for (key, val) in ["load-1m", "load-5m", "load-15m"]
+        .iter()
+        .zip(loadavg.split_whitespace())
+    {
+        res.insert(*key, val.parse().no_code()?);
+    }
+    let meminfo = std::fs::read_to_string("/proc/meminfo")?;
+    for line in meminfo.lines() {
+        let mut split = line.split_whitespace();
+        let key = match split.next() {
+            Some("MemTotal:") => "mem-total-kb",
+            Some("MemAvailable:") => "mem-available-kb",
+            _ => continue,
+        };
+        if let Some(val) = split.next() {
+            res.insert(key, val.parse().no_code()?);
+        }
+    }
+    Ok(res)
+}
+
+/// Publishes the status and properties of every app, along with the load and memory usage of
+/// the device. Meant to be run periodically. Returns the number of messages published.
+pub async fn publish_all() -> Result<usize, Error> {
+    crate::ensure_code!(
+        get().await?.is_some(),
+        crate::error::NOT_FOUND,
+        "MQTT Broker Not Configured"
+    );
+    let mut messages = Vec::new();
+    for (id, _) in crate::apps::list_info().await? {
+        let status = crate::apps::status(&id, false).await?.status;
+        messages.push(Message {
+            topic: format!("apps/{}/status", id),
+            payload: serde_json::to_vec(&status).with_code(crate::error::SERDE_ERROR)?,
+            retain: true,
+        });
+        match crate::logs::stats(&id).await {
+            Ok(mut properties) => messages.push(Message {
+                topic: format!("apps/{}/properties", id),
+                payload: {
+                    properties.redact();
+                    serde_json::to_vec(&properties).with_code(crate::error::SERDE_ERROR)?
+                },
+                retain: true,
+            }),
+            Err(e) => log::warn!("Could not read properties of {}: {}", id, e.failure),
+        }
+    }
+    messages.push(Message {
+        topic: "system/metrics".to_owned(),
+        payload: serde_json::to_vec(&system_metrics()?).with_code(crate::error::SERDE_ERROR)?,
+        retain: false,
+    });
+    send(&messages).await?;
+    Ok(messages.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_packets() {
+        let mut buf = Vec::new();
+        encode_len(127, &mut buf);
+        encode_len(128, &mut buf);
+        encode_len(16383, &mut buf);
+        assert_eq!(buf, vec![0x7F, 0x80, 0x01, 0xFF, 0x7F]);
+        let message = Message {
+            topic: "apps/lnd/status".to_owned(),
+            payload: b"\"RUNNING\"".to_vec(),
+            retain: true,
+        };
+        let mut expected = vec![0x31, 34, 0, 23];
+        expected.extend_from_slice(b"embassy/apps/lnd/status\"RUNNING\"");
+        assert_eq!(publish_packet("embassy", &message), expected);
+        let config = MqttConfig {
+            host: "localhost".to_owned(),
+            port: default_port(),
+            prefix: default_prefix(),
+            username: Some("ha".to_owned()),
+            password: None,
+        };
+        let connect = connect_packet(&config);
+        assert_eq!(&connect[2..10], b"\x00\x04MQTT\x04\x82");
+        assert_eq!(&connect[connect.len() - 4..], b"\x00\x02ha");
+    }
+}
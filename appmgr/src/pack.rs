@@ -7,8 +7,13 @@ use linear_map::LinearMap;
 use rand::SeedableRng;
 use tokio_tar as tar;
 
-use crate::config::{ConfigRuleEntry, ConfigSpec};
-use crate::manifest::{ImageConfig, Manifest};
+use crate::config::{ConfigRuleEntry, ConfigRuleEntryWithSuggestions, ConfigSpec};
+use crate::health::Probe;
+use crate::manifest::{ImageConfig, Manifest, ManifestViolations};
+use crate::s9pk::reader::next_entry;
+use crate::s9pk::{
+    S9pkBuilder, CONFIG_RULES_CBOR, CONFIG_SPEC_CBOR, INSTRUCTIONS_MD, MANIFEST_CBOR,
+};
 use crate::util::{from_cbor_async_reader, from_json_async_reader, from_yaml_async_reader};
 use crate::version::VersionT;
 
@@ -22,7 +27,15 @@ pub enum Error {
     InvalidOutputPath(String),
 }
 
-pub async fn pack(path: &str, output: &str) -> Result<(), failure::Error> {
+/// Packs the app at `path` into `output`. When `deterministic`, files are packed without their
+/// timestamps and owners and in a fixed order, so packing the same sources (and images) again
+/// produces the same bytes.
+pub async fn pack(
+    path: &str,
+    output: &str,
+    key: Option<&str>,
+    deterministic: bool,
+) -> Result<(), failure::Error> {
     let path = Path::new(path.trim_end_matches("/"));
     let output = Path::new(output);
     log::info!(
@@ -33,24 +46,19 @@ pub async fn pack(path: &str, output: &str) -> Result<(), failure::Error> {
         output.display(),
     );
     let out_file = tokio::fs::File::create(output).await?;
-    let mut out = tar::Builder::new(out_file);
+    let mut out = S9pkBuilder::new(out_file, deterministic);
     log::info!("Reading {}/manifest.yaml.", path.display());
-    let manifest: Manifest = crate::util::from_yaml_async_reader(
-        tokio::fs::File::open(path.join("manifest.yaml"))
-            .await
-            .with_context(|e| format!("{}: manifest.yaml", e))?,
-    )
-    .await?;
+    let manifest_yaml = tokio::fs::read_to_string(path.join("manifest.yaml"))
+        .await
+        .with_context(|e| format!("{}: manifest.yaml", e))?;
+    log::info!("Validating manifest.");
+    let violations = crate::manifest::validate(&manifest_yaml);
+    if !violations.is_empty() {
+        return Err(ManifestViolations(violations).into());
+    }
+    let manifest: Manifest = serde_yaml::from_str(&manifest_yaml)?;
     log::info!("Writing manifest to archive.");
-    let bin_manifest = serde_cbor::to_vec(&manifest)?;
-    let mut manifest_header = tar::Header::new_gnu();
-    manifest_header.set_size(bin_manifest.len() as u64);
-    out.append_data(
-        &mut manifest_header,
-        "manifest.cbor",
-        std::io::Cursor::new(bin_manifest),
-    )
-    .await?;
+    out.manifest(&manifest).await?;
     let manifest = manifest.into_latest();
     ensure!(
         crate::version::Current::new()
@@ -67,70 +75,60 @@ pub async fn pack(path: &str, output: &str) -> Result<(), failure::Error> {
     )
     .await?;
     log::info!("Writing config spec to archive.");
-    let bin_config_spec = serde_cbor::to_vec(&config_spec)?;
-    let mut config_spec_header = tar::Header::new_gnu();
-    config_spec_header.set_size(bin_config_spec.len() as u64);
-    out.append_data(
-        &mut config_spec_header,
-        "config_spec.cbor",
-        std::io::Cursor::new(bin_config_spec),
-    )
-    .await?;
+    out.config_spec(&config_spec).await?;
     log::info!("Reading {}/config_rules.yaml.", path.display());
-    let config_rules: Vec<ConfigRuleEntry> = from_yaml_async_reader(
+    let config_rules: Vec<ConfigRuleEntryWithSuggestions> = from_yaml_async_reader(
         tokio::fs::File::open(path.join("config_rules.yaml"))
             .await
             .with_context(|e| format!("{}: config_rules.yaml", e))?,
     )
     .await?;
+    log::info!("Validating config rules against config spec.");
+    for rule in &config_rules {
+        crate::config::rules::validate_vars(&rule.entry.rule.src, &config_spec)?;
+    }
     log::info!("Writing config rules to archive.");
-    let bin_config_rules = serde_cbor::to_vec(&config_rules)?;
-    let mut config_rules_header = tar::Header::new_gnu();
-    config_rules_header.set_size(bin_config_rules.len() as u64);
-    out.append_data(
-        &mut config_rules_header,
-        "config_rules.cbor",
-        std::io::Cursor::new(bin_config_rules),
-    )
-    .await?;
+    out.config_rules(&config_rules).await?;
     if manifest.has_instructions {
         log::info!("Packing instructions.md");
-        out.append_path_with_name(path.join("instructions.md"), "instructions.md")
-            .await?;
+        out.instructions(path.join("instructions.md")).await?;
     }
     log::info!("Copying over assets.");
     for asset in &manifest.assets {
         let src_path = Path::new("assets").join(&asset.src);
         log::info!("Reading {}/{}.", path.display(), src_path.display());
-        let file_path = path.join(&src_path);
-        let src = tokio::fs::File::open(&file_path)
-            .await
-            .with_context(|e| format!("{}: {}", e, src_path.display()))?;
         log::info!("Writing {} to archive.", src_path.display());
-        if src.metadata().await?.is_dir() {
-            out.append_dir_all(&asset.src, &file_path).await?;
-            let mut h = tar::Header::new_gnu();
-            h.set_size(0);
-            h.set_path(format!("APPMGR_DIR_END:{}", asset.src.display()))?;
-            h.set_cksum();
-            out.append(&h, tokio::io::empty()).await?;
-        } else {
-            out.append_path_with_name(&file_path, &asset.src).await?;
-        }
+        out.asset(asset, path.join(&src_path)).await?;
     }
-    match manifest.image {
-        ImageConfig::Tar => {
-            log::info!("Reading {}/image.tar.", path.display());
-            let image = tokio::fs::File::open(path.join("image.tar"))
-                .await
-                .with_context(|e| format!("{}: image.tar", e))?;
-            log::info!("Writing image.tar to archive.");
-            let mut header = tar::Header::new_gnu();
-            header.set_size(image.metadata().await?.len());
-            out.append_data(&mut header, "image.tar", image).await?;
-        }
+    if let ImageConfig::MultiArch { arches } = &manifest.image {
+        ensure!(!arches.is_empty(), "Multi-Arch Image Has No Architectures");
+    }
+    for tar_name in manifest.image.tar_names() {
+        log::info!("Reading {}/{}.", path.display(), tar_name);
+        let image = tokio::fs::File::open(path.join(&tar_name))
+            .await
+            .with_context(|e| format!("{}: {}", e, tar_name))?;
+        log::info!("Writing {} to archive.", tar_name);
+        out.image(&tar_name, image).await?;
+    }
+    out.finish().await?;
+    log::info!("Writing checksums to archive.");
+    crate::checksums::append(output).await?;
+    if let Some(key) = key {
+        log::info!("Signing {} with {}.", output.display(), key);
+        let public_key = crate::signing::sign(output, key).await?;
+        log::info!("Signed with public key {}.", public_key);
+    }
+    if deterministic {
+        let hash = crate::checksums::hash(tokio::fs::File::open(output).await?).await?;
+        log::info!(
+            "sha256 of {}: {}",
+            output.display(),
+            hash.iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        );
     }
-    out.into_inner().await?;
 
     Ok(())
 }
@@ -176,7 +174,12 @@ pub async fn verify(path: &str) -> Result<(), failure::Error> {
             .and_then(|a| a.to_str())
             .ok_or_else(|| Error::InvalidFileName(format!("{}", path.display())))?,
     );
-    {}
+    log::info!("Checking signature.");
+    match crate::signing::signer(path).await? {
+        Some(key) => log::info!("Signed with {}.", crate::signing::encode_key(&key)),
+        None => log::warn!("Package is not signed."),
+    }
+    crate::checksums::check(path).await?;
     log::info!("Opening file.");
     let r = tokio::fs::File::open(&path)
         .await
@@ -185,15 +188,7 @@ pub async fn verify(path: &str) -> Result<(), failure::Error> {
     let mut pkg = tar::Archive::new(r);
     let mut entries = pkg.entries()?;
     log::info!("Opening manifest from archive.");
-    let manifest = entries
-        .next()
-        .await
-        .ok_or_else(|| format_err!("missing manifest"))??;
-    ensure!(
-        manifest.path()?.to_str() == Some("manifest.cbor"),
-        "Package File Invalid or Corrupted: expected manifest.cbor, got {}",
-        manifest.path()?.display()
-    );
+    let manifest = next_entry(&mut entries, MANIFEST_CBOR).await?;
     log::trace!("Deserializing manifest.");
     let manifest: Manifest = from_cbor_async_reader(manifest).await?;
     let manifest = manifest.into_latest();
@@ -205,6 +200,55 @@ pub async fn verify(path: &str) -> Result<(), failure::Error> {
         manifest.os_version_required
     );
     ensure!(manifest.id == name, "Package Name Does Not Match Expected",);
+    match &manifest.image {
+        ImageConfig::MultiArch { arches } => {
+            ensure!(!arches.is_empty(), "Multi-Arch Image Has No Architectures")
+        }
+        ImageConfig::Registry { digest, .. } => ensure!(
+            crate::manifest::is_valid_digest(digest),
+            "Invalid Image Digest: {}",
+            digest
+        ),
+        ImageConfig::Static { binary, unit } => {
+            ensure!(
+                crate::backend::is_valid_binary(binary),
+                "Invalid Binary Name: {}",
+                binary
+            );
+            if let Some(user) = &unit.user {
+                ensure!(
+                    crate::backend::is_valid_user(user),
+                    "Invalid User: {}",
+                    user
+                );
+            }
+            // actions, hooks, migration scripts and command health checks are run in the image of
+            // the app
+            ensure!(
+                manifest.actions.is_empty(),
+                "Static Binaries Cannot Have Actions"
+            );
+            ensure!(
+                manifest.hooks.is_empty(),
+                "Static Binaries Cannot Have Hooks"
+            );
+            ensure!(
+                manifest
+                    .config_migrations
+                    .iter()
+                    .all(|m| m.script.is_none()),
+                "Static Binaries Cannot Have Config Migration Scripts"
+            );
+            ensure!(
+                manifest
+                    .health_checks
+                    .values()
+                    .all(|c| !matches!(c.probe, Probe::Command { .. })),
+                "Static Binaries Cannot Have Command Health Checks"
+            );
+        }
+        ImageConfig::Tar => (),
+    }
     if let (Some(public), Some(shared)) = (&manifest.public, &manifest.shared) {
         ensure!(
             !public.starts_with(shared) && !shared.starts_with(public),
@@ -224,35 +268,86 @@ pub async fn verify(path: &str) -> Result<(), failure::Error> {
             action.id
         );
     }
+    for (id, check) in &manifest.health_checks {
+        ensure!(
+            check.interval > 0 && check.timeout > 0 && check.retries > 0,
+            "Interval, Timeout And Retries Of Health Check {} Must Be Positive",
+            id
+        );
+        match &check.probe {
+            Probe::Command { command } => {
+                ensure!(!command.is_empty(), "Command Cannot Be Empty: {}", id)
+            }
+            Probe::Http { path, .. } => ensure!(
+                path.starts_with('/'),
+                "Path Of Health Check {} Must Start With /",
+                id
+            ),
+            Probe::Tcp { .. } => (),
+        }
+    }
+    for (id, dep) in &manifest.dependencies.0 {
+        if let Some(min_sync) = dep.min_sync {
+            ensure!(
+                (0.0..=100.0).contains(&min_sync),
+                "Sync Threshold Of Dependency {} Must Be Between 0 And 100",
+                id
+            );
+        }
+    }
+    for interface in &manifest.launch {
+        let port = manifest
+            .ports
+            .iter()
+            .find(|p| p.internal == interface.internal)
+            .ok_or_else(|| {
+                format_err!(
+                    "Launch Interface {} Uses Undeclared Port: {}",
+                    interface.name,
+                    interface.internal
+                )
+            })?;
+        ensure!(
+            interface.requires != Some(crate::manifest::Network::Lan) || port.lan.is_some(),
+            "Launch Interface {} Requires LAN But Port {} Is Not Exposed On LAN",
+            interface.name,
+            interface.internal
+        );
+        ensure!(
+            interface.path.starts_with("/"),
+            "Launch Path Must Be Absolute: {}",
+            interface.name
+        );
+    }
     log::info!("Opening config spec from archive.");
-    let config_spec = entries
-        .next()
-        .await
-        .ok_or_else(|| format_err!("missing config spec"))??;
-    ensure!(
-        config_spec.path()?.to_str() == Some("config_spec.cbor"),
-        "Package File Invalid or Corrupted: expected config_rules.cbor, got {}",
-        config_spec.path()?.display()
-    );
+    let config_spec = next_entry(&mut entries, CONFIG_SPEC_CBOR).await?;
     log::trace!("Deserializing config spec.");
     let config_spec: ConfigSpec = from_cbor_async_reader(config_spec).await?;
     log::trace!("Validating config spec.");
     config_spec.validate(&manifest)?;
+    for (var, path) in &manifest.env_map {
+        ensure!(
+            crate::config::env::is_valid_name(var),
+            "Invalid Environment Variable Name: {}",
+            var
+        );
+        ensure!(
+            config_spec.get_path(path).is_some(),
+            "Environment Variable {} Maps To Unknown Config Field: {}",
+            var,
+            path
+        );
+    }
     let config = config_spec.gen(&mut rand::rngs::StdRng::from_entropy(), &None)?;
     config_spec.matches(&config)?;
     log::info!("Opening config rules from archive.");
-    let config_rules = entries
-        .next()
-        .await
-        .ok_or_else(|| format_err!("missing config rules"))??;
-    ensure!(
-        config_rules.path()?.to_str() == Some("config_rules.cbor"),
-        "Package File Invalid or Corrupted: expected config_rules.cbor, got {}",
-        config_rules.path()?.display()
-    );
+    let config_rules = next_entry(&mut entries, CONFIG_RULES_CBOR).await?;
     log::trace!("Deserializing config rules.");
     let config_rules: Vec<ConfigRuleEntry> = from_cbor_async_reader(config_rules).await?;
     log::trace!("Validating config rules against config spec.");
+    for rule in &config_rules {
+        crate::config::rules::validate_vars(&rule.rule.src, &config_spec)?;
+    }
     let mut cfgs = LinearMap::new();
     cfgs.insert(name, Cow::Borrowed(&config));
     for rule in &config_rules {
@@ -260,15 +355,7 @@ pub async fn verify(path: &str) -> Result<(), failure::Error> {
             .with_context(|e| format!("Default Config does not satisfy: {}", e))?;
     }
     if manifest.has_instructions {
-        let instructions = entries
-            .next()
-            .await
-            .ok_or_else(|| format_err!("missing instructions"))??;
-        ensure!(
-            instructions.path()?.to_str() == Some("instructions.md"),
-            "Package File Invalid or Corrupted: expected instructions.md, got {}",
-            instructions.path()?.display()
-        );
+        next_entry(&mut entries, INSTRUCTIONS_MD).await?;
     }
     for asset_info in manifest.assets {
         validate_path(&asset_info.src)?;
@@ -319,70 +406,68 @@ pub async fn verify(path: &str) -> Result<(), failure::Error> {
             bail!("Asset Not Regular File: {}", asset_info.src.display());
         }
     }
-    match &manifest.image {
-        ImageConfig::Tar => {
-            #[derive(Clone, Debug, serde::Deserialize)]
-            #[serde(rename_all = "PascalCase")]
-            struct DockerManifest {
-                config: PathBuf,
-                repo_tags: Vec<String>,
-                layers: Vec<PathBuf>,
-            }
-            let image_name = format!("start9/{}", manifest.id);
-            log::debug!("Opening image.tar from archive.");
-            let image = entries
-                .next()
-                .await
-                .ok_or_else(|| format_err!("missing image.tar"))??;
-            let image_path = image.path()?;
-            if image_path != Path::new("image.tar") {
-                return Err(format_err!(
-                    "Package File Invalid or Corrupted: expected image.tar, got {}",
-                    image_path.display()
-                ));
-            }
-            log::info!("Verifying image.tar.");
-            let mut image_tar = tar::Archive::new(image);
-            let image_manifest = image_tar
-                .entries()?
-                .map(|e| {
-                    let e = e?;
-                    Ok((e.path()?.to_path_buf(), e))
-                })
-                .filter_map(|res: Result<(PathBuf, tar::Entry<_>), std::io::Error>| {
-                    futures::future::ready(match res {
-                        Ok((path, e)) => {
-                            if path == Path::new("manifest.json") {
-                                Some(Ok(e))
-                            } else {
-                                None
-                            }
-                        }
-                        Err(e) => Some(Err(e)),
-                    })
-                })
-                .next()
-                .await
-                .ok_or_else(|| format_err!("image.tar is missing manifest.json"))??;
-            let image_manifest: Vec<DockerManifest> =
-                from_json_async_reader(image_manifest).await?;
-            image_manifest
-                .into_iter()
-                .flat_map(|a| a.repo_tags)
-                .map(|t| {
-                    if t.starts_with("start9/") {
-                        if t.split(":").next().unwrap() != image_name {
-                            Err(format_err!("Contains prohibited image tag: {}", t))
+    #[derive(Clone, Debug, serde::Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct DockerManifest {
+        config: PathBuf,
+        repo_tags: Vec<String>,
+        layers: Vec<PathBuf>,
+    }
+    let image_name = format!("start9/{}", manifest.id);
+    for tar_name in manifest.image.tar_names() {
+        log::debug!("Opening {} from archive.", tar_name);
+        let image = entries
+            .next()
+            .await
+            .ok_or_else(|| format_err!("missing {}", tar_name))??;
+        let image_path = image.path()?;
+        if image_path != Path::new(&tar_name) {
+            return Err(format_err!(
+                "Package File Invalid or Corrupted: expected {}, got {}",
+                tar_name,
+                image_path.display()
+            ));
+        }
+        log::info!("Verifying {}.", tar_name);
+        let mut image_tar = tar::Archive::new(image);
+        let image_manifest = image_tar
+            .entries()?
+            .map(|e| {
+                let e = e?;
+                Ok((e.path()?.to_path_buf(), e))
+            })
+            .filter_map(|res: Result<(PathBuf, tar::Entry<_>), std::io::Error>| {
+                futures::future::ready(match res {
+                    Ok((path, e)) => {
+                        if path == Path::new("manifest.json") {
+                            Some(Ok(e))
                         } else {
-                            Ok(())
+                            None
                         }
+                    }
+                    Err(e) => Some(Err(e)),
+                })
+            })
+            .next()
+            .await
+            .ok_or_else(|| format_err!("{} is missing manifest.json", tar_name))??;
+        let image_manifest: Vec<DockerManifest> = from_json_async_reader(image_manifest).await?;
+        image_manifest
+            .into_iter()
+            .flat_map(|a| a.repo_tags)
+            .map(|t| {
+                if t.starts_with("start9/") {
+                    if t.split(":").next().unwrap() != image_name {
+                        Err(format_err!("Contains prohibited image tag: {}", t))
                     } else {
                         Ok(())
                     }
-                })
-                .collect::<Result<_, _>>()?;
-        }
-    };
+                } else {
+                    Ok(())
+                }
+            })
+            .collect::<Result<_, _>>()?;
+    }
 
     Ok(())
 }
@@ -0,0 +1,227 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Commands that finish sooner than this draw nothing.
+pub const DRAW_DELAY: Duration = Duration::from_millis(500);
+pub const BAR_WIDTH: usize = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Unit {
+    Bytes,
+    Steps,
+}
+
+/// Where a long running command is at, as drawn on the terminal.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProgressEvent {
+    pub phase: String,
+    pub done: u64,
+    /// `None` if unknown, i.e. a download without a content length.
+    pub total: Option<u64>,
+    pub unit: Unit,
+}
+impl ProgressEvent {
+    fn amount(&self, n: u64) -> String {
+        match self.unit {
+            Unit::Bytes => format!("{}KiB", n / 1024),
+            Unit::Steps => format!("{}", n),
+        }
+    }
+
+    /// The line drawn for the event, i.e. `Installing: Loading image [######    ] 60%`.
+    pub fn render(&self, label: &str) -> String {
+        let mut res = label.to_owned();
+        res.push(':');
+        if !self.phase.is_empty() {
+            res.push(' ');
+            res.push_str(&self.phase);
+        }
+        match self.total {
+            Some(total) if total > 0 => {
+                let done = self.done.min(total);
+                let filled = (done * BAR_WIDTH as u64 / total) as usize;
+                res.push_str(&format!(
+                    " [{}{}] ",
+                    "#".repeat(filled),
+                    " ".repeat(BAR_WIDTH - filled)
+                ));
+                match self.unit {
+                    Unit::Bytes => res.push_str(&format!("{}%", done * 100 / total)),
+                    Unit::Steps => res.push_str(&format!("{}/{}", done, total)),
+                }
+            }
+            _ if self.done > 0 => {
+                res.push(' ');
+                res.push_str(&self.amount(self.done));
+            }
+            _ => (),
+        }
+        res
+    }
+}
+
+struct Inner {
+    done: Arc<AtomicU64>,
+    total: Option<u64>,
+    unit: Unit,
+    phase: Mutex<String>,
+    finished: AtomicBool,
+}
+
+/// Progress shared between the task doing the work and the one drawing it.
+#[derive(Clone)]
+pub struct Progress(Arc<Inner>);
+impl Progress {
+    pub fn new(unit: Unit, total: Option<u64>) -> Self {
+        Progress(Arc::new(Inner {
+            done: Arc::new(AtomicU64::new(0)),
+            total,
+            unit,
+            phase: Mutex::new(String::new()),
+            finished: AtomicBool::new(false),
+        }))
+    }
+    /// The counter to add to, i.e. for a `CountingReader`.
+    pub fn counter(&self) -> Arc<AtomicU64> {
+        self.0.done.clone()
+    }
+    pub fn add(&self, n: u64) {
+        self.0.done.fetch_add(n, Ordering::SeqCst);
+    }
+    pub fn set_phase<S: Into<String>>(&self, phase: S) {
+        *self.0.phase.lock().unwrap() = phase.into();
+    }
+    pub fn event(&self) -> ProgressEvent {
+        ProgressEvent {
+            phase: self.0.phase.lock().unwrap().clone(),
+            done: self.0.done.load(Ordering::SeqCst),
+            total: self.0.total,
+            unit: self.0.unit,
+        }
+    }
+    fn finish(&self) {
+        self.0.finished.store(true, Ordering::SeqCst);
+    }
+    fn is_finished(&self) -> bool {
+        self.0.finished.load(Ordering::SeqCst)
+    }
+}
+
+lazy_static::lazy_static! {
+    // the progress of the command being run, so that code deep in it can report its phase
+    // without a handle being passed down
+    static ref CURRENT: Mutex<Option<Progress>> = Mutex::new(None);
+}
+
+/// Sets the phase of the running command, if it draws progress.
+pub fn phase<S: Into<String>>(phase: S) {
+    if let Some(progress) = &*CURRENT.lock().unwrap() {
+        progress.set_phase(phase);
+    }
+}
+
+/// Counts a step of the running command, if it draws progress.
+pub fn step() {
+    if let Some(progress) = &*CURRENT.lock().unwrap() {
+        progress.add(1);
+    }
+}
+
+/// Draws a progress bar on stderr until dropped or finished. Nothing is drawn when stderr is not a
+/// terminal, or when quiet, so output that is piped or parsed stays as it was.
+pub struct ProgressGuard {
+    pub progress: Progress,
+    handle: Option<tokio::task::JoinHandle<()>>,
+}
+impl ProgressGuard {
+    pub fn start(label: &'static str, unit: Unit, total: Option<u64>) -> Self {
+        let progress = Progress::new(unit, total);
+        *CURRENT.lock().unwrap() = Some(progress.clone());
+        let handle = if unsafe { libc::isatty(2) } == 1 {
+            Some(tokio::spawn(draw(label, progress.clone())))
+        } else {
+            None
+        };
+        ProgressGuard { progress, handle }
+    }
+    /// Draws the final state of the bar, if it was drawn at all.
+    pub async fn finish(mut self) {
+        self.progress.finish();
+        if let Some(handle) = self.handle.take() {
+            handle.await.unwrap_or_default();
+        }
+    }
+}
+impl Drop for ProgressGuard {
+    fn drop(&mut self) {
+        self.progress.finish();
+        let mut current = CURRENT.lock().unwrap();
+        if current
+            .as_ref()
+            .map_or(false, |p| Arc::ptr_eq(&p.0, &self.progress.0))
+        {
+            *current = None;
+        }
+    }
+}
+
+async fn draw(label: &'static str, progress: Progress) {
+    let started = std::time::Instant::now();
+    let mut drawn = false;
+    loop {
+        let finished = progress.is_finished();
+        if (drawn || started.elapsed() >= DRAW_DELAY) && !*crate::QUIET.read().await {
+            // \x1b[K clears what is left of a longer previous line
+            eprint!("\r{}\x1b[K", progress.event().render(label));
+            std::io::stderr().flush().unwrap_or_default();
+            drawn = true;
+        }
+        if finished {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    if drawn {
+        eprintln!();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut event = ProgressEvent {
+            phase: "Loading image".to_owned(),
+            done: 512,
+            total: Some(1024),
+            unit: Unit::Bytes,
+        };
+        assert_eq!(
+            event.render("Installing"),
+            format!(
+                "Installing: Loading image [{}{}] 50%",
+                "#".repeat(15),
+                " ".repeat(15)
+            )
+        );
+        event.total = None;
+        event.done = 4096;
+        assert_eq!(event.render("Installing"), "Installing: Loading image 4KiB");
+        let steps = ProgressEvent {
+            phase: "bitcoind".to_owned(),
+            done: 3,
+            total: Some(3),
+            unit: Unit::Steps,
+        };
+        assert_eq!(
+            steps.render("Backing up"),
+            format!("Backing up: bitcoind [{}] 3/3", "#".repeat(30))
+        );
+    }
+}
@@ -6,21 +6,38 @@ use crate::manifest::ManifestLatest;
 use crate::Error;
 use crate::ResultExt as _;
 
+/// Where Tor accepts SOCKS connections.
+pub const TOR_PROXY: &'static str = "socks5h://127.0.0.1:9050";
+
+/// A client for reaching the registry. While clearnet egress is blocked it goes through Tor, the
+/// only way out the firewall leaves.
+pub async fn client() -> Result<reqwest::Client, Error> {
+    let mut builder = reqwest::Client::builder();
+    if crate::firewall::settings().await?.tor_only {
+        builder =
+            builder.proxy(reqwest::Proxy::all(TOR_PROXY).with_code(crate::error::NETWORK_ERROR)?);
+    }
+    builder.build().with_code(crate::error::NETWORK_ERROR)
+}
+
 pub async fn manifest(id: &str, version: &VersionRange) -> Result<ManifestLatest, Error> {
-    let manifest: ManifestLatest = reqwest::get(&format!(
-        "{}/manifest/{}?spec={}",
-        &*crate::APP_REGISTRY_URL,
-        id,
-        version
-    ))
-    .compat()
-    .await
-    .with_code(crate::error::NETWORK_ERROR)?
-    .error_for_status()
-    .with_code(crate::error::REGISTRY_ERROR)?
-    .json()
-    .await
-    .with_code(crate::error::SERDE_ERROR)?;
+    let manifest: ManifestLatest = client()
+        .await?
+        .get(&format!(
+            "{}/manifest/{}?spec={}",
+            &*crate::APP_REGISTRY_URL,
+            id,
+            version
+        ))
+        .send()
+        .compat()
+        .await
+        .with_code(crate::error::NETWORK_ERROR)?
+        .error_for_status()
+        .with_code(crate::error::REGISTRY_ERROR)?
+        .json()
+        .await
+        .with_code(crate::error::SERDE_ERROR)?;
     Ok(manifest)
 }
 
@@ -30,41 +47,63 @@ pub async fn version(id: &str, version: &VersionRange) -> Result<emver::Version,
         version: emver::Version,
     }
 
-    let version: VersionRes = reqwest::get(&format!(
-        "{}/version/{}?spec={}",
-        &*crate::APP_REGISTRY_URL,
-        id,
-        version
-    ))
-    .compat()
-    .await
-    .with_code(crate::error::NETWORK_ERROR)?
-    .error_for_status()
-    .with_code(crate::error::REGISTRY_ERROR)?
-    .json()
-    .await
-    .with_code(crate::error::SERDE_ERROR)?;
+    let version: VersionRes = client()
+        .await?
+        .get(&format!(
+            "{}/version/{}?spec={}",
+            &*crate::APP_REGISTRY_URL,
+            id,
+            version
+        ))
+        .send()
+        .compat()
+        .await
+        .with_code(crate::error::NETWORK_ERROR)?
+        .error_for_status()
+        .with_code(crate::error::REGISTRY_ERROR)?
+        .json()
+        .await
+        .with_code(crate::error::SERDE_ERROR)?;
     Ok(version.version)
 }
 
 pub async fn config(id: &str, version: &VersionRange) -> Result<AppConfig, Error> {
-    let config: crate::inspect::AppConfig = reqwest::get(&format!(
-        "{}/config/{}?spec={}",
-        &*crate::APP_REGISTRY_URL,
-        id,
-        version
-    ))
-    .compat()
-    .await
-    .with_code(crate::error::NETWORK_ERROR)?
-    .error_for_status()
-    .with_code(crate::error::REGISTRY_ERROR)?
-    .json()
-    .await
-    .with_code(crate::error::SERDE_ERROR)?;
+    let config: crate::inspect::AppConfig = client()
+        .await?
+        .get(&format!(
+            "{}/config/{}?spec={}",
+            &*crate::APP_REGISTRY_URL,
+            id,
+            version
+        ))
+        .send()
+        .compat()
+        .await
+        .with_code(crate::error::NETWORK_ERROR)?
+        .error_for_status()
+        .with_code(crate::error::REGISTRY_ERROR)?
+        .json()
+        .await
+        .with_code(crate::error::SERDE_ERROR)?;
     Ok(AppConfig {
         config: None,
         spec: config.spec,
         rules: config.rules,
     })
 }
+
+pub async fn group(name: &str) -> Result<crate::groups::Group, Error> {
+    let group: crate::groups::Group = client()
+        .await?
+        .get(&format!("{}/group/{}", &*crate::APP_REGISTRY_URL, name))
+        .send()
+        .compat()
+        .await
+        .with_code(crate::error::NETWORK_ERROR)?
+        .error_for_status()
+        .with_code(crate::error::REGISTRY_ERROR)?
+        .json()
+        .await
+        .with_code(crate::error::SERDE_ERROR)?;
+    Ok(group)
+}
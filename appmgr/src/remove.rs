@@ -1,59 +1,141 @@
 use crate::failure::ResultExt;
 use std::path::Path;
 
-use linear_map::LinearMap;
+use futures::future::{BoxFuture, FutureExt};
+use linear_map::{set::LinearSet, LinearMap};
 
 use crate::dependencies::{DependencyError, TaggedDependencyError};
+use crate::util::Invoke;
 use crate::Error;
 use crate::ResultExt as _;
 
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RemoveRes {
+    /// Every installed app that depends on the removed one, directly or not, and the dependency
+    /// it loses. With `cascade`, these are removed as well.
+    pub broken: LinearMap<String, TaggedDependencyError>,
+    /// The ones that were running, and were stopped.
+    pub stopped: LinearSet<String>,
+    /// The apps removed, dependents first.
+    pub removed: Vec<String>,
+}
+
+// every installed app that depends on `name`, directly or not, with the dependency that breaks it
+async fn broken_dependents(name: &str) -> Result<LinearMap<String, TaggedDependencyError>, Error> {
+    fn broken_dependents_rec<'a>(
+        name: &'a str,
+        err: DependencyError,
+        res: &'a mut LinearMap<String, TaggedDependencyError>,
+    ) -> BoxFuture<'a, Result<(), Error>> {
+        async move {
+            for dependent in crate::apps::dependents(name, false).await? {
+                if res.contains_key(&dependent) {
+                    continue;
+                }
+                res.insert(
+                    dependent.clone(),
+                    TaggedDependencyError {
+                        dependency: name.to_owned(),
+                        error: err.clone(),
+                    },
+                );
+                broken_dependents_rec(&dependent, DependencyError::NotRunning, res).await?;
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+    let mut res = LinearMap::new();
+    broken_dependents_rec(name, DependencyError::NotInstalled, &mut res).await?;
+    res.remove(name);
+    Ok(res)
+}
+
+/// Removes an app, stopping the apps that depend on it, or with `cascade`, removing them first.
+/// With `keep_data`, everything is purged but the volume, which is left as orphaned data.
+/// The pre-remove hook of every app being removed runs before any of them is, and one that fails
+/// aborts the whole removal, unless `force` is set.
 pub async fn remove(
     name: &str,
     purge: bool,
+    keep_data: bool,
+    dry_run: bool,
+    force: bool,
+    cascade: bool,
+) -> Result<RemoveRes, Error> {
+    let mut res = RemoveRes {
+        broken: broken_dependents(name).await?,
+        ..Default::default()
+    };
+    let mut targets: Vec<String> = Vec::new();
+    if cascade {
+        // a dependent is always found after the app that brings it in
+        targets.extend(res.broken.keys().cloned());
+        targets.reverse();
+    }
+    targets.push(name.to_owned());
+    for id in &targets {
+        crate::ensure_code!(
+            !crate::apps::manifest(id).await?.system,
+            crate::error::GENERAL_ERROR,
+            "{} is a system package and cannot be removed",
+            id
+        );
+    }
+    if !dry_run {
+        for id in &targets {
+            match crate::hooks::pre_remove(id).await {
+                Err(e) if force => log::warn!("Removing anyway: {}", e.failure),
+                other => other?,
+            }
+        }
+    }
+    for id in targets {
+        for (stopped, _) in remove_unchecked(&id, purge, keep_data, dry_run).await? {
+            res.stopped.insert(stopped);
+        }
+        if !dry_run {
+            crate::events::emit(crate::events::EventKind::Removed, &id).await;
+        }
+        res.removed.push(id);
+    }
+    if !dry_run {
+        crate::docs::refresh().await;
+        if let Err(e) = crate::health::sync_monitor_unit().await {
+            log::warn!("Failed to update the health monitor: {}", e.failure);
+        }
+    }
+    Ok(res)
+}
+
+/// Removes an app even if it is a system package, i.e. while replacing it with a new version.
+pub(crate) async fn remove_unchecked(
+    name: &str,
+    purge: bool,
+    keep_data: bool,
     dry_run: bool,
 ) -> Result<LinearMap<String, TaggedDependencyError>, Error> {
+    let _lock = crate::util::lock_app(name).await?;
     let manifest = crate::apps::manifest(name).await?;
     let mut res = LinearMap::new();
     crate::stop_dependents(name, dry_run, DependencyError::NotInstalled, &mut res).await?;
     if dry_run {
         return Ok(res);
     }
-    let image_name = format!("start9/{}", name);
     log::info!("Removing app from manifest.");
     crate::apps::remove(name).await?;
-    log::info!("Stopping docker container.");
+    crate::config::watch::forget(name).await?;
+    crate::schedule::forget(name).await?;
+    log::info!("Stopping app.");
     let res = crate::control::stop_app(name, false, false)
         .await
         .unwrap_or_else(|e| {
             log::error!("Error stopping app: {}", e);
             LinearMap::new()
         });
-    log::info!("Removing docker container.");
-    if !std::process::Command::new("docker")
-        .args(&["rm", name])
-        .stdout(std::process::Stdio::null())
-        .stderr(match log::max_level() {
-            log::LevelFilter::Error => std::process::Stdio::null(),
-            _ => std::process::Stdio::inherit(),
-        })
-        .status()?
-        .success()
-    {
-        log::error!("Failed to Remove Docker Container");
-    };
-    if !std::process::Command::new("docker")
-        .args(&["rmi", &image_name])
-        .stdout(std::process::Stdio::null())
-        .stderr(match log::max_level() {
-            log::LevelFilter::Error => std::process::Stdio::null(),
-            _ => std::process::Stdio::inherit(),
-        })
-        .status()?
-        .success()
-    {
-        log::error!("Failed to Remove Docker Image");
-    };
-    if purge {
+    crate::backend::Backend::of_app(name).remove(name).await?;
+    if purge || keep_data {
         log::info!("Removing tor hidden service.");
         crate::tor::rm_svc(name).await?;
         log::info!("Removing app metadata.");
@@ -124,12 +206,16 @@ pub async fn remove(
                 }
             }
         }
-        log::info!("Destroying mounted volume.");
-        let volume_path = Path::new(crate::VOLUMES).join(name);
-        tokio::fs::remove_dir_all(&volume_path)
-            .await
-            .with_context(|e| format!("rm {}: {}", volume_path.display(), e))
-            .with_code(crate::error::FILESYSTEM_ERROR)?;
+        if keep_data {
+            log::info!("Keeping volume {}/{}.", crate::VOLUMES, name);
+        } else {
+            log::info!("Destroying mounted volume.");
+            let volume_path = Path::new(crate::VOLUMES).join(name);
+            tokio::fs::remove_dir_all(&volume_path)
+                .await
+                .with_context(|e| format!("rm {}: {}", volume_path.display(), e))
+                .with_code(crate::error::FILESYSTEM_ERROR)?;
+        }
         log::info!("Pruning unused docker images.");
         crate::ensure_code!(
             std::process::Command::new("docker")
@@ -148,3 +234,100 @@ pub async fn remove(
 
     Ok(res)
 }
+
+/// The volume of an app that is not installed, i.e. one removed with `keep_data`.
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OrphanedVolume {
+    pub id: String,
+    /// In bytes.
+    pub size: u64,
+}
+
+async fn disk_usage(path: &Path) -> Result<u64, Error> {
+    let output = tokio::process::Command::new("du")
+        .arg("-sb")
+        .arg(path)
+        .invoke("du")
+        .await?;
+    std::str::from_utf8(&output)
+        .no_code()?
+        .split_whitespace()
+        .next()
+        .and_then(|a| a.parse().ok())
+        .ok_or_else(|| failure::format_err!("Invalid Output From du: {}", path.display()))
+        .no_code()
+}
+
+pub async fn orphans() -> Result<Vec<OrphanedVolume>, Error> {
+    let installed = crate::apps::list_info().await?;
+    let mut res = Vec::new();
+    let mut entries = tokio::fs::read_dir(crate::VOLUMES).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let id = match entry.file_name().to_str() {
+            Some(a) => a.to_owned(),
+            None => continue,
+        };
+        if installed.contains_key(&id) || !entry.file_type().await?.is_dir() {
+            continue;
+        }
+        res.push(OrphanedVolume {
+            size: disk_usage(&entry.path()).await?,
+            id,
+        });
+    }
+    res.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(res)
+}
+
+// mount points under `path`, deepest first
+async fn mounts_under(path: &Path) -> Result<Vec<std::path::PathBuf>, Error> {
+    let mounts = tokio::fs::read_to_string("/proc/mounts").await?;
+    let mut res: Vec<std::path::PathBuf> = mounts
+        .lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        .map(std::path::PathBuf::from)
+        .filter(|mount_point| mount_point.starts_with(path) && mount_point != path)
+        .collect();
+    res.sort_by_key(|a| std::cmp::Reverse(a.components().count()));
+    Ok(res)
+}
+
+/// Deletes the orphaned volume of an app, along with whatever metadata a plain remove left.
+pub async fn purge_data(id: &str) -> Result<(), Error> {
+    crate::ensure_code!(
+        !crate::apps::list_info().await?.contains_key(id),
+        crate::error::GENERAL_ERROR,
+        "{} is installed, remove it with --purge instead",
+        id
+    );
+    let volume_path = Path::new(crate::VOLUMES).join(id);
+    let metadata_path = Path::new(crate::PERSISTENCE_DIR).join("apps").join(id);
+    crate::ensure_code!(
+        volume_path.exists() || metadata_path.exists(),
+        crate::error::NOT_FOUND,
+        "No Data For {}",
+        id
+    );
+    let _lock = crate::util::lock_app(id).await?;
+    if volume_path.exists() {
+        // binds of the volumes of its dependencies, which must not be deleted with it
+        for mount_point in mounts_under(&volume_path).await? {
+            crate::disks::unmount(&mount_point).await?;
+        }
+        log::info!("Destroying volume {}.", volume_path.display());
+        tokio::fs::remove_dir_all(&volume_path)
+            .await
+            .with_context(|e| format!("rm {}: {}", volume_path.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+    }
+    if metadata_path.exists() {
+        crate::tor::rm_svc(id).await?;
+        log::info!("Removing app metadata.");
+        tokio::fs::remove_dir_all(&metadata_path)
+            .await
+            .with_context(|e| format!("rm {}: {}", metadata_path.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,276 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use linear_map::LinearMap;
+
+use crate::apps::{AppInfo, DockerStatus};
+use crate::util::{Invoke, PersistencePath};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const REPLICATION_YAML: &'static str = "replication.yaml";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    /// Ships snapshots to the target.
+    Primary,
+    /// Holds the target, and can take over from it.
+    Standby,
+}
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Role::Primary => write!(f, "primary"),
+            Role::Standby => write!(f, "standby"),
+        }
+    }
+}
+impl std::str::FromStr for Role {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "primary" => Ok(Role::Primary),
+            "standby" => Ok(Role::Standby),
+            _ => Err(format_err!("Invalid Role: {}", s)).with_code(crate::error::GENERAL_ERROR),
+        }
+    }
+}
+
+/// Pairs this device with another one, which is kept up to date with encrypted snapshots of
+/// every app so that it can take over if this one fails. Experimental.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReplicationConfig {
+    pub role: Role,
+    /// Where snapshots are kept, as a duplicity URL. Usually a directory on the standby, i.e.
+    /// `sftp://embassy@<standby>.onion/replica` on the primary and `file:///root/replica` on the
+    /// standby.
+    pub target: String,
+    /// Reach the target through tor.
+    #[serde(default)]
+    pub tor: bool,
+    /// Snapshots are encrypted with this. Both devices must use the same one.
+    pub password: String,
+    /// When the last snapshot was shipped, in seconds since the epoch.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_sync: Option<u64>,
+}
+impl ReplicationConfig {
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.target.trim_end_matches('/'), path)
+    }
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReplicationRes {
+    pub succeeded: Vec<String>,
+    pub failed: LinearMap<String, String>,
+}
+
+pub async fn get() -> Result<Option<ReplicationConfig>, Error> {
+    let path = PersistencePath::from_ref(REPLICATION_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+async fn get_as(role: Role) -> Result<ReplicationConfig, Error> {
+    let config = get()
+        .await?
+        .ok_or_else(|| format_err!("Replication Not Configured"))
+        .with_code(crate::error::NOT_FOUND)?;
+    crate::ensure_code!(
+        config.role == role,
+        crate::error::GENERAL_ERROR,
+        "This Device Is The Replication {}",
+        config.role
+    );
+    Ok(config)
+}
+
+pub async fn pair(role: Role, target: &str, tor: bool, password: &str) -> Result<(), Error> {
+    crate::ensure_code!(
+        target.contains("://"),
+        crate::error::GENERAL_ERROR,
+        "Invalid Replication Target: {}, expected a duplicity URL",
+        target
+    );
+    crate::ensure_code!(
+        !password.is_empty(),
+        crate::error::GENERAL_ERROR,
+        "Replication Password Cannot Be Empty"
+    );
+    let mut f = PersistencePath::from_ref(REPLICATION_YAML)
+        .write(None)
+        .await?;
+    crate::util::to_yaml_async_writer(
+        &mut *f,
+        &ReplicationConfig {
+            role,
+            target: target.to_owned(),
+            tor,
+            password: password.to_owned(),
+            last_sync: None,
+        },
+    )
+    .await?;
+    f.commit().await
+}
+
+pub async fn unpair() -> Result<(), Error> {
+    PersistencePath::from_ref(REPLICATION_YAML).delete().await
+}
+
+async fn ship_app(config: &ReplicationConfig, id: &str) -> Result<(), Error> {
+    let volume_path = Path::new(crate::VOLUMES).join(id);
+    let hidden_service_path =
+        Path::new(crate::tor::HIDDEN_SERVICE_DIR_ROOT).join(format!("app-{}", id));
+    let running = crate::apps::status(id, false).await?.status == DockerStatus::Running;
+    // paused so the snapshot is consistent, as in a backup
+    if running {
+        crate::control::pause_app(id).await?;
+    }
+    let res = async {
+        crate::backup::duplicity(&config.password, config.tor)
+            .arg(&volume_path)
+            .arg(config.url(&format!("{}/data", id)))
+            .invoke("Duplicity")
+            .await?;
+        crate::backup::duplicity(&config.password, config.tor)
+            .arg(&hidden_service_path)
+            .arg(config.url(&format!("{}/tor", id)))
+            .invoke("Duplicity")
+            .await?;
+        Ok::<_, Error>(())
+    }
+    .await;
+    if running {
+        crate::control::resume_app(id).await?;
+    }
+    res
+}
+
+/// Ships a snapshot of every app, and of the state of appmgr, to the target. Snapshots are
+/// incremental, so this is meant to be run periodically. A failed app does not stop the others.
+pub async fn sync() -> Result<ReplicationRes, Error> {
+    let config = get_as(Role::Primary).await?;
+    let mut res = ReplicationRes::default();
+    for (id, _) in crate::apps::list_info().await? {
+        log::info!("Replicating {}.", id);
+        match ship_app(&config, &id).await {
+            Ok(()) => res.succeeded.push(id),
+            Err(e) => {
+                log::error!("Replication of {} failed: {}", id, e.failure);
+                res.failed.insert(id, format!("{}", e.failure));
+            }
+        }
+    }
+    // the app list, manifests and configs, but not the replication password
+    crate::backup::duplicity(&config.password, config.tor)
+        .arg(format!(
+            "--exclude={}",
+            Path::new(crate::PERSISTENCE_DIR)
+                .join(REPLICATION_YAML)
+                .display()
+        ))
+        .arg("--exclude=**.lock")
+        .arg(crate::PERSISTENCE_DIR)
+        .arg(config.url("appmgr"))
+        .invoke("Duplicity")
+        .await?;
+    let mut config = config;
+    config.last_sync = Some(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    );
+    let mut f = PersistencePath::from_ref(REPLICATION_YAML)
+        .write(None)
+        .await?;
+    crate::util::to_yaml_async_writer(&mut *f, &config).await?;
+    f.commit().await?;
+    Ok(res)
+}
+
+/// Brings up every app of the primary on this device, from the last snapshot it shipped. Apps
+/// that are not installed here are installed at the version the primary had. The primary must
+/// be shut down first, or both devices will serve the same tor addresses.
+pub async fn failover() -> Result<ReplicationRes, Error> {
+    let config = get_as(Role::Standby).await?;
+    let staging = Path::new(crate::TMP_DIR).join("replica");
+    if staging.exists() {
+        tokio::fs::remove_dir_all(&staging).await?;
+    }
+    crate::backup::duplicity(&config.password, config.tor)
+        .arg("--force")
+        .arg(config.url("appmgr"))
+        .arg(&staging)
+        .invoke("Duplicity")
+        .await?;
+    let apps: LinearMap<String, AppInfo> = crate::util::from_yaml_async_reader(
+        tokio::fs::File::open(staging.join("apps.yaml")).await?,
+    )
+    .await?;
+    let installed = crate::apps::list_info().await?;
+    let mut res = ReplicationRes::default();
+    for (id, info) in apps {
+        let app_res = async {
+            if installed.get(&id).map(|i| &i.version) != Some(&info.version) {
+                log::info!("Installing {} {}.", id, info.version);
+                crate::install_name(&format!("{}@={}", id, info.version), false, None).await?;
+            }
+            log::info!("Restoring {} from replica.", id);
+            crate::backup::restore_from_urls(
+                &id,
+                &config.url(&format!("{}/data", id)),
+                &config.url(&format!("{}/tor", id)),
+                None,
+                None,
+                &config.password,
+                config.tor,
+            )
+            .await?;
+            if crate::apps::info(&id).await?.configured {
+                crate::control::start_app(&id, true).await?;
+            }
+            Ok::<_, Error>(())
+        }
+        .await;
+        match app_res {
+            Ok(()) => res.succeeded.push(id),
+            Err(e) => {
+                log::error!("Failover of {} failed: {}", id, e.failure);
+                res.failed.insert(id, format!("{}", e.failure));
+            }
+        }
+    }
+    tokio::fs::remove_dir_all(&staging).await?;
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_url() {
+        let config = ReplicationConfig {
+            role: Role::Primary,
+            target: "sftp://embassy@standby.onion/replica/".to_owned(),
+            tor: true,
+            password: "hunter2".to_owned(),
+            last_sync: None,
+        };
+        assert_eq!(
+            config.url("bitcoind/data"),
+            "sftp://embassy@standby.onion/replica/bitcoind/data"
+        );
+        assert_eq!("standby".parse::<Role>().unwrap(), Role::Standby);
+        assert!("spare".parse::<Role>().is_err());
+    }
+}
@@ -0,0 +1,396 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use failure::ResultExt as _;
+use linear_map::LinearMap;
+
+use crate::apps::AppInfo;
+use crate::backend::Backend;
+use crate::util::{Invoke, PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const RETAINED_YAML: &'static str = "retained.yaml";
+pub const RETAINED_DIR: &'static str = "/root/volumes-retained";
+pub const RETENTION_YAML: &'static str = "retention.yaml";
+const MIB: u64 = 1024 * 1024;
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetentionSettings {
+    /// How long the previous version of an app is kept after an update. Off unless set, as a
+    /// retained version holds a full copy of the volume of the app.
+    #[serde(default)]
+    pub days: u64,
+}
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Retained {
+    pub version: emver::Version,
+    pub path: PathBuf,
+    pub retained_at: u64,
+    pub expires_at: u64,
+    /// The entry of the app in the app list, restored along with it on rollback.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info: Option<AppInfo>,
+}
+
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RetainedInfo {
+    #[serde(flatten)]
+    pub retained: Retained,
+    pub size: u64,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub async fn settings() -> Result<RetentionSettings, Error> {
+    let path = PersistencePath::from_ref(RETENTION_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await,
+        None => Ok(Default::default()),
+    }
+}
+
+/// Sets how many days the previous version of an app is retained after an update, 0 to retain
+/// nothing. Versions retained already keep the period they were retained with.
+pub async fn set_days(days: u64) -> Result<(), Error> {
+    let mut settings: YamlUpdateHandle<RetentionSettings> =
+        YamlUpdateHandle::new_or_default(PersistencePath::from_ref(RETENTION_YAML)).await?;
+    settings.days = days;
+    settings.commit().await?;
+    Ok(())
+}
+
+async fn disk_usage(path: &Path) -> Result<u64, Error> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let out = tokio::process::Command::new("du")
+        .arg("-sb")
+        .arg(path)
+        .invoke("Disk Usage")
+        .await
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(std::str::from_utf8(&out)
+        .no_code()?
+        .split_whitespace()
+        .next()
+        .map(|s| s.parse())
+        .transpose()
+        .no_code()?
+        .unwrap_or(0))
+}
+
+async fn image_size(name: &str) -> Result<u64, Error> {
+    let out = tokio::process::Command::new("docker")
+        .arg("image")
+        .arg("inspect")
+        .arg("--format={{.Size}}")
+        .arg(format!("start9/{}", name))
+        .invoke("Docker Image Inspect")
+        .await
+        .with_code(crate::error::DOCKER_ERROR)?;
+    std::str::from_utf8(&out)
+        .no_code()?
+        .trim()
+        .parse()
+        .no_code()
+}
+
+async fn retained_mut() -> Result<YamlUpdateHandle<LinearMap<String, Retained>>, Error> {
+    YamlUpdateHandle::new_or_default(PersistencePath::from_ref(RETAINED_YAML)).await
+}
+
+async fn remove_snapshot(path: &Path) -> Result<(), Error> {
+    if path.exists() {
+        tokio::fs::remove_dir_all(path)
+            .await
+            .with_context(|e| format!("rm {}: {}", path.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+    }
+    Ok(())
+}
+
+/// Saves the currently installed image, volume, and metadata of an app so that it can be rolled
+/// back to after an update. Replaces anything previously retained for the app. The app is
+/// stopped first so that its volume is not copied half way through a write, and is started again
+/// if retaining fails.
+pub async fn retain(name: &str, retention: Duration) -> Result<(), Error> {
+    if retention == Duration::from_secs(0) {
+        return Ok(());
+    }
+    let info = crate::apps::list_info()
+        .await?
+        .remove(name)
+        .ok_or_else(|| failure::format_err!("App Not Installed: {}", name))
+        .with_code(crate::error::NOT_FOUND)?;
+    let path = Path::new(RETAINED_DIR).join(name);
+    remove_snapshot(&path).await?;
+    tokio::fs::create_dir_all(&path).await?;
+    let docker = Backend::of_app(name) == Backend::Docker;
+    // before the app is stopped, so an update that cannot be retained leaves it running
+    let mut needed = disk_usage(&Path::new(crate::VOLUMES).join(name)).await?
+        + disk_usage(&PersistencePath::from_ref("apps").join(name).path()).await?;
+    if docker {
+        needed += image_size(name).await?;
+    }
+    let statvfs =
+        nix::sys::statvfs::statvfs(path.as_path()).with_code(crate::error::FILESYSTEM_ERROR)?;
+    let available = statvfs.blocks_available() as u64 * statvfs.fragment_size() as u64;
+    crate::ensure_code!(
+        needed <= available,
+        crate::error::RESOURCE_INSUFFICIENT,
+        "Not Enough Space To Retain {}: {} MiB Needed, {} MiB Available",
+        name,
+        needed / MIB,
+        available / MIB
+    );
+    let running =
+        crate::apps::status(name, false).await?.status != crate::apps::DockerStatus::Stopped;
+    if running {
+        crate::control::stop_app(name, false, false).await?;
+    }
+    if let Err(e) = snapshot(name, &info, &path, docker).await {
+        if let Err(e) = remove_snapshot(&path).await {
+            log::warn!("Failed to clean up {}: {}", path.display(), e.failure);
+        }
+        if running {
+            if let Err(e) = crate::control::start_app(name, false).await {
+                log::error!("Failed to restart {}: {}", name, e.failure);
+            }
+        }
+        return Err(e);
+    }
+    let retained_at = now();
+    let mut retained = retained_mut().await?;
+    retained.insert(
+        name.to_owned(),
+        Retained {
+            version: info.version.clone(),
+            path,
+            retained_at,
+            expires_at: retained_at + retention.as_secs(),
+            info: Some(info),
+        },
+    );
+    retained.commit().await?;
+    Ok(())
+}
+
+async fn snapshot(name: &str, info: &AppInfo, path: &Path, docker: bool) -> Result<(), Error> {
+    // the binary of a static app is retained with its metadata
+    if docker {
+        log::info!("Retaining image for {} v{}.", name, info.version);
+        tokio::process::Command::new("docker")
+            .arg("save")
+            .arg("-o")
+            .arg(path.join("image.tar"))
+            .arg(format!("start9/{}", name))
+            .invoke("Docker Save")
+            .await
+            .with_code(crate::error::DOCKER_ERROR)?;
+    }
+    log::info!("Retaining volume for {} v{}.", name, info.version);
+    copy(
+        &Path::new(crate::VOLUMES).join(name),
+        &path.join("volume"),
+        "Copy Volume",
+    )
+    .await?;
+    tokio::process::Command::new("cp")
+        .arg("-a")
+        .arg(PersistencePath::from_ref("apps").join(name).path())
+        .arg(path.join("metadata"))
+        .invoke("Copy Metadata")
+        .await
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(())
+}
+
+async fn read() -> Result<LinearMap<String, Retained>, Error> {
+    let path = PersistencePath::from_ref(RETAINED_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await,
+        None => Ok(LinearMap::new()),
+    }
+}
+
+pub async fn list() -> Result<LinearMap<String, RetainedInfo>, Error> {
+    let retained = read().await?;
+    let mut res = LinearMap::new();
+    for (name, retained) in retained {
+        let size = disk_usage(&retained.path).await?;
+        res.insert(name, RetainedInfo { retained, size });
+    }
+    Ok(res)
+}
+
+/// Removes retained data that has outlived its retention period, or all of it if `all` is set.
+pub async fn gc(all: bool) -> Result<Vec<String>, Error> {
+    let _io = crate::io_priority::enter(crate::io_priority::JobClass::Gc).await?;
+    let mut retained = retained_mut().await?;
+    let now = now();
+    let expired: Vec<String> = retained
+        .iter()
+        .filter(|(_, r)| all || r.expires_at <= now)
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in &expired {
+        if let Some(r) = retained.remove(name) {
+            log::info!("Discarding retained {} v{}.", name, r.version);
+            remove_snapshot(&r.path).await?;
+        }
+    }
+    retained.commit().await?;
+    Ok(expired)
+}
+
+pub async fn discard(name: &str) -> Result<(), Error> {
+    let mut retained = retained_mut().await?;
+    let r = retained
+        .remove(name)
+        .ok_or_else(|| failure::format_err!("Nothing retained for {}", name))
+        .with_code(crate::error::NOT_FOUND)?;
+    remove_snapshot(&r.path).await?;
+    retained.commit().await?;
+    Ok(())
+}
+
+// the volumes of other apps bound into this one, which must not be deleted along with it
+async fn unmount_binds(volume: &Path) -> Result<(), Error> {
+    let mounts = tokio::fs::read_to_string("/proc/self/mounts").await?;
+    let mut binds: Vec<PathBuf> = mounts
+        .lines()
+        .filter_map(|l| l.split_whitespace().nth(1))
+        .map(PathBuf::from)
+        .filter(|p| p.starts_with(volume) && p != volume)
+        .collect();
+    // innermost first
+    binds.sort();
+    for bind in binds.into_iter().rev() {
+        crate::disks::unmount(&bind).await?;
+    }
+    Ok(())
+}
+
+async fn copy(from: &Path, to: &Path, what: &str) -> Result<(), Error> {
+    tokio::process::Command::new("cp")
+        .arg("-a")
+        .arg("--reflink=auto")
+        .arg(from)
+        .arg(to)
+        .invoke(what)
+        .await
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(())
+}
+
+/// Puts the retained version of an app back in place, with the volume and metadata it had when
+/// it was retained. Whatever is installed in its place, even half way, is removed first. The
+/// retained copy is kept until it expires, so a rollback can be repeated.
+pub async fn rollback(name: &str) -> Result<(), Error> {
+    let retained = read()
+        .await?
+        .remove(name)
+        .ok_or_else(|| failure::format_err!("Nothing retained for {}", name))
+        .with_code(crate::error::NOT_FOUND)?;
+    crate::ensure_code!(
+        retained.path.exists(),
+        crate::error::FILESYSTEM_ERROR,
+        "{}: No Such File Or Directory",
+        retained.path.display()
+    );
+    if crate::apps::list_info().await?.contains_key(name) {
+        crate::remove::remove_unchecked(name, false, false, false).await?;
+    } else {
+        // an install that failed may have left its container or unit behind
+        Backend::of_app(name).remove(name).await?;
+    }
+    let lock = crate::util::lock_app(name).await?;
+    log::info!("Restoring metadata for {} v{}.", name, retained.version);
+    let metadata_path = PersistencePath::from_ref("apps").join(name).path();
+    remove_snapshot(&metadata_path).await?;
+    copy(
+        &retained.path.join("metadata"),
+        &metadata_path,
+        "Copy Metadata",
+    )
+    .await?;
+    log::info!("Restoring volume for {} v{}.", name, retained.version);
+    let volume_path = Path::new(crate::VOLUMES).join(name);
+    unmount_binds(&volume_path).await?;
+    remove_snapshot(&volume_path).await?;
+    copy(&retained.path.join("volume"), &volume_path, "Copy Volume").await?;
+    let image_path = retained.path.join("image.tar");
+    if image_path.exists() {
+        log::info!("Restoring image for {} v{}.", name, retained.version);
+        tokio::process::Command::new("docker")
+            .arg("load")
+            .arg("-i")
+            .arg(&image_path)
+            .invoke("Docker Load")
+            .await
+            .with_code(crate::error::DOCKER_ERROR)?;
+    }
+    let manifest = crate::apps::manifest(name).await?;
+    let (ip, tor_addr, tor_key) = crate::tor::set_svc(
+        name,
+        crate::tor::NewService {
+            ports: manifest.ports.clone(),
+            hidden_service_version: manifest.hidden_service_version,
+        },
+    )
+    .await?;
+    let mut env = Vec::new();
+    if let (Some(ref tor_addr), Some(ref tor_key)) = (&tor_addr, &tor_key) {
+        env.push(format!("TOR_ADDRESS={}", tor_addr));
+        env.push(format!("TOR_KEY={}", tor_key));
+    }
+    match Backend::of(&manifest.image) {
+        Backend::Docker => {
+            // the config is in the container rather than read at every start
+            env.extend(crate::config::env::read(name).await?);
+            crate::install::create_container(
+                &manifest,
+                &format!("start9/{}:latest", name),
+                ip,
+                &env,
+            )?;
+        }
+        Backend::Systemd => crate::backend::write_unit(&manifest, ip, &env).await?,
+    }
+    let info = match retained.info {
+        Some(info) => AppInfo {
+            tor_address: tor_addr,
+            ..info
+        },
+        // retained before the app list entry was
+        None => AppInfo {
+            title: manifest.title.clone(),
+            version: manifest.version.clone(),
+            tor_address: tor_addr,
+            configured: false,
+            recoverable: true,
+            needs_restart: false,
+            pinned: false,
+            system: manifest.system,
+            mode: crate::modes::current(&manifest).await?,
+            provenance: manifest.provenance.clone(),
+        },
+    };
+    crate::apps::add(name, info).await?;
+    drop(lock);
+    crate::dependencies::update_binds(name).await?;
+    crate::tor::reload().await?;
+    crate::docs::refresh().await;
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+use failure::ResultExt as _;
+use futures::future::{BoxFuture, FutureExt};
+use tokio::io::AsyncWriteExt;
+use tokio_tar as tar;
+
+use super::{CONFIG_RULES_CBOR, CONFIG_SPEC_CBOR, INSTRUCTIONS_MD, MANIFEST_CBOR};
+use crate::config::{ConfigRuleEntryWithSuggestions, ConfigSpec};
+use crate::manifest::{Asset, Manifest};
+use crate::Error;
+use crate::ResultExt as _;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    Manifest,
+    ConfigSpec,
+    ConfigRules,
+    Instructions,
+    Assets,
+    Images,
+}
+
+/// Like `append_dir_all`, but with the entries of each directory in order of their names rather
+/// than in whatever order the filesystem lists them.
+fn append_dir_sorted<'a>(
+    out: &'a mut tar::Builder<tokio::fs::File>,
+    name: PathBuf,
+    path: PathBuf,
+) -> BoxFuture<'a, Result<(), Error>> {
+    async move {
+        out.append_path_with_name(&path, &name).await?;
+        let mut children = Vec::new();
+        let mut dir = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            children.push(entry.file_name());
+        }
+        children.sort();
+        for child in children {
+            let child_path = path.join(&child);
+            if tokio::fs::metadata(&child_path).await?.is_dir() {
+                append_dir_sorted(out, name.join(&child), child_path).await?;
+            } else {
+                out.append_path_with_name(&child_path, name.join(&child))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+    .boxed()
+}
+
+/// Writes a package in the order the installer reads it: the manifest, config spec and config
+/// rules, then the instructions, assets and images the manifest declares.
+pub struct S9pkBuilder {
+    out: tar::Builder<tokio::fs::File>,
+    deterministic: bool,
+    last: Option<Stage>,
+}
+impl S9pkBuilder {
+    /// When `deterministic`, files are packed without their timestamps and owners and in a fixed
+    /// order, so packing the same sources again produces the same bytes.
+    pub fn new(file: tokio::fs::File, deterministic: bool) -> Self {
+        let mut out = tar::Builder::new(file);
+        if deterministic {
+            out.mode(tar::HeaderMode::Deterministic);
+        }
+        S9pkBuilder {
+            out,
+            deterministic,
+            last: None,
+        }
+    }
+
+    fn enter(&mut self, stage: Stage) -> Result<(), Error> {
+        let in_order = match (self.last, stage) {
+            (None, Stage::Manifest) => true,
+            (Some(Stage::Manifest), Stage::ConfigSpec) => true,
+            (Some(Stage::ConfigSpec), Stage::ConfigRules) => true,
+            (Some(last), stage) => {
+                last >= Stage::ConfigRules
+                    && (last < stage || (last == stage && stage != Stage::Instructions))
+            }
+            _ => false,
+        };
+        crate::ensure_code!(
+            in_order,
+            crate::error::GENERAL_ERROR,
+            "Cannot Pack {:?} After {:?}",
+            stage,
+            self.last
+        );
+        self.last = Some(stage);
+        Ok(())
+    }
+
+    async fn append(&mut self, path: &str, data: Vec<u8>) -> Result<(), Error> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        self.out
+            .append_data(&mut header, path, std::io::Cursor::new(data))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn manifest(&mut self, manifest: &Manifest) -> Result<(), Error> {
+        self.enter(Stage::Manifest)?;
+        let bin = serde_cbor::to_vec(manifest).with_code(crate::error::SERDE_ERROR)?;
+        self.append(MANIFEST_CBOR, bin).await
+    }
+
+    pub async fn config_spec(&mut self, config_spec: &ConfigSpec) -> Result<(), Error> {
+        self.enter(Stage::ConfigSpec)?;
+        let bin = serde_cbor::to_vec(config_spec).with_code(crate::error::SERDE_ERROR)?;
+        self.append(CONFIG_SPEC_CBOR, bin).await
+    }
+
+    pub async fn config_rules(
+        &mut self,
+        config_rules: &[ConfigRuleEntryWithSuggestions],
+    ) -> Result<(), Error> {
+        self.enter(Stage::ConfigRules)?;
+        let bin = serde_cbor::to_vec(&config_rules).with_code(crate::error::SERDE_ERROR)?;
+        self.append(CONFIG_RULES_CBOR, bin).await
+    }
+
+    pub async fn instructions<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        self.enter(Stage::Instructions)?;
+        self.out
+            .append_path_with_name(path, INSTRUCTIONS_MD)
+            .await?;
+        Ok(())
+    }
+
+    /// Packs the file or directory at `path` as `asset`. A directory is followed by an empty
+    /// `APPMGR_DIR_END:` entry, so the installer knows where it ends.
+    pub async fn asset<P: AsRef<Path>>(&mut self, asset: &Asset, path: P) -> Result<(), Error> {
+        self.enter(Stage::Assets)?;
+        let path = path.as_ref();
+        let src = tokio::fs::File::open(path)
+            .await
+            .with_context(|e| format!("{}: {}", path.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+        if src.metadata().await?.is_dir() {
+            if self.deterministic {
+                append_dir_sorted(&mut self.out, asset.src.clone(), path.to_owned()).await?;
+            } else {
+                self.out.append_dir_all(&asset.src, path).await?;
+            }
+            let mut h = tar::Header::new_gnu();
+            h.set_size(0);
+            h.set_path(format!("APPMGR_DIR_END:{}", asset.src.display()))?;
+            h.set_cksum();
+            self.out.append(&h, tokio::io::empty()).await?;
+        } else {
+            self.out.append_path_with_name(path, &asset.src).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn image(&mut self, tar_name: &str, image: tokio::fs::File) -> Result<(), Error> {
+        self.enter(Stage::Images)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(image.metadata().await?.len());
+        self.out.append_data(&mut header, tar_name, image).await?;
+        Ok(())
+    }
+
+    /// Ends the archive, and flushes it to its file.
+    pub async fn finish(self) -> Result<tokio::fs::File, Error> {
+        let mut file = self.out.into_inner().await?;
+        file.flush().await?;
+        Ok(file)
+    }
+}
@@ -0,0 +1,121 @@
+use std::io::SeekFrom;
+
+use linear_map::LinearMap;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use tokio_tar as tar;
+
+use crate::Error;
+
+pub mod builder;
+pub mod reader;
+
+pub use builder::S9pkBuilder;
+pub use reader::S9pkReader;
+
+pub const MANIFEST_CBOR: &'static str = "manifest.cbor";
+pub const CONFIG_SPEC_CBOR: &'static str = "config_spec.cbor";
+pub const CONFIG_RULES_CBOR: &'static str = "config_rules.cbor";
+pub const INSTRUCTIONS_MD: &'static str = "instructions.md";
+
+const BLOCK_LEN: u64 = 512;
+
+/// Where the data of an entry is in the package.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub offset: u64,
+    pub len: u64,
+}
+
+fn padded(len: u64) -> u64 {
+    (len + BLOCK_LEN - 1) / BLOCK_LEN * BLOCK_LEN
+}
+
+async fn read_block<R: AsyncRead + Unpin>(r: &mut R) -> Result<Option<[u8; 512]>, Error> {
+    let mut block = [0; 512];
+    match r.read_exact(&mut block).await {
+        Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        a => a,
+    }?;
+    Ok(Some(block))
+}
+
+/// Finds every entry of a tar archive from its headers alone, seeking past the data in between,
+/// so that even a package with a large image is indexed in a few reads.
+pub async fn index<R: AsyncRead + AsyncSeek + Unpin>(
+    r: &mut R,
+) -> Result<LinearMap<String, Section>, Error> {
+    let mut res = LinearMap::new();
+    let mut offset = 0;
+    let mut long_name: Option<String> = None;
+    r.seek(SeekFrom::Start(0)).await?;
+    while let Some(block) = read_block(r).await? {
+        if block.iter().all(|b| *b == 0) {
+            break;
+        }
+        let mut header = tar::Header::new_old();
+        header.as_mut_bytes().copy_from_slice(&block);
+        let len = header.entry_size()?;
+        let data_offset = offset + BLOCK_LEN;
+        offset = data_offset + padded(len);
+        let entry_type = header.entry_type();
+        if entry_type.is_gnu_longname() {
+            let mut name = vec![0; len as usize];
+            r.read_exact(&mut name).await?;
+            let name = String::from_utf8_lossy(&name);
+            long_name = Some(name.trim_end_matches('\0').to_owned());
+        } else if !entry_type.is_pax_global_extensions()
+            && !entry_type.is_pax_local_extensions()
+            && !entry_type.is_gnu_longlink()
+        {
+            let path = match long_name.take() {
+                Some(name) => name,
+                None => format!("{}", header.path()?.display()),
+            };
+            res.insert(
+                path,
+                Section {
+                    offset: data_offset,
+                    len,
+                },
+            );
+        }
+        r.seek(SeekFrom::Start(offset)).await?;
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_index() {
+        let long_name = format!("assets/{}.txt", "a".repeat(120));
+        let mut out = tar::Builder::new(Vec::new());
+        for (name, data) in &[
+            ("manifest.cbor", "manifest".to_owned()),
+            (long_name.as_str(), "long".to_owned()),
+            ("image.tar", "x".repeat(1000)),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            out.append_data(&mut header, name, data.as_bytes())
+                .await
+                .unwrap();
+        }
+        let archive = out.into_inner().await.unwrap();
+        let sections = index(&mut std::io::Cursor::new(archive.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            sections.keys().collect::<Vec<_>>(),
+            vec!["manifest.cbor", long_name.as_str(), "image.tar"]
+        );
+        let image = sections.get("image.tar").unwrap();
+        assert_eq!(image.len, 1000);
+        assert_eq!(
+            &archive[image.offset as usize..(image.offset + image.len) as usize],
+            "x".repeat(1000).as_bytes()
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::SeekFrom;
+use std::path::Path;
+
+use failure::ResultExt as _;
+use futures::stream::StreamExt;
+use linear_map::LinearMap;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio_tar as tar;
+
+use super::{Section, CONFIG_RULES_CBOR, CONFIG_SPEC_CBOR, INSTRUCTIONS_MD, MANIFEST_CBOR};
+use crate::checksums::component;
+use crate::config::{ConfigRuleEntry, ConfigSpec};
+use crate::manifest::{Manifest, ManifestLatest};
+use crate::util::from_cbor_async_reader;
+use crate::Error;
+use crate::ResultExt as _;
+
+fn missing(path: &str) -> Error {
+    Error::new(
+        failure::format_err!(
+            "Package File Invalid or Corrupted: Missing {}",
+            component(path)
+        ),
+        Some(crate::error::GENERAL_ERROR),
+    )
+}
+
+/// The next entry of a package read front to back, as when installing from a download, which
+/// must be the one at `path`.
+pub async fn next_entry<R: AsyncRead + Unpin>(
+    entries: &mut tar::Entries<R>,
+    path: &str,
+) -> Result<tar::Entry<tar::Archive<R>>, Error> {
+    let entry = entries.next().await.ok_or_else(|| missing(path))??;
+    let entry_path = format!("{}", entry.path()?.display());
+    crate::ensure_code!(
+        entry_path == path,
+        crate::error::GENERAL_ERROR,
+        "Package File Invalid or Corrupted: Expected {}, Got {}",
+        component(path),
+        component(&entry_path)
+    );
+    Ok(entry)
+}
+
+/// Reads the entries of a package in any order, without going through the ones before them.
+pub struct S9pkReader {
+    file: tokio::fs::File,
+    sections: LinearMap<String, Section>,
+}
+impl S9pkReader {
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut file = tokio::fs::File::open(path)
+            .await
+            .with_context(|e| format!("{}: {}", path.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+        let sections = super::index(&mut file).await?;
+        Ok(S9pkReader { file, sections })
+    }
+
+    pub fn sections(&self) -> &LinearMap<String, Section> {
+        &self.sections
+    }
+
+    /// The data of the entry at `path`, if the package has one.
+    pub async fn read_section(
+        &mut self,
+        path: &str,
+    ) -> Result<Option<tokio::io::Take<&mut tokio::fs::File>>, Error> {
+        let section = match self.sections.get(path) {
+            Some(a) => *a,
+            None => return Ok(None),
+        };
+        self.file.seek(SeekFrom::Start(section.offset)).await?;
+        Ok(Some((&mut self.file).take(section.len)))
+    }
+
+    async fn section(
+        &mut self,
+        path: &str,
+    ) -> Result<tokio::io::Take<&mut tokio::fs::File>, Error> {
+        self.read_section(path).await?.ok_or_else(|| missing(path))
+    }
+
+    pub async fn manifest(&mut self) -> Result<ManifestLatest, Error> {
+        let manifest: Manifest = from_cbor_async_reader(self.section(MANIFEST_CBOR).await?).await?;
+        Ok(manifest.into_latest())
+    }
+
+    pub async fn config_spec(&mut self) -> Result<ConfigSpec, Error> {
+        from_cbor_async_reader(self.section(CONFIG_SPEC_CBOR).await?).await
+    }
+
+    pub async fn config_rules(&mut self) -> Result<Vec<ConfigRuleEntry>, Error> {
+        from_cbor_async_reader(self.section(CONFIG_RULES_CBOR).await?).await
+    }
+
+    /// Instructions as written, in the format the manifest declares.
+    pub async fn instructions(&mut self) -> Result<Vec<u8>, Error> {
+        let mut res = Vec::new();
+        self.section(INSTRUCTIONS_MD)
+            .await?
+            .read_to_end(&mut res)
+            .await
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+        Ok(res)
+    }
+
+    /// The image tarball to load on `arch`.
+    pub async fn image(
+        &mut self,
+        arch: &str,
+    ) -> Result<tokio::io::Take<&mut tokio::fs::File>, Error> {
+        let manifest = self.manifest().await?;
+        let tar_name = manifest
+            .image
+            .tar_for(arch)
+            .ok_or_else(|| failure::format_err!("{} Has No Image For {}", manifest.id, arch))
+            .with_code(crate::error::VERSION_INCOMPATIBLE)?;
+        self.section(&tar_name).await
+    }
+}
@@ -0,0 +1,226 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use linear_map::LinearMap;
+
+use crate::apps::DockerStatus;
+use crate::dependencies::TaggedDependencyError;
+use crate::util::{PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const SCHEDULE_YAML: &'static str = "schedule.yaml";
+
+/// A time of day in the device's local time, as minutes since midnight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimeOfDay(u16);
+impl TimeOfDay {
+    pub fn now() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as libc::time_t)
+            .unwrap_or(0);
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe { libc::localtime_r(&secs, &mut tm) };
+        TimeOfDay((tm.tm_hour * 60 + tm.tm_min) as u16)
+    }
+}
+impl std::fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02}", self.0 / 60, self.0 % 60)
+    }
+}
+impl std::str::FromStr for TimeOfDay {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut split = s.splitn(2, ':');
+        let hours: u16 = split.next().unwrap().parse().no_code()?;
+        let minutes: u16 = split.next().unwrap_or("0").parse().no_code()?;
+        crate::ensure_code!(
+            hours < 24 && minutes < 60,
+            crate::error::GENERAL_ERROR,
+            "Invalid Time: {}",
+            s
+        );
+        Ok(TimeOfDay(hours * 60 + minutes))
+    }
+}
+impl<'de> serde::de::Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s: String = serde::de::Deserialize::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|e: Error| serde::de::Error::custom(e.failure))
+    }
+}
+impl serde::ser::Serialize for TimeOfDay {
+    fn serialize<S: serde::ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// The hours during which an app should be running. Windows may wrap past midnight.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Schedule {
+    pub start: TimeOfDay,
+    pub stop: TimeOfDay,
+    /// Whether the window was open the last time the schedule was enforced. The schedule only
+    /// acts when this changes, so starting or stopping the app by hand lasts until the next
+    /// window boundary.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open: Option<bool>,
+}
+impl Schedule {
+    pub fn is_open(&self, at: TimeOfDay) -> bool {
+        if self.start <= self.stop {
+            self.start <= at && at < self.stop
+        } else {
+            at >= self.start || at < self.stop
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EnforceRes {
+    pub started: Vec<String>,
+    pub stopped: Vec<String>,
+    pub stopped_dependents: LinearMap<String, TaggedDependencyError>,
+}
+
+pub async fn list() -> Result<LinearMap<String, Schedule>, Error> {
+    let path = PersistencePath::from_ref(SCHEDULE_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await,
+        None => Ok(LinearMap::new()),
+    }
+}
+
+async fn schedules_mut() -> Result<YamlUpdateHandle<LinearMap<String, Schedule>>, Error> {
+    YamlUpdateHandle::new_or_default(PersistencePath::from_ref(SCHEDULE_YAML)).await
+}
+
+pub async fn set(id: &str, start: TimeOfDay, stop: TimeOfDay) -> Result<(), Error> {
+    crate::ensure_code!(
+        crate::apps::list_info().await?.contains_key(id),
+        crate::error::NOT_FOUND,
+        "App Not Installed: {}",
+        id
+    );
+    crate::ensure_code!(
+        start != stop,
+        crate::error::GENERAL_ERROR,
+        "Start and stop times must differ"
+    );
+    let mut schedules = schedules_mut().await?;
+    schedules.insert(
+        id.to_owned(),
+        Schedule {
+            start,
+            stop,
+            open: None,
+        },
+    );
+    schedules.commit().await
+}
+
+pub async fn remove(id: &str) -> Result<(), Error> {
+    let mut schedules = schedules_mut().await?;
+    crate::ensure_code!(
+        schedules.remove(id).is_some(),
+        crate::error::NOT_FOUND,
+        "{} has no schedule",
+        id
+    );
+    schedules.commit().await
+}
+
+/// Drops the schedule of an app being uninstalled, if it has one.
+pub async fn forget(id: &str) -> Result<(), Error> {
+    let mut schedules = schedules_mut().await?;
+    if schedules.remove(id).is_some() {
+        schedules.commit().await?;
+    }
+    Ok(())
+}
+
+/// Starts apps whose window just opened and stops apps whose window just closed, along with their
+/// dependents. An app whose required dependencies are not running is not started, and is retried
+/// on the next run. Meant to be run every minute.
+pub async fn enforce() -> Result<EnforceRes, Error> {
+    let now = TimeOfDay::now();
+    let installed = crate::apps::list_info().await?;
+    let mut schedules = schedules_mut().await?;
+    let mut res = EnforceRes::default();
+    for (id, schedule) in schedules.iter_mut() {
+        if !installed.contains_key(id) {
+            continue;
+        }
+        let open = schedule.is_open(now);
+        if schedule.open == Some(open) {
+            continue;
+        }
+        if open {
+            let mut missing = Vec::new();
+            for (dep_id, dep_info) in crate::apps::manifest(id).await?.dependencies.0 {
+                if dep_info.optional.is_none()
+                    && (!installed.contains_key(&dep_id)
+                        || crate::apps::status(&dep_id, false).await?.status
+                            != DockerStatus::Running)
+                {
+                    missing.push(dep_id);
+                }
+            }
+            if !missing.is_empty() {
+                log::warn!(
+                    "Not starting {}, dependencies not running: {}",
+                    id,
+                    missing.join(", ")
+                );
+                continue;
+            }
+            log::info!("Starting {} for its scheduled window.", id);
+            crate::control::start_app(id, true).await?;
+            res.started.push(id.clone());
+        } else {
+            log::info!("Stopping {} at the end of its scheduled window.", id);
+            res.stopped_dependents
+                .extend(crate::control::stop_app(id, true, false).await?);
+            res.stopped.push(id.clone());
+        }
+        schedule.open = Some(open);
+    }
+    schedules.retain(|id, _| installed.contains_key(id));
+    schedules.commit().await?;
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        let t = |s: &str| s.parse::<TimeOfDay>().unwrap();
+        let day = Schedule {
+            start: t("09:00"),
+            stop: t("17:30"),
+            open: None,
+        };
+        assert!(day.is_open(t("09:00")));
+        assert!(day.is_open(t("17:29")));
+        assert!(!day.is_open(t("17:30")));
+        assert!(!day.is_open(t("3")));
+        let night = Schedule {
+            start: t("22:00"),
+            stop: t("6:00"),
+            open: None,
+        };
+        assert!(night.is_open(t("23:59")));
+        assert!(night.is_open(t("0:00")));
+        assert!(!night.is_open(t("6:00")));
+        assert!(!night.is_open(t("12:00")));
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert_eq!(t("7:05").to_string(), "07:05");
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use failure::ResultExt as _;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use crate::logs::{Level, Notification};
+use crate::util::{Invoke, PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const SECURITY_YAML: &'static str = "security.yaml";
+pub const AUTH_LOG: &'static str = "/var/log/auth.log";
+pub const AUTHORIZED_KEYS: &'static str = "/root/.ssh/authorized_keys";
+pub const AUTH_FAILURE_THRESHOLD: usize = 5;
+
+pub const AUTH_FAILURES_CODE: usize = 1001;
+pub const UNEXPECTED_PORT_CODE: usize = 1002;
+pub const AUTHORIZED_KEYS_CODE: usize = 1003;
+
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuditState {
+    #[serde(default)]
+    pub auth_log_offset: u64,
+    #[serde(default)]
+    pub authorized_keys_hash: Option<String>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn alert(code: usize, title: &str, message: String) -> Notification {
+    Notification {
+        time: now(),
+        level: Level::Error,
+        code,
+        title: title.to_owned(),
+        message,
+    }
+}
+
+// records the notification in the event log, where the UI can show it, as well as returning it
+async fn raise(res: &mut Vec<Notification>, app: Option<&str>, notification: Notification) {
+    crate::events::emit_alert(app, &notification).await;
+    res.push(notification);
+}
+
+/// Counts failed login attempts per source address in the part of the auth log written since
+/// the last audit.
+async fn auth_failures(state: &mut AuditState) -> Result<BTreeMap<IpAddr, usize>, Error> {
+    let mut res = BTreeMap::new();
+    let mut f = match tokio::fs::File::open(AUTH_LOG).await {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(res),
+        Err(e) => {
+            return Err(e)
+                .with_context(|e| format!("{}: {}", AUTH_LOG, e))
+                .with_code(crate::error::FILESYSTEM_ERROR)
+        }
+    };
+    let len = f.metadata().await?.len();
+    if len < state.auth_log_offset {
+        // log was rotated
+        state.auth_log_offset = 0;
+    }
+    f.seek(std::io::SeekFrom::Start(state.auth_log_offset))
+        .await?;
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf).await?;
+    state.auth_log_offset += buf.len() as u64;
+    for line in String::from_utf8_lossy(&buf).lines() {
+        if !(line.contains("Failed password")
+            || line.contains("Invalid user")
+            || line.contains("authentication failure"))
+        {
+            continue;
+        }
+        let ip = line
+            .split_whitespace()
+            .skip_while(|w| *w != "from" && !w.starts_with("rhost="))
+            .find_map(|w| w.trim_start_matches("rhost=").parse::<IpAddr>().ok());
+        if let Some(ip) = ip {
+            *res.entry(ip).or_insert(0) += 1;
+        }
+    }
+    Ok(res)
+}
+
+/// Reads the TCP ports a container is listening on from its network namespace.
+async fn listening_ports(app_id: &str) -> Result<BTreeSet<u16>, Error> {
+    let pid = tokio::process::Command::new("docker")
+        .args(&["inspect", app_id, "--format", "{{.State.Pid}}"])
+        .invoke("Docker Inspect")
+        .await
+        .with_code(crate::error::DOCKER_ERROR)?;
+    let pid = std::str::from_utf8(&pid).no_code()?.trim().to_owned();
+    let mut res = BTreeSet::new();
+    for table in &["tcp", "tcp6"] {
+        let path = Path::new("/proc").join(&pid).join("net").join(table);
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(a) => a,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|e| format!("{}: {}", path.display(), e))
+                    .with_code(crate::error::FILESYSTEM_ERROR)
+            }
+        };
+        for line in contents.lines().skip(1) {
+            let mut cols = line.split_whitespace();
+            let local = cols.nth(1);
+            let state = cols.nth(1);
+            if state != Some("0A") {
+                continue;
+            }
+            if let Some(port) = local
+                .and_then(|l| l.rsplit(":").next())
+                .and_then(|p| u16::from_str_radix(p, 16).ok())
+            {
+                res.insert(port);
+            }
+        }
+    }
+    Ok(res)
+}
+
+async fn authorized_keys_hash() -> Result<Option<String>, Error> {
+    match tokio::fs::read(AUTHORIZED_KEYS).await {
+        Ok(contents) => Ok(Some(
+            openssl::sha::sha256(&contents)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e)
+            .with_context(|e| format!("{}: {}", AUTHORIZED_KEYS, e))
+            .with_code(crate::error::FILESYSTEM_ERROR),
+    }
+}
+
+/// Checks the device for signs of intrusion since the last audit, optionally blocking LAN hosts
+/// with repeated authentication failures.
+pub async fn audit(block: bool) -> Result<Vec<Notification>, Error> {
+    let mut state: YamlUpdateHandle<AuditState> =
+        YamlUpdateHandle::new_or_default(PersistencePath::from_ref(SECURITY_YAML)).await?;
+    let mut res = Vec::new();
+
+    for (ip, count) in auth_failures(&mut *state).await? {
+        if count < AUTH_FAILURE_THRESHOLD {
+            continue;
+        }
+        let is_lan = match ip {
+            IpAddr::V4(ip) => ip.is_private() || ip.is_link_local(),
+            IpAddr::V6(_) => false,
+        };
+        let mut message = format!("{} failed login attempts from {}.", count, ip);
+        if block && is_lan {
+            crate::firewall::block_source(ip).await?;
+            message += " The address has been blocked.";
+        }
+        raise(
+            &mut res,
+            None,
+            alert(AUTH_FAILURES_CODE, "Repeated Login Failures", message),
+        )
+        .await;
+    }
+
+    for (app_id, _) in crate::apps::list_info().await? {
+        if crate::apps::status(&app_id, false).await?.status != crate::apps::DockerStatus::Running {
+            continue;
+        }
+        // a static app shares the network namespace of the host, so its ports cannot be told
+        // apart from everything else listening
+        if crate::backend::Backend::of_app(&app_id) == crate::backend::Backend::Systemd {
+            continue;
+        }
+        let manifest = crate::apps::manifest(&app_id).await?;
+        let expected: BTreeSet<u16> = manifest.ports.iter().map(|p| p.internal).collect();
+        let unexpected: Vec<String> = listening_ports(&app_id)
+            .await?
+            .difference(&expected)
+            .map(|p| format!("{}", p))
+            .collect();
+        if !unexpected.is_empty() {
+            raise(
+                &mut res,
+                Some(&app_id),
+                alert(
+                    UNEXPECTED_PORT_CODE,
+                    "Unexpected Listening Port",
+                    format!(
+                        "{} is listening on undeclared port(s): {}.",
+                        app_id,
+                        unexpected.join(", ")
+                    ),
+                ),
+            )
+            .await;
+        }
+    }
+
+    let hash = authorized_keys_hash().await?;
+    if state.authorized_keys_hash.is_some() && state.authorized_keys_hash != hash {
+        raise(
+            &mut res,
+            None,
+            alert(
+                AUTHORIZED_KEYS_CODE,
+                "Authorized SSH Keys Changed",
+                format!("{} was modified since the last audit.", AUTHORIZED_KEYS),
+            ),
+        )
+        .await;
+    }
+    state.authorized_keys_hash = hash;
+
+    state.commit().await?;
+    Ok(res)
+}
@@ -0,0 +1,229 @@
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use failure::ResultExt as _;
+use linear_map::LinearMap;
+use tokio::io::AsyncWriteExt;
+
+use crate::util::{Invoke, PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const SHARES_YAML: &'static str = "shares.yaml";
+pub const SHARES_DIR: &'static str = "/root/shares";
+pub const ETC_NGINX_SHARES_CONF: &'static str = "/etc/nginx/sites-available/start9-shares.conf";
+pub const ETC_NGINX_SHARES_ENABLED: &'static str = "/etc/nginx/sites-enabled/start9-shares.conf";
+pub const SHARE_PORT_BASE: u16 = 8800;
+
+/// A directory of static files served directly by nginx over Tor and LAN, without a package.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Share {
+    pub path: PathBuf,
+    pub port: u16,
+    pub tor_address: Option<String>,
+}
+
+fn hidden_service_dir(name: &str) -> String {
+    format!("share-{}", name)
+}
+
+pub async fn list() -> Result<LinearMap<String, Share>, Error> {
+    let path = PersistencePath::from_ref(SHARES_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await,
+        None => Ok(LinearMap::new()),
+    }
+}
+
+async fn shares_mut() -> Result<YamlUpdateHandle<LinearMap<String, Share>>, Error> {
+    YamlUpdateHandle::new_or_default(PersistencePath::from_ref(SHARES_YAML)).await
+}
+
+/// Appends the hidden services for all shares to the torrc being written by
+/// `tor::write_services`.
+pub async fn write_tor_services(f: &mut tokio::fs::File) -> Result<(), Error> {
+    for (name, share) in list().await? {
+        f.write_all(b"\n").await?;
+        f.write_all(format!("# HIDDEN SERVICE FOR SHARE {}\n", name).as_bytes())
+            .await?;
+        f.write_all(
+            format!(
+                "HiddenServiceDir {}/{}/\n",
+                crate::tor::HIDDEN_SERVICE_DIR_ROOT,
+                hidden_service_dir(&name)
+            )
+            .as_bytes(),
+        )
+        .await?;
+        f.write_all(format!("{}\n", crate::tor::HiddenServiceVersion::V3).as_bytes())
+            .await?;
+        f.write_all(format!("HiddenServicePort 80 127.0.0.1:{}\n", share.port).as_bytes())
+            .await?;
+        f.write_all(b"\n").await?;
+    }
+    Ok(())
+}
+
+async fn write_nginx_conf(shares: &LinearMap<String, Share>) -> Result<(), Error> {
+    let mut f = tokio::fs::File::create(ETC_NGINX_SHARES_CONF)
+        .await
+        .with_context(|e| format!("{}: {}", ETC_NGINX_SHARES_CONF, e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    for (name, share) in shares {
+        let hostname = match share
+            .tor_address
+            .as_ref()
+            .and_then(|a| a.strip_suffix(".onion"))
+        {
+            Some(a) => a,
+            None => {
+                log::warn!("Share {} has no Tor address, skipping.", name);
+                continue;
+            }
+        };
+        f.write_all(
+            format!(
+                include_str!("nginx-share.conf.template"),
+                hostname = hostname,
+                port = share.port,
+                root = share.path.display(),
+            )
+            .as_bytes(),
+        )
+        .await?;
+    }
+    f.sync_all().await?;
+    tokio::fs::os::unix::symlink(ETC_NGINX_SHARES_CONF, ETC_NGINX_SHARES_ENABLED)
+        .await
+        .or_else(|e| {
+            if e.kind() == std::io::ErrorKind::AlreadyExists {
+                Ok(())
+            } else {
+                Err(e)
+            }
+        })?;
+    log::info!("Reloading Nginx.");
+    let svc_exit = std::process::Command::new("service")
+        .args(&["nginx", "reload"])
+        .status()?;
+    crate::ensure_code!(
+        svc_exit.success(),
+        crate::error::GENERAL_ERROR,
+        "Failed to Reload Nginx: {}",
+        svc_exit
+            .code()
+            .or_else(|| { svc_exit.signal().map(|a| 128 + a) })
+            .unwrap_or(0)
+    );
+    Ok(())
+}
+
+/// Copies the contents of `src` into the share, overwriting files with the same name.
+pub async fn upload(name: &str, src: &Path) -> Result<(), Error> {
+    let share = list()
+        .await?
+        .remove(name)
+        .ok_or_else(|| failure::format_err!("Share Not Found: {}", name))
+        .with_code(crate::error::NOT_FOUND)?;
+    crate::ensure_code!(
+        src.is_dir(),
+        crate::error::FILESYSTEM_ERROR,
+        "Not A Directory: {}",
+        src.display()
+    );
+    log::info!("Copying {} to share {}.", src.display(), name);
+    tokio::process::Command::new("cp")
+        .arg("-r")
+        .arg(src.join("."))
+        .arg(&share.path)
+        .invoke("Copy Files")
+        .await
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(())
+}
+
+pub async fn add(name: &str, src: Option<&Path>) -> Result<Share, Error> {
+    crate::ensure_code!(
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
+        crate::error::GENERAL_ERROR,
+        "Invalid Share Name: {}",
+        name
+    );
+    crate::ensure_code!(
+        !crate::apps::list_info().await?.contains_key(name),
+        crate::error::GENERAL_ERROR,
+        "Share Name Conflicts With Installed App: {}",
+        name
+    );
+    let mut shares = shares_mut().await?;
+    crate::ensure_code!(
+        !shares.contains_key(name),
+        crate::error::GENERAL_ERROR,
+        "Share Already Exists: {}",
+        name
+    );
+    let port = (SHARE_PORT_BASE..)
+        .find(|p| shares.values().all(|s| s.port != *p))
+        .unwrap();
+    let path = Path::new(SHARES_DIR).join(name);
+    tokio::fs::create_dir_all(&path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    shares.insert(
+        name.to_owned(),
+        Share {
+            path,
+            port,
+            tor_address: None,
+        },
+    );
+    shares.commit().await?;
+    if let Some(src) = src {
+        upload(name, src).await?;
+    }
+    crate::tor::reload().await?;
+    let tor_address = crate::tor::read_hidden_service_hostname(
+        &hidden_service_dir(name),
+        Some(Duration::from_secs(30)),
+    )
+    .await?;
+    let mut shares = shares_mut().await?;
+    let share = shares.get_mut(name).unwrap();
+    share.tor_address = Some(tor_address);
+    let res = share.clone();
+    write_nginx_conf(&*shares).await?;
+    shares.commit().await?;
+    Ok(res)
+}
+
+pub async fn remove(name: &str) -> Result<(), Error> {
+    let mut shares = shares_mut().await?;
+    let share = shares
+        .remove(name)
+        .ok_or_else(|| failure::format_err!("Share Not Found: {}", name))
+        .with_code(crate::error::NOT_FOUND)?;
+    write_nginx_conf(&*shares).await?;
+    shares.commit().await?;
+    crate::tor::reload().await?;
+    let hidden_service_path =
+        Path::new(crate::tor::HIDDEN_SERVICE_DIR_ROOT).join(hidden_service_dir(name));
+    if hidden_service_path.exists() {
+        tokio::fs::remove_dir_all(&hidden_service_path)
+            .await
+            .with_context(|e| format!("{}: {}", hidden_service_path.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+    }
+    if share.path.exists() {
+        tokio::fs::remove_dir_all(&share.path)
+            .await
+            .with_context(|e| format!("{}: {}", share.path.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+    }
+    Ok(())
+}
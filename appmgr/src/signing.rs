@@ -0,0 +1,292 @@
+use std::convert::TryFrom;
+use std::io::SeekFrom;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use ed25519_dalek::{Signer as _, Verifier as _};
+use failure::ResultExt as _;
+use linear_map::LinearMap;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::util::{PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const TRUSTED_KEYS_YAML: &'static str = "trusted_keys.yaml";
+/// Ends the trailer appended to a signed package, after the end of its tar archive, where tar
+/// readers never look.
+pub const MAGIC: &'static [u8; 8] = b"s9pksig1";
+const TRAILER_LEN: u64 =
+    (ed25519_dalek::SIGNATURE_LENGTH + ed25519_dalek::PUBLIC_KEY_LENGTH + MAGIC.len()) as u64;
+
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SignatureInfo {
+    pub public_key: String,
+    /// The name the key is trusted under, if it is trusted.
+    pub trusted_as: Option<String>,
+}
+
+pub fn encode_key(key: &ed25519_dalek::PublicKey) -> String {
+    openssl::base64::encode_block(key.as_bytes())
+}
+
+pub fn decode_key(key: &str) -> Result<ed25519_dalek::PublicKey, Error> {
+    let bytes = openssl::base64::decode_block(key.trim())
+        .with_context(|e| format!("Invalid Public Key: {}", e))
+        .no_code()?;
+    Ok(ed25519_dalek::PublicKey::from_bytes(&bytes)
+        .with_context(|e| format!("Invalid Public Key: {}", e))
+        .no_code()?)
+}
+
+/// Creates a new developer key at `path`, and returns its public key.
+pub async fn generate_key<P: AsRef<Path>>(path: P) -> Result<String, Error> {
+    let path = path.as_ref();
+    crate::ensure_code!(
+        !path.exists(),
+        crate::error::FILESYSTEM_ERROR,
+        "{} Already Exists",
+        path.display()
+    );
+    let secret = ed25519_dalek::SecretKey::generate(&mut rand::rngs::OsRng);
+    let mut encoded = openssl::base64::encode_block(secret.as_bytes());
+    encoded.push('\n');
+    let mut options = std::fs::OpenOptions::new();
+    // the secret key is readable by its owner only
+    options.write(true).create_new(true).mode(0o600);
+    let mut f = tokio::fs::OpenOptions::from(options)
+        .open(path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    f.write_all(encoded.as_bytes()).await?;
+    f.flush().await?;
+    Ok(encode_key(&ed25519_dalek::PublicKey::from(&secret)))
+}
+
+async fn read_key(path: &Path) -> Result<ed25519_dalek::Keypair, Error> {
+    let encoded = tokio::fs::read_to_string(path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let bytes = openssl::base64::decode_block(encoded.trim())
+        .with_context(|e| format!("{}: Invalid Key: {}", path.display(), e))
+        .no_code()?;
+    let secret = ed25519_dalek::SecretKey::from_bytes(&bytes)
+        .with_context(|e| format!("{}: Invalid Key: {}", path.display(), e))
+        .no_code()?;
+    Ok(ed25519_dalek::Keypair {
+        public: ed25519_dalek::PublicKey::from(&secret),
+        secret,
+    })
+}
+
+// packages are too large to sign whole, so their sha256 is signed instead
+async fn digest(f: &mut tokio::fs::File, len: u64) -> Result<[u8; 32], Error> {
+    f.seek(SeekFrom::Start(0)).await?;
+    let mut hasher = openssl::sha::Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = std::cmp::min(remaining, buf.len() as u64) as usize;
+        f.read_exact(&mut buf[..n]).await?;
+        hasher.update(&buf[..n]);
+        remaining -= n as u64;
+    }
+    Ok(hasher.finish())
+}
+
+/// Signs the package at `path` with the developer key at `key_path`.
+pub async fn sign<P: AsRef<Path>, K: AsRef<Path>>(path: P, key_path: K) -> Result<String, Error> {
+    let path = path.as_ref();
+    let keypair = read_key(key_path.as_ref()).await?;
+    crate::ensure_code!(
+        read_trailer(path).await?.is_none(),
+        crate::error::GENERAL_ERROR,
+        "{} Is Already Signed",
+        path.display()
+    );
+    let mut f = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let len = f.metadata().await?.len();
+    let signature = keypair.sign(&digest(&mut f, len).await?);
+    f.seek(SeekFrom::End(0)).await?;
+    f.write_all(&signature.to_bytes()).await?;
+    f.write_all(keypair.public.as_bytes()).await?;
+    f.write_all(MAGIC).await?;
+    f.flush().await?;
+    Ok(encode_key(&keypair.public))
+}
+
+struct Trailer {
+    signed_len: u64,
+    signature: ed25519_dalek::Signature,
+    public_key: ed25519_dalek::PublicKey,
+}
+
+async fn read_trailer(path: &Path) -> Result<Option<Trailer>, Error> {
+    let mut f = tokio::fs::File::open(path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let len = f.metadata().await?.len();
+    if len < TRAILER_LEN {
+        return Ok(None);
+    }
+    f.seek(SeekFrom::Start(len - TRAILER_LEN)).await?;
+    let mut buf = vec![0; TRAILER_LEN as usize];
+    f.read_exact(&mut buf).await?;
+    let (signature, rest) = buf.split_at(ed25519_dalek::SIGNATURE_LENGTH);
+    let (public_key, magic) = rest.split_at(ed25519_dalek::PUBLIC_KEY_LENGTH);
+    if magic != MAGIC {
+        return Ok(None);
+    }
+    Ok(Some(Trailer {
+        signed_len: len - TRAILER_LEN,
+        signature: ed25519_dalek::Signature::try_from(signature)
+            .with_context(|e| format!("Invalid Package Signature: {}", e))
+            .with_code(crate::error::SIGNATURE_ERROR)?,
+        public_key: ed25519_dalek::PublicKey::from_bytes(public_key)
+            .with_context(|e| format!("Invalid Package Signing Key: {}", e))
+            .with_code(crate::error::SIGNATURE_ERROR)?,
+    }))
+}
+
+/// The key the package at `path` is signed with, or `None` if it is unsigned. An error if the
+/// signature does not match its contents.
+pub async fn signer<P: AsRef<Path>>(path: P) -> Result<Option<ed25519_dalek::PublicKey>, Error> {
+    let path = path.as_ref();
+    let trailer = match read_trailer(path).await? {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+    let mut f = tokio::fs::File::open(path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let digest = digest(&mut f, trailer.signed_len).await?;
+    trailer
+        .public_key
+        .verify(&digest, &trailer.signature)
+        .with_context(|_| format!("{}: Package Signature Does Not Match", path.display()))
+        .with_code(crate::error::SIGNATURE_ERROR)?;
+    Ok(Some(trailer.public_key))
+}
+
+/// Checks the signature of the package at `path`, and whether its key is trusted.
+pub async fn check<P: AsRef<Path>>(path: P) -> Result<Option<SignatureInfo>, Error> {
+    let public_key = match signer(path).await? {
+        Some(key) => encode_key(&key),
+        None => return Ok(None),
+    };
+    let trusted_as = list()
+        .await?
+        .into_iter()
+        .find(|(_, key)| key == &public_key)
+        .map(|(name, _)| name);
+    Ok(Some(SignatureInfo {
+        public_key,
+        trusted_as,
+    }))
+}
+
+/// Requires the package at `path` to be signed by a trusted key, and returns the key's name.
+pub async fn verify_trusted<P: AsRef<Path>>(path: P) -> Result<String, Error> {
+    let path = path.as_ref();
+    match check(path).await? {
+        Some(SignatureInfo {
+            trusted_as: Some(name),
+            ..
+        }) => {
+            log::info!("{} is signed by {}.", path.display(), name);
+            Ok(name)
+        }
+        Some(SignatureInfo { public_key, .. }) => Err(Error::new(
+            failure::format_err!("{}: Signed By Untrusted Key {}", path.display(), public_key),
+            Some(crate::error::SIGNATURE_ERROR),
+        )),
+        None => Err(Error::new(
+            failure::format_err!("{}: Package Is Not Signed", path.display()),
+            Some(crate::error::SIGNATURE_ERROR),
+        )),
+    }
+}
+
+async fn trusted_mut() -> Result<YamlUpdateHandle<LinearMap<String, String>>, Error> {
+    YamlUpdateHandle::new_or_default(PersistencePath::from_ref(TRUSTED_KEYS_YAML)).await
+}
+
+/// The trusted public keys, by name.
+pub async fn list() -> Result<LinearMap<String, String>, Error> {
+    let path = PersistencePath::from_ref(TRUSTED_KEYS_YAML);
+    Ok(match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await?,
+        None => LinearMap::new(),
+    })
+}
+
+pub async fn add(name: &str, key: &str) -> Result<(), Error> {
+    let key = encode_key(&decode_key(key)?);
+    let mut trusted = trusted_mut().await?;
+    if let Some((existing, _)) = trusted.iter().find(|(n, k)| *k == &key && *n != name) {
+        return Err(Error::new(
+            failure::format_err!("Key Already Trusted As {}", existing),
+            Some(crate::error::GENERAL_ERROR),
+        ));
+    }
+    trusted.insert(name.to_owned(), key);
+    trusted.commit().await?;
+    Ok(())
+}
+
+pub async fn remove(name: &str) -> Result<(), Error> {
+    let mut trusted = trusted_mut().await?;
+    trusted
+        .remove(name)
+        .ok_or_else(|| failure::format_err!("No Trusted Key Named {}", name))
+        .with_code(crate::error::NOT_FOUND)?;
+    trusted.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sign() {
+        let dir = std::env::temp_dir().join(format!("appmgr-test-sign-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let key_path = dir.join("developer.key");
+        let pkg_path = dir.join("app.s9pk");
+        tokio::fs::write(&pkg_path, b"not really a tar")
+            .await
+            .unwrap();
+        assert!(signer(&pkg_path).await.unwrap().is_none());
+        let public_key = generate_key(&key_path).await.unwrap();
+        assert_eq!(sign(&pkg_path, &key_path).await.unwrap(), public_key);
+        assert!(sign(&pkg_path, &key_path).await.is_err());
+        assert_eq!(
+            signer(&pkg_path).await.unwrap().map(|key| encode_key(&key)),
+            Some(public_key)
+        );
+        let mut f = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&pkg_path)
+            .await
+            .unwrap();
+        f.write_all(b"NOT").await.unwrap();
+        drop(f);
+        assert_eq!(
+            signer(&pkg_path).await.unwrap_err().code,
+            Some(crate::error::SIGNATURE_ERROR)
+        );
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
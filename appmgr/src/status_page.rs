@@ -0,0 +1,214 @@
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use failure::ResultExt as _;
+use linear_map::LinearMap;
+
+use crate::apps::DockerStatus;
+use crate::util::{Invoke, PersistencePath, YamlUpdateHandle};
+use crate::Error;
+use crate::ResultExt as _;
+
+pub const STATUS_PAGE_YAML: &'static str = "status-page.yaml";
+/// Where the page is published. The web server serves this directory without authentication, so
+/// nothing but the whitelisted fields may ever be written here.
+pub const STATUS_PAGE_DIR: &'static str = "/var/www/status";
+
+pub const STATUS_FIELD: &'static str = "status";
+pub const UPTIME_FIELD: &'static str = "uptime";
+pub const VERSION_FIELD: &'static str = "version";
+
+/// The apps shown on the public status page, and the fields shown for each: `status`, `uptime`,
+/// `version`, or the name of one of the app's properties, i.e. `Sync Progress`.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StatusPageConfig {
+    #[serde(default)]
+    pub apps: LinearMap<String, Vec<String>>,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AppEntry {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<DockerStatus>,
+    /// Seconds since the app was started, if it is running.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<emver::Version>,
+    #[serde(skip_serializing_if = "LinearMap::is_empty")]
+    pub properties: LinearMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StatusPage {
+    pub generated_at: u64,
+    pub apps: LinearMap<String, AppEntry>,
+}
+
+pub async fn get() -> Result<StatusPageConfig, Error> {
+    let path = PersistencePath::from_ref(STATUS_PAGE_YAML);
+    match path.maybe_read(false).await.transpose()? {
+        Some(mut f) => crate::util::from_yaml_async_reader(&mut *f).await,
+        None => Ok(StatusPageConfig::default()),
+    }
+}
+
+pub async fn allow(id: &str, fields: Vec<String>) -> Result<(), Error> {
+    crate::apps::manifest(id).await?;
+    crate::ensure_code!(
+        !fields.is_empty(),
+        crate::error::GENERAL_ERROR,
+        "At Least One Field Is Required"
+    );
+    let mut config: YamlUpdateHandle<StatusPageConfig> =
+        YamlUpdateHandle::new_or_default(PersistencePath::from_ref(STATUS_PAGE_YAML)).await?;
+    config.apps.insert(id.to_owned(), fields);
+    config.commit().await
+}
+
+pub async fn deny(id: &str) -> Result<(), Error> {
+    let mut config: YamlUpdateHandle<StatusPageConfig> =
+        YamlUpdateHandle::new_or_default(PersistencePath::from_ref(STATUS_PAGE_YAML)).await?;
+    config.apps.remove(id);
+    config.commit().await
+}
+
+// days since the epoch of a date in the proleptic gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// parses the UTC timestamps docker reports, i.e. 2021-03-04T12:34:56.123456789Z
+fn parse_docker_time(s: &str) -> Option<u64> {
+    let s = s.trim().strip_suffix('Z')?;
+    let (date, time) = s.split_at(s.find('T')?);
+    let mut date = date.split('-').map(|a| a.parse::<i64>());
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let time = time[1..].split('.').next()?;
+    let mut time = time.split(':').map(|a| a.parse::<i64>());
+    let (hour, min, sec) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + min * 60 + sec;
+    if secs < 0 {
+        None
+    } else {
+        Some(secs as u64)
+    }
+}
+
+async fn started_at(id: &str) -> Result<Option<u64>, Error> {
+    // systemd only reports it in local time, so static apps have no uptime
+    if crate::backend::Backend::of_app(id) == crate::backend::Backend::Systemd {
+        return Ok(None);
+    }
+    let output = tokio::process::Command::new("docker")
+        .args(&["inspect", id, "--format", "{{.State.StartedAt}}"])
+        .invoke("Docker")
+        .await?;
+    Ok(parse_docker_time(std::str::from_utf8(&output).no_code()?))
+}
+
+async fn entry(id: &str, fields: &[String], now: u64) -> Result<AppEntry, Error> {
+    let manifest = crate::apps::manifest(id).await?;
+    let mut res = AppEntry {
+        title: manifest.title,
+        ..Default::default()
+    };
+    let status = crate::apps::status(id, false).await?.status;
+    let mut properties: Option<LinearMap<String, String>> = None;
+    for field in fields {
+        match field.as_str() {
+            STATUS_FIELD => res.status = Some(status),
+            UPTIME_FIELD if status == DockerStatus::Running => {
+                res.uptime = started_at(id).await?.map(|t| now.saturating_sub(t))
+            }
+            UPTIME_FIELD => (),
+            VERSION_FIELD => res.version = Some(manifest.version.clone()),
+            name => {
+                if properties.is_none() {
+                    // masked properties come out masked, never revealed
+                    properties = Some(match crate::logs::stats(id).await {
+                        Ok(mut props) => {
+                            props.redact();
+                            props.rows()?.into_iter().collect()
+                        }
+                        Err(e) => {
+                            log::warn!("Could not read properties of {}: {}", id, e.failure);
+                            LinearMap::new()
+                        }
+                    });
+                }
+                if let Some(value) = properties.as_ref().and_then(|p| p.get(name)) {
+                    res.properties.insert(name.to_owned(), value.clone());
+                }
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Builds the status page from the whitelist. Apps that are no longer installed are left out.
+pub async fn render() -> Result<StatusPage, Error> {
+    let config = get().await?;
+    let installed = crate::apps::list_info().await?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut res = StatusPage {
+        generated_at: now,
+        apps: LinearMap::new(),
+    };
+    for (id, fields) in config.apps {
+        if installed.get(&id).is_none() {
+            continue;
+        }
+        res.apps.insert(id.clone(), entry(&id, &fields, now).await?);
+    }
+    Ok(res)
+}
+
+/// Renders the status page and writes it to `STATUS_PAGE_DIR`. Meant to be run periodically.
+pub async fn publish() -> Result<StatusPage, Error> {
+    let page = render().await?;
+    let dir = Path::new(STATUS_PAGE_DIR);
+    tokio::fs::create_dir_all(dir).await?;
+    let tmp = dir.join("status.json.tmp");
+    tokio::fs::write(
+        &tmp,
+        serde_json::to_vec(&page).with_code(crate::error::SERDE_ERROR)?,
+    )
+    .await
+    .with_context(|e| format!("{}: {}", tmp.display(), e))
+    .with_code(crate::error::FILESYSTEM_ERROR)?;
+    tokio::fs::rename(&tmp, dir.join("status.json"))
+        .await
+        .with_context(|e| format!("{}: {}", dir.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(page)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_docker_time() {
+        assert_eq!(parse_docker_time("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_docker_time("2021-03-04T12:34:56.123456789Z\n"),
+            Some(1614861296)
+        );
+        assert_eq!(parse_docker_time("2000-02-29T00:00:00Z"), Some(951782400));
+        assert_eq!(parse_docker_time("0001-01-01T00:00:00Z"), None);
+        assert_eq!(parse_docker_time("yesterday"), None);
+    }
+}
@@ -0,0 +1,148 @@
+use itertools::Itertools;
+
+use crate::manifest::Requirements;
+use crate::Error;
+use crate::ResultExt as _;
+
+const MIB: u64 = 1024 * 1024;
+
+/// What the host has to offer an app.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Resources {
+    /// Total memory, in MiB.
+    pub memory: u64,
+    /// Free space on the volume apps keep their data on, in MiB.
+    pub disk: u64,
+    pub cpu_arch: String,
+}
+
+pub fn resources() -> Result<Resources, Error> {
+    let sysinfo = nix::sys::sysinfo::sysinfo().with_code(crate::error::GENERAL_ERROR)?;
+    let statvfs =
+        nix::sys::statvfs::statvfs(crate::VOLUMES).with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(Resources {
+        memory: sysinfo.ram_total() / MIB,
+        disk: statvfs.blocks_available() as u64 * statvfs.fragment_size() as u64 / MIB,
+        cpu_arch: std::env::consts::ARCH.to_owned(),
+    })
+}
+
+/// A requirement of an app the host falls short of.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Shortfall {
+    CpuArch {
+        supported: Vec<String>,
+        actual: String,
+    },
+    Memory {
+        required: u64,
+        actual: u64,
+    },
+    Disk {
+        required: u64,
+        actual: u64,
+    },
+}
+impl std::fmt::Display for Shortfall {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Shortfall::CpuArch { supported, actual } => write!(
+                f,
+                "Runs on {} but this is {}",
+                supported.iter().join(", "),
+                actual
+            ),
+            Shortfall::Memory { required, actual } => write!(
+                f,
+                "Needs {} MiB of memory but there is {} MiB",
+                required, actual
+            ),
+            Shortfall::Disk { required, actual } => write!(
+                f,
+                "Needs {} MiB of disk but there is {} MiB free",
+                required, actual
+            ),
+        }
+    }
+}
+
+pub fn shortfalls(requirements: &Requirements, resources: &Resources) -> Vec<Shortfall> {
+    let mut res = Vec::new();
+    if !requirements.cpu_arch.is_empty() && !requirements.cpu_arch.contains(&resources.cpu_arch) {
+        res.push(Shortfall::CpuArch {
+            supported: requirements.cpu_arch.clone(),
+            actual: resources.cpu_arch.clone(),
+        });
+    }
+    match requirements.min_memory {
+        Some(required) if required > resources.memory => res.push(Shortfall::Memory {
+            required,
+            actual: resources.memory,
+        }),
+        _ => (),
+    }
+    match requirements.min_disk {
+        Some(required) if required > resources.disk => res.push(Shortfall::Disk {
+            required,
+            actual: resources.disk,
+        }),
+        _ => (),
+    }
+    res
+}
+
+/// Checks the host against what an app requires. An app cannot run on another architecture, so
+/// that is always refused. Too little memory or disk is refused for a new install, and only
+/// warned about when `strict` is off, i.e. for an app that is already installed and being updated.
+pub fn check(id: &str, requirements: &Requirements, strict: bool) -> Result<(), Error> {
+    let shortfalls = shortfalls(requirements, &resources()?);
+    let refuse = strict
+        || shortfalls
+            .iter()
+            .any(|s| matches!(s, Shortfall::CpuArch { .. }));
+    if refuse && !shortfalls.is_empty() {
+        return Err(failure::format_err!(
+            "Insufficient Resources For {}: {}",
+            id,
+            shortfalls.iter().join("; ")
+        ))
+        .with_code(crate::error::RESOURCE_INSUFFICIENT);
+    }
+    for shortfall in shortfalls {
+        log::warn!("{}: {}", id, shortfall);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shortfalls() {
+        let resources = Resources {
+            memory: 1024,
+            disk: 10240,
+            cpu_arch: "aarch64".to_owned(),
+        };
+        let mut requirements = Requirements {
+            min_memory: Some(512),
+            min_disk: Some(10240),
+            cpu_arch: vec!["aarch64".to_owned(), "x86_64".to_owned()],
+        };
+        assert_eq!(shortfalls(&requirements, &resources), Vec::new());
+        requirements.min_memory = Some(2048);
+        requirements.cpu_arch = vec!["x86_64".to_owned()];
+        assert_eq!(
+            shortfalls(&requirements, &resources)
+                .iter()
+                .map(|s| format!("{}", s))
+                .collect::<Vec<_>>(),
+            vec![
+                "Runs on x86_64 but this is aarch64",
+                "Needs 2048 MiB of memory but there is 1024 MiB",
+            ]
+        );
+    }
+}
@@ -221,6 +221,7 @@ pub async fn write_services(hidden_services: &ServicesMap) -> Result<(), Error>
         }
         f.write_all(b"\n").await?;
     }
+    crate::shares::write_tor_services(&mut f).await?;
     Ok(())
 }
 
@@ -381,8 +382,15 @@ pub async fn write_lan_services(hidden_services: &ServicesMap) -> Result<(), Err
 
 pub async fn read_tor_address(name: &str, timeout: Option<Duration>) -> Result<String, Error> {
     log::info!("Retrieving Tor hidden service address for {}.", name);
+    read_hidden_service_hostname(&format!("app-{}", name), timeout).await
+}
+
+pub async fn read_hidden_service_hostname(
+    dir_name: &str,
+    timeout: Option<Duration>,
+) -> Result<String, Error> {
     let addr_path = Path::new(HIDDEN_SERVICE_DIR_ROOT)
-        .join(format!("app-{}", name))
+        .join(dir_name)
         .join("hostname");
     if let Some(timeout) = timeout {
         let start = Instant::now();
@@ -527,6 +535,8 @@ pub async fn set_svc(
             .unwrap_or(0)
     );
     hidden_services.commit().await?;
+    // the rules exempting an app name its ip
+    crate::firewall::refresh().await?;
     Ok((ip, addr, key))
 }
 
@@ -571,6 +581,7 @@ pub async fn rm_svc(name: &str) -> Result<(), Error> {
             .unwrap_or(0)
     );
     hidden_services.commit().await?;
+    crate::firewall::refresh().await?;
     Ok(())
 }
 
@@ -591,10 +602,7 @@ pub async fn change_key(
         let key_path = hidden_service_path.join("hs_ed25519_secret_key");
         let mut key_data = b"== ed25519v1-secret: type0 ==".to_vec();
         key_data.extend_from_slice(&key.to_bytes());
-        tokio::fs::write(&key_path, key_data)
-            .await
-            .with_context(|e| format!("{}: {}", key_path.display(), e))
-            .with_code(crate::error::FILESYSTEM_ERROR)?;
+        crate::util::write_verified(&key_path, &key_data).await?;
     }
     log::info!("Reloading Tor.");
     let svc_exit = std::process::Command::new("service")
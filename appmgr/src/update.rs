@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use linear_map::LinearMap;
 
 use crate::dependencies::{DependencyError, TaggedDependencyError};
@@ -7,9 +9,29 @@ use crate::ResultExt as _;
 pub async fn update(
     name_version: &str,
     dry_run: bool,
+    retention: Duration,
+    ignore_pin: bool,
+    os_update: bool,
 ) -> Result<LinearMap<String, TaggedDependencyError>, Error> {
     let mut name_version_iter = name_version.split("@");
     let name = name_version_iter.next().unwrap();
+    let (pinned, system) = crate::apps::list_info()
+        .await?
+        .get(name)
+        .map(|info| (info.pinned, info.system))
+        .unwrap_or((false, false));
+    crate::ensure_code!(
+        !system || os_update,
+        crate::error::GENERAL_ERROR,
+        "{} is a system package and is updated with the OS",
+        name
+    );
+    crate::ensure_code!(
+        !pinned || ignore_pin,
+        crate::error::GENERAL_ERROR,
+        "{} is pinned to its current version, use --ignore-pin to update anyway",
+        name
+    );
     let version_req = name_version_iter
         .next()
         .map(|v| v.parse())
@@ -17,6 +39,14 @@ pub async fn update(
         .no_code()?
         .unwrap_or_else(emver::VersionRange::any);
     let version = crate::registry::version(name, &version_req).await?;
+    // before the current version is removed, so an update this host cannot run leaves it in place
+    crate::system::check(
+        name,
+        &crate::registry::manifest(name, &version_req)
+            .await?
+            .requirements,
+        false,
+    )?;
     let mut res = LinearMap::new();
     for dependent in crate::apps::dependents(name, false).await? {
         if crate::apps::status(&dependent, false).await?.status
@@ -75,10 +105,27 @@ pub async fn update(
     if dry_run {
         return Ok(res);
     }
-    let download_path = crate::install::download_name(name_version).await?;
-    crate::remove::remove(name, false, false).await?;
-    crate::install::install_path(download_path, Some(name)).await?;
+    let download_path = {
+        let _io = crate::io_priority::enter(crate::io_priority::JobClass::Download).await?;
+        crate::install::download_name(name_version).await?
+    };
+    crate::retention::retain(name, retention).await?;
+    crate::remove::remove_unchecked(name, false, false, false).await?;
+    if let Err(e) = crate::install::install_path(download_path, Some(name), None).await {
+        // with nothing retained, there is nothing to go back to
+        if retention > Duration::from_secs(0) {
+            log::error!("Update of {} failed, rolling back: {}", name, e.failure);
+            if let Err(e) = crate::retention::rollback(name).await {
+                log::error!("Rollback of {} failed: {}", name, e.failure);
+            }
+        }
+        return Err(e);
+    }
     crate::apps::set_recoverable(name, false).await?;
+    if pinned {
+        crate::apps::set_pinned(name, true).await?;
+    }
+    crate::retention::gc(false).await?;
 
     Ok(res)
 }
@@ -118,6 +118,34 @@ impl PersistencePath {
             e => e.with_code(crate::error::FILESYSTEM_ERROR),
         }
     }
+
+    /// Reads a yaml file that may be sealed under the device key, see `write_sealed`.
+    pub async fn maybe_read_sealed<T>(&self) -> Result<Option<T>, Error>
+    where
+        T: for<'de> serde::Deserialize<'de>,
+    {
+        let mut f = match self.maybe_read(false).await.transpose()? {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        let mut buffer = Vec::new();
+        f.read_to_end(&mut buffer).await?;
+        let buffer = crate::encryption::open_if_sealed(buffer).await?;
+        serde_yaml::from_slice(&buffer)
+            .map_err(failure::Error::from)
+            .with_code(crate::error::SERDE_ERROR)
+            .map(Some)
+    }
+
+    /// Writes a yaml file, sealed under the device key if configs are encrypted at rest.
+    pub async fn write_sealed<T: serde::Serialize>(&self, value: &T) -> Result<(), Error> {
+        let mut buffer = serde_yaml::to_vec(value).with_code(crate::error::SERDE_ERROR)?;
+        buffer.extend_from_slice(b"\n");
+        let buffer = crate::encryption::seal_if_enabled(buffer).await?;
+        let mut f = self.write(None).await?;
+        f.write_all(&buffer).await?;
+        f.commit().await
+    }
 }
 
 #[derive(Debug)]
@@ -149,17 +177,22 @@ impl PersistenceFile {
             drop(file);
         }
         if let Some(path) = self.needs_commit.take() {
-            tokio::fs::rename(path.tmp(), path.path())
-                .await
-                .with_context(|e| {
-                    format!(
-                        "{} -> {}: {}",
-                        path.tmp().display(),
-                        path.path().display(),
-                        e
-                    )
-                })
-                .with_code(crate::error::FILESYSTEM_ERROR)?;
+            if *crate::PARANOID_WRITES {
+                let expected = openssl::sha::sha256(&tokio::fs::read(path.tmp()).await?);
+                replace_verified(&path.tmp(), &path.path(), expected).await?;
+            } else {
+                tokio::fs::rename(path.tmp(), path.path())
+                    .await
+                    .with_context(|e| {
+                        format!(
+                            "{} -> {}: {}",
+                            path.tmp().display(),
+                            path.path().display(),
+                            e
+                        )
+                    })
+                    .with_code(crate::error::FILESYSTEM_ERROR)?;
+            }
             if let Some(lock) = self.lock.take() {
                 unlock(lock)
                     .await
@@ -206,6 +239,99 @@ impl Drop for PersistenceFile {
     }
 }
 
+// evicts a file from the page cache, so it is read back from the card rather than from memory
+fn drop_cache(path: &Path) -> Result<(), Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::File::open(path)
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    let res = unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+    crate::ensure_code!(
+        res == 0,
+        crate::error::FILESYSTEM_ERROR,
+        "{}: posix_fadvise: {}",
+        path.display(),
+        std::io::Error::from_raw_os_error(res)
+    );
+    Ok(())
+}
+
+async fn read_back(path: &Path) -> Result<[u8; 32], Error> {
+    drop_cache(path)?;
+    let data = tokio::fs::read(path)
+        .await
+        .with_context(|e| format!("{}: {}", path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    Ok(openssl::sha::sha256(&data))
+}
+
+/// Moves `tmp` (already synced) over `path`, checking that both read back with the sha256
+/// `expected`. The previous copy of `path` is kept as `<path>.bak` until the new one is verified,
+/// and is put back if it is not.
+async fn replace_verified(tmp: &Path, path: &Path, expected: [u8; 32]) -> Result<(), Error> {
+    crate::ensure_code!(
+        read_back(tmp).await? == expected,
+        crate::error::FILESYSTEM_ERROR,
+        "{} Failed Verification, Keeping Previous Copy of {}",
+        tmp.display(),
+        path.display()
+    );
+    let bak = PathBuf::from(format!("{}.bak", path.display()));
+    let has_bak = path.exists();
+    if has_bak {
+        if bak.exists() {
+            tokio::fs::remove_file(&bak).await?;
+        }
+        tokio::fs::hard_link(path, &bak)
+            .await
+            .with_context(|e| format!("{} -> {}: {}", path.display(), bak.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR)?;
+    }
+    tokio::fs::rename(tmp, path)
+        .await
+        .with_context(|e| format!("{} -> {}: {}", tmp.display(), path.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    if let Some(parent) = path.parent() {
+        File::open(parent).await?.sync_all().await?;
+    }
+    if read_back(path).await? != expected {
+        if has_bak {
+            log::error!(
+                "{} Failed Verification, Restoring Previous Copy.",
+                path.display()
+            );
+            tokio::fs::rename(&bak, path).await?;
+        }
+        return Err(format_err!("{} Failed Verification", path.display()))
+            .with_code(crate::error::FILESYSTEM_ERROR);
+    }
+    if has_bak {
+        tokio::fs::remove_file(&bak).await?;
+    }
+    Ok(())
+}
+
+/// Writes `data` to `path`. With paranoid writes on, it is written to `<path>.tmp`, synced and
+/// verified first, and only then moved over the previous copy.
+pub async fn write_verified(path: &Path, data: &[u8]) -> Result<(), Error> {
+    if !*crate::PARANOID_WRITES {
+        return tokio::fs::write(path, data)
+            .await
+            .with_context(|e| format!("{}: {}", path.display(), e))
+            .with_code(crate::error::FILESYSTEM_ERROR);
+    }
+    let tmp = PathBuf::from(format!("{}.tmp", path.display()));
+    let mut f = File::create(&tmp)
+        .await
+        .with_context(|e| format!("{}: {}", tmp.display(), e))
+        .with_code(crate::error::FILESYSTEM_ERROR)?;
+    f.write_all(data).await?;
+    f.sync_all().await?;
+    drop(f);
+    replace_verified(&tmp, path, openssl::sha::sha256(data)).await
+}
+
 pub trait UpdateHandleMode {}
 pub struct ForRead;
 impl UpdateHandleMode for ForRead {}
@@ -463,6 +589,82 @@ pub async fn unlock(lock: FileLock) -> std::io::Result<()> {
     tokio::task::spawn_blocking(move || lock.unlock()).await?
 }
 
+lazy_static::lazy_static! {
+    // one mutex per app, so that the tasks of this process queue on each other. The underlying
+    // locks are fcntl locks, which belong to the process and cannot tell its tasks apart.
+    static ref APP_LOCKS: std::sync::Mutex<
+        linear_map::LinearMap<String, std::sync::Arc<tokio::sync::Mutex<()>>>,
+    > = std::sync::Mutex::new(linear_map::LinearMap::new());
+}
+
+tokio::task_local! {
+    // the apps locked by the current `app_lock_scope`
+    static HELD_APP_LOCKS: std::cell::RefCell<linear_map::set::LinearSet<String>>;
+}
+
+/// Runs `fut` as a single holder of app locks: `lock_app` is re-entrant within it, and queues
+/// behind every other holder. Each task that locks apps needs its own, as does each future that
+/// locks apps while running alongside others in one task. Outside of any, locks are not
+/// re-entrant.
+pub async fn app_lock_scope<F: std::future::Future>(fut: F) -> F::Output {
+    HELD_APP_LOCKS.scope(Default::default(), fut).await
+}
+
+/// Held while an operation changes the state of an app, i.e. its config, so that conflicting
+/// operations (from this or another process) queue rather than interleave their writes.
+/// Re-entrant within an `app_lock_scope`, so an operation can call others that lock the same app.
+pub struct AppLock {
+    id: String,
+    // only set on the outermost guard of its scope
+    held: Option<(FileLock, tokio::sync::OwnedMutexGuard<()>)>,
+}
+impl fmt::Debug for AppLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AppLock").field(&self.id).finish()
+    }
+}
+impl Drop for AppLock {
+    fn drop(&mut self) {
+        if let Some((lock, guard)) = self.held.take() {
+            let _ = HELD_APP_LOCKS.try_with(|held| held.borrow_mut().remove(&self.id));
+            if let Err(e) = lock.unlock() {
+                log::warn!("Failed to unlock {}: {}", self.id, e);
+            }
+            drop(guard);
+        }
+    }
+}
+
+pub async fn lock_app(id: &str) -> Result<AppLock, Error> {
+    if HELD_APP_LOCKS
+        .try_with(|held| held.borrow().contains(id))
+        .unwrap_or(false)
+    {
+        return Ok(AppLock {
+            id: id.to_owned(),
+            held: None,
+        });
+    }
+    let mutex = APP_LOCKS
+        .lock()
+        .unwrap()
+        .entry(id.to_owned())
+        .or_insert_with(|| std::sync::Arc::new(tokio::sync::Mutex::new(())))
+        .clone();
+    let guard = mutex.lock_owned().await;
+    // outside of the app directory, which install and remove replace
+    let path = PersistencePath::from_ref("apps").join(id);
+    if let Some(parent) = path.path().parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let lock = path.lock(true).await?;
+    let _ = HELD_APP_LOCKS.try_with(|held| held.borrow_mut().insert(id.to_owned()));
+    Ok(AppLock {
+        id: id.to_owned(),
+        held: Some((lock, guard)),
+    })
+}
+
 pub async fn from_yaml_async_reader<T, R>(mut reader: R) -> Result<T, crate::Error>
 where
     T: for<'de> serde::Deserialize<'de>,
@@ -191,7 +191,10 @@ pub async fn self_update(requirement: emver::VersionRange) -> Result<(), Error>
         .collect();
     let url = format!("{}/appmgr?spec={}", &*crate::SYS_REGISTRY_URL, req_str);
     log::info!("Fetching new version from {}", url);
-    let response = reqwest::get(&url)
+    let response = crate::registry::client()
+        .await?
+        .get(&url)
+        .send()
         .compat()
         .await
         .with_code(crate::error::NETWORK_ERROR)?
@@ -191,7 +191,7 @@ mod legacy {
                 .ok_or_else(|| failure::format_err!("invalid app id"))?;
             crate::install::download_name(name_version).await?;
             super::remove::remove(name, false).await?;
-            crate::install::install_name(name_version, true).await?;
+            crate::install::install_name(name_version, true, None).await?;
             let config = crate::apps::config(name).await?;
             if let Some(cfg) = config.config {
                 if config.spec.matches(&cfg).is_ok() {
@@ -55,6 +55,10 @@ impl VersionT for Version {
                         configured: i.configured,
                         recoverable: false,
                         needs_restart: false,
+                        pinned: false,
+                        system: false,
+                        mode: None,
+                        provenance: Default::default(),
                     },
                 ))
             })
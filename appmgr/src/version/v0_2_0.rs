@@ -29,6 +29,10 @@ impl VersionT for Version {
                         configured: ai.configured,
                         recoverable: ai.recoverable,
                         needs_restart: false,
+                        pinned: false,
+                        system: false,
+                        mode: None,
+                        provenance: Default::default(),
                     },
                 )
             })
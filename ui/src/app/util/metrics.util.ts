@@ -28,6 +28,9 @@ const schemaV2 = {
         'copyable': { 'type': 'boolean', 'default': false },
         'qr': { 'type': 'boolean', 'default': false },
         'masked': { 'type': 'boolean', 'default': false },
+        'renderer': { 'type': 'string', 'enum': ['plain', 'masked', 'qr', 'markdown'] },
+        'qr-payload': { 'type': 'string' },
+        'truncate': { 'type': 'integer', 'minimum': 0 },
       },
       'required': ['type', 'value', 'description', 'copyable', 'qr', 'masked'],
       'additionalProperties': false,
@@ -184,6 +187,9 @@ interface AppMetricString extends AppMetricBase {
   copyable: boolean
   qr: boolean
   masked: boolean
+  renderer?: 'plain' | 'masked' | 'qr' | 'markdown'
+  'qr-payload'?: string
+  truncate?: number
 }
 
 interface AppMetricObject extends AppMetricBase {